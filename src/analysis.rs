@@ -0,0 +1,173 @@
+// analysis.rs
+// Crooks relation check: ln(P_F(W) / P_R(-W)) = beta (W - Delta F)
+
+use crate::histogram::{Histogram, Normalization};
+
+// Log ratio of the forward and reverse work densities in one bin of the shared grid
+pub struct CrooksPoint {
+    pub work: f64,      // Bin centre W
+    pub log_ratio: f64, // ln(P_F(W) / P_R(-W))
+    pub weight: f64,    // Inverse variance of the log ratio from the bin counts
+}
+
+// Binned Crooks ratio together with its weighted straight-line fit
+pub struct CrooksRatio {
+    pub points: Vec<CrooksPoint>,
+    pub slope: f64,     // Fitted slope, expected to be beta = 1/kT
+    pub intercept: f64, // Fitted intercept, expected to be -beta Delta F
+}
+
+impl CrooksRatio {
+    // Free-energy difference where the fitted line crosses zero
+    pub fn delta_f(&self) -> f64 {
+        -self.intercept / self.slope
+    }
+
+    // Weighted squared residuals about the line per degree of freedom, near one
+    // when ln(P_F/P_R) is linear in W up to the counting noise of the bins
+    pub fn reduced_chi_square(&self) -> f64 {
+        let residuals: f64 = self
+            .points
+            .iter()
+            .map(|p| p.weight * (p.log_ratio - self.slope * p.work - self.intercept).powi(2))
+            .sum();
+        residuals / self.points.len().saturating_sub(2).max(1) as f64
+    }
+}
+
+// Histograms of the forward work and the negated reverse work on one shared grid.
+// Like the other functions here, this takes the reverse work as measured.
+pub fn work_histograms(forward: &[f64], reverse: &[f64], bins: usize) -> (Histogram, Histogram) {
+    let reverse: Vec<f64> = reverse.iter().map(|w| -w).collect();
+
+    let (min, max) = Histogram::auto_range([forward, reverse.as_slice()]);
+    (
+        Histogram::build(forward, min, max, bins),
+        Histogram::build(&reverse, min, max, bins),
+    )
+}
+
+// Histograms of the forward heat and the negated reverse heat on one shared
+// grid, for comparing P_F(Q) with P_R(-Q) as for the work
+pub fn heat_histograms(forward: &[f64], reverse: &[f64], bins: usize) -> (Histogram, Histogram) {
+    work_histograms(forward, reverse, bins)
+}
+
+// Compute ln(P_F/P_R) in every bin of the shared grid populated by both
+// ensembles, or None when the histograms overlap too little to fit a line
+pub fn crooks_ratio(forward_work: &[f64], reverse_work: &[f64], bins: usize) -> Option<CrooksRatio> {
+    let (forward, reverse) = work_histograms(forward_work, reverse_work, bins);
+    let forward_counts = forward.heights(Normalization::Counts);
+    let reverse_counts = reverse.heights(Normalization::Counts);
+    let forward_p = forward.heights(Normalization::Probability);
+    let reverse_p = reverse.heights(Normalization::Probability);
+
+    let points: Vec<CrooksPoint> = (0..bins)
+        .filter(|&i| forward_counts[i] > 0.0 && reverse_counts[i] > 0.0)
+        .map(|i| CrooksPoint {
+            work: forward.centre(i),
+            log_ratio: forward_p[i].ln() - reverse_p[i].ln(),
+            weight: 1.0 / (1.0 / forward_counts[i] + 1.0 / reverse_counts[i]),
+        })
+        .collect();
+
+    let (slope, intercept) = weighted_fit(&points)?;
+    Some(CrooksRatio {
+        points,
+        slope,
+        intercept,
+    })
+}
+
+// How a crossing estimate was obtained
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrossingMethod {
+    // Linear interpolation of ln P_F - ln P_R between the bins around a sign change
+    Interpolated,
+    // No populated crossing: midpoint of the bound -<W_R> <= Delta F <= <W_F>
+    Bracketed,
+}
+
+// Delta F read off where P_F(W) = P_R(-W)
+pub struct Crossing {
+    pub delta_f: f64,
+    pub lower: f64, // Bracket known to contain the crossing
+    pub upper: f64,
+    pub method: CrossingMethod,
+}
+
+// Locate the crossing of the forward and negated reverse work histograms. The
+// log density difference is interpolated linearly between the centres of the
+// populated bins on either side of a sign change; when there are several, the
+// best-populated one wins. With no overlap or no sign change, fall back to the
+// second-law bracket, which always holds but can be wide.
+pub fn crossing(forward_work: &[f64], reverse_work: &[f64], bins: usize) -> Crossing {
+    let (forward, reverse) = work_histograms(forward_work, reverse_work, bins);
+    let forward_counts = forward.heights(Normalization::Counts);
+    let reverse_counts = reverse.heights(Normalization::Counts);
+    let forward_p = forward.heights(Normalization::Probability);
+    let reverse_p = reverse.heights(Normalization::Probability);
+
+    let populated: Vec<(f64, f64, f64)> = (0..bins)
+        .filter(|&i| forward_counts[i] > 0.0 && reverse_counts[i] > 0.0)
+        .map(|i| {
+            (
+                forward.centre(i),
+                forward_p[i].ln() - reverse_p[i].ln(),
+                forward_counts[i].min(reverse_counts[i]),
+            )
+        })
+        .collect();
+
+    let best = populated
+        .windows(2)
+        .filter(|pair| pair[0].1 == 0.0 || pair[0].1.signum() != pair[1].1.signum())
+        .max_by(|a, b| (a[0].2 + a[1].2).total_cmp(&(b[0].2 + b[1].2)));
+
+    match best {
+        Some(pair) => {
+            let (w0, d0, _) = pair[0];
+            let (w1, d1, _) = pair[1];
+            let delta_f = if d0 == d1 { w0 } else { w0 + (w1 - w0) * d0 / (d0 - d1) };
+            Crossing {
+                delta_f,
+                lower: w0,
+                upper: w1,
+                method: CrossingMethod::Interpolated,
+            }
+        }
+        None => {
+            let mean = |work: &[f64]| work.iter().sum::<f64>() / work.len() as f64;
+            let lower = -mean(reverse_work);
+            let upper = mean(forward_work);
+            Crossing {
+                delta_f: 0.5 * (lower + upper),
+                lower,
+                upper,
+                method: CrossingMethod::Bracketed,
+            }
+        }
+    }
+}
+
+// Weighted least-squares straight line through the Crooks points, None through
+// fewer than two of them or all at one W
+fn weighted_fit(points: &[CrooksPoint]) -> Option<(f64, f64)> {
+    if points.len() < 2 {
+        return None;
+    }
+    let sw: f64 = points.iter().map(|p| p.weight).sum();
+    let mean_x = points.iter().map(|p| p.weight * p.work).sum::<f64>() / sw;
+    let mean_y = points.iter().map(|p| p.weight * p.log_ratio).sum::<f64>() / sw;
+    let sxy: f64 = points
+        .iter()
+        .map(|p| p.weight * (p.work - mean_x) * (p.log_ratio - mean_y))
+        .sum();
+    let sxx: f64 = points.iter().map(|p| p.weight * (p.work - mean_x).powi(2)).sum();
+    if sxx <= 0.0 {
+        return None;
+    }
+
+    let slope = sxy / sxx;
+    Some((slope, mean_y - slope * mean_x))
+}
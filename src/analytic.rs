@@ -0,0 +1,148 @@
+// analytic.rs
+// Exact reference solutions for the particle dragged through a harmonic landscape
+
+use crate::protocol::Protocol;
+use crate::simulation::{Control, Initial, TrapParams};
+use std::f64::consts::PI;
+
+// Fine steps used to integrate the mean trajectory along a protocol
+const REFERENCE_STEPS: usize = 100_000;
+
+// Normal work distribution
+#[derive(Clone, Copy, Debug)]
+pub struct GaussianWork {
+    pub mean: f64,
+    pub variance: f64,
+}
+
+impl GaussianWork {
+    // Standard deviation of the work
+    pub fn std(&self) -> f64 {
+        self.variance.sqrt()
+    }
+}
+
+// Exact Delta F of the forward protocol and the work distributions of both
+// directions, the reverse one as measured
+pub struct HarmonicReference {
+    pub delta_f: f64,
+    pub forward: GaussianWork,
+    pub reverse: GaussianWork,
+}
+
+// Landscape stiffness kappa and centre c, when the total potential
+// k/2 (x - lambda)^2 + kappa/2 (x - c)^2 is quadratic, which the nearest-image
+// trap of a periodic box and reflecting walls rule out
+pub fn quadratic(params: &TrapParams) -> Option<(f64, f64)> {
+    if params.expression.is_some() || params.box_length.is_some() || params.walls != (f64::NEG_INFINITY, f64::INFINITY) {
+        return None;
+    }
+    params.landscape.quadratic()
+}
+
+// F = k kappa / (2 (k + kappa)) (lambda - c)^2 - f m - f^2 / (2 (k + kappa)),
+// the minimum of the total potential with the tilt f and the untilted minimum
+// m = (k lambda + kappa c) / (k + kappa), plus kT/2 ln((k + kappa) / 2 pi kT)
+// from the Gaussian integral along the pulling axis and kT/2 ln(k / 2 pi kT)
+// from each transverse axis
+pub fn free_energy(params: &TrapParams, control: Control) -> Option<f64> {
+    let (kappa, centre) = quadratic(params)?;
+    let (k, lambda, kt, f) = (control.stiffness, control.centre, params.temperature.kt(), params.tilt);
+    let m = (k * lambda + kappa * centre) / (k + kappa);
+    let minimum = 0.5 * k * kappa / (k + kappa) * (lambda - centre) * (lambda - centre) - f * m - 0.5 * f * f / (k + kappa);
+    let transverse = (params.dimensions - 1) as f64 * (k / (2.0 * PI * kt)).ln();
+    Some(minimum + 0.5 * kt * (((k + kappa) / (2.0 * PI * kt)).ln() + transverse))
+}
+
+// Mean work of a protocol started from equilibrium. The dynamics are linear,
+// so the mean position obeys the noiseless equation of motion, overdamped or
+// inertial, and the mean work increment k/2 (lambda'^2 - lambda^2) - k <x>
+// (lambda' - lambda) is linear in <x>; both are integrated on a fine grid with
+// the trap moved before each RK4 step, which also handles jumps in lambda.
+fn mean_work(params: &TrapParams, protocol: &Protocol) -> f64 {
+    let (kappa, centre) = quadratic(params).expect("mean_work needs a quadratic potential");
+    let k = params.stiffness;
+    let force = |x: f64, lambda: f64| -k * (x - lambda) - kappa * (x - centre) + params.tilt;
+    let inertial = params.integrator.is_inertial();
+    let derivative = |(x, v): (f64, f64), lambda: f64| {
+        if inertial {
+            (v, (force(x, lambda) - params.friction * v) / params.mass)
+        } else {
+            (force(x, lambda) / params.friction, 0.0)
+        }
+    };
+
+    let mut lambda = protocol.lambda_start();
+    let mut state = ((k * lambda + kappa * centre + params.tilt) / (k + kappa), 0.0);
+    let h = protocol.duration / REFERENCE_STEPS as f64;
+    let mut work = 0.0;
+    for step in 1..=REFERENCE_STEPS {
+        let next_lambda = protocol.lambda_at(step as f64 * h);
+        work += 0.5 * k * (next_lambda * next_lambda - lambda * lambda) - k * state.0 * (next_lambda - lambda);
+        lambda = next_lambda;
+
+        let shift = |s: (f64, f64), d: (f64, f64), by: f64| (s.0 + by * d.0, s.1 + by * d.1);
+        let k1 = derivative(state, lambda);
+        let k2 = derivative(shift(state, k1, 0.5 * h), lambda);
+        let k3 = derivative(shift(state, k2, 0.5 * h), lambda);
+        let k4 = derivative(shift(state, k3, h), lambda);
+        state.0 += h / 6.0 * (k1.0 + 2.0 * k2.0 + 2.0 * k3.0 + k4.0);
+        state.1 += h / 6.0 * (k1.1 + 2.0 * k2.1 + 2.0 * k3.1 + k4.1);
+    }
+    work
+}
+
+// Mean dissipated work of counterdiabatic driving, which holds the density at
+// the equilibrium one of the target trap, N(lambda, kT/kappa) on every axis.
+// The particles then move with the mean velocity u = lambda_dot - kappa_dot/(2 kappa)
+// (x - lambda) and dissipate gamma <u^2> = gamma (lambda_dot^2 +
+// d kT kappa_dot^2 / (4 kappa^3)) per unit time, the same in both directions.
+pub fn counterdiabatic_dissipation(params: &TrapParams, protocol: &Protocol) -> f64 {
+    let h = protocol.duration / REFERENCE_STEPS as f64;
+    let kt = params.temperature.kt();
+    let target = |t: f64| (protocol.lambda_at(t), protocol.stiffness_at(t).unwrap_or(params.stiffness));
+    (0..REFERENCE_STEPS)
+        .map(|step| {
+            let ((l0, k0), (l1, k1)) = (target(step as f64 * h), target((step + 1) as f64 * h));
+            let kappa = 0.5 * (k0 + k1);
+            let (lambda_rate, kappa_rate) = ((l1 - l0) / h, (k1 - k0) / h);
+            params.friction * (lambda_rate.powi(2) + params.dimensions as f64 * kt * kappa_rate.powi(2) / (4.0 * kappa.powi(3))) * h
+        })
+        .sum()
+}
+
+// Exact reference for a particle in a flat or harmonic landscape started from
+// the Boltzmann distribution. The work is then Gaussian, and the Jarzynski
+// equality fixes its variance to 2 kT (<W> - Delta F) in either direction.
+// None for expressions, other landscapes, starts at the trap centre, escorted
+// dynamics, driven stiffness, which makes the work distribution skewed, and
+// colored noise, under which the Jarzynski equality itself need not hold.
+// Counterdiabatic driving keeps the work Gaussian with a known dissipation.
+pub fn harmonic_reference(params: &TrapParams, protocol: &Protocol) -> Option<HarmonicReference> {
+    if params.initial != Initial::Boltzmann
+        || params.escort != 0.0
+        || protocol.stiffness.is_some()
+        || params.correlation_time.is_some()
+    {
+        return None;
+    }
+    let end = params.control(protocol, protocol.duration);
+    let delta_f = free_energy(params, end)? - free_energy(params, params.control(protocol, 0.0))?;
+    let kt = params.temperature.kt();
+    let gaussian = |mean: f64, delta_f: f64| GaussianWork {
+        mean,
+        variance: 2.0 * kt * (mean - delta_f),
+    };
+    let mean = |protocol: &Protocol, delta_f: f64| {
+        if params.counterdiabatic {
+            delta_f + counterdiabatic_dissipation(params, protocol)
+        } else {
+            mean_work(params, protocol)
+        }
+    };
+    Some(HarmonicReference {
+        delta_f,
+        forward: gaussian(mean(protocol, delta_f), delta_f),
+        reverse: gaussian(mean(&protocol.reversed(), -delta_f), -delta_f),
+    })
+}
@@ -0,0 +1,531 @@
+// animation.rs
+// The animated field of the Crooks series, rendered frame by frame into a pixel buffer
+
+use crate::colorbar::Colorbar;
+use crate::colormap::{ColourScale, Colormap, ScaleSettings};
+use crate::config::Config;
+use crate::contour;
+use crate::equalise::Equaliser;
+use crate::generators;
+#[cfg(feature = "gpu")]
+use crate::gpu::{FieldParams, GpuField};
+use crate::summation;
+use crate::surface;
+use crate::text;
+use rayon::prelude::*;
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+// Size relative to the largest term below which the series terms are dropped
+const SERIES_EPSILON: f64 = 1e-17;
+
+// Parameter overlay: screen pixels per font pixel, offset from the corners,
+// and text colour
+pub const OVERLAY_SCALE: usize = 2;
+pub const OVERLAY_MARGIN: usize = 8;
+pub const OVERLAY_COLOUR: u32 = 0xf0f0f0;
+
+// Colour of the line between the forward and reverse halves of the split layout
+const SPLIT_DIVIDER: u32 = 0xffffff;
+
+// Zoom factor of one scroll-wheel notch, and the range the zoom is kept in
+pub const ZOOM_STEP: f64 = 1.1;
+const ZOOM_LIMITS: (f64, f64) = (1e-3, 1e6);
+
+// Degrees the surface turns per pixel of dragging, and the range its
+// elevation is kept in, from nearly edge-on to straight down
+const ROTATE_RATE: f64 = 0.3;
+const ELEVATION_LIMITS: (f64, f64) = (5.0, 90.0);
+
+// Where the field is evaluated and coloured
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Cpu,
+    // A wgpu compute shader, in single precision; needs the gpu feature
+    Gpu,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cpu" => Ok(Backend::Cpu),
+            "gpu" => Ok(Backend::Gpu),
+            _ => Err(format!("unknown backend '{}'", s)),
+        }
+    }
+}
+
+// Function to compute the Crooks fluctuation theorem, with compensated summation
+// since the terms fall off exponentially. Every term carries the same factor
+// sin(time) and a sech factor that peaks where 2 pi i + time is nearest zero:
+// for time >= 0 that is the first term and they shrink by e^(-2 pi) each, but
+// the negative times of the split layout grow for about |time| / (2 pi) terms
+// first. So the series stops at the first term below SERIES_EPSILON of the
+// largest so far, which is never one still climbing; `terms` caps the count.
+fn crooks_fluctuation_theorem(terms: u32, coefficient: f64, exponent: f64, time: f64) -> f64 {
+    let term = |i: u32| {
+        let term = (2.0 * PI * i as f64 + time).sin() / (2.0 * PI * i as f64 + time).cosh();
+        (coefficient * term).powf(exponent)
+    };
+    let mut largest = 0.0_f64;
+    summation::compensated_sum((1..=terms).map(term).take_while(move |t| {
+        largest = largest.max(t.abs());
+        t.abs() > SERIES_EPSILON * largest
+    }))
+}
+
+// Map from screen pixels to the plane the field is evaluated on, in which the
+// series argument is time + (u + v) / 100: the point shown at the centre of
+// the screen and the screen pixels per unit. At zoom 1 centred on the middle
+// of the screen, the plane coordinates are the pixel coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View {
+    pub centre: (f64, f64),
+    pub zoom: f64,
+}
+
+impl View {
+    // The unzoomed view of a screen of the given size
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            centre: (width as f64 / 2.0, height as f64 / 2.0),
+            zoom: 1.0,
+        }
+    }
+
+    // Plane point under the screen point (x, y)
+    fn to_plane(self, x: f64, y: f64, width: usize, height: usize) -> (f64, f64) {
+        (
+            self.centre.0 + (x - width as f64 / 2.0) / self.zoom,
+            self.centre.1 + (y - height as f64 / 2.0) / self.zoom,
+        )
+    }
+}
+
+// Everything the field depends on, which decides whether the cached one is
+// still current
+#[derive(Clone, Copy, PartialEq)]
+struct FieldKey {
+    time: f64,
+    split: bool,
+    view: View,
+    size: (usize, usize),
+    series: (u32, f64, f64),
+}
+
+// State of the animation: the series parameters, the clock, and how the field
+// is presented, with the field itself cached between frames at the same time
+pub struct Scene {
+    pub width: usize,
+    pub height: usize,
+    pub terms: u32, // Upper bound; the series converges after a few terms
+    pub coefficient: f64,
+    pub exponent: f64,
+    pub scale_factor: f64, // Adjusted scale factor for better variability
+    pub time: f64,
+    pub time_step: f64,
+    pub frame: u32, // Frames advanced so far, which keys the noise
+    pub colormap: Colormap,
+    pub colour_scale: ColourScale,
+    pub split: bool,
+    pub contours: bool, // Contour lines of the field at the configured levels
+    pub surface: bool, // Height-mapped surface in place of the flat frame
+    pub azimuth: f64, // Degrees the surface is turned about the vertical
+    pub elevation: f64, // Degrees the surface is looked down on from the horizontal
+    view: View,
+    ssaa: usize, // Field samples per pixel along each side, averaged in colour
+    field: Vec<f64>, // Field at every sample, the ssaa * ssaa of each pixel together
+    field_key: Option<FieldKey>, // What the cached field was last evaluated for
+    range: Option<(f64, f64)>, // Field values at the ends of the last frame's colour scale
+    colorbar: Option<Colorbar>, // Legend of the last frame, when its colours stand for values
+    equaliser: Equaliser, // Quantiles of the recent frames for the equalising scale
+    equalised: Option<FieldKey>, // Field the equaliser last took in, none when it starts afresh
+    #[cfg(feature = "gpu")]
+    gpu: Option<GpuField>, // Compute shader of the gpu backend, unless it failed
+}
+
+impl Scene {
+    // Scene at time zero presented as configured
+    pub fn new(config: &Config, width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            terms: 100,
+            coefficient: 2.0,
+            exponent: 3.0,
+            scale_factor: 1e3,
+            time: 0.0,
+            time_step: 0.05,
+            frame: 0,
+            colormap: config.colormap,
+            colour_scale: config.colour_scale,
+            split: config.split,
+            contours: config.contours,
+            surface: config.surface,
+            azimuth: config.surface_azimuth,
+            elevation: config.surface_elevation,
+            view: View::new(width, height),
+            ssaa: config.ssaa,
+            field: Vec::new(),
+            field_key: None,
+            range: None,
+            colorbar: None,
+            equaliser: Equaliser::default(),
+            equalised: None,
+            #[cfg(feature = "gpu")]
+            gpu: match config.backend {
+                Backend::Gpu => GpuField::new().map_err(|e| eprintln!("{}; rendering on the CPU", e)).ok(),
+                Backend::Cpu => None,
+            },
+        }
+    }
+
+    // Move the clock on by one frame
+    pub fn advance(&mut self) {
+        self.time += self.time_step;
+        self.frame += 1;
+    }
+
+    // Screen column of the forward half a screen column is drawn from. In the
+    // split layout the right half is mirrored about the dividing line, so that
+    // matching points of the two halves face each other.
+    fn unmirror(&self, x: f64) -> f64 {
+        if self.split && x >= (self.width / 2) as f64 {
+            (self.width - 1) as f64 - x
+        } else {
+            x
+        }
+    }
+
+    // Argument of the series at the point offset from pixel (x, y) by a
+    // fraction of a pixel, through the view. In the split layout the left half
+    // is driven forward as usual and the right half shows the time-reversed
+    // drive, the argument negated.
+    fn field_argument(&self, x: usize, y: usize, (dx, dy): (f64, f64)) -> f64 {
+        let (u, v) = self.view.to_plane(self.unmirror(x as f64 + dx), y as f64 + dy, self.width, self.height);
+        let argument = self.time + u / 100.0 + v / 100.0;
+        if self.split && x >= self.width / 2 {
+            -argument
+        } else {
+            argument
+        }
+    }
+
+    // Zoom by a factor about the screen point (x, y), which keeps showing the
+    // same point of the plane
+    pub fn zoom_at(&mut self, x: f64, y: f64, factor: f64) {
+        let x = self.unmirror(x);
+        let before = self.view.to_plane(x, y, self.width, self.height);
+        self.view.zoom = (self.view.zoom * factor).clamp(ZOOM_LIMITS.0, ZOOM_LIMITS.1);
+        let after = self.view.to_plane(x, y, self.width, self.height);
+        self.view.centre.0 += before.0 - after.0;
+        self.view.centre.1 += before.1 - after.1;
+    }
+
+    // Drag the plane by (dx, dy) screen pixels with the mouse at column x, so
+    // that the point under the mouse follows it; in the mirrored half a drag
+    // to the right moves the plane left
+    pub fn pan(&mut self, x: f64, dx: f64, dy: f64) {
+        let dx = if self.unmirror(x) != x { -dx } else { dx };
+        self.view.centre.0 -= dx / self.view.zoom;
+        self.view.centre.1 -= dy / self.view.zoom;
+    }
+
+    // Change the size of the frame, keeping the zoom and the plane point at the
+    // top-left corner
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.view.centre.0 += (width as f64 - self.width as f64) / 2.0 / self.view.zoom;
+        self.view.centre.1 += (height as f64 - self.height as f64) / 2.0 / self.view.zoom;
+        self.width = width;
+        self.height = height;
+    }
+
+    // View scaled so that the plane rectangle from the origin to (width,
+    // height), what an unzoomed frame of that size shows, fits the frame
+    pub fn fit(&mut self, width: usize, height: usize) {
+        self.view = View {
+            centre: (width as f64 / 2.0, height as f64 / 2.0),
+            zoom: (self.width as f64 / width as f64).min(self.height as f64 / height as f64),
+        };
+    }
+
+    // Turn the surface by a drag of (dx, dy) pixels: across about the
+    // vertical, up and down towards or away from looking straight down
+    pub fn rotate(&mut self, dx: f64, dy: f64) {
+        self.azimuth = (self.azimuth - dx * ROTATE_RATE).rem_euclid(360.0);
+        self.elevation = (self.elevation + dy * ROTATE_RATE).clamp(ELEVATION_LIMITS.0, ELEVATION_LIMITS.1);
+    }
+
+    // Back to the unzoomed view
+    pub fn reset_view(&mut self) {
+        self.view = View::new(self.width, self.height);
+    }
+
+    // Render the current frame as 0RGB pixels, row by row, on the GPU when the
+    // gpu backend is running and on the CPU otherwise
+    pub fn render(&mut self, config: &Config) -> Vec<u32> {
+        // The surface needs the heights as well as the colours, so it is
+        // always built on the CPU
+        if self.surface {
+            let (colours, values) = self.render_cpu(config);
+            return surface::draw(&values, &colours, self.width, self.height, self.azimuth, self.elevation);
+        }
+        #[cfg(feature = "gpu")]
+        let (mut buffer, values) = match self.render_gpu(config) {
+            Some(frame) => frame,
+            None => self.render_cpu(config),
+        };
+        #[cfg(not(feature = "gpu"))]
+        let (mut buffer, values) = self.render_cpu(config);
+
+        // Contours of the values the colours were made from
+        if self.contours {
+            contour::draw(&mut buffer, &values, self.width, self.height, &config.contour_levels);
+        }
+
+        // Divider between the forward and reverse halves
+        if self.split && self.width >= 2 {
+            for row in buffer.chunks_mut(self.width) {
+                row[self.width / 2 - 1..=self.width / 2].fill(SPLIT_DIVIDER);
+            }
+        }
+        buffer
+    }
+
+    // The frame from the compute shader, which evaluates the field afresh every
+    // time, and with contours the shader's field brought into [0, 1] by the
+    // range it was coloured over; if the GPU fails, the scene carries on with
+    // the CPU
+    #[cfg(feature = "gpu")]
+    fn render_gpu(&mut self, config: &Config) -> Option<(Vec<u32>, Vec<f64>)> {
+        // Percentiles and quantiles need every value in order, and
+        // supersampling many values to a pixel, which are left to the CPU
+        if matches!(self.colour_scale, ColourScale::Percentile | ColourScale::Equalise) || self.ssaa > 1 {
+            return None;
+        }
+        self.equalised = None;
+        let mut gpu = self.gpu.take()?;
+        let settings = self.scale_settings(config);
+        let params = FieldParams {
+            width: self.width,
+            height: self.height,
+            terms: self.terms,
+            split: self.split,
+            frame: self.frame,
+            key: generators::counter_key(config.seed),
+            colormap: self.colormap,
+            colour_scale: self.colour_scale,
+            time: self.time,
+            coefficient: self.coefficient,
+            exponent: self.exponent,
+            wave_factor: self.scale_factor,
+            centre: self.view.centre,
+            zoom: self.view.zoom,
+            fixed: settings.fixed,
+            symlog_threshold: settings.symlog_threshold,
+        };
+        match gpu.render(&params, self.contours) {
+            Ok(frame) => {
+                self.range = self.colour_scale.range_of(frame.bounds, &settings).filter(|(low, high)| high > low);
+                self.colorbar = self.colorbar(self.range, settings);
+                self.gpu = Some(gpu);
+                let values = frame.field.unwrap_or_default().into_iter().map(|v| self.place(v as f64, &settings)).collect();
+                Some((frame.pixels, values))
+            }
+            Err(e) => {
+                eprintln!("{}; rendering on the CPU from now on", e);
+                None
+            }
+        }
+    }
+
+    // The frame computed on the CPU, and for the contours or the surface each
+    // pixel's normalised value
+    fn render_cpu(&mut self, config: &Config) -> (Vec<u32>, Vec<f64>) {
+        self.evaluate(config);
+        let values = if self.contours || self.surface { self.normalised(config) } else { Vec::new() };
+        (self.colour(config), values)
+    }
+
+    // Evaluate the field at every sample and find the range the colour scale
+    // runs over. The field is only re-evaluated when time has moved or the
+    // layout, view or series changed, so a paused frame costs just its
+    // colouring.
+    fn evaluate(&mut self, config: &Config) {
+        let (width, height) = (self.width, self.height);
+        let key = FieldKey {
+            time: self.time,
+            split: self.split,
+            view: self.view,
+            size: (width, height),
+            series: (self.terms, self.coefficient, self.exponent),
+        };
+        if self.field_key != Some(key) {
+            // Samples on an even grid over each pixel, centred on the point an
+            // unsupersampled pixel is evaluated at
+            let n = self.ssaa;
+            self.field = (0..width * height * n * n)
+                .into_par_iter()
+                .map(|i| {
+                    let (pixel, sample) = (i / (n * n), i % (n * n));
+                    let offset = |k: usize| (k as f64 + 0.5) / n as f64 - 0.5;
+                    let argument = self.field_argument(pixel % width, pixel / width, (offset(sample % n), offset(sample / n)));
+                    crooks_fluctuation_theorem(self.terms, self.coefficient, self.exponent, argument)
+                })
+                .collect();
+            self.field_key = Some(key);
+        }
+        let settings = self.scale_settings(config);
+        self.range = match self.colour_scale {
+            ColourScale::Equalise => {
+                // Each new field is taken in once, however often it is
+                // coloured, and blended with the last only if the equaliser
+                // has been in use since
+                if self.equalised != Some(key) {
+                    let smoothing = if self.equalised.is_some() { config.equalise_smoothing } else { 0.0 };
+                    self.equaliser.update(&self.field, smoothing);
+                    self.equalised = Some(key);
+                }
+                self.equaliser.range()
+            }
+            ColourScale::Wave => {
+                self.equalised = None;
+                None
+            }
+            scale => {
+                self.equalised = None;
+                scale.range(&self.field, &settings).filter(|(low, high)| high > low)
+            }
+        };
+        self.colorbar = self.colorbar(self.range, settings);
+    }
+
+    // The mean of each pixel's samples brought into [0, 1] by the colour scale
+    // over the range evaluate found
+    fn normalised(&self, config: &Config) -> Vec<f64> {
+        let settings = self.scale_settings(config);
+        self.field
+            .par_chunks(self.ssaa * self.ssaa)
+            .map(|pixel| pixel.iter().map(|&v| self.place(v, &settings)).sum::<f64>() / pixel.len() as f64)
+            .collect()
+    }
+
+    // Position in [0, 1] the colour scale gives a value of the field
+    fn place(&self, value: f64, settings: &ScaleSettings) -> f64 {
+        match (self.colour_scale, self.range) {
+            (ColourScale::Equalise, Some(_)) => self.equaliser.position(value),
+            (scale, range) => scale.place(value, range, settings),
+        }
+    }
+
+    // What the colour scale takes from the scene and the configuration
+    fn scale_settings(&self, config: &Config) -> ScaleSettings {
+        ScaleSettings {
+            wave_factor: self.scale_factor,
+            fixed: (config.colour_min, config.colour_max),
+            symlog_threshold: config.symlog_threshold,
+            percentile: config.colour_percentile,
+        }
+    }
+
+    // Legend of a frame whose colour scale ran over the range: none without
+    // one, as for the wave scale, which folds the values, or a flat frame, or
+    // for the noise map, which is not a function of the values
+    fn colorbar(&self, range: Option<(f64, f64)>, settings: ScaleSettings) -> Option<Colorbar> {
+        let range = range.filter(|_| self.colormap != Colormap::Noise)?;
+        Some(Colorbar {
+            colormap: self.colormap,
+            scale: self.colour_scale,
+            range,
+            settings,
+            equaliser: (self.colour_scale == ColourScale::Equalise).then(|| self.equaliser.clone()),
+        })
+    }
+
+    // Compute the colour values for each pixel in parallel, the mean colour of
+    // its samples, each placed on the colour scale as it is coloured
+    fn colour(&self, config: &Config) -> Vec<u32> {
+        let (width, colormap, frame) = (self.width, self.colormap, self.frame);
+        let count = (self.ssaa * self.ssaa) as u32;
+        let settings = self.scale_settings(config);
+        self.field
+            .par_chunks(self.ssaa * self.ssaa)
+            .enumerate()
+            .map(|(i, pixel)| {
+                let mut sum = [0u32; 3];
+                for (sample, &value) in pixel.iter().enumerate() {
+                    // Random factors for the three colour channels of the noise map, hashed
+                    // from the pixel, the sample, the frame and the seed, so a frame is the
+                    // same on any number of threads
+                    let noise = match colormap {
+                        Colormap::Noise => {
+                            let (x, y) = ((i % width) as u32, (i / width) as u32);
+                            let [red, green, blue, _] = generators::counter_uniforms(config.seed, [x, y, frame, sample as u32]);
+                            [red, green, blue]
+                        }
+                        _ => [1.0; 3],
+                    };
+                    let rgb = colormap.rgb(self.place(value, &settings), noise);
+                    sum.iter_mut().zip(rgb).for_each(|(total, channel)| *total += channel as u32);
+                }
+                let [red, green, blue] = sum.map(|total| (total + count / 2) / count);
+                (red << 16) | (green << 8) | blue
+            })
+            .collect()
+    }
+
+    // Overlay lines describing the frame: the time, the series parameters,
+    // the seed and the presentation, and the view once it has been moved
+    pub fn describe(&self, config: &Config) -> Vec<String> {
+        let mut lines = vec![
+            format!("time {:.2}", self.time),
+            format!("terms {}", self.terms),
+            format!("coefficient {}", self.coefficient),
+            format!("exponent {}", self.exponent),
+            format!("seed {}", config.seed),
+            format!("{:?}, {:?} scale", self.colormap, self.colour_scale),
+        ];
+        if self.surface {
+            lines.push(format!("surface, azimuth {:.0}, elevation {:.0}", self.azimuth, self.elevation));
+        }
+        if self.view != View::new(self.width, self.height) {
+            let (u, v) = self.view.centre;
+            lines.push(format!("zoom {:.3}x at ({:.1}, {:.1})", self.view.zoom, u, v));
+        }
+        lines
+    }
+
+    // Overlay lines reading out the pixel (x, y) of the last frame: the point
+    // of the plane it shows, the field value there and where the colour scale
+    // put it. None over the surface, whose pixels are not the plane's.
+    pub fn probe(&self, config: &Config, x: usize, y: usize) -> Vec<String> {
+        if self.surface || x >= self.width || y >= self.height {
+            return Vec::new();
+        }
+        let (u, v) = self.view.to_plane(self.unmirror(x as f64), y as f64, self.width, self.height);
+        let value = crooks_fluctuation_theorem(self.terms, self.coefficient, self.exponent, self.field_argument(x, y, (0.0, 0.0)));
+        let normalised = self.place(value, &self.scale_settings(config));
+        let half = if self.split && x >= self.width / 2 { ", reverse" } else { "" };
+        vec![
+            format!("probe ({:.1}, {:.1}){}", u, v, half),
+            format!("value {:.4e}, normalised {:.3}", value, normalised),
+        ]
+    }
+
+    // Draw overlay lines in the top-left corner, in the split layout label the
+    // reverse half in the top-right one, and on the right a colorbar when the
+    // colours stand for values of the field
+    pub fn draw_overlay(&self, buffer: &mut [u32], lines: &[String]) {
+        if let Some(colorbar) = &self.colorbar {
+            colorbar.draw(buffer, self.width);
+        }
+        if self.split {
+            let label = ["reverse".to_string()];
+            let x = self.width.saturating_sub(OVERLAY_MARGIN + text::panel_size(&label, OVERLAY_SCALE).0);
+            text::draw_panel(buffer, self.width, x, OVERLAY_MARGIN, &label, OVERLAY_COLOUR, OVERLAY_SCALE);
+        }
+        text::draw_panel(buffer, self.width, OVERLAY_MARGIN, OVERLAY_MARGIN, lines, OVERLAY_COLOUR, OVERLAY_SCALE);
+    }
+}
@@ -0,0 +1,66 @@
+// antithetic.rs
+// Antithetic trajectory pairs: the second of each pair replays the first's noise mirrored
+
+use crate::unirand::{GaussianMethod, UniformRng};
+
+// The partner's generator: every uniform u of the shared stream becomes 1 - u
+// (0 in place of 1, to stay in [0, 1)) and every normal deviate z becomes -z,
+// so the two trajectories of a pair see noise of equal law and opposite sign
+pub struct MirroredRng {
+    pub inner: Box<dyn UniformRng>,
+}
+
+impl UniformRng for MirroredRng {
+    fn uniform(&mut self) -> f64 {
+        let u = 1.0 - self.inner.uniform();
+        if u < 1.0 {
+            u
+        } else {
+            0.0
+        }
+    }
+
+    fn gaussian_method(&self) -> GaussianMethod {
+        self.inner.gaussian_method()
+    }
+
+    fn generate_gaussian(&mut self) -> f64 {
+        -self.inner.generate_gaussian()
+    }
+
+    fn split(&self, stream: u64) -> Box<dyn UniformRng> {
+        Box::new(MirroredRng {
+            inner: self.inner.split(stream),
+        })
+    }
+}
+
+// Mean of a statistic over antithetic pairs, with the standard error from
+// the spread of the pair averages, which the negative correlation between
+// partners shrinks
+pub struct PairedMean {
+    pub mean: f64,
+    pub std_error: f64,
+    pub pairs: usize,
+    // Variance of the mean from as many independent trajectories over the
+    // paired one; above one the pairs pay off
+    pub variance_reduction: f64,
+}
+
+// Pair up values 2k and 2k + 1, dropping an unpaired last value
+pub fn paired_mean(values: &[f64]) -> PairedMean {
+    let averages: Vec<f64> = values.chunks_exact(2).map(|pair| 0.5 * (pair[0] + pair[1])).collect();
+    let pairs = averages.len().max(1);
+    let variance = |values: &[f64]| {
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / (values.len() - 1).max(1) as f64
+    };
+    let paired = variance(&averages);
+    let independent = variance(&values[..2 * averages.len()]) / 2.0;
+    PairedMean {
+        mean: averages.iter().sum::<f64>() / pairs as f64,
+        std_error: (paired / pairs as f64).sqrt(),
+        pairs: averages.len(),
+        variance_reduction: independent / paired,
+    }
+}
@@ -0,0 +1,237 @@
+// capture.rs
+// Saving frames of the animation to image files, animated GIFs and video
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame, RgbImage, RgbaImage};
+use std::fs::{self, File};
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::str::FromStr;
+use std::sync::mpsc::{self, SyncSender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Frame as an RGB image, from 0RGB pixels
+pub fn to_rgb(buffer: &[u32], width: usize, height: usize) -> RgbImage {
+    RgbImage::from_fn(width as u32, height as u32, |x, y| {
+        let pixel = buffer[y as usize * width + x as usize];
+        image::Rgb([(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8])
+    })
+}
+
+// Current UTC time as YYYYMMDD-HHMMSS-mmm, for file names that sort by time.
+// The date comes from the day count by Hinnant's civil-from-days algorithm.
+pub fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (days, seconds) = ((now.as_secs() / 86_400) as i64, now.as_secs() % 86_400);
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let m = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * m + 2) / 5 + 1;
+    let month = if m < 10 { m + 3 } else { m - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        now.subsec_millis()
+    )
+}
+
+// Save a frame as a timestamped PNG in the directory, created if need be,
+// returning the path written
+pub fn save_screenshot(buffer: &[u32], width: usize, height: usize, dir: &str) -> Result<String, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("screenshot: cannot create '{}': {}", dir, e))?;
+    let path = Path::new(dir).join(format!("crooks-{}.png", timestamp()));
+    to_rgb(buffer, width, height)
+        .save(&path)
+        .map_err(|e| format!("screenshot: cannot write '{}': {}", path.display(), e))?;
+    Ok(path.display().to_string())
+}
+
+// Save a frame as the index-th of a numbered PNG sequence in the directory,
+// frame_00000.png onwards, the pattern video tools such as ffmpeg read
+pub fn save_numbered(buffer: &[u32], width: usize, height: usize, dir: &str, index: usize) -> Result<String, String> {
+    let path = Path::new(dir).join(format!("frame_{:05}.png", index));
+    to_rgb(buffer, width, height)
+        .save(&path)
+        .map_err(|e| format!("headless: cannot write '{}': {}", path.display(), e))?;
+    Ok(path.display().to_string())
+}
+
+// Kept frames that may wait for the GIF writer before the animation waits too
+const GIF_QUEUE: usize = 8;
+
+// Animated GIF being written from the frames pushed to it. Every skip-th
+// frame is kept and shown for the real time until the next kept one, so the
+// clip plays at the speed it was recorded. Palette quantisation, the slow
+// part, runs on a thread of its own so the animation keeps its frame rate.
+pub struct GifRecorder {
+    pub path: String,
+    skip: usize,
+    seen: usize,
+    pending: Option<(RgbaImage, Instant)>, // Last kept frame, waiting for its delay
+    frames: SyncSender<Frame>,
+    writer: JoinHandle<Result<usize, String>>,
+}
+
+impl GifRecorder {
+    // Start a GIF with a timestamped name in the directory, keeping one frame
+    // in `skip` and quantising with the NeuQuant speed `speed`, from 1 (best
+    // palette) to 30 (fastest)
+    pub fn start(dir: &str, skip: usize, speed: i32) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("gif: cannot create '{}': {}", dir, e))?;
+        let path = Path::new(dir).join(format!("crooks-{}.gif", timestamp()));
+        let file = File::create(&path).map_err(|e| format!("gif: cannot create '{}': {}", path.display(), e))?;
+        let path = path.display().to_string();
+        let (frames, received) = mpsc::sync_channel::<Frame>(GIF_QUEUE);
+        let name = path.clone();
+        let writer = thread::spawn(move || {
+            let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), speed);
+            encoder.set_repeat(Repeat::Infinite).map_err(|e| format!("gif: {}: {}", name, e))?;
+            let mut written = 0;
+            for frame in received {
+                encoder.encode_frame(frame).map_err(|e| format!("gif: {}: {}", name, e))?;
+                written += 1;
+            }
+            Ok(written)
+        });
+        Ok(Self {
+            path,
+            skip: skip.max(1),
+            seen: 0,
+            pending: None,
+            frames,
+            writer,
+        })
+    }
+
+    // Offer a frame, which is kept if it is due
+    pub fn push(&mut self, buffer: &[u32], width: usize, height: usize) {
+        let due = self.seen.is_multiple_of(self.skip);
+        self.seen += 1;
+        if !due {
+            return;
+        }
+        let now = Instant::now();
+        let image = DynamicImage::ImageRgb8(to_rgb(buffer, width, height)).into_rgba8();
+        if let Some((previous, since)) = self.pending.replace((image, now)) {
+            self.send(previous, now - since);
+        }
+    }
+
+    // Hand a frame to the writer; if the writer has stopped, finish reports why
+    fn send(&self, image: RgbaImage, shown: Duration) {
+        let _ = self.frames.send(Frame::from_parts(image, 0, 0, Delay::from_saturating_duration(shown)));
+    }
+
+    // Write the last frame and close the file, returning the number of frames written
+    pub fn finish(mut self) -> Result<usize, String> {
+        if let Some((last, since)) = self.pending.take() {
+            self.send(last, since.elapsed());
+        }
+        // Closing the channel ends the writer's loop
+        let Self { path, frames, writer, .. } = self;
+        drop(frames);
+        writer.join().unwrap_or_else(|_| Err(format!("gif: the writer of {} failed", path)))
+    }
+}
+
+// Video codec of recordings made through ffmpeg
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoCodec {
+    H264,
+    H265,
+}
+
+impl FromStr for VideoCodec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "h264" | "avc" => Ok(VideoCodec::H264),
+            "h265" | "hevc" => Ok(VideoCodec::H265),
+            _ => Err(format!("unknown video codec '{}'", s)),
+        }
+    }
+}
+
+impl VideoCodec {
+    // ffmpeg encoder and its options; the hvc1 tag lets QuickTime play H.265
+    fn arguments(self) -> &'static [&'static str] {
+        match self {
+            VideoCodec::H264 => &["-c:v", "libx264"],
+            VideoCodec::H265 => &["-c:v", "libx265", "-tag:v", "hvc1"],
+        }
+    }
+}
+
+// MP4 being encoded by an ffmpeg child process that reads raw RGB frames on
+// its standard input, one per frame pushed, played back at a fixed rate
+pub struct VideoRecorder {
+    pub path: String,
+    ffmpeg: Child,
+    input: Option<ChildStdin>,
+    frames: usize,
+    rgb: Vec<u8>,
+}
+
+impl VideoRecorder {
+    // Start ffmpeg on a timestamped MP4 in the directory, failing with a
+    // clear message when ffmpeg is not installed
+    pub fn start(dir: &str, width: usize, height: usize, fps: u32, codec: VideoCodec, bitrate: &str) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("video: cannot create '{}': {}", dir, e))?;
+        let path = Path::new(dir).join(format!("crooks-{}.mp4", timestamp())).display().to_string();
+        let size = format!("{}x{}", width, height);
+        let mut ffmpeg = Command::new("ffmpeg")
+            .args(["-loglevel", "error", "-y", "-f", "rawvideo", "-pixel_format", "rgb24"])
+            .args(["-video_size", &size, "-framerate", &fps.to_string(), "-i", "-"])
+            .args(codec.arguments())
+            .args(["-b:v", bitrate, "-pix_fmt", "yuv420p", &path])
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                ErrorKind::NotFound => "video: ffmpeg not found; install it and put it on the PATH to record MP4".to_string(),
+                _ => format!("video: cannot start ffmpeg: {}", e),
+            })?;
+        let input = ffmpeg.stdin.take();
+        Ok(Self {
+            path,
+            ffmpeg,
+            input,
+            frames: 0,
+            rgb: Vec::with_capacity(3 * width * height),
+        })
+    }
+
+    // Send a frame to ffmpeg; an error means ffmpeg has gone and the
+    // recording should be finished
+    pub fn push(&mut self, buffer: &[u32]) -> Result<(), String> {
+        self.rgb.clear();
+        self.rgb.extend(buffer.iter().flat_map(|&pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]));
+        let input = self.input.as_mut().ok_or_else(|| format!("video: {} is already closed", self.path))?;
+        input.write_all(&self.rgb).map_err(|e| format!("video: ffmpeg stopped taking frames for {}: {}", self.path, e))?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    // Close ffmpeg's input and wait for it to finish the file, returning the
+    // number of frames written
+    pub fn finish(mut self) -> Result<usize, String> {
+        drop(self.input.take());
+        let status = self.ffmpeg.wait().map_err(|e| format!("video: waiting for ffmpeg: {}", e))?;
+        if status.success() {
+            Ok(self.frames)
+        } else {
+            Err(format!("video: ffmpeg failed on {} ({})", self.path, status))
+        }
+    }
+}
@@ -0,0 +1,202 @@
+// chain.rs
+// Chain of interacting particles whose end is pulled by the moving trap
+
+use crate::integrator::State;
+use crate::model::Model;
+use crate::protocol::Protocol;
+use crate::simulation::{gaussian, Trajectory};
+use crate::units::Temperature;
+use std::str::FromStr;
+
+// Largest drift per step as a fraction of the WCA diameter, and the most
+// substeps a single time step may be divided into to respect it
+const MAX_DRIFT: f64 = 0.02;
+const MAX_SUBSTEPS: usize = 1000;
+
+// Pair interactions between the particles of the chain
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interaction {
+    // Harmonic bonds between neighbours only
+    Harmonic,
+    // Harmonic bonds plus Weeks-Chandler-Andersen repulsion between every pair
+    Wca,
+}
+
+impl FromStr for Interaction {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "harmonic" => Ok(Interaction::Harmonic),
+            "wca" => Ok(Interaction::Wca),
+            _ => Err(format!("unknown interaction '{}'", name)),
+        }
+    }
+}
+
+// N overdamped particles on a line. Particle 0 is tethered to the origin and
+// neighbours are bonded by springs of the bond stiffness and rest length; the
+// last particle is the externally driven coordinate, held by the trap
+// k/2 (x_N - lambda)^2, so all work is done through it.
+pub struct ChainParams {
+    pub particles: usize,
+    pub interaction: Interaction,
+    pub bond_stiffness: f64,
+    pub bond_length: f64,
+    pub wca_epsilon: f64,
+    pub wca_sigma: f64,
+    pub stiffness: f64, // Trap spring constant on the driven particle
+    pub friction: f64,
+    pub temperature: Temperature,
+    pub dt: f64,
+    pub relaxation: f64, // Time the chain relaxes at fixed lambda before it is driven
+}
+
+impl ChainParams {
+    // WCA pair energy and the magnitude of its repulsive force at separation r
+    fn wca(&self, r: f64) -> (f64, f64) {
+        let cutoff = 2f64.powf(1.0 / 6.0) * self.wca_sigma;
+        let r = r.abs();
+        if r >= cutoff {
+            return (0.0, 0.0);
+        }
+        let s6 = (self.wca_sigma / r).powi(6);
+        let energy = 4.0 * self.wca_epsilon * (s6 * s6 - s6) + self.wca_epsilon;
+        let force = 24.0 * self.wca_epsilon * (2.0 * s6 * s6 - s6) / r;
+        (energy, force)
+    }
+
+    // Internal energy of the chain, independent of lambda
+    fn internal_energy(&self, x: &[f64]) -> f64 {
+        let mut energy = 0.5 * self.bond_stiffness * x[0] * x[0];
+        for pair in x.windows(2) {
+            let stretch = pair[1] - pair[0] - self.bond_length;
+            energy += 0.5 * self.bond_stiffness * stretch * stretch;
+        }
+        if self.interaction == Interaction::Wca {
+            for i in 0..x.len() {
+                for j in i + 1..x.len() {
+                    energy += self.wca(x[j] - x[i]).0;
+                }
+            }
+        }
+        energy
+    }
+
+    // Energy of the trap on the driven particle
+    fn trap_energy(&self, x: &[f64], lambda: f64) -> f64 {
+        let d = x[x.len() - 1] - lambda;
+        0.5 * self.stiffness * d * d
+    }
+
+    // Force on every particle from the tether, the bonds, the pair repulsion and the trap
+    fn forces(&self, x: &[f64], lambda: f64) -> Vec<f64> {
+        let n = x.len();
+        let mut force = vec![0.0; n];
+        force[0] -= self.bond_stiffness * x[0];
+        for i in 0..n - 1 {
+            let tension = self.bond_stiffness * (x[i + 1] - x[i] - self.bond_length);
+            force[i] += tension;
+            force[i + 1] -= tension;
+        }
+        if self.interaction == Interaction::Wca {
+            for i in 0..n {
+                for j in i + 1..n {
+                    let push = self.wca(x[j] - x[i]).1 * (x[j] - x[i]).signum();
+                    force[i] -= push;
+                    force[j] += push;
+                }
+            }
+        }
+        force[n - 1] -= self.stiffness * (x[n - 1] - lambda);
+        force
+    }
+
+    // Overdamped Euler-Maruyama step at fixed lambda, returning the heat
+    // absorbed from the bath. With lambda fixed no work is done, so the heat
+    // is the energy change of the step; the Stratonovich estimate used for the
+    // single particle is too noisy near the steep WCA core to be useful here.
+    // The step is split whenever the drift would move a particle by more than
+    // a small fraction of the WCA diameter, since the core is far stiffer than
+    // the bonds and would otherwise make the chain explode.
+    fn step(&self, x: &mut [f64], lambda: f64) -> f64 {
+        let initial = self.internal_energy(x) + self.trap_energy(x, lambda);
+        let mut force = self.forces(x, lambda);
+        let largest = force.iter().fold(0.0f64, |a, f| a.max(f.abs()));
+        let drift = largest * self.dt / self.friction;
+        let substeps = ((drift / (MAX_DRIFT * self.wca_sigma)).ceil() as usize).clamp(1, MAX_SUBSTEPS);
+        let dt = self.dt / substeps as f64;
+        let noise = (2.0 * self.temperature.kt() * dt / self.friction).sqrt();
+
+        for substep in 0..substeps {
+            if substep > 0 {
+                force = self.forces(x, lambda);
+            }
+            for (xi, fi) in x.iter_mut().zip(&force) {
+                *xi += fi / self.friction * dt + noise * gaussian();
+            }
+        }
+        self.internal_energy(x) + self.trap_energy(x, lambda) - initial
+    }
+
+    // A chain stretched evenly from the origin to the given end, but never
+    // closer than the bond length so the repulsion starts out soft, then
+    // relaxed at fixed lambda so that it samples the equilibrium distribution
+    fn relaxed(&self, end: f64, lambda: f64) -> Vec<f64> {
+        let spacing = (end / self.particles as f64).max(self.bond_length);
+        let mut x: Vec<f64> = (1..=self.particles).map(|i| spacing * i as f64).collect();
+        for _ in 0..(self.relaxation / self.dt).round() as usize {
+            self.step(&mut x, lambda);
+        }
+        x
+    }
+
+    // Steps needed to cover a protocol
+    fn steps(&self, protocol: &Protocol) -> usize {
+        (protocol.duration / self.dt).round() as usize
+    }
+}
+
+impl Model for ChainParams {
+    fn temperature(&self) -> Temperature {
+        self.temperature
+    }
+
+    // Only the trap depends on lambda, so the driven coordinate is enough for
+    // energy differences between states; the internal energy is left out
+    fn energy(&self, state: &State, lambda: f64) -> f64 {
+        self.trap_energy(&[state.x[0]], lambda)
+    }
+
+    fn sample_equilibrium(&self, lambda: f64) -> State {
+        let x = self.relaxed(lambda, lambda);
+        State::at(x[self.particles - 1])
+    }
+
+    // The chain's internal coordinates are not carried by State, so each
+    // trajectory rebuilds the chain with its end at the start position and
+    // relaxes it at the initial lambda before driving. Work is accumulated
+    // on the driven particle only, since the trap acts on nothing else.
+    fn run_trajectory(&self, protocol: &Protocol, start: State) -> Trajectory {
+        let mut lambda = protocol.lambda_start();
+        let mut x = self.relaxed(start.x[0], lambda);
+        let initial_energy = self.internal_energy(&x) + self.trap_energy(&x, lambda);
+        let mut work = 0.0;
+        let mut heat = 0.0;
+
+        for step in 1..=self.steps(protocol) {
+            let next_lambda = protocol.lambda_at(step as f64 * self.dt);
+            work += self.trap_energy(&x, next_lambda) - self.trap_energy(&x, lambda);
+            lambda = next_lambda;
+            heat += self.step(&mut x, lambda);
+        }
+
+        Trajectory {
+            work,
+            heat,
+            energy_change: self.internal_energy(&x) + self.trap_energy(&x, lambda) - initial_energy,
+            final_state: State::at(x[self.particles - 1]),
+            entropy_production: None,
+        }
+    }
+}
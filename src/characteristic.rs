@@ -0,0 +1,101 @@
+// characteristic.rs
+// Characteristic functions of the work by FFT and the Crooks symmetry between them
+
+use crate::units::Temperature;
+use rustfft::num_complex::Complex64;
+use rustfft::{FftDirection, FftPlanner};
+use std::f64::consts::PI;
+use std::fs;
+
+// G_F(u) = <e^(iuW_F)> and the reverse side of the Crooks symmetry on one u grid
+pub struct CharacteristicCurves {
+    pub u: Vec<f64>,
+    pub forward: Vec<Complex64>,  // G_F(u)
+    pub mirrored: Vec<Complex64>, // G_R(-u + i beta) e^(-beta Delta F), equal to G_F(u) by Crooks
+}
+
+impl CharacteristicCurves {
+    // Largest |G_F(u) - G_R(-u + i beta) e^(-beta Delta F)| for u up to u_max
+    pub fn max_deviation(&self, u_max: f64) -> f64 {
+        self.u
+            .iter()
+            .zip(self.forward.iter().zip(&self.mirrored))
+            .take_while(|(&u, _)| u <= u_max)
+            .map(|(_, (f, m))| (f - m).norm())
+            .fold(0.0, f64::max)
+    }
+
+    // Largest u up to which |G_F(u)| stays above a floor, beyond which both
+    // curves are lost in sampling noise of order 1/sqrt(N)
+    pub fn resolved_range(&self, floor: f64) -> f64 {
+        self.u
+            .iter()
+            .zip(&self.forward)
+            .take_while(|(_, g)| g.norm() >= floor)
+            .last()
+            .map_or(0.0, |(&u, _)| u)
+    }
+
+    // Write both curves as `u,re_forward,im_forward,re_mirrored,im_mirrored` CSV lines
+    pub fn write_csv(&self, path: &str) -> Result<(), String> {
+        let mut text = String::from("u,re_forward,im_forward,re_mirrored,im_mirrored\n");
+        for (u, (f, m)) in self.u.iter().zip(self.forward.iter().zip(&self.mirrored)) {
+            text.push_str(&format!("{},{},{},{},{}\n", u, f.re, f.im, m.re, m.im));
+        }
+        fs::write(path, text).map_err(|e| format!("characteristic: cannot write {}: {}", path, e))
+    }
+}
+
+// (1/N) sum_i e^(log_weight(W_i)) e^(+-iuW_i) at u_m = 2 pi m / (points width),
+// m = 0..=points/2. The weights are binned onto a grid of the given width from
+// the smallest sample, zero-padded to `points` and transformed by one FFT; the
+// phase of the grid origin is restored afterwards. Weights are scaled by their
+// largest value before binning, and the scale is restored in log space with
+// `log_factor` folded in, so large exponents do not overflow.
+fn transform(work: &[f64], log_weight: impl Fn(f64) -> f64, log_factor: f64, width: f64, points: usize, direction: FftDirection) -> Vec<Complex64> {
+    let origin = work.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max_log = work.iter().map(|&w| log_weight(w)).fold(f64::NEG_INFINITY, f64::max);
+    let mut grid = vec![Complex64::new(0.0, 0.0); points];
+    for &w in work {
+        let bin = (((w - origin) / width) as usize).min(points - 1);
+        grid[bin] += (log_weight(w) - max_log).exp();
+    }
+    FftPlanner::new().plan_fft(points, direction).process(&mut grid);
+
+    let sign = match direction {
+        FftDirection::Inverse => 1.0,
+        FftDirection::Forward => -1.0,
+    };
+    let scale = (max_log + log_factor).exp() / work.len() as f64;
+    grid.iter()
+        .take(points / 2 + 1)
+        .enumerate()
+        .map(|(m, g)| {
+            let u = 2.0 * PI * m as f64 / (points as f64 * width);
+            g * scale * Complex64::from_polar(1.0, sign * u * (origin + 0.5 * width))
+        })
+        .collect()
+}
+
+// Characteristic functions of both sides of the Crooks symmetry
+// G_F(u) = G_R(-u + i beta) e^(-beta Delta F), where the right side is the
+// characteristic function of the reweighted reverse work, <e^(-iuW_R) e^(-beta W_R)>.
+// Both are binned with a shared width, `bins` across the wider of the two work
+// ranges, and transformed with `points` FFT points, which sets the u spacing.
+pub fn crooks_symmetry(forward: &[f64], reverse: &[f64], temperature: Temperature, delta_f: f64, bins: usize, points: usize) -> CharacteristicCurves {
+    let beta = temperature.beta();
+    let range = |work: &[f64]| {
+        let (lo, hi) = work.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &w| (lo.min(w), hi.max(w)));
+        hi - lo
+    };
+    let width = range(forward).max(range(reverse)).max(1e-12) * (1.0 + 1e-9) / bins as f64;
+    let points = points.max(bins);
+
+    let forward_curve = transform(forward, |_| 0.0, 0.0, width, points, FftDirection::Inverse);
+    let mirrored = transform(reverse, |w| -beta * w, -beta * delta_f, width, points, FftDirection::Forward);
+    CharacteristicCurves {
+        u: (0..forward_curve.len()).map(|m| 2.0 * PI * m as f64 / (points as f64 * width)).collect(),
+        forward: forward_curve,
+        mirrored,
+    }
+}
@@ -0,0 +1,99 @@
+// colorbar.rs
+// A colorbar for the animation: the colormap as a vertical bar along the
+// right edge of the frame, labelled with the field values its ends and
+// quarters stand for
+
+use crate::colormap::{ColourScale, Colormap, ScaleSettings};
+use crate::equalise::Equaliser;
+use crate::text;
+
+// Width of the bar, and the pixels between it and the edge of the frame
+const BAR_WIDTH: usize = 16;
+const BAR_MARGIN: usize = 8;
+
+// Length of the tick marks, which point left from the bar to their labels
+const TICK_LENGTH: usize = 5;
+
+// Labelled points along the bar, from its low end to its high end
+const TICKS: usize = 5;
+
+// Screen pixels per font pixel of the labels, and the colour of the labels,
+// ticks and outline
+const LABEL_SCALE: usize = 2;
+const LABEL_COLOUR: u32 = 0xf0f0f0;
+
+// A field value in four significant figures, in exponent form when it is very
+// small or large, and as 0 when it is only rounding error next to the span of
+// the bar
+fn tick_label(value: f64, span: f64) -> String {
+    let magnitude = value.abs();
+    if magnitude <= span * 1e-4 {
+        "0".to_string()
+    } else if (1e-3..1e4).contains(&magnitude) {
+        let decimals = (3 - magnitude.log10().floor() as i32).max(0) as usize;
+        format!("{:.*}", decimals, value)
+    } else {
+        format!("{:.3e}", value)
+    }
+}
+
+// Legend of a frame whose colours stand for field values: its colormap, and
+// the scale that brought the values between the ends of the range into it,
+// with the equaliser's table on the equalising scale
+pub struct Colorbar {
+    pub colormap: Colormap,
+    pub scale: ColourScale,
+    pub range: (f64, f64),
+    pub settings: ScaleSettings,
+    pub equaliser: Option<Equaliser>,
+}
+
+impl Colorbar {
+    // Draw the bar from the low end of the scale, at its bottom, to the high
+    // end, at its top, on the right of the frame from a sixth of the way down
+    // to halfway, its ticks evenly spaced along it in colour
+    pub fn draw(&self, buffer: &mut [u32], width: usize) {
+        let height = buffer.len() / width;
+        let (top, bottom) = (height / 6, height / 2);
+        // Rounding error shows as such against the span of a linear scale, and
+        // against the linear threshold of a symlog one; log and equalising
+        // scales label values far smaller than their span
+        let span = match self.scale {
+            ColourScale::Log | ColourScale::Equalise => 0.0,
+            ColourScale::Symlog => self.settings.symlog_threshold,
+            _ => self.range.1 - self.range.0,
+        };
+        let value_at = |t: f64| match &self.equaliser {
+            Some(equaliser) => equaliser.value_at(t),
+            None => self.scale.value_at(t, self.range, &self.settings),
+        };
+        let labels: Vec<String> = (0..TICKS).map(|i| tick_label(value_at(i as f64 / (TICKS - 1) as f64), span)).collect();
+        let sizes: Vec<(usize, usize)> = labels.iter().map(|label| text::panel_size(std::slice::from_ref(label), LABEL_SCALE)).collect();
+        let label_width = sizes.iter().map(|size| size.0).max().unwrap_or(0);
+        if top == 0 || bottom <= top + 1 || width < BAR_MARGIN + BAR_WIDTH + 1 + TICK_LENGTH + label_width {
+            return;
+        }
+        let left = width - BAR_MARGIN - BAR_WIDTH;
+
+        // The colormap from high at the top to low at the bottom, outlined
+        for y in top..=bottom {
+            let t = (bottom - y) as f64 / (bottom - top) as f64;
+            let [red, green, blue] = self.colormap.rgb(t, [1.0; 3]);
+            let colour = ((red as u32) << 16) | ((green as u32) << 8) | blue as u32;
+            let row = &mut buffer[y * width..][..width];
+            row[left..left + BAR_WIDTH].fill(colour);
+            row[left - 1] = LABEL_COLOUR;
+            row[left + BAR_WIDTH] = LABEL_COLOUR;
+        }
+        buffer[(top - 1) * width + left - 1..][..BAR_WIDTH + 2].fill(LABEL_COLOUR);
+        buffer[(bottom + 1) * width + left - 1..][..BAR_WIDTH + 2].fill(LABEL_COLOUR);
+
+        // Ticks at the ends and quarters, each with its value on a panel beside it
+        for (i, (label, size)) in labels.iter().zip(&sizes).enumerate() {
+            let y = bottom - (bottom - top) * i / (TICKS - 1);
+            buffer[y * width + left - 1 - TICK_LENGTH..][..TICK_LENGTH].fill(LABEL_COLOUR);
+            let x = left - 1 - TICK_LENGTH - size.0;
+            text::draw_panel(buffer, width, x, y.saturating_sub(size.1 / 2), std::slice::from_ref(label), LABEL_COLOUR, LABEL_SCALE);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+// colormap.rs
+// Colour maps and scales from a field value to RGB for the animated visualisation
+
+use std::str::FromStr;
+
+// Coefficients c0..c6 per channel of the degree-six polynomial fits to the
+// matplotlib perceptually uniform maps (Zucker), accurate to about 1/255
+pub type Polynomial = [[f64; 3]; 7];
+
+const VIRIDIS: Polynomial = [
+    [0.277_727_327_223_417_7, 0.005_407_344_544_966_578, 0.334_099_805_335_306_1],
+    [0.105_093_043_108_577_4, 1.404_613_529_898_575, 1.384_590_162_594_685],
+    [-0.330_861_828_725_556_3, 0.214_847_559_468_213, 0.095_095_163_028_236_59],
+    [-4.634_230_498_983_486, -5.799_100_973_351_585, -19.332_440_956_279_87],
+    [6.228_269_936_347_081, 14.179_933_366_805_09, 56.690_552_600_681_05],
+    [4.776_384_997_670_288, -13.745_145_377_746_01, -65.353_032_633_372_34],
+    [-5.435_455_855_934_631, 4.645_852_612_178_535, 26.312_435_249_583_2],
+];
+
+const PLASMA: Polynomial = [
+    [0.058_732_343_923_997_02, 0.023_336_708_925_656_64, 0.543_340_182_674_875_4],
+    [2.176_514_634_195_958, 0.238_383_417_126_018_2, 0.753_960_459_978_403_6],
+    [-2.689_460_476_458_034, -7.455_851_135_738_909, 3.110_799_939_717_086],
+    [6.130_348_345_893_603, 42.346_188_147_722_7, -28.518_854_653_321_58],
+    [-11.107_436_190_622_71, -82.666_311_094_280_45, 60.139_847_674_182_63],
+    [10.023_065_576_470_65, 71.413_617_700_953_5, -54.072_186_555_600_67],
+    [-3.658_713_842_777_788, -22.931_534_654_611_49, 18.191_907_785_398_28],
+];
+
+const INFERNO: Polynomial = [
+    [0.000_218_940_369_119_226_5, 0.001_651_004_631_001_012, -0.019_480_898_437_091_84],
+    [0.106_513_419_485_611_6, 0.563_956_436_788_409_1, 3.932_712_388_889_277],
+    [11.602_493_082_471_87, -3.972_853_965_665_698, -15.942_394_106_291_4],
+    [-41.703_996_131_394_59, 17.436_398_882_053_13, 44.354_145_198_728_13],
+    [77.162_935_699_427, -33.402_358_942_100_92, -81.807_309_257_389_93],
+    [-71.319_428_244_992_14, 32.626_064_263_977_23, 73.209_519_858_032_02],
+    [25.131_126_224_773_41, -12.242_668_952_385_67, -23.070_325_002_871_72],
+];
+
+const MAGMA: Polynomial = [
+    [-0.002_136_485_053_939_582, -0.000_749_655_052_795_221, -0.005_386_127_855_323_933],
+    [0.251_660_540_737_164_2, 0.677_523_243_683_766_8, 2.494_026_599_312_351],
+    [8.353_717_279_216_625, -3.577_719_514_958_484, 0.314_467_903_013_257_3],
+    [-27.668_733_085_768_66, 14.264_730_780_965_33, -13.649_213_188_139_22],
+    [52.176_139_812_340_68, -27.943_606_071_683_51, 12.944_169_442_383_94],
+    [-50.768_525_364_735_88, 29.046_582_821_272_91, 4.234_152_993_845_98],
+    [18.655_705_065_918_83, -11.489_773_519_977_11, -5.601_961_508_734_096],
+];
+
+// How the field value becomes a colour
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Colormap {
+    Viridis,
+    Plasma,
+    Inferno,
+    Magma,
+    Grayscale,
+    // The original mapping: a red-green-blue ramp scaled per channel by
+    // random factors hashed from the pixel and frame
+    Noise,
+}
+
+impl FromStr for Colormap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viridis" => Ok(Colormap::Viridis),
+            "plasma" => Ok(Colormap::Plasma),
+            "inferno" => Ok(Colormap::Inferno),
+            "magma" => Ok(Colormap::Magma),
+            "grayscale" | "greyscale" => Ok(Colormap::Grayscale),
+            "noise" => Ok(Colormap::Noise),
+            _ => Err(format!("unknown colormap '{}'", s)),
+        }
+    }
+}
+
+impl Colormap {
+    // The map after this one, wrapping round, for cycling at runtime
+    pub fn next(self) -> Self {
+        match self {
+            Colormap::Viridis => Colormap::Plasma,
+            Colormap::Plasma => Colormap::Inferno,
+            Colormap::Inferno => Colormap::Magma,
+            Colormap::Magma => Colormap::Grayscale,
+            Colormap::Grayscale => Colormap::Noise,
+            Colormap::Noise => Colormap::Viridis,
+        }
+    }
+
+    // Coefficients of the polynomial fit behind the map, for the maps that have one
+    #[cfg(feature = "gpu")]
+    pub fn polynomial(self) -> Option<&'static Polynomial> {
+        match self {
+            Colormap::Viridis => Some(&VIRIDIS),
+            Colormap::Plasma => Some(&PLASMA),
+            Colormap::Inferno => Some(&INFERNO),
+            Colormap::Magma => Some(&MAGMA),
+            Colormap::Grayscale | Colormap::Noise => None,
+        }
+    }
+
+    // Colour of the value t, clamped to [0, 1]; only the noise map uses the
+    // per-channel random factors
+    pub fn rgb(self, t: f64, noise: [f64; 3]) -> [u8; 3] {
+        let t = t.clamp(0.0, 1.0);
+        let channels = match self {
+            Colormap::Viridis => polynomial(&VIRIDIS, t),
+            Colormap::Plasma => polynomial(&PLASMA, t),
+            Colormap::Inferno => polynomial(&INFERNO, t),
+            Colormap::Magma => polynomial(&MAGMA, t),
+            Colormap::Grayscale => [t; 3],
+            Colormap::Noise => [t * noise[0], (1.0 - t) * noise[1], (0.5 - (t - 0.5).abs()) * 2.0 * noise[2]],
+        };
+        channels.map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8)
+    }
+}
+
+// Decades below the largest value of a frame the log scale reaches down to
+// at most, so that values just above zero do not take the whole colormap
+const LOG_DECADES: i32 = 12;
+
+// How the field value is brought into [0, 1] before the colormap
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColourScale {
+    // sin of the scaled value folded into [0, 1], the original presentation,
+    // which shows contours rather than magnitudes
+    Wave,
+    // Linear between the smallest and largest value of each frame
+    Auto,
+    // Linear over a fixed range, values outside it clamped
+    Fixed,
+    // Logarithmic from the smallest positive value of each frame, or
+    // LOG_DECADES below the largest if that is nearer, to the largest; zero
+    // and negative values at the bottom
+    Log,
+    // Linear within the threshold of zero and logarithmic beyond it, either
+    // side, between the smallest and largest value of each frame
+    Symlog,
+    // Linear between two percentiles of each frame, clipping its outliers
+    Percentile,
+    // Through the quantiles of each frame, so every colour is used about as
+    // often, smoothed over time by the scene's equaliser
+    Equalise,
+}
+
+// What the colour scales take besides the values
+#[derive(Clone, Copy, Debug)]
+pub struct ScaleSettings {
+    pub wave_factor: f64,
+    pub fixed: (f64, f64),
+    pub symlog_threshold: f64,
+    pub percentile: f64, // Share in percent clipped at each end by the percentile scale
+}
+
+// Smallest, largest and smallest positive value of a frame, NaN ignored; the
+// ones with no value are infinite
+#[derive(Clone, Copy, Debug)]
+pub struct Bounds {
+    pub min: f64,
+    pub max: f64,
+    pub min_positive: f64,
+}
+
+impl Bounds {
+    pub fn of(values: &[f64]) -> Self {
+        values.iter().fold(
+            Bounds {
+                min: f64::INFINITY,
+                max: f64::NEG_INFINITY,
+                min_positive: f64::INFINITY,
+            },
+            |b, &v| Bounds {
+                min: b.min.min(v),
+                max: b.max.max(v),
+                min_positive: if v > 0.0 { b.min_positive.min(v) } else { b.min_positive },
+            },
+        )
+    }
+}
+
+// Sign-preserving logarithm of the symlog scale, linear near zero
+fn symlog(v: f64, threshold: f64) -> f64 {
+    v.signum() * (v.abs() / threshold).ln_1p()
+}
+
+// The values at the given percentile from either end of a frame, NaN ignored
+fn percentiles(values: &[f64], percent: f64) -> Option<(f64, f64)> {
+    let mut finite: Vec<f64> = values.iter().copied().filter(|v| !v.is_nan()).collect();
+    if finite.is_empty() {
+        return None;
+    }
+    let last = finite.len() - 1;
+    let rank = ((percent / 100.0 * last as f64).round() as usize).min(last);
+    let low = *finite.select_nth_unstable_by(rank, f64::total_cmp).1;
+    let high = *finite.select_nth_unstable_by(last - rank, f64::total_cmp).1;
+    Some((low, high))
+}
+
+impl FromStr for ColourScale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wave" => Ok(ColourScale::Wave),
+            "auto" => Ok(ColourScale::Auto),
+            "fixed" => Ok(ColourScale::Fixed),
+            "log" => Ok(ColourScale::Log),
+            "symlog" => Ok(ColourScale::Symlog),
+            "percentile" => Ok(ColourScale::Percentile),
+            "equalise" => Ok(ColourScale::Equalise),
+            _ => Err(format!("unknown colour scale '{}'", s)),
+        }
+    }
+}
+
+impl ColourScale {
+    // The scale after this one, wrapping round, for cycling at runtime
+    pub fn next(self) -> Self {
+        match self {
+            ColourScale::Wave => ColourScale::Auto,
+            ColourScale::Auto => ColourScale::Fixed,
+            ColourScale::Fixed => ColourScale::Log,
+            ColourScale::Log => ColourScale::Symlog,
+            ColourScale::Symlog => ColourScale::Percentile,
+            ColourScale::Percentile => ColourScale::Equalise,
+            ColourScale::Equalise => ColourScale::Wave,
+        }
+    }
+
+    // Field values at the ends of the scale for a frame, from its bounds;
+    // none for the wave scale, which folds them, or the percentile and
+    // equalising scales, which need every value
+    pub fn range_of(self, bounds: Bounds, settings: &ScaleSettings) -> Option<(f64, f64)> {
+        match self {
+            ColourScale::Wave | ColourScale::Percentile | ColourScale::Equalise => None,
+            ColourScale::Auto | ColourScale::Symlog => Some((bounds.min, bounds.max)),
+            ColourScale::Fixed => Some(settings.fixed),
+            ColourScale::Log => (bounds.max > 0.0).then(|| (bounds.min_positive.max(bounds.max * 10f64.powi(-LOG_DECADES)), bounds.max)),
+        }
+    }
+
+    // Field values at the ends of the scale for a frame
+    pub fn range(self, values: &[f64], settings: &ScaleSettings) -> Option<(f64, f64)> {
+        match self {
+            ColourScale::Percentile => percentiles(values, settings.percentile),
+            _ => self.range_of(Bounds::of(values), settings),
+        }
+    }
+
+    // Position in [0, 1] of a value on the scale between low and high
+    fn position(self, v: f64, (low, high): (f64, f64), settings: &ScaleSettings) -> f64 {
+        match self {
+            ColourScale::Log => {
+                if v <= low {
+                    0.0
+                } else {
+                    ((v / low).ln() / (high / low).ln()).clamp(0.0, 1.0)
+                }
+            }
+            ColourScale::Symlog => {
+                let (f_low, f_high) = (symlog(low, settings.symlog_threshold), symlog(high, settings.symlog_threshold));
+                ((symlog(v, settings.symlog_threshold) - f_low) / (f_high - f_low)).clamp(0.0, 1.0)
+            }
+            _ => ((v - low) / (high - low)).clamp(0.0, 1.0),
+        }
+    }
+
+    // Value at the position t in [0, 1] of the scale between low and high, the
+    // inverse of position, for labelling a colorbar
+    pub fn value_at(self, t: f64, (low, high): (f64, f64), settings: &ScaleSettings) -> f64 {
+        match self {
+            ColourScale::Log => low * (high / low).powf(t),
+            ColourScale::Symlog => {
+                let threshold = settings.symlog_threshold;
+                let (f_low, f_high) = (symlog(low, threshold), symlog(high, threshold));
+                let f = f_low + t * (f_high - f_low);
+                f.signum() * threshold * f.abs().exp_m1()
+            }
+            _ => low + t * (high - low),
+        }
+    }
+
+    // Position in [0, 1] of a single value on the scale: the wave through
+    // sin(wave_factor v), the others over the range of its frame, the frame's
+    // own or the fixed one. A flat frame, without a range, maps to the middle
+    // of the colormap. The equalising scale keeps its mapping between frames,
+    // so it is applied by an Equaliser instead.
+    pub fn place(self, v: f64, range: Option<(f64, f64)>, settings: &ScaleSettings) -> f64 {
+        match (self, range) {
+            (ColourScale::Wave, _) => (v * settings.wave_factor).sin() * 0.5 + 0.5,
+            (_, Some(range)) => self.position(v, range, settings),
+            (_, None) => 0.5,
+        }
+    }
+}
+
+// Evaluate the fit by Horner's rule, all three channels at once
+fn polynomial(coefficients: &Polynomial, t: f64) -> [f64; 3] {
+    coefficients.iter().rev().fold([0.0; 3], |acc, c| [0, 1, 2].map(|i| acc[i] * t + c[i]))
+}
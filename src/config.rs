@@ -0,0 +1,1113 @@
+// config.rs
+// Run configuration: built-in defaults, overridden by an optional
+// `key = value` file (--config path) and then by `--key value` arguments
+
+use crate::animation::Backend;
+use crate::capture::VideoCodec;
+use crate::chain::{ChainParams, Interaction};
+use crate::colormap::{ColourScale, Colormap};
+use crate::ensemble;
+use crate::expr::Expression;
+use crate::integrator::{Integrator, Noise};
+use crate::markov::MarkovParams;
+use crate::metric::{self, Metric};
+use crate::model::{Model, ModelKind};
+use crate::potential::{DoubleWell, Flat, Harmonic, Landscape, Morse, Periodic, Potential};
+use crate::protocol::{Direction, Protocol};
+use crate::recording::Selection;
+use crate::reduction;
+use crate::schedule::{self, Schedule, Shape};
+use crate::simulation::{Initial, TrapParams};
+use crate::spin::SpinParams;
+use crate::unirand::{self, GaussianMethod, RngKind, Sampling, MAX_SEED};
+use crate::units::{Temperature, UnitSystem, HBAR};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct Config {
+    pub stiffness: f64,
+    pub friction: f64,
+    pub mass: f64,
+    pub units: UnitSystem,
+    pub kt: f64,
+    pub temperature: f64,
+    pub dt: f64,
+    pub integrator: Integrator,
+    pub initial: Initial,
+    pub equilibration: f64,
+    pub adaptive: bool,
+    pub escort: f64,
+    pub tolerance: f64,
+    pub tilt: f64,
+    pub box_length: f64,
+    pub wall_lower: f64,
+    pub wall_upper: f64,
+    pub noise: Noise,
+    pub gaussian: GaussianMethod,
+    pub rng: RngKind,
+    pub sampling: Sampling,
+    pub antithetic: bool,
+    pub deterministic: bool,
+    pub control_variates: bool,
+    pub correlation_time: f64,
+    pub counterdiabatic: bool,
+    pub model: ModelKind,
+    pub spin_rate: f64,
+    pub lattice_sites: usize,
+    pub lattice_start: f64,
+    pub lattice_spacing: f64,
+    pub hop_rate: f64,
+    pub particles: usize,
+    pub interaction: Interaction,
+    pub bond_stiffness: f64,
+    pub bond_length: f64,
+    pub wca_epsilon: f64,
+    pub wca_sigma: f64,
+    pub chain_relaxation: f64,
+    pub landscape: Landscape,
+    pub landscape_stiffness: f64,
+    pub landscape_centre: f64,
+    pub barrier: f64,
+    pub well_position: f64,
+    pub morse_depth: f64,
+    pub morse_range: f64,
+    pub amplitude: f64,
+    pub period: f64,
+    pub potential_expr: Option<String>,
+    pub defines: HashMap<String, f64>,
+    pub expression: Option<Arc<Expression>>,
+    pub sample_range: f64,
+    pub dimensions: usize,
+    pub lambda_start: f64,
+    pub lambda_end: f64,
+    pub schedule: Shape,
+    pub schedule_knots: Vec<(f64, f64)>,
+    pub schedule_expr: Option<String>,
+    pub schedule_expression: Option<Arc<Expression>>,
+    pub stiffness_schedule: Option<Shape>,
+    pub stiffness_end: f64,
+    pub stiffness_knots: Vec<(f64, f64)>,
+    pub stiffness_expr: Option<String>,
+    pub stiffness_expression: Option<Arc<Expression>>,
+    pub duration: f64,
+    pub trajectories: usize,
+    pub seed: i32,
+    pub seed_drawn: bool, // Whether the seed came from OS entropy rather than the configuration
+    seed_given: bool,
+    pub bins: usize,
+    pub bootstrap: usize,
+    pub confidence: f64,
+    pub cumulant_order: usize,
+    pub min_effective_samples: f64,
+    pub selftest_tolerance: f64,
+    pub rngtest_draws: usize,
+    pub rates: Vec<f64>,
+    pub temperatures: Vec<f64>,
+    pub replica_output: Option<String>,
+    pub hysteresis_output: Option<String>,
+    pub summary_output: Option<String>,
+    pub mbar_states: usize,
+    pub mbar_samples: usize,
+    pub profile_stride: usize,
+    pub profile_output: Option<String>,
+    pub windows: usize,
+    pub window_samples: usize,
+    pub record_file: String,
+    pub record_count: usize,
+    pub record_stride: usize,
+    pub record_select: Selection,
+    pub playback_speed: f64,
+    pub measurement_error: f64,
+    pub feedback_gain: f64,
+    pub cycles: usize,
+    pub transient_cycles: usize,
+    pub optimize_knots: usize,
+    pub optimize_trajectories: usize,
+    pub optimize_iterations: usize,
+    pub metric_points: usize,
+    pub metric_time: f64,
+    pub metric_stride: usize,
+    pub fdt_frequencies: Vec<f64>,
+    pub fdt_amplitude: f64,
+    pub fdt_time: f64,
+    pub fdt_transient: f64,
+    pub fdt_trajectories: usize,
+    pub fdt_stride: usize,
+    pub characteristic_bins: usize,
+    pub characteristic_points: usize,
+    pub characteristic_output: Option<String>,
+    pub hbar: Option<f64>,
+    pub quantum_output: Option<String>,
+    pub direction: Direction,
+    pub colormap: Colormap,
+    pub colour_scale: ColourScale,
+    pub colour_min: f64,
+    pub colour_max: f64,
+    pub symlog_threshold: f64,
+    pub colour_percentile: f64,
+    pub equalise_smoothing: f64,
+    pub overlay: bool,
+    pub live: bool,
+    pub split: bool,
+    pub output_dir: String,
+    pub gif_skip: usize,
+    pub gif_speed: i32,
+    pub video_fps: u32,
+    pub video_codec: VideoCodec,
+    pub video_bitrate: String,
+    pub headless: bool,
+    pub frames: usize,
+    pub screen_width: usize,
+    pub screen_height: usize,
+    pub panel: bool,
+    pub backend: Backend,
+    pub tui: bool,
+    pub sixel: bool,
+    pub sixel_width: usize,
+    pub contours: bool,
+    pub contour_levels: Vec<f64>,
+    pub surface: bool,
+    pub surface_azimuth: f64,
+    pub surface_elevation: f64,
+    pub ssaa: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            stiffness: 1.0,
+            friction: 1.0,
+            mass: 1.0,
+            units: UnitSystem::Reduced,
+            kt: 1.0,
+            temperature: 300.0,
+            dt: 1e-3,
+            integrator: Integrator::EulerMaruyama,
+            initial: Initial::Boltzmann,
+            equilibration: 0.0,
+            adaptive: false,
+            escort: 0.0,
+            tolerance: 1e-3,
+            tilt: 0.0,
+            box_length: 0.0,
+            wall_lower: f64::NEG_INFINITY,
+            wall_upper: f64::INFINITY,
+            noise: Noise::White,
+            gaussian: GaussianMethod::BoxMuller,
+            rng: RngKind::Marsaglia,
+            sampling: Sampling::Pseudo,
+            antithetic: false,
+            deterministic: false,
+            control_variates: false,
+            correlation_time: 0.1,
+            counterdiabatic: false,
+            model: ModelKind::Particle,
+            spin_rate: 5.0,
+            lattice_sites: 31,
+            lattice_start: -5.0,
+            lattice_spacing: 0.5,
+            hop_rate: 5.0,
+            particles: 8,
+            interaction: Interaction::Harmonic,
+            bond_stiffness: 10.0,
+            bond_length: 1.0,
+            wca_epsilon: 1.0,
+            wca_sigma: 0.8,
+            chain_relaxation: 5.0,
+            landscape: Landscape::Flat,
+            landscape_stiffness: 1.0,
+            landscape_centre: 0.0,
+            barrier: 2.0,
+            well_position: 2.5,
+            morse_depth: 5.0,
+            morse_range: 1.0,
+            amplitude: 1.0,
+            period: 2.0,
+            potential_expr: None,
+            defines: HashMap::new(),
+            expression: None,
+            sample_range: 10.0,
+            dimensions: 1,
+            lambda_start: 0.0,
+            lambda_end: 5.0,
+            schedule: Shape::Linear,
+            schedule_knots: Vec::new(),
+            schedule_expr: None,
+            schedule_expression: None,
+            stiffness_schedule: None,
+            stiffness_end: 1.0,
+            stiffness_knots: Vec::new(),
+            stiffness_expr: None,
+            stiffness_expression: None,
+            duration: 5.0,
+            trajectories: 10_000,
+            seed: 12345,
+            seed_drawn: false,
+            seed_given: false,
+            bins: 40,
+            bootstrap: 200,
+            confidence: 0.95,
+            cumulant_order: 2,
+            min_effective_samples: 100.0,
+            selftest_tolerance: 0.1,
+            rngtest_draws: 1_000_000,
+            rates: Vec::new(),
+            temperatures: Vec::new(),
+            replica_output: None,
+            hysteresis_output: None,
+            summary_output: None,
+            mbar_states: 6,
+            mbar_samples: 2000,
+            profile_stride: 50,
+            profile_output: None,
+            windows: 11,
+            window_samples: 2000,
+            record_file: "trajectories.csv".to_string(),
+            record_count: 20,
+            record_stride: 10,
+            record_select: Selection::First,
+            playback_speed: 1.0,
+            measurement_error: 0.5,
+            feedback_gain: 1.0,
+            cycles: 16,
+            transient_cycles: 4,
+            optimize_knots: 4,
+            optimize_trajectories: 1000,
+            optimize_iterations: 100,
+            metric_points: 21,
+            metric_time: 1000.0,
+            metric_stride: 100,
+            fdt_frequencies: vec![0.2, 0.5, 1.0, 2.0, 5.0],
+            fdt_amplitude: 0.1,
+            fdt_time: 50.0,
+            fdt_transient: 10.0,
+            fdt_trajectories: 200,
+            fdt_stride: 20,
+            characteristic_bins: 512,
+            characteristic_points: 8192,
+            characteristic_output: None,
+            hbar: None,
+            quantum_output: None,
+            direction: Direction::Forward,
+            colormap: Colormap::Viridis,
+            colour_scale: ColourScale::Wave,
+            colour_min: -1e-8,
+            colour_max: 1e-8,
+            symlog_threshold: 1e-6,
+            colour_percentile: 1.0,
+            equalise_smoothing: 0.9,
+            overlay: true,
+            live: false,
+            split: false,
+            output_dir: "captures".to_string(),
+            gif_skip: 2,
+            gif_speed: 10,
+            video_fps: 30,
+            video_codec: VideoCodec::H264,
+            video_bitrate: "4M".to_string(),
+            headless: false,
+            frames: 100,
+            screen_width: 1920,
+            screen_height: 1080,
+            panel: false,
+            backend: Backend::Cpu,
+            tui: false,
+            sixel: false,
+            sixel_width: 640,
+            contours: false,
+            contour_levels: (1..10).map(|i| i as f64 / 10.0).collect(),
+            surface: false,
+            surface_azimuth: 45.0,
+            surface_elevation: 35.0,
+            ssaa: 1,
+        }
+    }
+}
+
+// Boolean keys that may be given on the command line without a value
+const FLAGS: &[&str] = &["adaptive", "antithetic", "contours", "control_variates", "counterdiabatic", "deterministic", "headless", "live", "panel", "sixel", "split", "surface", "tui"];
+
+// Optical-tweezers RNA hairpin toy model after Collin et al. (Nature 2005): a
+// folded and an unfolded basin separated by a barrier, the unfolded one tilted
+// up by its folding free energy, pulled across by a stiff trap
+const HAIRPIN: &[(&str, &str)] = &[
+    ("model", "particle"),
+    ("integrator", "euler"),
+    ("potential_expr", "b*((x/a)^2 - 1)^2 + g*(x + a) + 0.5*k*(x - lambda)^2"),
+    ("define", "b=4"),
+    ("define", "a=2"),
+    ("define", "g=0.75"),
+    ("stiffness", "2"),
+    ("friction", "0.25"),
+    ("lambda_start", "-3"),
+    ("lambda_end", "3"),
+    ("duration", "10"),
+    ("trajectories", "4000"),
+    ("bins", "50"),
+];
+
+// Small dragged harmonic trap with Delta F known exactly and about one kT of
+// dissipation, so every estimator converges with a few thousand trajectories
+const SELFTEST: &[(&str, &str)] = &[
+    ("model", "particle"),
+    ("integrator", "euler"),
+    ("landscape", "harmonic"),
+    ("landscape_stiffness", "1"),
+    ("landscape_centre", "0"),
+    ("stiffness", "1"),
+    ("friction", "1"),
+    ("kt", "1"),
+    ("dt", "1e-3"),
+    ("lambda_start", "0"),
+    ("schedule", "linear"),
+    ("lambda_end", "2"),
+    ("duration", "2"),
+    ("trajectories", "4000"),
+    ("bins", "30"),
+    ("seed", "1"),
+];
+
+// Parse a single value, naming the offending key on failure
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("config: invalid value '{}' for '{}'", value, key))
+}
+
+impl Config {
+    // Apply one setting by name
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "stiffness" => self.stiffness = parse(key, value)?,
+            "friction" => self.friction = parse(key, value)?,
+            "mass" => self.mass = parse(key, value)?,
+            "units" => self.units = parse(key, value)?,
+            "kt" => self.kt = parse(key, value)?,
+            "temperature" => self.temperature = parse(key, value)?,
+            "dt" => self.dt = parse(key, value)?,
+            "integrator" => self.integrator = parse(key, value)?,
+            "initial" => self.initial = parse(key, value)?,
+            "equilibration" => self.equilibration = parse(key, value)?,
+            "adaptive" => self.adaptive = parse(key, value)?,
+            "escort" => self.escort = parse(key, value)?,
+            "tolerance" => self.tolerance = parse(key, value)?,
+            "tilt" => self.tilt = parse(key, value)?,
+            "box_length" => self.box_length = parse(key, value)?,
+            "wall_lower" => self.wall_lower = parse(key, value)?,
+            "wall_upper" => self.wall_upper = parse(key, value)?,
+            "noise" => self.noise = parse(key, value)?,
+            "gaussian" => self.gaussian = parse(key, value)?,
+            "rng" => self.rng = parse(key, value)?,
+            "sampling" => self.sampling = parse(key, value)?,
+            "antithetic" => self.antithetic = parse(key, value)?,
+            "deterministic" => self.deterministic = parse(key, value)?,
+            "control_variates" => self.control_variates = parse(key, value)?,
+            "correlation_time" => self.correlation_time = parse(key, value)?,
+            "counterdiabatic" => self.counterdiabatic = parse(key, value)?,
+            "model" => self.model = parse(key, value)?,
+            "spin_rate" => self.spin_rate = parse(key, value)?,
+            "lattice_sites" => self.lattice_sites = parse(key, value)?,
+            "lattice_start" => self.lattice_start = parse(key, value)?,
+            "lattice_spacing" => self.lattice_spacing = parse(key, value)?,
+            "hop_rate" => self.hop_rate = parse(key, value)?,
+            "particles" => self.particles = parse(key, value)?,
+            "interaction" => self.interaction = parse(key, value)?,
+            "bond_stiffness" => self.bond_stiffness = parse(key, value)?,
+            "bond_length" => self.bond_length = parse(key, value)?,
+            "wca_epsilon" => self.wca_epsilon = parse(key, value)?,
+            "wca_sigma" => self.wca_sigma = parse(key, value)?,
+            "chain_relaxation" => self.chain_relaxation = parse(key, value)?,
+            "landscape" => self.landscape = parse(key, value)?,
+            "landscape_stiffness" => self.landscape_stiffness = parse(key, value)?,
+            "landscape_centre" => self.landscape_centre = parse(key, value)?,
+            "barrier" => self.barrier = parse(key, value)?,
+            "well_position" => self.well_position = parse(key, value)?,
+            "morse_depth" => self.morse_depth = parse(key, value)?,
+            "morse_range" => self.morse_range = parse(key, value)?,
+            "amplitude" => self.amplitude = parse(key, value)?,
+            "period" => self.period = parse(key, value)?,
+            "potential_expr" => self.potential_expr = Some(value.trim_matches('"').to_string()),
+            "define" => {
+                let (name, constant) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("config: define expects name=value, got '{}'", value))?;
+                self.defines.insert(name.trim().to_string(), parse(key, constant.trim())?);
+            }
+            "sample_range" => self.sample_range = parse(key, value)?,
+            "dimensions" => self.dimensions = parse(key, value)?,
+            "lambda_start" => self.lambda_start = parse(key, value)?,
+            "lambda_end" => self.lambda_end = parse(key, value)?,
+            "schedule" => self.schedule = parse(key, value)?,
+            "schedule_knots" => self.schedule_knots = schedule::parse_knots(value.trim_matches('"'))?,
+            "schedule_expr" => self.schedule_expr = Some(value.trim_matches('"').to_string()),
+            "stiffness_schedule" => self.stiffness_schedule = Some(parse(key, value)?),
+            "stiffness_end" => self.stiffness_end = parse(key, value)?,
+            "stiffness_knots" => self.stiffness_knots = schedule::parse_knots(value.trim_matches('"'))?,
+            "stiffness_expr" => self.stiffness_expr = Some(value.trim_matches('"').to_string()),
+            "duration" => self.duration = parse(key, value)?,
+            "trajectories" => self.trajectories = parse(key, value)?,
+            "seed" => {
+                self.seed = parse(key, value)?;
+                self.seed_given = true;
+            }
+            "bins" => self.bins = parse(key, value)?,
+            "bootstrap" => self.bootstrap = parse(key, value)?,
+            "confidence" => self.confidence = parse(key, value)?,
+            "cumulant_order" => self.cumulant_order = parse(key, value)?,
+            "min_effective_samples" => self.min_effective_samples = parse(key, value)?,
+            "selftest_tolerance" => self.selftest_tolerance = parse(key, value)?,
+            "rngtest_draws" => self.rngtest_draws = parse(key, value)?,
+            "preset" => match value {
+                "hairpin" => {
+                    for (key, value) in HAIRPIN {
+                        self.set(key, value)?;
+                    }
+                }
+                "selftest" => {
+                    for (key, value) in SELFTEST {
+                        self.set(key, value)?;
+                    }
+                }
+                _ => return Err(format!("config: unknown preset '{}'", value)),
+            },
+            "rates" => {
+                self.rates = value
+                    .trim_matches('"')
+                    .split(',')
+                    .map(|d| parse(key, d.trim()))
+                    .collect::<Result<_, _>>()?
+            }
+            "temperatures" => {
+                self.temperatures = value
+                    .trim_matches('"')
+                    .split(',')
+                    .map(|t| parse(key, t.trim()))
+                    .collect::<Result<_, _>>()?
+            }
+            "replica_output" => self.replica_output = Some(value.trim_matches('"').to_string()),
+            "hysteresis_output" => self.hysteresis_output = Some(value.trim_matches('"').to_string()),
+            "summary_output" => self.summary_output = Some(value.trim_matches('"').to_string()),
+            "mbar_states" => self.mbar_states = parse(key, value)?,
+            "mbar_samples" => self.mbar_samples = parse(key, value)?,
+            "profile_stride" => self.profile_stride = parse(key, value)?,
+            "profile_output" => self.profile_output = Some(value.trim_matches('"').to_string()),
+            "windows" => self.windows = parse(key, value)?,
+            "window_samples" => self.window_samples = parse(key, value)?,
+            "record_file" => self.record_file = value.trim_matches('"').to_string(),
+            "record_count" => self.record_count = parse(key, value)?,
+            "record_stride" => self.record_stride = parse(key, value)?,
+            "record_select" => self.record_select = parse(key, value)?,
+            "playback_speed" => self.playback_speed = parse(key, value)?,
+            "colormap" => self.colormap = parse(key, value)?,
+            "colour_scale" => self.colour_scale = parse(key, value)?,
+            "colour_min" => self.colour_min = parse(key, value)?,
+            "colour_max" => self.colour_max = parse(key, value)?,
+            "symlog_threshold" => self.symlog_threshold = parse(key, value)?,
+            "colour_percentile" => self.colour_percentile = parse(key, value)?,
+            "equalise_smoothing" => self.equalise_smoothing = parse(key, value)?,
+            "overlay" => self.overlay = parse(key, value)?,
+            "live" => self.live = parse(key, value)?,
+            "split" => self.split = parse(key, value)?,
+            "output_dir" | "out" => self.output_dir = value.trim_matches('"').to_string(),
+            "gif_skip" => self.gif_skip = parse(key, value)?,
+            "gif_speed" => self.gif_speed = parse(key, value)?,
+            "video_fps" => self.video_fps = parse(key, value)?,
+            "video_codec" => self.video_codec = parse(key, value)?,
+            "video_bitrate" => self.video_bitrate = value.to_string(),
+            "headless" => self.headless = parse(key, value)?,
+            "frames" => self.frames = parse(key, value)?,
+            "screen_width" => self.screen_width = parse(key, value)?,
+            "screen_height" => self.screen_height = parse(key, value)?,
+            "panel" => self.panel = parse(key, value)?,
+            "backend" => self.backend = parse(key, value)?,
+            "tui" => self.tui = parse(key, value)?,
+            "sixel" => self.sixel = parse(key, value)?,
+            "sixel_width" => self.sixel_width = parse(key, value)?,
+            "contours" => self.contours = parse(key, value)?,
+            "contour_levels" => {
+                self.contour_levels = value
+                    .trim_matches('"')
+                    .split(',')
+                    .map(|l| parse(key, l.trim()))
+                    .collect::<Result<_, _>>()?
+            }
+            "surface" => self.surface = parse(key, value)?,
+            "surface_azimuth" => self.surface_azimuth = parse(key, value)?,
+            "surface_elevation" => self.surface_elevation = parse(key, value)?,
+            "ssaa" => self.ssaa = parse(key, value)?,
+            "measurement_error" => self.measurement_error = parse(key, value)?,
+            "feedback_gain" => self.feedback_gain = parse(key, value)?,
+            "cycles" => self.cycles = parse(key, value)?,
+            "transient_cycles" => self.transient_cycles = parse(key, value)?,
+            "optimize_knots" => self.optimize_knots = parse(key, value)?,
+            "optimize_trajectories" => self.optimize_trajectories = parse(key, value)?,
+            "optimize_iterations" => self.optimize_iterations = parse(key, value)?,
+            "metric_points" => self.metric_points = parse(key, value)?,
+            "metric_time" => self.metric_time = parse(key, value)?,
+            "metric_stride" => self.metric_stride = parse(key, value)?,
+            "fdt_frequencies" => {
+                self.fdt_frequencies = value
+                    .trim_matches('"')
+                    .split(',')
+                    .map(|w| parse(key, w.trim()))
+                    .collect::<Result<_, _>>()?
+            }
+            "fdt_amplitude" => self.fdt_amplitude = parse(key, value)?,
+            "fdt_time" => self.fdt_time = parse(key, value)?,
+            "fdt_transient" => self.fdt_transient = parse(key, value)?,
+            "fdt_trajectories" => self.fdt_trajectories = parse(key, value)?,
+            "fdt_stride" => self.fdt_stride = parse(key, value)?,
+            "characteristic_bins" => self.characteristic_bins = parse(key, value)?,
+            "characteristic_points" => self.characteristic_points = parse(key, value)?,
+            "characteristic_output" => self.characteristic_output = Some(value.trim_matches('"').to_string()),
+            "hbar" => self.hbar = Some(parse(key, value)?),
+            "quantum_output" => self.quantum_output = Some(value.trim_matches('"').to_string()),
+            "direction" => self.direction = parse(key, value)?,
+            _ => return Err(format!("config: unknown setting '{}'", key)),
+        }
+        Ok(())
+    }
+
+    // Apply every `key = value` line of a file; blank lines and # comments are skipped
+    pub fn load_file(&mut self, path: &str) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("config: cannot read {}: {}", path, e))?;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("config: {}:{}: expected key = value", path, number + 1))?;
+            self.set(key.trim(), value.trim())?;
+        }
+        Ok(())
+    }
+
+    // Build a configuration from `--key value` pairs, loading --config first
+    // so that explicit arguments always win over the file
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut pairs = Vec::new();
+        let mut iter = args.iter().peekable();
+        while let Some(arg) = iter.next() {
+            let key = arg
+                .strip_prefix("--")
+                .ok_or_else(|| format!("config: unexpected argument '{}'", arg))?
+                .replace('-', "_");
+            // A flag followed by another option, or by nothing, is switched on
+            let flag = FLAGS.contains(&key.as_str());
+            let value = match iter.next_if(|next| !(flag && next.starts_with("--"))) {
+                Some(value) => value.clone(),
+                None if flag => "true".to_string(),
+                None => return Err(format!("config: missing value for '{}'", arg)),
+            };
+            pairs.push((key, value));
+        }
+
+        let mut config = Self::default();
+        for (_, path) in pairs.iter().filter(|(key, _)| key == "config") {
+            config.load_file(path)?;
+        }
+        for (key, value) in pairs.iter().filter(|(key, _)| key != "config") {
+            config.set(key, value)?;
+        }
+        // Without a seed from the file, a preset or the arguments, draw one from
+        // the OS so that runs differ; it is reported so the run can be replayed
+        if !config.seed_given {
+            config.seed = (OsRng.next_u32() % (MAX_SEED as u32 + 1)) as i32;
+            config.seed_drawn = true;
+        }
+        config.compile_expressions()?;
+        // The sampler and the master seed are fixed for the whole run before any stream is seeded
+        unirand::select_gaussian(config.gaussian);
+        unirand::select_rng(config.rng);
+        unirand::select_sampling(config.sampling);
+        unirand::select_antithetic(config.antithetic);
+        unirand::set_master_seed(config.seed);
+        reduction::select_deterministic(config.deterministic);
+        if config.schedule == Shape::Geodesic {
+            config.schedule_knots = config.metric().geodesic_knots();
+        }
+        Ok(config)
+    }
+
+    // Change one setting of a configuration already in use, as --key value
+    // would have set it, recompiling what depends on it; on error the
+    // configuration is left as it was
+    #[cfg(feature = "egui")]
+    pub fn update(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let mut updated = self.clone();
+        updated.set(key, value)?;
+        updated.compile_expressions()?;
+        if updated.schedule == Shape::Geodesic {
+            updated.schedule_knots = updated.metric().geodesic_knots();
+        }
+        *self = updated;
+        Ok(())
+    }
+
+    // Parse the potential and schedule expressions once, with k and kt available
+    // as constants unless the configuration defines them explicitly
+    fn compile_expressions(&mut self) -> Result<(), String> {
+        let kt = self.bath().kt();
+        let mut constants = HashMap::from([("k".to_string(), self.stiffness), ("kt".to_string(), kt)]);
+        constants.extend(self.defines.iter().map(|(name, value)| (name.clone(), *value)));
+
+        if let Some(source) = &self.potential_expr {
+            self.expression = Some(Arc::new(Expression::parse(source, &["x", "lambda"], &constants)?));
+        }
+        if let Some(source) = &self.schedule_expr {
+            self.schedule_expression = Some(Arc::new(Expression::parse(source, &["t", "s"], &constants)?));
+        }
+        if let Some(source) = &self.stiffness_expr {
+            self.stiffness_expression = Some(Arc::new(Expression::parse(source, &["t", "s"], &constants)?));
+        }
+        if let Some(shape) = self.stiffness_schedule {
+            if self.model != ModelKind::Particle || self.expression.is_some() {
+                return Err("config: stiffness_schedule needs the particle model with a built-in landscape".to_string());
+            }
+            let positive = match shape {
+                Shape::Linear | Shape::Sinusoidal => self.stiffness > 0.0 && self.stiffness_end > 0.0,
+                Shape::Piecewise if self.stiffness_knots.is_empty() => {
+                    return Err("config: piecewise stiffness schedule needs stiffness_knots".to_string())
+                }
+                Shape::Piecewise => self.stiffness_knots.iter().all(|&(_, k)| k > 0.0),
+                Shape::Custom if self.stiffness_expression.is_none() => {
+                    return Err("config: custom stiffness schedule needs stiffness_expr".to_string())
+                }
+                Shape::Custom => true,
+                Shape::Geodesic => return Err("config: the geodesic schedule drives the trap centre only".to_string()),
+            };
+            if !positive {
+                return Err("config: the driven stiffness must stay positive".to_string());
+            }
+        }
+        if !(self.dt > 0.0 && self.dt.is_finite()) {
+            return Err("config: dt must be positive".to_string());
+        }
+        if !(self.friction > 0.0 && self.friction.is_finite()) {
+            return Err("config: friction must be positive".to_string());
+        }
+        if !(self.mass > 0.0 && self.mass.is_finite()) {
+            return Err("config: mass must be positive".to_string());
+        }
+        // A flat or inverted trap has no Boltzmann density to start from
+        if !(self.stiffness > 0.0 && self.stiffness.is_finite()) {
+            return Err("config: stiffness must be positive".to_string());
+        }
+        if !(self.duration > 0.0 && self.duration.is_finite()) || self.rates.iter().any(|&d| !(d > 0.0 && d.is_finite())) {
+            return Err("config: duration and rates must be positive".to_string());
+        }
+        if self.bins == 0 {
+            return Err("config: bins must be at least 1".to_string());
+        }
+        // The reverse ensemble's streams follow the forward ones' in the
+        // master seed's substreams
+        if !(1..=ensemble::REVERSE_STREAM).contains(&(self.trajectories as u64)) {
+            return Err(format!("config: trajectories must be between 1 and {}", ensemble::REVERSE_STREAM));
+        }
+        if self.initial == Initial::Trap && self.equilibration <= 0.0 {
+            return Err("config: initial = trap needs an equilibration period".to_string());
+        }
+        if self.adaptive && self.integrator != Integrator::EulerMaruyama {
+            return Err("config: adaptive time stepping needs the euler integrator".to_string());
+        }
+        if self.adaptive && self.escort != 0.0 {
+            return Err("config: escorted pulling needs fixed time steps".to_string());
+        }
+        if self.tilt != 0.0 && self.model != ModelKind::Particle {
+            return Err("config: tilt needs the particle model".to_string());
+        }
+        if self.box_length < 0.0 {
+            return Err("config: box_length must be positive, or 0 for no box".to_string());
+        }
+        if self.box_length > 0.0 {
+            if self.model != ModelKind::Particle || self.expression.is_some() || self.tilt != 0.0 {
+                return Err("config: a periodic box needs the particle model with a built-in landscape and no tilt".to_string());
+            }
+            let fits = match self.landscape {
+                Landscape::Flat => true,
+                Landscape::Periodic => {
+                    let periods = self.box_length / self.period;
+                    (periods - periods.round()).abs() < 1e-9 && periods.round() >= 1.0
+                }
+                _ => false,
+            };
+            if !fits {
+                return Err("config: a periodic box needs a flat landscape or a whole number of periodic landscape periods".to_string());
+            }
+        }
+        if self.wall_lower.is_finite() || self.wall_upper.is_finite() {
+            if self.model != ModelKind::Particle || self.box_length > 0.0 {
+                return Err("config: reflecting walls need the particle model outside a periodic box".to_string());
+            }
+            if self.wall_lower >= self.wall_upper {
+                return Err("config: wall_lower must lie below wall_upper".to_string());
+            }
+        }
+        if self.noise == Noise::Colored {
+            if self.model != ModelKind::Particle || self.integrator != Integrator::EulerMaruyama || self.adaptive {
+                return Err("config: colored noise needs the particle model with the fixed-step euler integrator".to_string());
+            }
+            if self.correlation_time <= 0.0 {
+                return Err("config: correlation_time must be positive".to_string());
+            }
+        }
+        if self.counterdiabatic {
+            let plain = self.tilt == 0.0 && self.box_length == 0.0 && !self.wall_lower.is_finite() && !self.wall_upper.is_finite();
+            if self.model != ModelKind::Particle
+                || self.landscape != Landscape::Flat
+                || self.expression.is_some()
+                || self.integrator != Integrator::EulerMaruyama
+                || self.adaptive
+                || self.escort != 0.0
+                || self.noise != Noise::White
+                || !plain
+            {
+                return Err(
+                    "config: counterdiabatic driving needs the overdamped particle in a bare harmonic trap \
+                     (flat landscape, euler integrator, white noise, no tilt, box, walls or escort)"
+                        .to_string(),
+                );
+            }
+        }
+        if !(self.kt > 0.0 && self.kt.is_finite()) {
+            return Err("config: kt must be positive".to_string());
+        }
+        if !(self.temperature > 0.0 && self.temperature.is_finite()) {
+            return Err("config: temperature must be positive".to_string());
+        }
+        if self.temperatures.iter().any(|&t| t <= 0.0) {
+            return Err("config: temperatures must be positive".to_string());
+        }
+        if self.measurement_error <= 0.0 {
+            return Err("config: measurement_error must be positive".to_string());
+        }
+        if !(2..=4).contains(&self.cumulant_order) {
+            return Err(format!("config: cumulant_order must be 2, 3 or 4, got {}", self.cumulant_order));
+        }
+        if self.cycles == 0 {
+            return Err("config: cycles must be at least 1".to_string());
+        }
+        if self.optimize_knots == 0 || self.optimize_trajectories == 0 {
+            return Err("config: optimize_knots and optimize_trajectories must be at least 1".to_string());
+        }
+        // The bootstrap replicates resample from the substreams after the
+        // forward trajectories, below the reverse streams
+        if self.trajectories as u64 + self.bootstrap as u64 > ensemble::REVERSE_STREAM {
+            return Err(format!("config: trajectories plus bootstrap must be at most {}", ensemble::REVERSE_STREAM));
+        }
+        // MBAR's equilibrium samples follow the switching trajectories, below
+        // the reverse streams
+        let mbar_streams = (self.mbar_states.max(2) as u64).saturating_mul(self.mbar_samples as u64);
+        if mbar_streams.saturating_add(self.trajectories as u64) > ensemble::REVERSE_STREAM {
+            return Err(format!("config: trajectories plus mbar_states times mbar_samples must be at most {}", ensemble::REVERSE_STREAM));
+        }
+        // WHAM's windows take the substreams from the first, one block after
+        // another, also below the reverse streams
+        if (self.windows.max(2) as u64).saturating_mul(self.window_samples as u64) > ensemble::REVERSE_STREAM {
+            return Err(format!("config: windows times window_samples must be at most {}", ensemble::REVERSE_STREAM));
+        }
+        // The optimiser's check trajectories follow its training ones, below
+        // the reverse streams
+        if self.optimize_trajectories as u64 + self.trajectories as u64 > ensemble::REVERSE_STREAM {
+            return Err(format!("config: optimize_trajectories plus trajectories must be at most {}", ensemble::REVERSE_STREAM));
+        }
+        if self.metric_points < 2 || self.metric_time <= 0.0 {
+            return Err("config: metric_points must be at least 2 and metric_time positive".to_string());
+        }
+        if self.fdt_frequencies.iter().any(|&w| w * self.fdt_time < 2.0 * std::f64::consts::PI) {
+            return Err("config: fdt_time must span at least one period of every fdt frequency".to_string());
+        }
+        if self.fdt_amplitude == 0.0 || self.fdt_trajectories == 0 || self.fdt_stride == 0 {
+            return Err("config: fdt_amplitude must be nonzero and fdt_trajectories and fdt_stride at least 1".to_string());
+        }
+        if self.characteristic_bins == 0 || self.characteristic_points < self.characteristic_bins {
+            return Err("config: characteristic_bins must be at least 1 and characteristic_points no fewer than the bins".to_string());
+        }
+        unirand::check_seed(self.seed).map_err(|e| format!("config: {}", e))?;
+        if self.antithetic && self.trajectories % 2 == 1 {
+            return Err("config: antithetic pairs need an even number of trajectories".to_string());
+        }
+        if self.selftest_tolerance <= 0.0 {
+            return Err("config: selftest_tolerance must be positive".to_string());
+        }
+        if self.rngtest_draws < 1000 {
+            return Err("config: rngtest_draws must be at least 1000".to_string());
+        }
+        if self.colour_min >= self.colour_max {
+            return Err("config: colour_min must be below colour_max".to_string());
+        }
+        if !(self.symlog_threshold > 0.0 && self.symlog_threshold.is_finite()) {
+            return Err("config: symlog_threshold must be positive".to_string());
+        }
+        if !(0.0..50.0).contains(&self.colour_percentile) {
+            return Err("config: colour_percentile must be at least 0 and below 50".to_string());
+        }
+        if !(0.0..1.0).contains(&self.equalise_smoothing) {
+            return Err("config: equalise_smoothing must be at least 0 and below 1".to_string());
+        }
+        if self.gif_skip == 0 {
+            return Err("config: gif_skip must be at least 1".to_string());
+        }
+        if !(1..=30).contains(&self.gif_speed) {
+            return Err("config: gif_speed must be between 1 and 30".to_string());
+        }
+        if self.frames == 0 {
+            return Err("config: frames must be at least 1".to_string());
+        }
+        if cfg!(not(feature = "gpu")) && self.backend == Backend::Gpu {
+            return Err("config: backend gpu needs a build with --features gpu".to_string());
+        }
+        if self.screen_width == 0 || self.screen_height == 0 {
+            return Err("config: screen_width and screen_height must be positive".to_string());
+        }
+        if self.sixel_width < 16 {
+            return Err("config: sixel_width must be at least 16".to_string());
+        }
+        if self.contour_levels.iter().any(|level| !(0.0..=1.0).contains(level)) {
+            return Err("config: contour_levels must lie between 0 and 1".to_string());
+        }
+        if !self.surface_azimuth.is_finite() {
+            return Err("config: surface_azimuth must be finite".to_string());
+        }
+        if !(5.0..=90.0).contains(&self.surface_elevation) {
+            return Err("config: surface_elevation must be between 5 and 90 degrees".to_string());
+        }
+        // The field at every sample is kept, ssaa squared values to a pixel
+        if !(1..=4).contains(&self.ssaa) {
+            return Err("config: ssaa must be between 1 and 4".to_string());
+        }
+        if self.video_fps == 0 {
+            return Err("config: video_fps must be positive".to_string());
+        }
+        // A number of bits per second with an optional k or M suffix, as ffmpeg takes it
+        let digits = self.video_bitrate.trim_end_matches(['k', 'K', 'm', 'M']);
+        if digits.parse::<f64>().map_or(true, |rate| rate <= 0.0) || self.video_bitrate.len() > digits.len() + 1 {
+            return Err(format!("config: video_bitrate '{}' is not a rate like 4M or 800k", self.video_bitrate));
+        }
+        if self.hbar.is_some_and(|hbar| hbar <= 0.0) {
+            return Err("config: hbar must be positive".to_string());
+        }
+        if self.particles == 0 {
+            return Err("config: particles must be at least 1".to_string());
+        }
+        if !(1..=3).contains(&self.dimensions) {
+            return Err(format!("config: dimensions must be 1, 2 or 3, got {}", self.dimensions));
+        }
+        if self.dimensions > 1 && self.expression.is_some() {
+            return Err("config: potential_expr is one-dimensional and needs dimensions = 1".to_string());
+        }
+        match self.schedule {
+            Shape::Piecewise if self.schedule_knots.is_empty() => Err("config: piecewise schedule needs schedule_knots".to_string()),
+            Shape::Custom if self.schedule_expression.is_none() => Err("config: custom schedule needs schedule_expr".to_string()),
+            Shape::Geodesic if self.model != ModelKind::Particle || self.stiffness_schedule.is_some() => {
+                Err("config: the geodesic schedule needs the particle model with a fixed stiffness".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Bath temperature: kT as given in reduced units, or k_B T from kelvin in SI
+    pub fn bath(&self) -> Temperature {
+        match self.units {
+            UnitSystem::Reduced => Temperature::from_kt(self.kt),
+            UnitSystem::Si => Temperature::from_kelvin(self.temperature),
+        }
+    }
+
+    // Reduced Planck constant: one in reduced units unless given, and its
+    // value in J s in SI units
+    pub fn hbar(&self) -> f64 {
+        self.hbar.unwrap_or(match self.units {
+            UnitSystem::Reduced => 1.0,
+            UnitSystem::Si => HBAR,
+        })
+    }
+
+    // The selected static landscape with its parameters
+    pub fn potential(&self) -> Box<dyn Potential> {
+        match self.landscape {
+            Landscape::Flat => Box::new(Flat),
+            Landscape::Harmonic => Box::new(Harmonic {
+                stiffness: self.landscape_stiffness,
+                centre: self.landscape_centre,
+            }),
+            Landscape::DoubleWell => Box::new(DoubleWell {
+                barrier: self.barrier,
+                position: self.well_position,
+            }),
+            Landscape::Morse => Box::new(Morse {
+                depth: self.morse_depth,
+                range: self.morse_range,
+                centre: self.landscape_centre,
+            }),
+            Landscape::Periodic => Box::new(Periodic {
+                amplitude: self.amplitude,
+                period: self.period,
+            }),
+        }
+    }
+
+    // Physical and numerical parameters of the simulated system
+    pub fn trap_params(&self) -> TrapParams {
+        TrapParams {
+            landscape: self.potential(),
+            expression: self.expression.clone(),
+            sample_range: self.sample_range,
+            dimensions: self.dimensions,
+            stiffness: self.stiffness,
+            friction: self.friction,
+            mass: self.mass,
+            temperature: self.bath(),
+            dt: self.dt,
+            integrator: self.integrator,
+            initial: self.initial,
+            equilibration: self.equilibration,
+            tolerance: if self.adaptive { Some(self.tolerance) } else { None },
+            escort: self.escort,
+            correlation_time: match self.noise {
+                Noise::White => None,
+                Noise::Colored => Some(self.correlation_time),
+            },
+            tilt: self.tilt,
+            box_length: if self.box_length > 0.0 { Some(self.box_length) } else { None },
+            walls: (self.wall_lower, self.wall_upper),
+            counterdiabatic: self.counterdiabatic,
+        }
+    }
+
+    // The selected model backend: the trapped particle, the driven spin, the
+    // lattice jump process or the interacting chain
+    pub fn model(&self) -> Box<dyn Model> {
+        match self.model {
+            ModelKind::Particle => Box::new(self.trap_params()),
+            ModelKind::Spin => Box::new(SpinParams {
+                rate: self.spin_rate,
+                temperature: self.bath(),
+                dt: self.dt,
+            }),
+            ModelKind::Markov => Box::new(MarkovParams::new(
+                self.lattice_sites,
+                self.lattice_start,
+                self.lattice_spacing,
+                self.stiffness,
+                self.hop_rate,
+                self.bath(),
+                self.dt,
+            )),
+            ModelKind::Chain => Box::new(ChainParams {
+                particles: self.particles,
+                interaction: self.interaction,
+                bond_stiffness: self.bond_stiffness,
+                bond_length: self.bond_length,
+                wca_epsilon: self.wca_epsilon,
+                wca_sigma: self.wca_sigma,
+                stiffness: self.stiffness,
+                friction: self.friction,
+                temperature: self.bath(),
+                dt: self.dt,
+                relaxation: self.chain_relaxation,
+            }),
+        }
+    }
+
+    // The forward control schedule
+    pub fn schedule(&self) -> Schedule {
+        build_schedule(
+            self.schedule,
+            (self.lambda_start, self.lambda_end),
+            &self.schedule_knots,
+            &self.schedule_expression,
+        )
+    }
+
+    // The forward stiffness schedule from stiffness to stiffness_end, when driven
+    pub fn stiffness_schedule(&self) -> Option<Schedule> {
+        self.stiffness_schedule.map(|shape| {
+            build_schedule(
+                shape,
+                (self.stiffness, self.stiffness_end),
+                &self.stiffness_knots,
+                &self.stiffness_expression,
+            )
+        })
+    }
+
+    // Thermodynamic metric of the trap position between lambda_start and lambda_end
+    pub fn metric(&self) -> Metric {
+        metric::measure(
+            &self.trap_params(),
+            self.lambda_start,
+            self.lambda_end,
+            self.metric_points,
+            self.metric_time,
+            self.metric_stride,
+            self.seed,
+        )
+    }
+
+    // The forward pulling protocol
+    pub fn protocol(&self) -> Protocol {
+        Protocol {
+            schedule: self.schedule(),
+            stiffness: self.stiffness_schedule(),
+            duration: self.duration,
+        }
+    }
+
+    // The forward protocol at every configured switching duration, or just the
+    // configured duration when no rates are given
+    pub fn rate_protocols(&self) -> Vec<Protocol> {
+        let durations = if self.rates.is_empty() { vec![self.duration] } else { self.rates.clone() };
+        durations
+            .into_iter()
+            .map(|duration| Protocol {
+                schedule: self.schedule(),
+                stiffness: self.stiffness_schedule(),
+                duration,
+            })
+            .collect()
+    }
+
+    // The configuration at every listed temperature, kT in reduced units or
+    // kelvin in SI, or just the configured one when none are listed
+    pub fn temperature_replicas(&self) -> Vec<Config> {
+        if self.temperatures.is_empty() {
+            return vec![self.clone()];
+        }
+        self.temperatures
+            .iter()
+            .map(|&t| {
+                let mut replica = self.clone();
+                match self.units {
+                    UnitSystem::Reduced => replica.kt = t,
+                    UnitSystem::Si => replica.temperature = t,
+                }
+                replica
+            })
+            .collect()
+    }
+}
+
+// A schedule of the given shape between two values, or through knots or an
+// expression compiled at startup
+fn build_schedule(shape: Shape, (from, to): (f64, f64), knots: &[(f64, f64)], expression: &Option<Arc<Expression>>) -> Schedule {
+    match shape {
+        Shape::Linear => Schedule::Linear { from, to },
+        Shape::Sinusoidal => Schedule::Sinusoidal { from, to },
+        Shape::Piecewise | Shape::Geodesic => Schedule::Piecewise {
+            knots: Arc::new(knots.to_vec()),
+            reversed: false,
+        },
+        Shape::Custom => Schedule::Custom {
+            expression: expression.clone().expect("custom schedule compiled at startup"),
+            reversed: false,
+        },
+    }
+}
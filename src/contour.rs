@@ -0,0 +1,91 @@
+// contour.rs
+// Iso-value lines of a frame's normalised field by marching squares, drawn
+// over its colours
+
+use rayon::prelude::*;
+
+// Colour of the contour lines
+const CONTOUR_COLOUR: u32 = 0xffffff;
+
+// A piece of contour between two points of the frame
+type Segment = ((f64, f64), (f64, f64));
+
+// Point where the edge from value a at p to value b at q crosses the level
+fn crossing(p: (f64, f64), q: (f64, f64), a: f64, b: f64, level: f64) -> (f64, f64) {
+    let t = ((level - a) / (b - a)).clamp(0.0, 1.0);
+    (p.0 + t * (q.0 - p.0), p.1 + t * (q.1 - p.1))
+}
+
+// Segments of the level's contour through the square of pixel centres with
+// (x, y) at its top-left corner. The crossings are found edge by edge; where
+// all four edges are crossed, a saddle, the mean of the corners decides which
+// pair of opposite corners the contour separates.
+fn segments(values: &[f64], width: usize, x: usize, y: usize, level: f64, out: &mut Vec<Segment>) {
+    let corners = [(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)];
+    let value = corners.map(|(cx, cy)| values[cy * width + cx]);
+    if value.iter().any(|v| v.is_nan()) {
+        return;
+    }
+    let above = value.map(|v| v >= level);
+    if above.iter().all(|&a| a == above[0]) {
+        return;
+    }
+    let point = corners.map(|(cx, cy)| (cx as f64 + 0.5, cy as f64 + 0.5));
+    // Crossings of the top, right, bottom and left edges, in that order
+    let mut crossed = Vec::with_capacity(4);
+    for edge in 0..4 {
+        let next = (edge + 1) % 4;
+        if above[edge] != above[next] {
+            crossed.push(crossing(point[edge], point[next], value[edge], value[next], level));
+        }
+    }
+    match crossed[..] {
+        [p, q] => out.push((p, q)),
+        [top, right, bottom, left] => {
+            let centre_above = value.iter().sum::<f64>() / 4.0 >= level;
+            if centre_above == above[0] {
+                // The top-left and bottom-right corners join through the centre
+                out.push((top, right));
+                out.push((bottom, left));
+            } else {
+                out.push((left, top));
+                out.push((right, bottom));
+            }
+        }
+        _ => {}
+    }
+}
+
+// Draw the contours of the values, in [0, 1] row by row, at each level over
+// the frame's pixels
+pub fn draw(buffer: &mut [u32], values: &[f64], width: usize, height: usize, levels: &[f64]) {
+    if width < 2 || height < 2 {
+        return;
+    }
+    // Segments a row of squares at a time in parallel, then drawn in turn, as
+    // neighbouring rows touch the same pixels
+    let pixels: Vec<usize> = (0..height - 1)
+        .into_par_iter()
+        .flat_map_iter(|y| {
+            let mut found = Vec::new();
+            for x in 0..width - 1 {
+                for &level in levels {
+                    segments(values, width, x, y, level, &mut found);
+                }
+            }
+            // The segments are at most a pixel and a half long, so points every
+            // half pixel along them leave no gaps
+            found.into_iter().flat_map(move |(p, q)| {
+                let steps = ((q.0 - p.0).abs().max((q.1 - p.1).abs()) * 2.0).ceil() as usize;
+                (0..=steps).map(move |i| {
+                    let t = if steps == 0 { 0.0 } else { i as f64 / steps as f64 };
+                    let (px, py) = (p.0 + t * (q.0 - p.0), p.1 + t * (q.1 - p.1));
+                    (py as usize).min(height - 1) * width + (px as usize).min(width - 1)
+                })
+            })
+        })
+        .collect();
+    for i in pixels {
+        buffer[i] = CONTOUR_COLOUR;
+    }
+}
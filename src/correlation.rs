@@ -0,0 +1,92 @@
+// correlation.rs
+// Error bars for correlated series: integrated autocorrelation time and block averaging
+
+// Window constant of Sokal's automatic windowing for the autocorrelation sum
+const SOKAL_WINDOW: f64 = 5.0;
+
+// Mean with a standard error that accounts for serial correlation
+pub struct CorrelatedMean {
+    pub mean: f64,
+    pub std_error: f64,
+    pub tau: f64,          // Integrated autocorrelation time, 0.5 for uncorrelated samples
+    pub inefficiency: f64, // Statistical inefficiency g = 1 + 2 tau_excess = 2 tau
+    pub effective_samples: f64,
+}
+
+// Standard error of the mean from non-overlapping blocks of a given size
+pub struct BlockEstimate {
+    pub block_size: usize,
+    pub blocks: usize,
+    pub std_error: f64,
+}
+
+fn mean(series: &[f64]) -> f64 {
+    series.iter().sum::<f64>() / series.len() as f64
+}
+
+// Normalised autocorrelation function rho(t) for lags 0..=max_lag
+pub fn autocorrelation(series: &[f64], max_lag: usize) -> Vec<f64> {
+    let n = series.len();
+    let m = mean(series);
+    let variance = series.iter().map(|x| (x - m).powi(2)).sum::<f64>() / n as f64;
+    if variance == 0.0 {
+        return vec![1.0];
+    }
+    (0..=max_lag.min(n - 1))
+        .map(|lag| {
+            let c: f64 = (0..n - lag).map(|i| (series[i] - m) * (series[i + lag] - m)).sum();
+            c / ((n - lag) as f64 * variance)
+        })
+        .collect()
+}
+
+// Integrated autocorrelation time tau = 1/2 + sum_t rho(t), truncated at the
+// first window W >= 5 tau(W) (Sokal) to keep the noise of the tail out
+pub fn integrated_time(series: &[f64]) -> f64 {
+    let rho = autocorrelation(series, series.len() / 2);
+    let mut tau = 0.5;
+    for (lag, r) in rho.iter().enumerate().skip(1) {
+        tau += r;
+        if lag as f64 >= SOKAL_WINDOW * tau {
+            break;
+        }
+    }
+    tau.max(0.5)
+}
+
+// Mean and correlation-corrected standard error of a series
+pub fn correlated_mean(series: &[f64]) -> CorrelatedMean {
+    let n = series.len() as f64;
+    let m = mean(series);
+    let variance = series.iter().map(|x| (x - m).powi(2)).sum::<f64>() / (n - 1.0).max(1.0);
+    let tau = integrated_time(series);
+    let inefficiency = 2.0 * tau;
+    CorrelatedMean {
+        mean: m,
+        std_error: (variance * inefficiency / n).sqrt(),
+        tau,
+        inefficiency,
+        effective_samples: n / inefficiency,
+    }
+}
+
+// Block standard errors for block sizes 1, 2, 4, ... while at least four blocks
+// remain (Flyvbjerg-Petersen). The error grows with block size until blocks are
+// longer than the correlation time, then plateaus at the true standard error.
+pub fn block_analysis(series: &[f64]) -> Vec<BlockEstimate> {
+    let mut estimates = Vec::new();
+    let mut block_size = 1;
+    while series.len() / block_size >= 4 {
+        let block_means: Vec<f64> = series.chunks_exact(block_size).map(mean).collect();
+        let blocks = block_means.len();
+        let m = mean(&block_means);
+        let variance = block_means.iter().map(|x| (x - m).powi(2)).sum::<f64>() / (blocks - 1) as f64;
+        estimates.push(BlockEstimate {
+            block_size,
+            blocks,
+            std_error: (variance / blocks as f64).sqrt(),
+        });
+        block_size *= 2;
+    }
+    estimates
+}
@@ -0,0 +1,185 @@
+// distributions.rs
+// Exponential, gamma and Poisson variates from any uniform generator
+
+use crate::unirand::UniformRng;
+
+// Lanczos coefficients for g = 7, nine terms (Godfrey), good to about 1e-15
+const LANCZOS_G: f64 = 7.0;
+const LANCZOS: [f64; 9] = [
+    0.999_999_999_999_809_9,
+    676.520_368_121_885_1,
+    -1_259.139_216_722_402_8,
+    771.323_428_777_653_1,
+    -176.615_029_162_140_6,
+    12.507_343_278_686_905,
+    -0.138_571_095_265_720_12,
+    9.984_369_578_019_572e-6,
+    1.505_632_735_149_311_6e-7,
+];
+
+// Mean above which poisson switches from counting uniforms to transformed rejection
+const POISSON_REJECTION_MEAN: f64 = 10.0;
+
+// ln Gamma(x) for x > 0 by the Lanczos approximation
+pub fn ln_gamma(x: f64) -> f64 {
+    if x < 0.5 {
+        // Reflection keeps the series where it converges
+        return (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + LANCZOS_G + 0.5;
+    let series = LANCZOS[1..].iter().enumerate().fold(LANCZOS[0], |sum, (i, c)| sum + c / (x + (i + 1) as f64));
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+}
+
+// Inverse of the standard normal cumulative distribution by Acklam's rational
+// approximations, central and tail, with a relative error below 1.2e-9
+pub fn inverse_normal_cdf(p: f64) -> f64 {
+    const A: [f64; 6] = [-3.969_683_028_665_376e1, 2.209_460_984_245_205e2, -2.759_285_104_469_687e2, 1.383_577_518_672_69e2, -3.066_479_806_614_716e1, 2.506_628_277_459_239];
+    const B: [f64; 5] = [-5.447_609_879_822_406e1, 1.615_858_368_580_409e2, -1.556_989_798_598_866e2, 6.680_131_188_771_972e1, -1.328_068_155_288_572e1];
+    const C: [f64; 6] = [-7.784_894_002_430_293e-3, -3.223_964_580_411_365e-1, -2.400_758_277_161_838, -2.549_732_539_343_734, 4.374_664_141_464_968, 2.938_163_982_698_783];
+    const D: [f64; 4] = [7.784_695_709_041_462e-3, 3.224_671_290_700_398e-1, 2.445_134_137_142_996, 3.754_408_661_907_416];
+    const LOW: f64 = 0.02425;
+
+    let tail = |q: f64| {
+        let q = (-2.0 * q.ln()).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5]) / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if p < LOW {
+        tail(p)
+    } else if p > 1.0 - LOW {
+        -tail(1.0 - p)
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+// Exponential variate of the given rate, by inversion of a uniform shifted to
+// (0, 1] so its logarithm is finite
+pub fn exponential<R: UniformRng + ?Sized>(rng: &mut R, rate: f64) -> f64 {
+    -(1.0 - rng.uniform()).ln() / rate
+}
+
+// Gamma variate of the given shape and scale by Marsaglia and Tsang's (2000)
+// squeeze on a cubed normal; a shape below one is raised by one and the
+// variate scaled back by U^(1/shape)
+pub fn gamma<R: UniformRng + ?Sized>(rng: &mut R, shape: f64, scale: f64) -> f64 {
+    if shape < 1.0 {
+        let boost = (1.0 - rng.uniform()).powf(1.0 / shape);
+        return gamma(rng, shape + 1.0, scale) * boost;
+    }
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        let x = rng.generate_gaussian();
+        let v = 1.0 + c * x;
+        if v <= 0.0 {
+            continue;
+        }
+        let v = v * v * v;
+        let u = 1.0 - rng.uniform();
+        if u < 1.0 - 0.0331 * x.powi(4) || u.ln() < 0.5 * x * x + d * (1.0 - v + v.ln()) {
+            return d * v * scale;
+        }
+    }
+}
+
+// Poisson variate of the given mean: for small means by counting uniforms
+// until their product drops below e^-mean, otherwise by Hormann's (1993)
+// transformed rejection with squeeze (PTRS), whose cost does not grow with the mean
+pub fn poisson<R: UniformRng + ?Sized>(rng: &mut R, mean: f64) -> u64 {
+    if mean < POISSON_REJECTION_MEAN {
+        let limit = (-mean).exp();
+        let (mut count, mut product) = (0, rng.uniform());
+        while product > limit {
+            count += 1;
+            product *= rng.uniform();
+        }
+        return count;
+    }
+    let (root, ln_mean) = (mean.sqrt(), mean.ln());
+    let b = 0.931 + 2.53 * root;
+    let a = -0.059 + 0.02483 * b;
+    let inverse_alpha = 1.1239 + 1.1328 / (b - 3.4);
+    let v_r = 0.9277 - 3.6224 / (b - 2.0);
+    loop {
+        let u = rng.uniform() - 0.5;
+        let v = rng.uniform();
+        let us = 0.5 - u.abs();
+        let k = ((2.0 * a / us + b) * u + mean + 0.43).floor();
+        if us >= 0.07 && v <= v_r {
+            return k as u64;
+        }
+        if k < 0.0 || (us < 0.013 && v > us) {
+            continue;
+        }
+        if v.ln() + inverse_alpha.ln() - (a / (us * us) + b).ln() <= -mean + k * ln_mean - ln_gamma(k + 1.0) {
+            return k as u64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unirand::{GaussianMethod, MarsagliaUniRng};
+
+    const DRAWS: usize = 200_000;
+
+    // Generator on Marsaglia's own test seeds, so the checks are the same on
+    // every run
+    fn rng() -> MarsagliaUniRng {
+        let mut rng = MarsagliaUniRng::with_gaussian(GaussianMethod::BoxMuller);
+        rng.start(12, 34, 56, 78);
+        rng
+    }
+
+    // Sample mean and variance within five standard errors of the exact ones;
+    // the variance's error follows from the excess kurtosis
+    fn assert_moments(name: &str, draws: &[f64], mean: f64, variance: f64, excess_kurtosis: f64) {
+        let n = draws.len() as f64;
+        let sample_mean = draws.iter().sum::<f64>() / n;
+        let sample_variance = draws.iter().map(|x| (x - sample_mean).powi(2)).sum::<f64>() / (n - 1.0);
+        let mean_error = (variance / n).sqrt();
+        let variance_error = variance * ((2.0 + excess_kurtosis) / n).sqrt();
+        assert!((sample_mean - mean).abs() < 5.0 * mean_error, "{}: mean {} against {}", name, sample_mean, mean);
+        assert!(
+            (sample_variance - variance).abs() < 5.0 * variance_error,
+            "{}: variance {} against {}",
+            name,
+            sample_variance,
+            variance
+        );
+    }
+
+    #[test]
+    fn exponential_moments() {
+        let mut rng = rng();
+        let rate = 2.5;
+        let draws: Vec<f64> = (0..DRAWS).map(|_| exponential(&mut rng, rate)).collect();
+        assert_moments("exponential", &draws, 1.0 / rate, 1.0 / (rate * rate), 6.0);
+    }
+
+    #[test]
+    fn gamma_moments() {
+        let mut rng = rng();
+        let scale = 2.0;
+        // Below one the shape is boosted, from one up it is drawn directly
+        for shape in [0.4, 3.5] {
+            let draws: Vec<f64> = (0..DRAWS).map(|_| gamma(&mut rng, shape, scale)).collect();
+            assert_moments(&format!("gamma({})", shape), &draws, shape * scale, shape * scale * scale, 6.0 / shape);
+        }
+    }
+
+    #[test]
+    fn poisson_moments() {
+        let mut rng = rng();
+        // Either side of the switch from counting uniforms to rejection
+        for mean in [0.7 * POISSON_REJECTION_MEAN, 4.2 * POISSON_REJECTION_MEAN] {
+            let draws: Vec<f64> = (0..DRAWS).map(|_| poisson(&mut rng, mean) as f64).collect();
+            assert_moments(&format!("poisson({})", mean), &draws, mean, mean, 1.0 / mean);
+        }
+    }
+}
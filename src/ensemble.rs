@@ -0,0 +1,194 @@
+// ensemble.rs
+// Parallel ensembles of independent pulling trajectories
+
+use crate::integrator::State;
+use crate::kde::{Bandwidth, Kde};
+use crate::model::Model;
+use crate::protocol::{Direction, Protocol};
+use crate::unirand;
+use rayon::prelude::*;
+use std::ops::Range;
+
+// Substream of the master seed the reverse ensemble is seeded from, halfway
+// through the substreams the generators keep apart, so that the two
+// directions never share random numbers while each runs at most this many
+// trajectories
+pub const REVERSE_STREAM: u64 = unirand::MAX_STREAMS / 2;
+
+// Per-trajectory results of one protocol direction, stored as contiguous arrays
+// indexed by trajectory
+pub struct Ensemble {
+    pub direction: Direction,
+    pub work: Vec<f64>,
+    pub heat: Vec<f64>,
+    pub energy_change: Vec<f64>,
+    pub initial_states: Vec<State>,
+    pub final_states: Vec<State>,
+    pub entropy_production: Vec<f64>, // Total entropy production in units of k_B
+    pub entropy_exact: bool,          // Whether the model supplied it or it was estimated
+    pub antithetic: bool,             // Whether trajectories 2k and 2k + 1 ran on mirrored noise
+}
+
+// Forward and reverse ensembles of the same protocol, as needed by the Crooks relation
+pub struct PairedEnsembles {
+    pub forward: Ensemble,
+    pub reverse: Ensemble,
+}
+
+impl Ensemble {
+    // Run independent trajectories of one protocol direction across the rayon
+    // pool. Trajectory i always draws from substream i of the seed, or point i
+    // of the seed's Sobol sequence or hypercube, whichever worker runs it, each
+    // starting from equilibrium in the initial trap of that direction. Odd
+    // trajectories mirror the even ones before them when pairs are antithetic.
+    pub fn run(model: &dyn Model, protocol: &Protocol, direction: Direction, trajectories: usize, seed: i32) -> Self {
+        let oriented = protocol.oriented(direction);
+        let results: Vec<_> = (0..trajectories)
+            .into_par_iter()
+            .map(|i| {
+                unirand::seed_trajectory(seed, i, trajectories);
+                let start = model.sample_start(&oriented);
+                (start, model.run_trajectory(&oriented, start))
+            })
+            .collect();
+
+        let mut ensemble = Self {
+            direction,
+            work: results.iter().map(|(_, r)| r.work).collect(),
+            heat: results.iter().map(|(_, r)| r.heat).collect(),
+            energy_change: results.iter().map(|(_, r)| r.energy_change).collect(),
+            initial_states: results.iter().map(|(start, _)| *start).collect(),
+            final_states: results.iter().map(|(_, r)| r.final_state).collect(),
+            entropy_production: Vec::new(),
+            entropy_exact: true,
+            antithetic: unirand::antithetic(),
+        };
+        match results.iter().map(|(_, r)| r.entropy_production).collect::<Option<Vec<f64>>>() {
+            Some(entropy) => ensemble.entropy_production = entropy,
+            None => {
+                ensemble.entropy_production = ensemble.estimated_entropy_production(model.temperature().beta());
+                ensemble.entropy_exact = false;
+            }
+        }
+        ensemble
+    }
+
+    // Total entropy production -beta Q + ln p(x_0, 0) - ln p(x_t, t) with the
+    // initial and final densities along the pulling axis estimated by KDEs of
+    // the ensemble itself, for models that cannot supply p(x, t)
+    fn estimated_entropy_production(&self, beta: f64) -> Vec<f64> {
+        let initial = Kde::new(self.initial_states.iter().map(|s| s.x[0]).collect(), Bandwidth::Silverman);
+        let last = Kde::new(self.final_states.iter().map(|s| s.x[0]).collect(), Bandwidth::Silverman);
+        (0..self.len())
+            .into_par_iter()
+            .map(|i| {
+                let system = initial.density(self.initial_states[i].x[0]).ln() - last.density(self.final_states[i].x[0]).ln();
+                system - beta * self.heat[i]
+            })
+            .collect()
+    }
+
+    // Mean total entropy production over the ensemble
+    pub fn mean_entropy_production(&self) -> f64 {
+        self.entropy_production.iter().sum::<f64>() / self.len() as f64
+    }
+
+    // Number of trajectories
+    pub fn len(&self) -> usize {
+        self.work.len()
+    }
+
+    // Mean work over the ensemble
+    pub fn mean_work(&self) -> f64 {
+        self.work.iter().sum::<f64>() / self.len() as f64
+    }
+
+    // Mean heat absorbed over the ensemble
+    pub fn mean_heat(&self) -> f64 {
+        self.heat.iter().sum::<f64>() / self.len() as f64
+    }
+
+    // Standard deviation of the work over the ensemble
+    pub fn work_std(&self) -> f64 {
+        std_dev(&self.work)
+    }
+
+    // Standard deviation of the heat absorbed, the size of its fluctuations
+    pub fn heat_std(&self) -> f64 {
+        std_dev(&self.heat)
+    }
+
+    // First-law residual Delta U - W - Q of every trajectory
+    pub fn first_law_residuals(&self) -> Vec<f64> {
+        (0..self.len())
+            .map(|i| self.energy_change[i] - self.work[i] - self.heat[i])
+            .collect()
+    }
+
+    // Root-mean-square first-law residual, which should vanish as dt -> 0
+    pub fn first_law_rms(&self) -> f64 {
+        let residuals = self.first_law_residuals();
+        (residuals.iter().map(|r| r * r).sum::<f64>() / self.len() as f64).sqrt()
+    }
+
+    // Mean final position along the pulling axis over the ensemble
+    pub fn mean_final_position(&self) -> f64 {
+        self.final_states.iter().map(|s| s.x[0]).sum::<f64>() / self.len() as f64
+    }
+}
+
+// Sample standard deviation, NaN for no samples
+fn std_dev(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return f64::NAN;
+    }
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    (values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / (values.len() - 1).max(1) as f64).sqrt()
+}
+
+// Run the forward protocol and its time reverse with the same number of trajectories
+pub fn run_paired(model: &dyn Model, protocol: &Protocol, trajectories: usize, seed: i32) -> PairedEnsembles {
+    PairedEnsembles {
+        forward: Ensemble::run(model, protocol, Direction::Forward, trajectories, seed),
+        reverse: Ensemble::run(model, protocol, Direction::Reverse, trajectories, reverse_seed(seed)),
+    }
+}
+
+// Seed of the reverse ensemble paired with a forward one
+pub fn reverse_seed(seed: i32) -> i32 {
+    unirand::stream_seed(seed, REVERSE_STREAM)
+}
+
+// Work of trajectories `range` of an ensemble of `trajectories`, seeded as in
+// Ensemble::run, so that batches run one after another add up to the same
+// samples as the whole ensemble at once
+pub fn work_batch(
+    model: &dyn Model,
+    protocol: &Protocol,
+    direction: Direction,
+    range: Range<usize>,
+    trajectories: usize,
+    seed: i32,
+) -> Vec<f64> {
+    let oriented = protocol.oriented(direction);
+    range
+        .into_par_iter()
+        .map(|i| {
+            unirand::seed_trajectory(seed, i, trajectories);
+            let start = model.sample_start(&oriented);
+            model.run_trajectory(&oriented, start).work
+        })
+        .collect()
+}
+
+// Draw independent equilibrium phase-space points at fixed lambda, sample i
+// seeded as trajectory i of an ensemble
+pub fn equilibrium_samples(model: &dyn Model, lambda: f64, samples: usize, seed: i32) -> Vec<State> {
+    (0..samples)
+        .into_par_iter()
+        .map(|i| {
+            unirand::seed_trajectory(seed, i, samples);
+            model.sample_equilibrium(lambda)
+        })
+        .collect()
+}
@@ -0,0 +1,73 @@
+// equalise.rs
+// Histogram equalisation of the animation's field: each value is coloured by
+// its rank in the frame, read off a table of the frame's quantiles, so that
+// the whole colormap is in use whatever the series parameters make of the
+// field. The table is blended with the previous frame's, which stops the
+// colours flickering as the frames' histograms change.
+
+use rayon::prelude::*;
+
+// Intervals the quantile table divides the values into
+const LEVELS: usize = 256;
+
+// Field values at the quantiles 0, 1/LEVELS, ..., 1 of the recent frames,
+// empty until the first frame
+#[derive(Clone, Debug, Default)]
+pub struct Equaliser {
+    quantiles: Vec<f64>,
+}
+
+impl Equaliser {
+    // Take in a frame's quantiles, keeping the share smoothing of the table so
+    // far; NaN values are ignored. An average of increasing tables increases,
+    // so the table stays one.
+    pub fn update(&mut self, values: &[f64], smoothing: f64) {
+        let mut sorted: Vec<f64> = values.iter().copied().filter(|v| !v.is_nan()).collect();
+        if sorted.is_empty() {
+            return;
+        }
+        sorted.par_sort_unstable_by(f64::total_cmp);
+        let last = sorted.len() - 1;
+        let fresh = (0..=LEVELS).map(|k| sorted[(k * last + LEVELS / 2) / LEVELS]);
+        if self.quantiles.len() == LEVELS + 1 {
+            self.quantiles.iter_mut().zip(fresh).for_each(|(q, f)| *q = smoothing * *q + (1.0 - smoothing) * f);
+        } else {
+            self.quantiles = fresh.collect();
+        }
+    }
+
+    // Smallest and largest value of the table, unless it is flat or empty
+    pub fn range(&self) -> Option<(f64, f64)> {
+        let (&low, &high) = (self.quantiles.first()?, self.quantiles.last()?);
+        (high > low).then_some((low, high))
+    }
+
+    // Position in [0, 1] of a value: its fractional index in the table, in
+    // the middle of any run of quantiles equal to it
+    pub fn position(&self, v: f64) -> f64 {
+        if v.is_nan() {
+            return v;
+        }
+        let below = self.quantiles.partition_point(|&q| q < v);
+        let through = self.quantiles.partition_point(|&q| q <= v);
+        let index = if through > below {
+            (below + through - 1) as f64 / 2.0
+        } else if below == 0 {
+            0.0
+        } else if below > LEVELS {
+            LEVELS as f64
+        } else {
+            let (low, high) = (self.quantiles[below - 1], self.quantiles[below]);
+            (below - 1) as f64 + (v - low) / (high - low)
+        };
+        index / LEVELS as f64
+    }
+
+    // Value at the position t in [0, 1], the inverse of position, for
+    // labelling a colorbar
+    pub fn value_at(&self, t: f64) -> f64 {
+        let index = t.clamp(0.0, 1.0) * LEVELS as f64;
+        let k = (index as usize).min(LEVELS - 1);
+        self.quantiles[k] + (index - k as f64) * (self.quantiles[k + 1] - self.quantiles[k])
+    }
+}
@@ -0,0 +1,182 @@
+// estimators.rs
+// Free-energy estimators built on nonequilibrium work samples
+
+use crate::summation::compensated_sum;
+use crate::units::Temperature;
+
+// Jarzynski free-energy estimate with its leading-order finite-sample bias
+pub struct JarzynskiEstimate {
+    pub delta_f: f64, // -kT ln <exp(-beta W)>
+    pub bias: f64,    // Estimated systematic overestimate of delta_f for this sample size
+    pub samples: usize,
+    pub effective_samples: f64, // Kish size (sum w)^2 / sum w^2 of the weights w = exp(-beta W)
+    pub max_weight: f64,        // Share of the total weight carried by the lowest-work trajectory
+}
+
+// Jarzynski estimate corrected with control variates of known mean
+pub struct ControlVariateEstimate {
+    pub delta_f: f64,
+    pub std_error: f64,          // Delta-method standard error of delta_f
+    pub variance_reduction: f64, // Variance of the plain exponential average over the corrected one
+}
+
+// Sample mean of exp(-x) with its standard error, for integral fluctuation
+// theorems of the form <exp(-x)> = 1
+pub struct IntegralCheck {
+    pub mean: f64,
+    pub std_error: f64,
+}
+
+// Numerically stable ln(sum(exp(values))), with the sum compensated
+pub fn log_sum_exp(values: impl Iterator<Item = f64> + Clone) -> f64 {
+    let max = values.clone().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        return max;
+    }
+    max + compensated_sum(values.map(|v| (v - max).exp())).ln()
+}
+
+// Delta F = -kT ln <exp(-beta W)>, evaluated with log-sum-exp so that large
+// work values cannot overflow or underflow the exponential average. The bias
+// is the first-order term kT/(2N) Var(e^(-beta W)) / <e^(-beta W)>^2, which
+// shrinks as 1/N but can be large when the work spread is many kT. The
+// effective sample size N / (1 + Var/<>^2) of the same weights counts how many
+// trajectories the average really rests on.
+pub fn jarzynski(work: &[f64], temperature: Temperature) -> JarzynskiEstimate {
+    let (kt, beta) = (temperature.kt(), temperature.beta());
+    let ln_n = (work.len() as f64).ln();
+
+    let ln_mean = log_sum_exp(work.iter().map(|w| -beta * w)) - ln_n;
+    let ln_mean_sq = log_sum_exp(work.iter().map(|w| -2.0 * beta * w)) - ln_n;
+    let relative_variance = (ln_mean_sq - 2.0 * ln_mean).exp() - 1.0;
+
+    let min_work = work.iter().fold(f64::INFINITY, |a, &w| a.min(w));
+    JarzynskiEstimate {
+        delta_f: -kt * ln_mean,
+        bias: kt * relative_variance / (2.0 * work.len() as f64),
+        samples: work.len(),
+        effective_samples: work.len() as f64 / (relative_variance + 1.0),
+        max_weight: (-beta * min_work - ln_mean - ln_n).exp(),
+    }
+}
+
+// Cumulant expansion of -kT ln <exp(-beta W)>,
+// Delta F = k1 - beta k2/2 + beta^2 k3/6 - beta^3 k4/24 + ...,
+// truncated after the given order (2 to 4). The second-order form
+// <W> - beta var(W)/2 is exact for Gaussian work, so the estimate is a cheap
+// near-equilibrium cross-check that needs none of the rare low-work samples the
+// exponential average hinges on. Cumulants use plain 1/N central moments.
+pub fn cumulant(work: &[f64], temperature: Temperature, order: usize) -> f64 {
+    let beta = temperature.beta();
+    let n = work.len() as f64;
+    let mean = compensated_sum(work.iter().copied()) / n;
+    let moment = |k: i32| compensated_sum(work.iter().map(|w| (w - mean).powi(k))) / n;
+    let variance = moment(2);
+    let cumulants = [mean, variance, moment(3), moment(4) - 3.0 * variance * variance];
+    let mut factorial = 1.0;
+    cumulants
+        .iter()
+        .take(order)
+        .enumerate()
+        .map(|(k, kappa)| {
+            factorial *= (k + 1) as f64;
+            (-beta).powi(k as i32) * kappa / factorial
+        })
+        .sum()
+}
+
+// Bennett acceptance ratio estimate of Delta F from forward work and reverse
+// work (the reverse protocol measures -Delta F). Solves the self-consistent
+// BAR equation sum_F f(M + beta (W_F - dF)) = sum_R f(-M + beta (W_R + dF)),
+// with f the Fermi function and M = ln(n_F / n_R), by bisection on dF.
+pub fn bar(forward: &[f64], reverse: &[f64], temperature: Temperature) -> f64 {
+    let beta = temperature.beta();
+    let m = (forward.len() as f64 / reverse.len() as f64).ln();
+    let fermi = |x: f64| 1.0 / (1.0 + x.exp());
+    let imbalance = |delta_f: f64| {
+        let lhs = compensated_sum(forward.iter().map(|w| fermi(m + beta * (w - delta_f))));
+        let rhs = compensated_sum(reverse.iter().map(|w| fermi(-m + beta * (w + delta_f))));
+        lhs - rhs
+    };
+
+    // The imbalance increases with dF and changes sign inside the work range
+    let mut lo = forward.iter().chain(reverse).fold(f64::INFINITY, |a, &w| a.min(w).min(-w));
+    let mut hi = forward.iter().chain(reverse).fold(f64::NEG_INFINITY, |a, &w| a.max(w).max(-w));
+    for _ in 0..200 {
+        let mid = 0.5 * (lo + hi);
+        if imbalance(mid) < 0.0 {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+        if hi - lo <= 1e-12 * (hi.abs() + lo.abs()).max(f64::MIN_POSITIVE) {
+            break;
+        }
+    }
+    0.5 * (lo + hi)
+}
+
+// <exp(-x)> over the samples, which an integral fluctuation theorem fixes to one
+pub fn integral_check(values: &[f64]) -> IntegralCheck {
+    let n = values.len() as f64;
+    let terms: Vec<f64> = values.iter().map(|x| (-x).exp()).collect();
+    let mean = compensated_sum(terms.iter().copied()) / n;
+    let variance = compensated_sum(terms.iter().map(|t| (t - mean).powi(2))) / (n - 1.0);
+    IntegralCheck {
+        mean,
+        std_error: (variance / n).sqrt(),
+    }
+}
+
+// Jarzynski estimate with the work W and its square as control variates, for
+// when the work distribution is known exactly, as for the harmonic trap. The
+// weights e^(-beta W), scaled by the lowest work so they cannot overflow, are
+// regressed on the controls W - mean and (W - mean)^2 - variance, whose
+// expectations vanish; subtracting the fitted controls removes the part of the
+// weights' scatter they explain, leaving an unbiased average of smaller
+// variance by the factor 1 / (1 - R^2). None when the corrected average is not
+// positive, which happens only for very few samples.
+pub fn control_variate(work: &[f64], temperature: Temperature, mean: f64, variance: f64) -> Option<ControlVariateEstimate> {
+    let (kt, beta) = (temperature.kt(), temperature.beta());
+    let n = work.len() as f64;
+    let min_work = work.iter().fold(f64::INFINITY, |a, &w| a.min(w));
+    let weights: Vec<f64> = work.iter().map(|w| (-beta * (w - min_work)).exp()).collect();
+    let first: Vec<f64> = work.iter().map(|w| w - mean).collect();
+    let second: Vec<f64> = work.iter().map(|w| (w - mean).powi(2) - variance).collect();
+
+    // Sample means, and covariances of the controls with each other and with the weights
+    let average = |x: &[f64]| compensated_sum(x.iter().copied()) / n;
+    let (weight_mean, mean0, mean1) = (average(&weights), average(&first), average(&second));
+    let covariance = |x: &[f64], mx: f64, y: &[f64], my: f64| {
+        compensated_sum(x.iter().zip(y).map(|(a, b)| (a - mx) * (b - my))) / (n - 1.0)
+    };
+    let (s00, s01, s11) = (
+        covariance(&first, mean0, &first, mean0),
+        covariance(&first, mean0, &second, mean1),
+        covariance(&second, mean1, &second, mean1),
+    );
+    let (t0, t1) = (
+        covariance(&first, mean0, &weights, weight_mean),
+        covariance(&second, mean1, &weights, weight_mean),
+    );
+    let weight_variance = covariance(&weights, weight_mean, &weights, weight_mean);
+
+    // Least-squares coefficients from the 2x2 normal equations
+    let determinant = s00 * s11 - s01 * s01;
+    let coefficients = if determinant > 0.0 {
+        [(s11 * t0 - s01 * t1) / determinant, (s00 * t1 - s01 * t0) / determinant]
+    } else {
+        [0.0, 0.0]
+    };
+    let corrected = weight_mean - coefficients[0] * mean0 - coefficients[1] * mean1;
+    if corrected <= 0.0 {
+        return None;
+    }
+    let explained = coefficients[0] * t0 + coefficients[1] * t1;
+    let residual_variance = (weight_variance - explained).max(0.0);
+    Some(ControlVariateEstimate {
+        delta_f: min_work - kt * corrected.ln(),
+        std_error: kt * (residual_variance / n).sqrt() / corrected,
+        variance_reduction: weight_variance / residual_variance,
+    })
+}
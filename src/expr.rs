@@ -0,0 +1,438 @@
+// expr.rs
+// Runtime expressions from the configuration, such as potentials U(x, lambda)
+// and schedules lambda(t). The text is parsed once at startup into a tree with
+// named constants folded in, and the derivative with respect to the first
+// variable (the force, for potentials) is derived symbolically from it.
+
+use std::collections::HashMap;
+
+// Elementary functions callable from an expression
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    Ln,
+    Sqrt,
+    Abs,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Const(f64),
+    Var(usize), // Index into the variable list given at parse time
+    Neg(Box<Node>),
+    Add(Box<Node>, Box<Node>),
+    Sub(Box<Node>, Box<Node>),
+    Mul(Box<Node>, Box<Node>),
+    Div(Box<Node>, Box<Node>),
+    Pow(Box<Node>, Box<Node>),
+    Call(Function, Box<Node>),
+}
+
+// A parsed expression together with its symbolic derivative
+pub struct Expression {
+    source: String,
+    value: Node,
+    derivative: Node,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "sinh" => Function::Sinh,
+            "cosh" => Function::Cosh,
+            "tanh" => Function::Tanh,
+            "exp" => Function::Exp,
+            "ln" | "log" => Function::Ln,
+            "sqrt" => Function::Sqrt,
+            "abs" => Function::Abs,
+            _ => return None,
+        })
+    }
+
+    fn apply(&self, u: f64) -> f64 {
+        match self {
+            Function::Sin => u.sin(),
+            Function::Cos => u.cos(),
+            Function::Tan => u.tan(),
+            Function::Sinh => u.sinh(),
+            Function::Cosh => u.cosh(),
+            Function::Tanh => u.tanh(),
+            Function::Exp => u.exp(),
+            Function::Ln => u.ln(),
+            Function::Sqrt => u.sqrt(),
+            Function::Abs => u.abs(),
+        }
+    }
+}
+
+// Constructors that fold constants as the tree is built, keeping derivatives compact
+fn neg(a: Node) -> Node {
+    match a {
+        Node::Const(c) => Node::Const(-c),
+        Node::Neg(inner) => *inner,
+        a => Node::Neg(Box::new(a)),
+    }
+}
+
+fn add(a: Node, b: Node) -> Node {
+    match (a, b) {
+        (Node::Const(x), Node::Const(y)) => Node::Const(x + y),
+        (Node::Const(0.0), other) | (other, Node::Const(0.0)) => other,
+        (a, b) => Node::Add(Box::new(a), Box::new(b)),
+    }
+}
+
+fn sub(a: Node, b: Node) -> Node {
+    match (a, b) {
+        (Node::Const(x), Node::Const(y)) => Node::Const(x - y),
+        (a, Node::Const(0.0)) => a,
+        (Node::Const(0.0), b) => neg(b),
+        (a, b) => Node::Sub(Box::new(a), Box::new(b)),
+    }
+}
+
+fn mul(a: Node, b: Node) -> Node {
+    match (a, b) {
+        (Node::Const(x), Node::Const(y)) => Node::Const(x * y),
+        (Node::Const(0.0), _) | (_, Node::Const(0.0)) => Node::Const(0.0),
+        (Node::Const(1.0), other) | (other, Node::Const(1.0)) => other,
+        (a, b) => Node::Mul(Box::new(a), Box::new(b)),
+    }
+}
+
+fn div(a: Node, b: Node) -> Node {
+    match (a, b) {
+        (Node::Const(x), Node::Const(y)) => Node::Const(x / y),
+        (Node::Const(0.0), _) => Node::Const(0.0),
+        (a, Node::Const(1.0)) => a,
+        (a, b) => Node::Div(Box::new(a), Box::new(b)),
+    }
+}
+
+fn pow(a: Node, b: Node) -> Node {
+    match (a, b) {
+        (Node::Const(x), Node::Const(y)) => Node::Const(x.powf(y)),
+        (a, Node::Const(1.0)) => a,
+        (_, Node::Const(0.0)) => Node::Const(1.0),
+        (a, b) => Node::Pow(Box::new(a), Box::new(b)),
+    }
+}
+
+fn call(f: Function, a: Node) -> Node {
+    match a {
+        Node::Const(c) => Node::Const(f.apply(c)),
+        a => Node::Call(f, Box::new(a)),
+    }
+}
+
+impl Node {
+    fn eval(&self, vars: &[f64]) -> f64 {
+        match self {
+            Node::Const(c) => *c,
+            Node::Var(i) => vars[*i],
+            Node::Neg(a) => -a.eval(vars),
+            Node::Add(a, b) => a.eval(vars) + b.eval(vars),
+            Node::Sub(a, b) => a.eval(vars) - b.eval(vars),
+            Node::Mul(a, b) => a.eval(vars) * b.eval(vars),
+            Node::Div(a, b) => a.eval(vars) / b.eval(vars),
+            Node::Pow(a, b) => {
+                let exponent = b.eval(vars);
+                if exponent == exponent.round() && exponent.abs() < 64.0 {
+                    a.eval(vars).powi(exponent as i32) // Keeps negative bases valid for integer powers
+                } else {
+                    a.eval(vars).powf(exponent)
+                }
+            }
+            Node::Call(f, a) => f.apply(a.eval(vars)),
+        }
+    }
+
+    // Symbolic derivative with respect to the first variable
+    fn derivative(&self) -> Node {
+        match self {
+            Node::Const(_) => Node::Const(0.0),
+            Node::Var(i) => Node::Const(if *i == 0 { 1.0 } else { 0.0 }),
+            Node::Neg(a) => neg(a.derivative()),
+            Node::Add(a, b) => add(a.derivative(), b.derivative()),
+            Node::Sub(a, b) => sub(a.derivative(), b.derivative()),
+            Node::Mul(a, b) => add(mul(a.derivative(), (**b).clone()), mul((**a).clone(), b.derivative())),
+            Node::Div(a, b) => div(
+                sub(mul(a.derivative(), (**b).clone()), mul((**a).clone(), b.derivative())),
+                pow((**b).clone(), Node::Const(2.0)),
+            ),
+            Node::Pow(a, b) => match **b {
+                Node::Const(c) => mul(mul(Node::Const(c), pow((**a).clone(), Node::Const(c - 1.0))), a.derivative()),
+                _ => mul(
+                    self.clone(),
+                    add(
+                        mul(b.derivative(), call(Function::Ln, (**a).clone())),
+                        div(mul((**b).clone(), a.derivative()), (**a).clone()),
+                    ),
+                ),
+            },
+            Node::Call(f, a) => {
+                let u = (**a).clone();
+                let outer = match f {
+                    Function::Sin => call(Function::Cos, u),
+                    Function::Cos => neg(call(Function::Sin, u)),
+                    Function::Tan => div(Node::Const(1.0), pow(call(Function::Cos, u), Node::Const(2.0))),
+                    Function::Sinh => call(Function::Cosh, u),
+                    Function::Cosh => call(Function::Sinh, u),
+                    Function::Tanh => sub(Node::Const(1.0), pow(call(Function::Tanh, u), Node::Const(2.0))),
+                    Function::Exp => call(Function::Exp, u),
+                    Function::Ln => div(Node::Const(1.0), u),
+                    Function::Sqrt => div(Node::Const(0.5), call(Function::Sqrt, u)),
+                    Function::Abs => div(u.clone(), call(Function::Abs, u)),
+                };
+                mul(outer, a.derivative())
+            }
+        }
+    }
+}
+
+// Recursive-descent parser over the expression text
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    variables: &'a [&'a str],
+    constants: &'a HashMap<String, f64>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expression: expected '{}' at position {}", c, self.pos))
+        }
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Node, String> {
+        let mut node = self.term()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.pos += 1;
+            let rhs = self.term()?;
+            node = if op == '+' { add(node, rhs) } else { sub(node, rhs) };
+        }
+        Ok(node)
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Node, String> {
+        let mut node = self.unary()?;
+        while let Some(op @ ('*' | '/')) = self.peek() {
+            self.pos += 1;
+            let rhs = self.unary()?;
+            node = if op == '*' { mul(node, rhs) } else { div(node, rhs) };
+        }
+        Ok(node)
+    }
+
+    // unary := '-' unary | power
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.peek() == Some('-') {
+            self.pos += 1;
+            return Ok(neg(self.unary()?));
+        }
+        self.power()
+    }
+
+    // power := atom ('^' unary)?, right associative
+    fn power(&mut self) -> Result<Node, String> {
+        let base = self.atom()?;
+        if self.peek() == Some('^') {
+            self.pos += 1;
+            return Ok(pow(base, self.unary()?));
+        }
+        Ok(base)
+    }
+
+    // atom := number | name | name '(' expr ')' | '(' expr ')'
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let node = self.expr()?;
+                self.expect(')')?;
+                Ok(node)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.pos;
+                let digits = |p: &mut Self| {
+                    while p.pos < p.chars.len() && (p.chars[p.pos].is_ascii_digit() || p.chars[p.pos] == '.') {
+                        p.pos += 1;
+                    }
+                };
+                digits(self);
+                if matches!(self.chars.get(self.pos), Some('e' | 'E')) {
+                    let mantissa_end = self.pos;
+                    self.pos += 1;
+                    if matches!(self.chars.get(self.pos), Some('+' | '-')) {
+                        self.pos += 1;
+                    }
+                    if self.chars.get(self.pos).is_some_and(|c| c.is_ascii_digit()) {
+                        digits(self);
+                    } else {
+                        self.pos = mantissa_end; // Not an exponent after all
+                    }
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                text.parse()
+                    .map(Node::Const)
+                    .map_err(|_| format!("expression: invalid number '{}'", text))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let start = self.pos;
+                while self.pos < self.chars.len() && (self.chars[self.pos].is_alphanumeric() || self.chars[self.pos] == '_') {
+                    self.pos += 1;
+                }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                if self.peek() == Some('(') {
+                    let f = Function::from_name(&name).ok_or_else(|| format!("expression: unknown function '{}'", name))?;
+                    self.pos += 1;
+                    let argument = self.expr()?;
+                    self.expect(')')?;
+                    return Ok(call(f, argument));
+                }
+                if let Some(i) = self.variables.iter().position(|v| *v == name) {
+                    return Ok(Node::Var(i));
+                }
+                match name.as_str() {
+                    "pi" => Ok(Node::Const(std::f64::consts::PI)),
+                    _ => self
+                        .constants
+                        .get(&name)
+                        .map(|&c| Node::Const(c))
+                        .ok_or_else(|| format!("expression: unknown name '{}'", name)),
+                }
+            }
+            Some(c) => Err(format!("expression: unexpected '{}' at position {}", c, self.pos)),
+            None => Err("expression: unexpected end of input".to_string()),
+        }
+    }
+}
+
+impl Expression {
+    // Parse an expression in the named variables; any other names must be
+    // supplied as constants
+    pub fn parse(source: &str, variables: &[&str], constants: &HashMap<String, f64>) -> Result<Self, String> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            pos: 0,
+            variables,
+            constants,
+        };
+        let value = parser.expr()?;
+        if parser.peek().is_some() {
+            return Err(format!("expression: trailing input at position {}", parser.pos));
+        }
+        let derivative = value.derivative();
+        Ok(Self {
+            source: source.to_string(),
+            value,
+            derivative,
+        })
+    }
+
+    // The text the expression was parsed from
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    // Value at the given variables, in the order they were named at parse time
+    pub fn eval(&self, vars: &[f64]) -> f64 {
+        self.value.eval(vars)
+    }
+
+    // Derivative with respect to the first variable
+    pub fn derivative(&self, vars: &[f64]) -> f64 {
+        self.derivative.eval(vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Expression {
+        let constants = HashMap::from([("k".to_string(), 2.0)]);
+        Expression::parse(source, &["x", "lambda"], &constants).unwrap_or_else(|e| panic!("{}: {}", source, e))
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        let cases = [
+            ("1 + 2 * 3", 7.0),
+            ("(1 + 2) * 3", 9.0),
+            ("2 * 3 ^ 2", 18.0),
+            ("8 - 3 - 2", 3.0),
+            ("12 / 3 / 2", 2.0),
+            ("2 ^ 3 ^ 2", 512.0),
+            ("1.5e1 + 2E-1", 15.2),
+            ("k * pi", 2.0 * std::f64::consts::PI),
+        ];
+        for (source, expected) in cases {
+            let value = parse(source).eval(&[0.0, 0.0]);
+            assert!((value - expected).abs() < 1e-12, "{} gave {}, expected {}", source, value, expected);
+        }
+    }
+
+    #[test]
+    fn unary_minus_binds_below_power() {
+        let cases = [
+            ("-2 ^ 2", -4.0),
+            ("(-2) ^ 2", 4.0),
+            ("2 ^ -1", 0.5),
+            ("3 * -x", -4.5),
+            ("--x", 1.5),
+            ("1 - -x", 2.5),
+        ];
+        for (source, expected) in cases {
+            let value = parse(source).eval(&[1.5, 0.0]);
+            assert!((value - expected).abs() < 1e-12, "{} gave {}, expected {}", source, value, expected);
+        }
+    }
+
+    #[test]
+    fn derivative_matches_closed_forms() {
+        let harmonic = parse("0.5 * k * (x - lambda)^2");
+        let product = parse("sin(x) * exp(-x / 2) + sqrt(x) ^ 3");
+        for &(x, lambda) in &[(0.3, -1.0), (1.7, 0.4), (2.5, 2.5)] {
+            let force = harmonic.derivative(&[x, lambda]);
+            assert!((force - 2.0 * (x - lambda)).abs() < 1e-12, "harmonic at {}: {}", x, force);
+            let expected = (x.cos() - 0.5 * x.sin()) * (-x / 2.0).exp() + 1.5 * x.sqrt();
+            let slope = product.derivative(&[x, lambda]);
+            assert!((slope - expected).abs() < 1e-12, "product at {}: {} vs {}", x, slope, expected);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let constants = HashMap::new();
+        for source in ["1 +", "(x", "x y", "foo(x)", "q + 1", "2 * / 3"] {
+            assert!(Expression::parse(source, &["x"], &constants).is_err(), "{} should not parse", source);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+// fdt.rs
+// Fluctuation-dissipation ratio of the particle dragged at constant speed, frequency by frequency
+
+use crate::config::Config;
+use crate::integrator::State;
+use crate::simulation::{self, TrapParams};
+use crate::unirand;
+use rayon::prelude::*;
+use std::f64::consts::PI;
+
+// Response and fluctuation sides of the fluctuation-dissipation theorem at one
+// angular frequency, for the displacement A = x - lambda(t) from the trap centre
+pub struct FdtPoint {
+    pub omega: f64,
+    pub in_phase: f64,    // chi'(omega), the reactive response
+    pub dissipative: f64, // chi''(omega), the measured dissipative response
+    pub fluctuation: f64, // beta omega int_0^inf C(t) cos(omega t) dt, which chi'' equals in equilibrium
+}
+
+impl FdtPoint {
+    // X(omega) = chi'' / (beta omega C(omega)), one in equilibrium
+    pub fn ratio(&self) -> f64 {
+        self.dissipative / self.fluctuation
+    }
+}
+
+// What one trajectory contributes: the projections of A onto cos and sin of
+// every frequency over whole periods after the transient, and A sampled every
+// stride steps for the correlation function
+struct Record {
+    projections: Vec<(f64, f64)>,
+    samples: Vec<f64>,
+}
+
+// Drive one trajectory, with the trap centre moving from lambda_start at
+// constant speed plus an optional amplitude sin(omega t) wiggle. Runs sharing
+// a stream see the same noise, so their difference is the response alone.
+fn drive(params: &TrapParams, start: State, config: &Config, windows: &[usize], wiggle: Option<(f64, f64)>) -> Record {
+    let speed = (config.lambda_end - config.lambda_start) / config.duration;
+    let transient = (config.fdt_transient / params.dt).round() as usize;
+    let steps = transient + (config.fdt_time / params.dt).round() as usize;
+    let trap = |t: f64| config.lambda_start + speed * t;
+    let mut state = start;
+    let mut record = Record {
+        projections: vec![(0.0, 0.0); config.fdt_frequencies.len()],
+        samples: Vec::new(),
+    };
+    for step in 1..=steps {
+        let t = step as f64 * params.dt;
+        let shift = wiggle.map_or(0.0, |(omega, amplitude)| amplitude * (omega * t).sin());
+        params.integrator.step(params, &mut state, params.fixed(trap(t) + shift));
+        if step <= transient {
+            continue;
+        }
+        let a = params.minimum_image(state.x[0] - trap(t));
+        for ((projection, &omega), &window) in record.projections.iter_mut().zip(&config.fdt_frequencies).zip(windows) {
+            if step <= transient + window {
+                projection.0 += a * (omega * t).cos() * params.dt;
+                projection.1 += a * (omega * t).sin() * params.dt;
+            }
+        }
+        if (step - transient).is_multiple_of(config.fdt_stride) {
+            record.samples.push(a);
+        }
+    }
+    record
+}
+
+// Stationary autocovariance of A at lags 0, 1, ... samples, averaged over time
+// origins in every trajectory, up to a quarter of the record length
+fn autocovariance(series: &[&[f64]]) -> Vec<f64> {
+    let count: usize = series.iter().map(|s| s.len()).sum();
+    let mean = series.iter().copied().flatten().sum::<f64>() / count as f64;
+    (0..=series[0].len() / 4)
+        .map(|lag| {
+            let (sum, pairs) = series.iter().fold((0.0, 0), |(sum, pairs), s| {
+                let products: f64 = (0..s.len() - lag).map(|i| (s[i] - mean) * (s[i + lag] - mean)).sum();
+                (sum + products, pairs + s.len() - lag)
+            });
+            sum / pairs as f64
+        })
+        .collect()
+}
+
+// Measure chi''(omega) and the correlation side of the theorem at every
+// configured frequency, in the steady state of a trap dragged at the mean
+// speed of the configured protocol. Each trajectory runs once unperturbed and
+// once per frequency with the trap wiggled by fdt_amplitude sin(omega t), all
+// on its own substream i of the seed. The wiggle acts as a force k eps
+// sin(omega t) on x, so chi'' follows from the cos component of the change in
+// A; the correlation function comes from the unperturbed runs.
+pub fn measure(config: &Config) -> Vec<FdtPoint> {
+    let params = config.trap_params();
+    let (beta, k) = (params.temperature.beta(), params.stiffness);
+
+    // Each frequency is projected over the whole periods that fit in fdt_time
+    let windows: Vec<usize> = config
+        .fdt_frequencies
+        .iter()
+        .map(|&omega| {
+            let period = 2.0 * PI / omega;
+            ((config.fdt_time / period).floor() * period / params.dt).round() as usize
+        })
+        .collect();
+
+    let runs: Vec<(Record, Vec<(f64, f64)>)> = (0..config.fdt_trajectories)
+        .into_par_iter()
+        .map(|i| {
+            unirand::seed_stream(config.seed, i);
+            let start = simulation::sample_equilibrium(&params, params.fixed(config.lambda_start));
+            let plain = drive(&params, start, config, &windows, None);
+            let responses = config
+                .fdt_frequencies
+                .iter()
+                .enumerate()
+                .map(|(f, &omega)| {
+                    unirand::seed_stream(config.seed, i);
+                    let start = simulation::sample_equilibrium(&params, params.fixed(config.lambda_start));
+                    let wiggled = drive(&params, start, config, &windows, Some((omega, config.fdt_amplitude)));
+                    (wiggled.projections[f].0 - plain.projections[f].0, wiggled.projections[f].1 - plain.projections[f].1)
+                })
+                .collect();
+            (plain, responses)
+        })
+        .collect();
+
+    let series: Vec<&[f64]> = runs.iter().map(|(plain, _)| plain.samples.as_slice()).collect();
+    let covariance = autocovariance(&series);
+    let spacing = config.fdt_stride as f64 * params.dt;
+    let n = config.fdt_trajectories as f64;
+
+    config
+        .fdt_frequencies
+        .iter()
+        .enumerate()
+        .map(|(f, &omega)| {
+            let (cos, sin) = runs.iter().fold((0.0, 0.0), |(c, s), (_, r)| (c + r[f].0 / n, s + r[f].1 / n));
+            let scale = 2.0 / (k * config.fdt_amplitude * windows[f] as f64 * params.dt);
+            // Trapezoid rule for int_0^inf C(t) cos(omega t) dt over the measured lags
+            let last = covariance.len() - 1;
+            let transform: f64 = covariance
+                .iter()
+                .enumerate()
+                .map(|(lag, c)| {
+                    let weight = if lag == 0 || lag == last { 0.5 } else { 1.0 };
+                    weight * c * (omega * lag as f64 * spacing).cos() * spacing
+                })
+                .sum();
+            FdtPoint {
+                omega,
+                in_phase: scale * sin,
+                dissipative: -scale * cos,
+                fluctuation: beta * omega * transform,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,79 @@
+// feedback.rs
+// Measurement and feedback (Maxwell demon) protocols and the Sagawa-Ueda equality
+
+use crate::estimators::log_sum_exp;
+use crate::protocol::Protocol;
+use crate::simulation::{self, gaussian, Control, TrapParams};
+use crate::unirand;
+use rayon::prelude::*;
+
+// Outcome of one trajectory driven with feedback
+pub struct FeedbackTrajectory {
+    pub work: f64,        // Work including the jump of the trap after the measurement
+    pub delta_f: f64,     // F(lambda_final) - F(lambda_start) of the protocol actually run
+    pub information: f64, // Mutual information ln p(y|x) / p(y) gained by the measurement
+}
+
+// Measure the particle's position x0 at the start with Gaussian error, getting
+// y = x0 + error xi, then jump the trap by gain (y - lambda_0) and run the
+// configured protocol shifted by that offset. The final trap position depends
+// on y, so each trajectory has its own Delta F(y), found by quadrature, and
+// Sagawa & Ueda (PRL 2010) give <exp(-beta (W - Delta F) - I)> = 1 with
+//   I = ln p(y | x0) - ln p(y),   p(y) = integral of p_eq(x) p(y | x) dx
+pub fn run(
+    params: &TrapParams,
+    protocol: &Protocol,
+    error: f64,
+    gain: f64,
+    trajectories: usize,
+    seed: i32,
+) -> Vec<FeedbackTrajectory> {
+    let beta = params.temperature.beta();
+    let start = params.control(protocol, 0.0);
+    let start_free_energy = simulation::free_energy(params, start);
+
+    // Normalised equilibrium weights on the grid, for the marginal p(y)
+    let (lo, step, energies) = simulation::tabulate(params, start);
+    let floor = energies.iter().cloned().fold(f64::INFINITY, f64::min);
+    let ln_norm = log_sum_exp(energies.iter().map(|e| -(e - floor) * beta));
+    let ln_weights: Vec<(f64, f64)> = energies
+        .iter()
+        .enumerate()
+        .map(|(i, e)| (lo + i as f64 * step, -(e - floor) * beta - ln_norm))
+        .collect();
+    let ln_likelihood = |y: f64, x: f64| -(y - x) * (y - x) / (2.0 * error * error);
+
+    (0..trajectories)
+        .into_par_iter()
+        .map(|i| {
+            unirand::seed_stream(seed, i);
+            let mut state = simulation::sample_equilibrium(params, start);
+            let x0 = state.x[0];
+            let y = x0 + error * gaussian();
+            let offset = gain * (y - start.centre);
+            let shifted = |t: f64| {
+                let control = params.control(protocol, t);
+                Control {
+                    centre: control.centre + offset,
+                    ..control
+                }
+            };
+
+            let mut control = shifted(0.0);
+            let mut work = params.potential(&state.x, control) - params.potential(&state.x, start);
+            for step in 1..=params.steps(protocol) {
+                let next = shifted(step as f64 * params.dt);
+                work += params.potential(&state.x, next) - params.potential(&state.x, control);
+                control = next;
+                params.integrator.step(params, &mut state, control);
+            }
+
+            let ln_marginal = log_sum_exp(ln_weights.iter().map(|&(x, ln_w)| ln_w + ln_likelihood(y, x)));
+            FeedbackTrajectory {
+                work,
+                delta_f: simulation::free_energy(params, control) - start_free_energy,
+                information: ln_likelihood(y, x0) - ln_marginal,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,221 @@
+// field.wgsl
+// The animated field evaluated and coloured on the GPU, one invocation per
+// pixel, mirroring Scene::render on the CPU in single precision
+
+struct Params {
+    width: u32,
+    height: u32,
+    terms: u32,
+    split: u32,
+    frame: u32,
+    key0: u32,
+    key1: u32,
+    colormap: u32, // 0 polynomial fit, 1 grayscale, 2 noise
+    scale: u32,    // 0 wave, 1 auto, 2 fixed, 3 log, 4 symlog
+    pad0: u32,
+    pad1: u32,
+    pad2: u32,
+    time: f32,
+    coefficient: f32,
+    exponent: f32,
+    wave_factor: f32,
+    centre_x: f32,
+    centre_y: f32,
+    zoom: f32,
+    fixed_low: f32,
+    fixed_high: f32,
+    symlog_threshold: f32,
+    pad4: f32,
+    pad5: f32,
+    polynomial: array<vec4<f32>, 7>,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read_write> field: array<f32>;
+@group(0) @binding(2) var<storage, read_write> bounds: array<atomic<u32>, 3>; // min, max, min positive
+@group(0) @binding(3) var<storage, read_write> pixels: array<u32>;
+
+// Invocations per workgroup; rows of workgroups beyond the first carry on the
+// pixel count where one row would pass the dispatch limit
+const WORKGROUP_SIZE: u32 = 256u;
+
+const PI: f32 = 3.14159265358979;
+
+// Size relative to the largest term below which the series terms are dropped,
+// about the single-precision epsilon
+const SERIES_EPSILON: f32 = 6e-8;
+
+// Smallest share of the largest value the log scale reaches down to, twelve
+// decades as on the CPU
+const LOG_FLOOR: f32 = 1e-12;
+
+// Philox4x32 round multipliers and key increments
+const PHILOX_MULTIPLIERS = vec2<u32>(0xd2511f53u, 0xcd9e8d57u);
+const PHILOX_WEYL = vec2<u32>(0x9e3779b9u, 0xbb67ae85u);
+
+// x to a power, whole powers of a negative x keeping their sign as powf does
+fn signed_pow(x: f32, exponent: f32) -> f32 {
+    let magnitude = pow(abs(x), exponent);
+    if x < 0.0 && exponent == floor(exponent) && (i32(exponent) & 1) == 1 {
+        return -magnitude;
+    }
+    return magnitude;
+}
+
+// The Crooks series at one argument, summed with compensation until the terms
+// fall below SERIES_EPSILON of the largest so far, as on the CPU
+fn series(argument: f32) -> f32 {
+    var sum = 0.0;
+    var compensation = 0.0;
+    var largest = 0.0;
+    for (var i = 1u; i <= params.terms; i++) {
+        let a = 2.0 * PI * f32(i) + argument;
+        let term = signed_pow(params.coefficient * sin(a) / cosh(a), params.exponent);
+        largest = max(largest, abs(term));
+        if !(abs(term) > SERIES_EPSILON * largest) {
+            break;
+        }
+        let y = term - compensation;
+        let t = sum + y;
+        compensation = (t - sum) - y;
+        sum = t;
+    }
+    return sum;
+}
+
+// Screen column of the forward half a column is drawn from, the right half of
+// the split layout mirrored about the dividing line
+fn unmirror(x: u32) -> u32 {
+    if params.split != 0u && x >= params.width / 2u {
+        return params.width - 1u - x;
+    }
+    return x;
+}
+
+// Floats as unsigned integers in the same order, for atomic minimum and maximum
+fn ordered(v: f32) -> u32 {
+    let bits = bitcast<u32>(v);
+    if (bits & 0x80000000u) != 0u {
+        return ~bits;
+    }
+    return bits | 0x80000000u;
+}
+
+fn unordered(bits: u32) -> f32 {
+    if (bits & 0x80000000u) != 0u {
+        return bitcast<f32>(bits & 0x7fffffffu);
+    }
+    return bitcast<f32>(~bits);
+}
+
+// Sign-preserving logarithm of the symlog scale, linear near zero
+fn symlog(v: f32) -> f32 {
+    return sign(v) * log(1.0 + abs(v) / params.symlog_threshold);
+}
+
+// High word of the 64-bit product of two words, from their 16-bit halves
+fn mul_hi(a: u32, b: u32) -> u32 {
+    let a_lo = a & 0xffffu;
+    let a_hi = a >> 16u;
+    let b_lo = b & 0xffffu;
+    let b_hi = b >> 16u;
+    let lo_lo = a_lo * b_lo;
+    let hi_lo = a_hi * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let cross = (lo_lo >> 16u) + (hi_lo & 0xffffu) + (lo_hi & 0xffffu);
+    return a_hi * b_hi + (hi_lo >> 16u) + (lo_hi >> 16u) + (cross >> 16u);
+}
+
+// Ten Philox rounds of the counter under the seed's key
+fn philox(counter: vec4<u32>) -> vec4<u32> {
+    var c = counter;
+    var k = vec2<u32>(params.key0, params.key1);
+    for (var round = 0u; round < 10u; round++) {
+        if round > 0u {
+            k += PHILOX_WEYL;
+        }
+        let hi0 = mul_hi(PHILOX_MULTIPLIERS.x, c.x);
+        let hi1 = mul_hi(PHILOX_MULTIPLIERS.y, c.z);
+        c = vec4<u32>(hi1 ^ c.y ^ k.x, PHILOX_MULTIPLIERS.y * c.z, hi0 ^ c.w ^ k.y, PHILOX_MULTIPLIERS.x * c.x);
+    }
+    return c;
+}
+
+// First pass: the field at every pixel, and its range for the auto scale
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn evaluate(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {
+    let i = id.x + id.y * groups.x * WORKGROUP_SIZE;
+    if i >= params.width * params.height {
+        return;
+    }
+    let x = i % params.width;
+    let y = i / params.width;
+    let u = params.centre_x + (f32(unmirror(x)) - f32(params.width) / 2.0) / params.zoom;
+    let v = params.centre_y + (f32(y) - f32(params.height) / 2.0) / params.zoom;
+    var argument = params.time + u / 100.0 + v / 100.0;
+    if params.split != 0u && x >= params.width / 2u {
+        argument = -argument;
+    }
+    let value = series(argument);
+    field[i] = value;
+    if value == value {
+        atomicMin(&bounds[0], ordered(value));
+        atomicMax(&bounds[1], ordered(value));
+        if value > 0.0 {
+            atomicMin(&bounds[2], ordered(value));
+        }
+    }
+}
+
+// Second pass: every value into [0, 1] by the colour scale and through the colormap
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn colour(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {
+    let i = id.x + id.y * groups.x * WORKGROUP_SIZE;
+    if i >= params.width * params.height {
+        return;
+    }
+    let value = field[i];
+    var t = 0.5;
+    if params.scale == 0u {
+        t = sin(value * params.wave_factor) * 0.5 + 0.5;
+    } else {
+        var low = params.fixed_low;
+        var high = params.fixed_high;
+        if params.scale != 2u {
+            low = unordered(atomicLoad(&bounds[0]));
+            high = unordered(atomicLoad(&bounds[1]));
+        }
+        if params.scale == 3u {
+            low = max(unordered(atomicLoad(&bounds[2])), high * LOG_FLOOR);
+        }
+        if high > low {
+            if params.scale == 3u {
+                t = 0.0;
+                if value > low {
+                    t = clamp(log(value / low) / log(high / low), 0.0, 1.0);
+                }
+            } else if params.scale == 4u {
+                t = clamp((symlog(value) - symlog(low)) / (symlog(high) - symlog(low)), 0.0, 1.0);
+            } else {
+                t = clamp((value - low) / (high - low), 0.0, 1.0);
+            }
+        }
+    }
+    t = clamp(t, 0.0, 1.0);
+
+    var channels: vec3<f32>;
+    if params.colormap == 0u {
+        channels = vec3<f32>(0.0);
+        for (var j = 6; j >= 0; j--) {
+            channels = channels * t + params.polynomial[j].xyz;
+        }
+    } else if params.colormap == 1u {
+        channels = vec3<f32>(t);
+    } else {
+        let words = philox(vec4<u32>(i % params.width, i / params.width, params.frame, 0u));
+        let noise = vec3<f32>(words.xyz) / 4294967296.0;
+        channels = vec3<f32>(t, 1.0 - t, (0.5 - abs(t - 0.5)) * 2.0) * noise;
+    }
+    let rgb = vec3<u32>(clamp(channels, vec3<f32>(0.0), vec3<f32>(1.0)) * 255.0);
+    pixels[i] = (rgb.x << 16u) | (rgb.y << 8u) | rgb.z;
+}
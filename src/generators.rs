@@ -0,0 +1,234 @@
+// generators.rs
+// Alternative uniform generators behind the UniformRng trait, to compare how
+// images and statistics depend on the generator
+
+use crate::unirand::{GaussianMethod, UniformRng};
+
+// Increment of the SplitMix64 sequence, 2^64 over the golden ratio
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// Next output of a SplitMix64 sequence (Steele, Lea and Flood 2014), used to
+// spread a seed over a generator's state
+pub fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(GOLDEN_GAMMA);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// 64-bit key of substream `stream` of a key, the two mixed so neighbouring
+// streams get unrelated keys
+fn substream_key(key: u64, stream: u64) -> u64 {
+    let mut state = key ^ stream.wrapping_add(1).wrapping_mul(GOLDEN_GAMMA);
+    splitmix64(&mut state)
+}
+
+// Uniform in [0, 1) from the top 53 bits of a 64-bit word
+fn unit(bits: u64) -> f64 {
+    (bits >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+}
+
+// xoshiro256++ (Blackman and Vigna 2019): 256 bits of state, period 2^256 - 1
+pub struct Xoshiro256 {
+    state: [u64; 4],
+    gaussian: GaussianMethod,
+    key: u64, // Key the state was spread from, from which substreams split
+}
+
+impl Xoshiro256 {
+    pub fn new(seed: i32, gaussian: GaussianMethod) -> Self {
+        Self::from_key(seed as u64, gaussian)
+    }
+
+    fn from_key(key: u64, gaussian: GaussianMethod) -> Self {
+        let mut sequence = key;
+        let state = [(); 4].map(|_| splitmix64(&mut sequence));
+        Self { state, gaussian, key }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[0].wrapping_add(s[3]).rotate_left(23).wrapping_add(s[0]);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+}
+
+impl UniformRng for Xoshiro256 {
+    fn uniform(&mut self) -> f64 {
+        unit(self.next_u64())
+    }
+
+    fn gaussian_method(&self) -> GaussianMethod {
+        self.gaussian
+    }
+
+    fn split(&self, stream: u64) -> Box<dyn UniformRng> {
+        Box::new(Self::from_key(substream_key(self.key, stream), self.gaussian))
+    }
+}
+
+// Multiplier of the 128-bit PCG LCG
+const PCG_MULTIPLIER: u128 = 0x2360_ed05_1fc6_5da4_4385_df64_9fcc_f645;
+
+// PCG64 (O'Neill 2014): a 128-bit LCG whose high and low halves are folded
+// and rotated by its top bits (XSL-RR). Every odd increment gives a distinct
+// sequence, so substreams share the seed and differ in the increment.
+pub struct Pcg64 {
+    state: u128,
+    increment: u128,
+    gaussian: GaussianMethod,
+    key: u64, // Key the state was set from, shared by every substream
+}
+
+impl Pcg64 {
+    pub fn new(seed: i32, gaussian: GaussianMethod) -> Self {
+        let mut sequence = seed as u64;
+        let key = splitmix64(&mut sequence);
+        Self::with_increment(key, splitmix64(&mut sequence), gaussian)
+    }
+
+    // O'Neill's seeding: step once from zero, add the initial state, step again
+    fn with_increment(key: u64, sequence: u64, gaussian: GaussianMethod) -> Self {
+        let mut rng = Self {
+            state: 0,
+            increment: ((sequence as u128) << 1) | 1,
+            gaussian,
+            key,
+        };
+        rng.step();
+        rng.state = rng.state.wrapping_add(key as u128);
+        rng.step();
+        rng
+    }
+
+    fn step(&mut self) {
+        self.state = self.state.wrapping_mul(PCG_MULTIPLIER).wrapping_add(self.increment);
+    }
+
+    // Step, then fold and rotate the new state
+    fn next_u64(&mut self) -> u64 {
+        self.step();
+        (((self.state >> 64) as u64) ^ self.state as u64).rotate_right((self.state >> 122) as u32)
+    }
+}
+
+impl UniformRng for Pcg64 {
+    fn uniform(&mut self) -> f64 {
+        unit(self.next_u64())
+    }
+
+    fn gaussian_method(&self) -> GaussianMethod {
+        self.gaussian
+    }
+
+    fn split(&self, stream: u64) -> Box<dyn UniformRng> {
+        Box::new(Self::with_increment(self.key, stream, self.gaussian))
+    }
+}
+
+// Philox4x32 round multipliers and key increments (Salmon et al. 2011)
+const PHILOX_MULTIPLIERS: [u32; 2] = [0xd251_1f53, 0xcd9e_8d57];
+const PHILOX_WEYL: [u32; 2] = [0x9e37_79b9, 0xbb67_ae85];
+const PHILOX_ROUNDS: usize = 10;
+
+// Philox4x32-10 (Salmon, Moraes, Dror and Shaw 2011): a keyed bijection of a
+// 128-bit counter, each block of four words the encryption of the next count.
+// The low half of the counter counts blocks and the high half names the
+// substream; substreams take a key apart from their root's.
+pub struct Philox4x32 {
+    key: [u32; 2],
+    block: u64,
+    stream: u64,
+    output: [u32; 4], // Words of the current block
+    used: usize,      // Words of the current block already drawn
+    gaussian: GaussianMethod,
+}
+
+// Ten Philox rounds: the block of four words a counter encrypts to under a key
+fn philox_block(key: [u32; 2], counter: [u32; 4]) -> [u32; 4] {
+    let (mut c, mut k) = (counter, key);
+    for round in 0..PHILOX_ROUNDS {
+        if round > 0 {
+            k[0] = k[0].wrapping_add(PHILOX_WEYL[0]);
+            k[1] = k[1].wrapping_add(PHILOX_WEYL[1]);
+        }
+        let p0 = PHILOX_MULTIPLIERS[0] as u64 * c[0] as u64;
+        let p1 = PHILOX_MULTIPLIERS[1] as u64 * c[2] as u64;
+        c = [(p1 >> 32) as u32 ^ c[1] ^ k[0], p1 as u32, (p0 >> 32) as u32 ^ c[3] ^ k[1], p0 as u32];
+    }
+    c
+}
+
+// Four uniforms in [0, 1), 32 random bits each, hashed statelessly from a seed
+// and a counter such as (x, y, frame, 0): the same inputs give the same
+// numbers whichever thread asks and in whatever order
+pub fn counter_uniforms(seed: i32, counter: [u32; 4]) -> [f64; 4] {
+    philox_block(counter_key(seed), counter).map(|word| word as f64 * (1.0 / 4_294_967_296.0))
+}
+
+// Philox key the counter-based uniforms of a seed are drawn under
+pub fn counter_key(seed: i32) -> [u32; 2] {
+    let mut sequence = seed as u64;
+    let key = splitmix64(&mut sequence);
+    [key as u32, (key >> 32) as u32]
+}
+
+impl Philox4x32 {
+    pub fn new(seed: i32, gaussian: GaussianMethod) -> Self {
+        let mut sequence = seed as u64;
+        Self::with_key(splitmix64(&mut sequence), 0, gaussian)
+    }
+
+    fn with_key(key: u64, stream: u64, gaussian: GaussianMethod) -> Self {
+        Self {
+            key: [key as u32, (key >> 32) as u32],
+            block: 0,
+            stream,
+            output: [0; 4],
+            used: 4,
+            gaussian,
+        }
+    }
+
+    fn key64(&self) -> u64 {
+        ((self.key[1] as u64) << 32) | self.key[0] as u64
+    }
+
+    // Encrypt the counter of the next block under the key
+    fn refill(&mut self) {
+        let counter = [self.block as u32, (self.block >> 32) as u32, self.stream as u32, (self.stream >> 32) as u32];
+        self.output = philox_block(self.key, counter);
+        self.used = 0;
+        self.block = self.block.wrapping_add(1);
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        if self.used == 4 {
+            self.refill();
+        }
+        self.used += 1;
+        self.output[self.used - 1]
+    }
+}
+
+impl UniformRng for Philox4x32 {
+    fn uniform(&mut self) -> f64 {
+        unit(((self.next_u32() as u64) << 32) | self.next_u32() as u64)
+    }
+
+    fn gaussian_method(&self) -> GaussianMethod {
+        self.gaussian
+    }
+
+    fn split(&self, stream: u64) -> Box<dyn UniformRng> {
+        Box::new(Self::with_key(substream_key(self.key64(), 0), stream, self.gaussian))
+    }
+}
@@ -0,0 +1,343 @@
+// gpu.rs
+// The gpu backend of the animation: the field evaluated and coloured by the
+// compute shader in field.wgsl, the frame read back for the window
+
+use crate::colormap::{Bounds, ColourScale, Colormap};
+
+// Invocations per workgroup of the shader, and workgroups per dispatch row
+const WORKGROUP_SIZE: usize = 256;
+const MAX_GROUPS: usize = 65_535;
+
+// Bytes of the shader's Params: twelve words, twelve floats and seven vec4s
+const PARAMS_SIZE: u64 = (12 + 12 + 7 * 4) * 4;
+
+// Everything one frame of the shader depends on
+pub struct FieldParams {
+    pub width: usize,
+    pub height: usize,
+    pub terms: u32,
+    pub split: bool,
+    pub frame: u32,
+    pub key: [u32; 2], // Philox key of the noise map
+    pub colormap: Colormap,
+    pub colour_scale: ColourScale,
+    pub time: f64,
+    pub coefficient: f64,
+    pub exponent: f64,
+    pub wave_factor: f64,
+    pub centre: (f64, f64),
+    pub zoom: f64,
+    pub fixed: (f64, f64),
+    pub symlog_threshold: f64,
+}
+
+impl FieldParams {
+    // The uniform block in the layout of the shader's Params
+    fn bytes(&self) -> Vec<u8> {
+        let (colormap, polynomial) = match (self.colormap, self.colormap.polynomial()) {
+            (_, Some(polynomial)) => (0, *polynomial),
+            (Colormap::Grayscale, None) => (1, [[0.0; 3]; 7]),
+            _ => (2, [[0.0; 3]; 7]),
+        };
+        let scale = match self.colour_scale {
+            ColourScale::Wave => 0,
+            ColourScale::Auto => 1,
+            ColourScale::Fixed => 2,
+            ColourScale::Log => 3,
+            ColourScale::Symlog => 4,
+            ColourScale::Percentile | ColourScale::Equalise => unreachable!("the percentile and equalising scales are rendered on the CPU"),
+        };
+        let words = [
+            self.width as u32,
+            self.height as u32,
+            self.terms,
+            self.split as u32,
+            self.frame,
+            self.key[0],
+            self.key[1],
+            colormap,
+            scale,
+            0,
+            0,
+            0,
+        ];
+        let floats = [
+            self.time,
+            self.coefficient,
+            self.exponent,
+            self.wave_factor,
+            self.centre.0,
+            self.centre.1,
+            self.zoom,
+            self.fixed.0,
+            self.fixed.1,
+            self.symlog_threshold,
+            0.0,
+            0.0,
+        ];
+        let mut bytes = Vec::with_capacity(PARAMS_SIZE as usize);
+        bytes.extend(words.iter().flat_map(|word| word.to_le_bytes()));
+        bytes.extend(floats.iter().flat_map(|&value| (value as f32).to_le_bytes()));
+        for row in polynomial {
+            bytes.extend([row[0], row[1], row[2], 0.0].iter().flat_map(|&c| (c as f32).to_le_bytes()));
+        }
+        bytes
+    }
+}
+
+// A frame read back from the shader: its 0RGB pixels, row by row, the
+// bounds of its field, and the field itself if asked for
+pub struct Frame {
+    pub pixels: Vec<u32>,
+    pub bounds: Bounds,
+    pub field: Option<Vec<f32>>,
+}
+
+// Buffers sized for one frame
+struct FrameBuffers {
+    pixels: usize,
+    field: wgpu::Buffer,
+    field_readback: wgpu::Buffer,
+    output: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+// The device and the two passes of the shader: evaluate, then colour
+pub struct GpuField {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    layout: wgpu::BindGroupLayout,
+    evaluate: wgpu::ComputePipeline,
+    colour: wgpu::ComputePipeline,
+    params: wgpu::Buffer,
+    bounds: wgpu::Buffer,
+    bounds_readback: wgpu::Buffer,
+    frame: Option<FrameBuffers>,
+}
+
+impl GpuField {
+    // Open the first adapter wgpu offers and build the shader's pipelines
+    pub fn new() -> Result<Self, String> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        }))
+        .map_err(|e| format!("gpu: no adapter: {}", e))?;
+        let info = adapter.get_info();
+        println!("GPU: {} ({:?})", info.name, info.backend);
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("crooks"),
+            ..Default::default()
+        }))
+        .map_err(|e| format!("gpu: no device: {}", e))?;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("field"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("field.wgsl").into()),
+        });
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty,
+            count: None,
+        };
+        let storage = wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("field"),
+            entries: &[
+                entry(
+                    0,
+                    wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                ),
+                entry(1, storage),
+                entry(2, storage),
+                entry(3, storage),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("field"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        let (evaluate, colour) = (pipeline("evaluate"), pipeline("colour"));
+
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("params"),
+            size: PARAMS_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bounds = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bounds"),
+            size: 12,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bounds_readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bounds readback"),
+            size: 12,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Ok(Self {
+            device,
+            queue,
+            layout,
+            evaluate,
+            colour,
+            params,
+            bounds,
+            bounds_readback,
+            frame: None,
+        })
+    }
+
+    // Buffers for frames of the given number of pixels, made anew when it changes
+    fn prepare(&mut self, pixels: usize) {
+        if self.frame.as_ref().is_none_or(|frame| frame.pixels != pixels) {
+            let size = 4 * pixels as u64;
+            let buffer = |label, usage| {
+                self.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(label),
+                    size,
+                    usage,
+                    mapped_at_creation: false,
+                })
+            };
+            let field = buffer("field", wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC);
+            let field_readback = buffer("field readback", wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST);
+            let output = buffer("pixels", wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC);
+            let readback = buffer("readback", wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST);
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("field"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: self.params.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: field.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.bounds.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: output.as_entire_binding(),
+                    },
+                ],
+            });
+            self.frame = Some(FrameBuffers {
+                pixels,
+                field,
+                field_readback,
+                output,
+                readback,
+                bind_group,
+            });
+        }
+    }
+
+    // Run both passes over a frame and read it back
+    pub fn render(&mut self, params: &FieldParams, with_field: bool) -> Result<Frame, String> {
+        let pixels = params.width * params.height;
+        self.queue.write_buffer(&self.params, 0, &params.bytes());
+        // Empty range for the frame: smallest and smallest positive above
+        // everything, largest below
+        let empty = [u32::MAX, 0, u32::MAX];
+        self.queue.write_buffer(&self.bounds, 0, &empty.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<u8>>());
+        let groups = pixels.div_ceil(WORKGROUP_SIZE);
+        let (columns, rows) = (groups.min(MAX_GROUPS) as u32, groups.div_ceil(MAX_GROUPS) as u32);
+
+        self.prepare(pixels);
+        let frame = self.frame.as_ref().expect("frame buffers were just prepared");
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("field") });
+        for pipeline in [&self.evaluate, &self.colour] {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &frame.bind_group, &[]);
+            pass.dispatch_workgroups(columns, rows, 1);
+        }
+        encoder.copy_buffer_to_buffer(&frame.output, 0, &frame.readback, 0, 4 * pixels as u64);
+        encoder.copy_buffer_to_buffer(&self.bounds, 0, &self.bounds_readback, 0, 12);
+        if with_field {
+            encoder.copy_buffer_to_buffer(&frame.field, 0, &frame.field_readback, 0, 4 * pixels as u64);
+        }
+        self.queue.submit([encoder.finish()]);
+
+        let (slice, bounds_slice) = (frame.readback.slice(..), self.bounds_readback.slice(..));
+        let field_slice = with_field.then(|| frame.field_readback.slice(..));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        for readback in [slice, bounds_slice].into_iter().chain(field_slice) {
+            let sender = sender.clone();
+            readback.map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        }
+        self.device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .map_err(|e| format!("gpu: {}", e))?;
+        for _ in 0..2 + with_field as usize {
+            receiver
+                .recv()
+                .map_err(|e| format!("gpu: {}", e))?
+                .map_err(|e| format!("gpu: cannot read the frame back: {}", e))?;
+        }
+        let words = |slice: wgpu::BufferSlice| -> Vec<u32> {
+            slice
+                .get_mapped_range()
+                .chunks_exact(4)
+                .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+                .collect()
+        };
+        let (pixels, bounds) = (words(slice), words(bounds_slice));
+        let field = field_slice.map(|slice| words(slice).into_iter().map(f32::from_bits).collect());
+        frame.readback.unmap();
+        self.bounds_readback.unmap();
+        if with_field {
+            frame.field_readback.unmap();
+        }
+        // No positive value leaves the smallest of them at NaN, here infinite as
+        // on the CPU
+        let min_positive = unordered(bounds[2]) as f64;
+        let bounds = Bounds {
+            min: unordered(bounds[0]) as f64,
+            max: unordered(bounds[1]) as f64,
+            min_positive: if min_positive.is_nan() { f64::INFINITY } else { min_positive },
+        };
+        Ok(Frame { pixels, bounds, field })
+    }
+}
+
+// A float from the order-preserving unsigned form the shader's atomics keep
+// the range in
+fn unordered(bits: u32) -> f32 {
+    if bits & 0x8000_0000 != 0 {
+        f32::from_bits(bits & 0x7fff_ffff)
+    } else {
+        f32::from_bits(!bits)
+    }
+}
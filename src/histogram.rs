@@ -0,0 +1,143 @@
+// histogram.rs
+// Fixed-grid histograms of work samples, mergeable across rayon workers
+
+use rayon::prelude::*;
+
+// How bin counts are turned into heights
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Normalization {
+    Counts,      // Raw counts per bin
+    Probability, // Fraction of samples per bin, summing to one
+    Density,     // Probability per unit width, integrating to one
+}
+
+// Equal-width bins over [min, min + bins * width)
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    pub min: f64,
+    pub width: f64,
+    pub counts: Vec<u64>,
+    pub total: u64,     // Samples offered, including those outside the range
+    pub periodic: bool, // Whether values outside the range wrap around it
+}
+
+impl Histogram {
+    // Empty histogram with a given bin count spanning [min, max)
+    pub fn with_range(min: f64, max: f64, bins: usize) -> Self {
+        Self::with_width(min, (max - min) / bins as f64, bins)
+    }
+
+    // Empty histogram with a given bin width starting at min
+    pub fn with_width(min: f64, width: f64, bins: usize) -> Self {
+        Self {
+            min,
+            width,
+            counts: vec![0; bins],
+            total: 0,
+            periodic: false,
+        }
+    }
+
+    // Empty histogram over one period [min, max), wrapping every value into it
+    pub fn periodic(min: f64, max: f64, bins: usize) -> Self {
+        Self {
+            periodic: true,
+            ..Self::with_range(min, max, bins)
+        }
+    }
+
+    // Empty grid for particle positions: the whole periodic box [-L/2, L/2)
+    // when there is one, otherwise the range covering every sample
+    pub fn for_positions<'a>(sets: impl IntoIterator<Item = &'a [f64]>, box_length: Option<f64>, bins: usize) -> Self {
+        match box_length {
+            Some(length) => Self::periodic(-0.5 * length, 0.5 * length, bins),
+            None => {
+                let (min, max) = Self::auto_range(sets);
+                Self::with_range(min, max, bins)
+            }
+        }
+    }
+
+    // Range covering every sample in the given sets, widened a hair so the
+    // maximum lands inside the last bin rather than on its upper edge
+    pub fn auto_range<'a>(sets: impl IntoIterator<Item = &'a [f64]>) -> (f64, f64) {
+        let (min, max) = sets
+            .into_iter()
+            .flatten()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        let span = if max > min { max - min } else { min.abs().max(1.0) * 1e-9 };
+        (min, min + span * (1.0 + 1e-9))
+    }
+
+    // Histogram of the samples on the grid [min, max), filled in parallel with
+    // one partial histogram per rayon worker merged at the end
+    pub fn build(samples: &[f64], min: f64, max: f64, bins: usize) -> Self {
+        samples
+            .par_iter()
+            .fold(
+                || Self::with_range(min, max, bins),
+                |mut histogram, &v| {
+                    histogram.add(v);
+                    histogram
+                },
+            )
+            .reduce(|| Self::with_range(min, max, bins), Histogram::merge)
+    }
+
+    // Upper edge of the last bin
+    pub fn max(&self) -> f64 {
+        self.min + self.width * self.counts.len() as f64
+    }
+
+    // Centre of bin i
+    pub fn centre(&self, i: usize) -> f64 {
+        self.min + (i as f64 + 0.5) * self.width
+    }
+
+    // Bin index of a value, or None if it falls outside a non-periodic range
+    pub fn bin_of(&self, value: f64) -> Option<usize> {
+        let offset = value - self.min;
+        let offset = if self.periodic { offset.rem_euclid(self.max() - self.min) } else { offset };
+        let bin = (offset / self.width).floor();
+        if bin >= 0.0 && (bin as usize) < self.counts.len() {
+            Some(bin as usize)
+        } else {
+            None
+        }
+    }
+
+    // Add one sample
+    pub fn add(&mut self, value: f64) {
+        self.total += 1;
+        if let Some(bin) = self.bin_of(value) {
+            self.counts[bin] += 1;
+        }
+    }
+
+    // Combine with a histogram built on the same grid, e.g. by another rayon worker.
+    // Usable directly as the reduce step of `fold(...).reduce(...)`.
+    pub fn merge(mut self, other: Histogram) -> Histogram {
+        assert!(
+            self.counts.len() == other.counts.len()
+                && self.min == other.min
+                && self.width == other.width
+                && self.periodic == other.periodic,
+            "merge: histograms must share the same grid"
+        );
+        for (a, b) in self.counts.iter_mut().zip(&other.counts) {
+            *a += b;
+        }
+        self.total += other.total;
+        self
+    }
+
+    // Bin heights under the requested normalization
+    pub fn heights(&self, normalization: Normalization) -> Vec<f64> {
+        let scale = match normalization {
+            Normalization::Counts => 1.0,
+            Normalization::Probability => 1.0 / self.total as f64,
+            Normalization::Density => 1.0 / (self.total as f64 * self.width),
+        };
+        self.counts.iter().map(|&c| c as f64 * scale).collect()
+    }
+}
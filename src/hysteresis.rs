@@ -0,0 +1,103 @@
+// hysteresis.rs
+// Force-extension curves of forward and reverse pulls and the area of their hysteresis loop
+
+use crate::ensemble;
+use crate::integrator::State;
+use crate::protocol::{Direction, Protocol};
+use crate::reduction;
+use crate::simulation::{self, Control, TrapParams};
+use crate::unirand;
+
+// Step of the central difference taken in lambda for the generalized force
+const FORCE_STEP: f64 = 1e-5;
+
+// Mean force on the control parameter against the trap position along one pull
+pub struct ForceCurve {
+    pub lambda: Vec<f64>, // Trap centre at every step
+    pub force: Vec<f64>,  // Mean generalized force <dU/dlambda> at that step
+    pub work: Vec<f64>,   // Work of every pull, in no particular order
+}
+
+// Forward and reverse curves, which together close the hysteresis loop
+pub struct Hysteresis {
+    pub forward: ForceCurve,
+    pub reverse: ForceCurve,
+}
+
+impl ForceCurve {
+    // Mean work over the pulls
+    pub fn mean_work(&self) -> f64 {
+        self.work.iter().sum::<f64>() / self.work.len() as f64
+    }
+
+    // Integral of the mean force along the curve by the trapezoid rule, which
+    // recovers the mean work of the pull
+    pub fn work_integral(&self) -> f64 {
+        self.lambda
+            .windows(2)
+            .zip(self.force.windows(2))
+            .map(|(l, f)| 0.5 * (f[0] + f[1]) * (l[1] - l[0]))
+            .sum()
+    }
+}
+
+impl Hysteresis {
+    // Area enclosed by the loop, the forward work integral plus the reverse one
+    // taken back along the same path. Delta F cancels between the two, so the
+    // area is the total mean dissipated work <W_diss,F> + <W_diss,R>.
+    pub fn loop_area(&self) -> f64 {
+        self.forward.work_integral() + self.reverse.work_integral()
+    }
+}
+
+// Generalized force dU/dlambda on the trap centre, by central difference so
+// that landscapes and expression potentials are handled alike
+pub fn lambda_force(params: &TrapParams, state: &State, control: Control) -> f64 {
+    let shifted = |by: f64| Control {
+        centre: control.centre + by,
+        ..control
+    };
+    (params.potential(&state.x, shifted(FORCE_STEP)) - params.potential(&state.x, shifted(-FORCE_STEP))) / (2.0 * FORCE_STEP)
+}
+
+// Average the force on the control parameter over independent pulls in one
+// direction, each worker summing its own trajectories before they are merged
+fn force_curve(params: &TrapParams, protocol: &Protocol, direction: Direction, trajectories: usize, seed: i32) -> ForceCurve {
+    let oriented = protocol.oriented(direction);
+    let points = params.steps(&oriented) + 1;
+    let controls: Vec<Control> = (0..points).map(|step| params.control(&oriented, step as f64 * params.dt)).collect();
+    let (force_sum, work) = reduction::fold_reduce(
+        trajectories,
+        || (vec![0.0; points], Vec::new()),
+        |(mut forces, mut work), i| {
+            unirand::seed_stream(seed, i);
+            let start = simulation::sample_equilibrium(params, controls[0]);
+            let trajectory = simulation::run_recorded(params, &oriented, start, |step, state, _| {
+                forces[step] += lambda_force(params, state, controls[step]);
+            });
+            work.push(trajectory.work);
+            (forces, work)
+        },
+        |(mut a, mut wa), (b, wb)| {
+            a.iter_mut().zip(&b).for_each(|(x, y)| *x += y);
+            wa.extend(wb);
+            (a, wa)
+        },
+    );
+
+    let n = trajectories as f64;
+    ForceCurve {
+        lambda: controls.iter().map(|c| c.centre).collect(),
+        force: force_sum.iter().map(|f| f / n).collect(),
+        work,
+    }
+}
+
+// Record the mean force-extension curves of the forward and reverse pulls, with
+// the reverse streams seeded apart from the forward ones as for the ensembles
+pub fn record(params: &TrapParams, protocol: &Protocol, trajectories: usize, seed: i32) -> Hysteresis {
+    Hysteresis {
+        forward: force_curve(params, protocol, Direction::Forward, trajectories, seed),
+        reverse: force_curve(params, protocol, Direction::Reverse, trajectories, ensemble::reverse_seed(seed)),
+    }
+}
@@ -0,0 +1,258 @@
+// integrator.rs
+// Langevin integrators advancing the particle at fixed control parameter
+
+use crate::protocol::Protocol;
+use crate::simulation::{gaussian, Control, TrapParams};
+use std::str::FromStr;
+
+// Deepest halving of a nominal step by the adaptive integrator, a factor 2^20
+const MAX_REFINEMENT: u32 = 20;
+
+// Position or velocity in up to three dimensions; axis 0 is the pulling
+// direction and components beyond the simulated dimensions stay zero
+pub type Vector = [f64; 3];
+
+// Phase-space state of the particle; the velocity is unused when overdamped
+#[derive(Clone, Copy, Debug)]
+pub struct State {
+    pub x: Vector,
+    pub v: Vector,
+    pub noise: Vector, // Current force of a colored bath, zero for white noise
+}
+
+impl State {
+    // A state on the pulling axis at rest
+    pub fn at(x: f64) -> Self {
+        Self {
+            x: [x, 0.0, 0.0],
+            v: [0.0; 3],
+            noise: [0.0; 3],
+        }
+    }
+}
+
+// Statistics of the random force exerted by the bath
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Noise {
+    // Delta-correlated, consistent with the friction: the bath assumed by the
+    // fluctuation theorems
+    White,
+    // Ornstein-Uhlenbeck force with <zeta(t) zeta(s)> = gamma kT / tau e^(-|t - s| / tau),
+    // tending to white noise as tau -> 0. The friction stays instantaneous, so
+    // the bath no longer satisfies the fluctuation-dissipation relation.
+    Colored,
+}
+
+impl FromStr for Noise {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "white" => Ok(Noise::White),
+            "colored" | "coloured" | "ou" | "ornstein-uhlenbeck" => Ok(Noise::Colored),
+            _ => Err(format!("unknown noise '{}'", name)),
+        }
+    }
+}
+
+// Choice of equations of motion and discretisation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Integrator {
+    // Overdamped dynamics, dx = F/gamma dt + sqrt(2 kT dt / gamma) xi
+    EulerMaruyama,
+    // Underdamped dynamics with inertia, using the Gronbech-Jensen-Farago
+    // velocity-Verlet form of the friction and noise terms
+    VelocityVerlet,
+    // Underdamped BAOAB splitting (Leimkuhler-Matthews): half kick, half drift,
+    // exact Ornstein-Uhlenbeck velocity update, half drift, half kick. Samples
+    // configurations accurately even at large time steps.
+    Baoab,
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "euler" | "euler-maruyama" | "overdamped" => Ok(Integrator::EulerMaruyama),
+            "verlet" | "velocity-verlet" | "underdamped" => Ok(Integrator::VelocityVerlet),
+            "baoab" => Ok(Integrator::Baoab),
+            _ => Err(format!("unknown integrator '{}'", name)),
+        }
+    }
+}
+
+impl Integrator {
+    // Whether the particle carries a velocity that must be initialised
+    pub fn is_inertial(&self) -> bool {
+        matches!(self, Integrator::VelocityVerlet | Integrator::Baoab)
+    }
+
+    // Advance the state by one time step with the trap held fixed, returning
+    // the heat absorbed from the bath: the work done on the particle by the
+    // friction and noise forces over the step, evaluated independently of the
+    // energy so that the first law can be checked afterwards. The particle is
+    // then wrapped into a periodic box or reflected off the walls.
+    pub fn step(&self, params: &TrapParams, state: &mut State, control: Control) -> f64 {
+        let dt = params.dt;
+        let axes = 0..params.dimensions;
+        let heat = match self {
+            Integrator::EulerMaruyama if params.correlation_time.is_some() => colored_step(params, state, control),
+            Integrator::EulerMaruyama => {
+                // The bath force balances the systematic force, so Q = -F o dx (Stratonovich)
+                let noise = (2.0 * params.temperature.kt() * dt / params.friction).sqrt();
+                let force = params.force(&state.x, control);
+                let x0 = state.x;
+                for d in axes.clone() {
+                    state.x[d] += force[d] / params.friction * dt + noise * gaussian();
+                }
+                let new_force = params.force(&state.x, control);
+                axes.map(|d| -0.5 * (force[d] + new_force[d]) * (state.x[d] - x0[d])).sum()
+            }
+            Integrator::VelocityVerlet => {
+                let m = params.mass;
+                let damping = params.friction * dt / (2.0 * m);
+                let b = 1.0 / (1.0 + damping);
+                let a = (1.0 - damping) * b;
+                let mut kick = [0.0; 3];
+                for d in axes.clone() {
+                    kick[d] = (2.0 * params.friction * params.temperature.kt() * dt).sqrt() * gaussian();
+                }
+
+                let force = params.force(&state.x, control);
+                let x0 = state.x;
+                for d in axes.clone() {
+                    state.x[d] += b * dt * state.v[d] + b * dt * dt / (2.0 * m) * force[d] + b * dt / (2.0 * m) * kick[d];
+                }
+                let new_force = params.force(&state.x, control);
+                for d in axes.clone() {
+                    state.v[d] = a * state.v[d] + dt / (2.0 * m) * (a * force[d] + new_force[d]) + b / m * kick[d];
+                }
+
+                // Friction acts on the mean velocity dx/dt of the step in this scheme
+                axes.map(|d| {
+                    let dx = state.x[d] - x0[d];
+                    (-params.friction * dx / dt + kick[d] / dt) * dx
+                })
+                .sum()
+            }
+            Integrator::Baoab => {
+                let m = params.mass;
+                let c1 = (-params.friction * dt / m).exp();
+                let c2 = ((1.0 - c1 * c1) * params.temperature.kt() / m).sqrt();
+
+                let force = params.force(&state.x, control);
+                for d in axes.clone() {
+                    state.v[d] += 0.5 * dt * force[d] / m;
+                    state.x[d] += 0.5 * dt * state.v[d];
+                }
+                // Only the O step couples to the bath, so its kinetic energy change is the heat
+                let mut heat = 0.0;
+                for d in axes.clone() {
+                    let v0 = state.v[d];
+                    state.v[d] = c1 * state.v[d] + c2 * gaussian();
+                    heat += 0.5 * m * (state.v[d] * state.v[d] - v0 * v0);
+                    state.x[d] += 0.5 * dt * state.v[d];
+                }
+                let force = params.force(&state.x, control);
+                for d in axes {
+                    state.v[d] += 0.5 * dt * force[d] / m;
+                }
+                heat
+            }
+        };
+        heat + params.confine(state, control)
+    }
+}
+
+// Overdamped step gamma dx = (F + zeta) dt driven by the colored bath force,
+// which is advanced exactly over the step and applied at its average over it.
+// The heat is -F o dx as for white noise, so the first law still holds.
+fn colored_step(params: &TrapParams, state: &mut State, control: Control) -> f64 {
+    let (dt, tau) = (params.dt, params.correlation_time.expect("colored step needs a correlation time"));
+    let axes = 0..params.dimensions;
+    let decay = (-dt / tau).exp();
+    let kick = (params.friction * params.temperature.kt() / tau * (1.0 - decay * decay)).sqrt();
+    let force = params.force(&state.x, control);
+    let x0 = state.x;
+    for d in axes.clone() {
+        let zeta = state.noise[d];
+        state.noise[d] = decay * zeta + kick * gaussian();
+        state.x[d] += (force[d] + 0.5 * (zeta + state.noise[d])) / params.friction * dt;
+    }
+    let new_force = params.force(&state.x, control);
+    axes.map(|d| -0.5 * (force[d] + new_force[d]) * (state.x[d] - x0[d])).sum()
+}
+
+// Overdamped Euler-Maruyama move over time h with the trap fixed, driven by the
+// given Wiener increments, returning the heat absorbed (Stratonovich, as in step)
+fn euler_move(params: &TrapParams, state: &mut State, control: Control, h: f64, increments: &Vector) -> f64 {
+    let noise = (2.0 * params.temperature.kt() / params.friction).sqrt();
+    let force = params.force(&state.x, control);
+    let x0 = state.x;
+    for d in 0..params.dimensions {
+        state.x[d] += force[d] / params.friction * h + noise * increments[d];
+    }
+    let new_force = params.force(&state.x, control);
+    (0..params.dimensions).map(|d| -0.5 * (force[d] + new_force[d]) * (state.x[d] - x0[d])).sum()
+}
+
+// Advance the overdamped particle over the span [t, t + h] along the protocol with
+// error control by step doubling, returning the work and heat. One full step
+// is compared with two half steps driven by the same Brownian path, the
+// halves' increments being drawn from the Brownian bridge; where they differ
+// by more than the tolerance the interval is split and each half refined in
+// turn. The trap moves at every accepted sub-step boundary, so work is
+// accounted on the variable grid actually used.
+fn refine(
+    params: &TrapParams,
+    protocol: &Protocol,
+    state: &mut State,
+    (t, h): (f64, f64),
+    increments: &Vector,
+    depth: u32,
+    tolerance: f64,
+) -> (f64, f64) {
+    let mut first = [0.0; 3];
+    let mut second = [0.0; 3];
+    for d in 0..params.dimensions {
+        first[d] = 0.5 * increments[d] + 0.5 * h.sqrt() * gaussian();
+        second[d] = increments[d] - first[d];
+    }
+    let (start, middle, end) = (
+        params.control(protocol, t),
+        params.control(protocol, t + 0.5 * h),
+        params.control(protocol, t + h),
+    );
+
+    let mut single = *state;
+    euler_move(params, &mut single, end, h, increments);
+    let mut double = *state;
+    let mut heat = euler_move(params, &mut double, middle, 0.5 * h, &first);
+    let mid = double;
+    heat += euler_move(params, &mut double, end, 0.5 * h, &second);
+
+    let error = (0..params.dimensions).map(|d| (single.x[d] - double.x[d]).abs()).fold(0.0, f64::max);
+    if error <= tolerance || depth >= MAX_REFINEMENT {
+        let work = params.potential(&state.x, middle) - params.potential(&state.x, start) + params.potential(&mid.x, end)
+            - params.potential(&mid.x, middle);
+        *state = double;
+        return (work, heat);
+    }
+    let (w1, q1) = refine(params, protocol, state, (t, 0.5 * h), &first, depth + 1, tolerance);
+    let (w2, q2) = refine(params, protocol, state, (t + 0.5 * h, 0.5 * h), &second, depth + 1, tolerance);
+    (w1 + w2, q1 + q2)
+}
+
+// One nominal step dt from time t with adaptive refinement, returning the work
+// and heat; only the overdamped integrator supports it. The particle is wrapped
+// into a periodic box or reflected off the walls only after the whole step, so
+// that the single and double moves compared during refinement stay comparable.
+pub fn adaptive_step(params: &TrapParams, protocol: &Protocol, state: &mut State, t: f64, tolerance: f64) -> (f64, f64) {
+    let mut increments = [0.0; 3];
+    for increment in increments.iter_mut().take(params.dimensions) {
+        *increment = params.dt.sqrt() * gaussian();
+    }
+    let (work, heat) = refine(params, protocol, state, (t, params.dt), &increments, 0, tolerance);
+    (work, heat + params.confine(state, params.control(protocol, t + params.dt)))
+}
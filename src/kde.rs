@@ -0,0 +1,137 @@
+// kde.rs
+// Gaussian kernel density estimates of work distributions
+
+use crate::histogram::Histogram;
+use crate::reduction;
+use rayon::prelude::*;
+use std::f64::consts::PI;
+
+// Largest sample used when scoring candidate bandwidths by cross-validation,
+// which costs O(n^2) per candidate
+const CV_MAX_SAMPLES: usize = 2000;
+const CV_CANDIDATES: usize = 40;
+
+// Bandwidth selection rule
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bandwidth {
+    Silverman,      // Rule of thumb, good for near-Gaussian work distributions
+    CrossValidated, // Least-squares cross-validation, better for skewed or multimodal data
+}
+
+// Smooth density estimate built from a set of samples
+pub struct Kde {
+    samples: Vec<f64>,
+    pub bandwidth: f64,
+}
+
+// Standard normal density
+fn phi(u: f64) -> f64 {
+    (-0.5 * u * u).exp() / (2.0 * PI).sqrt()
+}
+
+// Silverman's rule h = 0.9 min(sigma, IQR / 1.34) n^(-1/5)
+pub fn silverman_bandwidth(samples: &[f64]) -> f64 {
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let sigma = (samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let quantile = |q: f64| sorted[((n - 1.0) * q).round() as usize];
+    let iqr = quantile(0.75) - quantile(0.25);
+
+    let spread = if iqr > 0.0 { sigma.min(iqr / 1.34) } else { sigma };
+    0.9 * spread * n.powf(-0.2)
+}
+
+// Least-squares cross-validation score; lower is better
+fn lscv_score(samples: &[f64], h: f64) -> f64 {
+    let n = samples.len() as f64;
+    let (convolved, leave_one_out) = reduction::fold_reduce(
+        samples.len(),
+        || (0.0, 0.0),
+        |sums, i| {
+            samples.iter().fold(sums, |(c, l), &xj| {
+                let u = (samples[i] - xj) / h;
+                (c + phi(u / 2f64.sqrt()) / 2f64.sqrt(), l + phi(u))
+            })
+        },
+        |a, b| (a.0 + b.0, a.1 + b.1),
+    );
+
+    // Remove the i == j self terms from the leave-one-out sum
+    let leave_one_out = leave_one_out - n * phi(0.0);
+    convolved / (n * n * h) - 2.0 * leave_one_out / (n * (n - 1.0) * h)
+}
+
+// Bandwidth minimizing the LSCV score over a log-spaced range around Silverman's value
+pub fn cross_validated_bandwidth(samples: &[f64]) -> f64 {
+    let stride = samples.len().div_ceil(CV_MAX_SAMPLES);
+    let subset: Vec<f64> = samples.iter().step_by(stride).cloned().collect();
+    let reference = silverman_bandwidth(&subset);
+
+    (0..CV_CANDIDATES)
+        .map(|i| reference * 10f64.powf(-1.0 + 1.5 * i as f64 / (CV_CANDIDATES - 1) as f64))
+        .map(|h| (h, lscv_score(&subset, h)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(h, _)| h * (subset.len() as f64 / samples.len() as f64).powf(0.2)) // Rescale to the full sample size
+        .unwrap()
+}
+
+impl Kde {
+    pub fn new(samples: Vec<f64>, bandwidth: Bandwidth) -> Self {
+        let bandwidth = match bandwidth {
+            Bandwidth::Silverman => silverman_bandwidth(&samples),
+            Bandwidth::CrossValidated => cross_validated_bandwidth(&samples),
+        };
+        Self { samples, bandwidth }
+    }
+
+    // Estimated density at x
+    pub fn density(&self, x: f64) -> f64 {
+        let sum: f64 = self.samples.iter().map(|&s| phi((x - s) / self.bandwidth)).sum();
+        sum / (self.samples.len() as f64 * self.bandwidth)
+    }
+}
+
+// Crossing of the KDEs of forward work and negated reverse work (as measured)
+// over their combined range, returned with the forward bandwidth used
+pub fn work_crossing(forward: &[f64], reverse: &[f64], rule: Bandwidth, steps: usize) -> Option<(f64, f64)> {
+    let negated: Vec<f64> = reverse.iter().map(|w| -w).collect();
+    let (lo, hi) = Histogram::auto_range([forward, negated.as_slice()]);
+    let forward_kde = Kde::new(forward.to_vec(), rule);
+    let reverse_kde = Kde::new(negated, rule);
+    crossing(&forward_kde, &reverse_kde, lo, hi, steps).map(|w| (w, forward_kde.bandwidth))
+}
+
+// Locate W where the forward density equals the density of the negated reverse
+// work, scanning [lo, hi] for a sign change of ln f_F - ln f_R and refining it
+// by bisection. Returns None if the curves never cross inside the range.
+pub fn crossing(forward: &Kde, reverse: &Kde, lo: f64, hi: f64, steps: usize) -> Option<f64> {
+    let difference = |w: f64| forward.density(w).ln() - reverse.density(w).ln();
+
+    let grid: Vec<f64> = (0..=steps).map(|i| lo + (hi - lo) * i as f64 / steps as f64).collect();
+    let values: Vec<f64> = grid.par_iter().map(|&w| difference(w)).collect();
+
+    // Prefer the bracket with the highest combined density, where the estimate is best supported
+    let (mut a, mut b) = (0..steps)
+        .filter(|&i| values[i].is_finite() && values[i + 1].is_finite() && values[i].signum() != values[i + 1].signum())
+        .map(|i| (grid[i], grid[i + 1]))
+        .max_by(|x, y| {
+            let support = |w: f64| forward.density(w) + reverse.density(w);
+            support(x.0).total_cmp(&support(y.0))
+        })?;
+
+    let mut fa = difference(a);
+    for _ in 0..60 {
+        let mid = 0.5 * (a + b);
+        let fm = difference(mid);
+        if fm.signum() == fa.signum() {
+            a = mid;
+            fa = fm;
+        } else {
+            b = mid;
+        }
+    }
+    Some(0.5 * (a + b))
+}
@@ -0,0 +1,108 @@
+// latin.rs
+// Latin hypercube sampling of the first draws of every trajectory of an ensemble
+
+use crate::distributions::inverse_normal_cdf;
+use crate::generators::splitmix64;
+use crate::unirand::{GaussianMethod, UniformRng};
+
+// Uniform and Gaussian draws of each trajectory that are stratified, enough
+// for the position, velocity and bath force of a particle in three dimensions
+pub const LATIN_DIMENSIONS: usize = 9;
+
+// Rounds of the Feistel network behind the permutations
+const FEISTEL_ROUNDS: u64 = 4;
+
+// Permutation of 0..n keyed by a 64-bit key, evaluated at one point without
+// storing it: a balanced Feistel network on the smallest even number of bits
+// covering n, walked along its cycle until it lands back inside 0..n
+fn permute(index: u64, n: u64, key: u64) -> u64 {
+    let half = (64 - (n.max(2) - 1).leading_zeros()).div_ceil(2);
+    let mask = (1u64 << half) - 1;
+    let mut x = index;
+    loop {
+        let (mut left, mut right) = (x >> half, x & mask);
+        for round in 0..FEISTEL_ROUNDS {
+            let mut state = key ^ (round << 56) ^ right;
+            (left, right) = (right, left ^ (splitmix64(&mut state) & mask));
+        }
+        x = (left << half) | right;
+        if x < n {
+            return x;
+        }
+    }
+}
+
+// Trajectory `index` of an ensemble of `count`: the d-th uniform it draws
+// lies in stratum pi_d(index) of `count` equal strata of [0, 1), and the d-th
+// Gaussian in the stratum of equal probability, pi_d being an independent
+// random permutation for each draw, so that across the ensemble every one of
+// the first LATIN_DIMENSIONS draws covers its range exactly once per stratum.
+// The position within a stratum, and every later draw, comes from a
+// pseudo-random generator.
+pub struct LatinRng {
+    index: u64,
+    count: u64,
+    key: u64,         // Key of the permutations, from the seed
+    uniforms: usize,  // Uniforms drawn so far
+    gaussians: usize, // Gaussians drawn so far
+    tail: Box<dyn UniformRng>,
+}
+
+impl LatinRng {
+    // Hypercube of `count` trajectories randomised by a seed, over a pseudo-random
+    // generator that supplies the Gaussian sampler and, split alongside, the jitter
+    pub fn new(seed: i32, count: usize, tail: Box<dyn UniformRng>) -> Self {
+        let mut sequence = seed as u64;
+        Self {
+            index: 0,
+            count: count.max(1) as u64,
+            key: splitmix64(&mut sequence),
+            uniforms: 0,
+            gaussians: 0,
+            tail,
+        }
+    }
+
+    // Point in [0, 1) within this trajectory's stratum of draw `dimension`
+    fn stratified(&mut self, dimension: u64) -> f64 {
+        let stratum = permute(self.index, self.count, self.key.wrapping_add(dimension.wrapping_mul(0x9e37_79b9_7f4a_7c15)));
+        (stratum as f64 + self.tail.uniform()) / self.count as f64
+    }
+}
+
+impl UniformRng for LatinRng {
+    fn uniform(&mut self) -> f64 {
+        if self.uniforms >= LATIN_DIMENSIONS {
+            return self.tail.uniform();
+        }
+        self.uniforms += 1;
+        self.stratified(2 * self.uniforms as u64)
+    }
+
+    fn gaussian_method(&self) -> GaussianMethod {
+        self.tail.gaussian_method()
+    }
+
+    // Stratified Gaussians by inversion of the normal distribution, kept off
+    // the open ends of [0, 1)
+    fn generate_gaussian(&mut self) -> f64 {
+        if self.gaussians >= LATIN_DIMENSIONS {
+            return self.tail.generate_gaussian();
+        }
+        self.gaussians += 1;
+        let p = self.stratified(2 * self.gaussians as u64 + 1);
+        inverse_normal_cdf(p.clamp(f64::MIN_POSITIVE, 1.0 - f64::EPSILON))
+    }
+
+    // Trajectory `stream` of the same hypercube
+    fn split(&self, stream: u64) -> Box<dyn UniformRng> {
+        Box::new(Self {
+            index: stream % self.count,
+            count: self.count,
+            key: self.key,
+            uniforms: 0,
+            gaussians: 0,
+            tail: self.tail.split(stream),
+        })
+    }
+}
@@ -0,0 +1,102 @@
+// live.rs
+// Forward and reverse ensembles run in the background while the animation plays
+
+use crate::config::Config;
+use crate::ensemble;
+use crate::estimators;
+use crate::protocol::Direction;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Trajectories of each direction run between updates of the shared samples
+const LIVE_BATCH: usize = 64;
+
+// Running Delta F estimates after some number of trajectories of each direction
+#[derive(Clone, Copy)]
+pub struct Convergence {
+    pub samples: usize,
+    pub jarzynski_forward: f64,
+    pub jarzynski_reverse: f64, // Sign flipped to estimate Delta F of the forward protocol
+    pub bar: f64,
+}
+
+// Work samples gathered so far, the reverse ones as measured, and the
+// estimates after every batch
+#[derive(Clone, Default)]
+pub struct LiveSamples {
+    pub forward: Vec<f64>,
+    pub reverse: Vec<f64>,
+    pub history: Vec<Convergence>,
+}
+
+// Handle on the background run, whose batches alternate between the two
+// directions so that both ensembles grow together up to the configured size.
+// Dropping the handle stops the run after its current batch.
+pub struct LiveRun {
+    samples: Arc<Mutex<LiveSamples>>,
+    stopped: Arc<AtomicBool>,
+    pub trajectories: usize,
+    pub exact_delta_f: Option<f64>,
+}
+
+impl LiveRun {
+    // Start the configured ensembles on a thread of their own; their
+    // trajectories still spread over the rayon pool batch by batch
+    pub fn start(config: &Config) -> Self {
+        let samples = Arc::new(Mutex::new(LiveSamples::default()));
+        let shared = Arc::clone(&samples);
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stop = Arc::clone(&stopped);
+        let (trajectories, seed) = (config.trajectories, config.seed);
+        let exact_delta_f = config.model().exact_delta_f(&config.protocol());
+        let config = config.clone();
+        thread::spawn(move || {
+            let (model, protocol) = (config.model(), config.protocol());
+            let bath = model.temperature();
+            let mut gathered = LiveSamples::default();
+            for start in (0..trajectories).step_by(LIVE_BATCH) {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let range = start..(start + LIVE_BATCH).min(trajectories);
+                let forward = ensemble::work_batch(model.as_ref(), &protocol, Direction::Forward, range.clone(), trajectories, seed);
+                let reverse = ensemble::work_batch(
+                    model.as_ref(),
+                    &protocol,
+                    Direction::Reverse,
+                    range,
+                    trajectories,
+                    ensemble::reverse_seed(seed),
+                );
+                gathered.forward.extend(forward);
+                gathered.reverse.extend(reverse);
+                gathered.history.push(Convergence {
+                    samples: gathered.forward.len(),
+                    jarzynski_forward: estimators::jarzynski(&gathered.forward, bath).delta_f,
+                    jarzynski_reverse: -estimators::jarzynski(&gathered.reverse, bath).delta_f,
+                    bar: estimators::bar(&gathered.forward, &gathered.reverse, bath),
+                });
+                // Publish under the lock only once the estimates are in
+                *shared.lock().expect("live samples lock poisoned") = gathered.clone();
+            }
+        });
+        Self {
+            samples,
+            stopped,
+            trajectories,
+            exact_delta_f,
+        }
+    }
+
+    // Copy of the samples gathered so far
+    pub fn snapshot(&self) -> LiveSamples {
+        self.samples.lock().expect("live samples lock poisoned").clone()
+    }
+}
+
+impl Drop for LiveRun {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
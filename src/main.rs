@@ -2,82 +2,2037 @@
 // by maths.earth 2024
 // https://en.wikipedia.org/wiki/Crooks_fluctuation_theorem
 
+mod analysis;
+mod analytic;
+mod animation;
+mod antithetic;
+mod capture;
+mod chain;
+mod characteristic;
+mod colorbar;
+mod colormap;
+mod config;
+mod contour;
+mod correlation;
+mod distributions;
+mod ensemble;
+mod equalise;
+mod estimators;
+mod expr;
+mod fdt;
+mod feedback;
+mod generators;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod histogram;
+mod hysteresis;
+mod integrator;
+mod kde;
+mod latin;
+mod live;
+mod markov;
+mod mbar;
+mod metric;
+mod model;
+mod normality;
+mod optimize;
+#[cfg(feature = "egui")]
+mod panel;
+mod plot;
+#[cfg(feature = "extended-precision")]
+mod precision;
+mod potential;
+mod profile;
+mod protocol;
+mod quantum;
+mod recording;
+mod reduction;
+mod replica;
+mod resample;
+mod rngtest;
+mod schedule;
+mod simulation;
+mod sixel;
+mod sobol;
+mod spin;
+mod steady;
+mod summary;
+mod summation;
+mod surface;
+mod text;
+#[cfg(feature = "tui")]
+mod tui;
 mod unirand;
+mod units;
+mod wham;
 
-use image::ImageBuffer;
-use minifb::{Key, Window, WindowOptions};
-use rayon::prelude::*;
+use animation::{Scene, OVERLAY_COLOUR, OVERLAY_MARGIN, OVERLAY_SCALE, ZOOM_STEP};
+use config::Config;
+use kde::Bandwidth;
+use model::ModelKind;
+use histogram::Normalization;
+use integrator::Noise;
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use ensemble::{Ensemble, PairedEnsembles};
+use profile::Profile;
+use recording::Recording;
+use protocol::{Direction, Protocol};
+use schedule::Schedule;
+use simulation::TrapParams;
+use summary::WorkSummary;
+use units::{Temperature, UnitSystem};
+use rustfft::num_complex::Complex64;
 use std::f64::consts::PI;
-use unirand::RNG;
+use std::sync::Arc;
+use std::time::Instant;
+use unirand::MarsagliaUniRng;
 
 // Constants for image dimensions
 const WIDTH: usize = 1024;
 const HEIGHT: usize = 768;
 
-// Function to compute the Crooks fluctuation theorem
-fn crooks_fluctuation_theorem(terms: u32, coefficient: f64, exponent: f64, time: f64) -> f64 {
-    let mut sum = 0.0;
-    for i in 1..=terms {
-        let term = (2.0 * PI * i as f64 + time).sin() / (2.0 * PI * i as f64 + time).cosh();
-        sum += (coefficient * term).powf(exponent);
+// Weight of the newest frame in the smoothed frame rate of the animation
+const OVERLAY_SMOOTHING: f64 = 0.1;
+
+// Size of the live work-histogram panel in the bottom-right corner of the animation
+const LIVE_PANEL_WIDTH: usize = 360;
+const LIVE_PANEL_HEIGHT: usize = 240;
+
+// Smallest window side the animation draws into; a smaller, e.g. minimised,
+// window keeps its last frame
+const MIN_FRAME: usize = 16;
+
+// Seconds a capture message stays on screen, and the first part of that during
+// which a white border flashes round the frame
+const FLASH_SECONDS: f64 = 1.5;
+const FLASH_BORDER_SECONDS: f64 = 0.15;
+const FLASH_BORDER: usize = 6;
+
+// Most recent batches shown by the scrolling Delta F convergence plot
+const LIVE_HISTORY: usize = 100;
+
+// Number of grid points scanned when locating the KDE crossing
+const KDE_GRID: usize = 200;
+
+// Bootstrap replicates behind the standard errors of the precision command
+// when the bootstrap key leaves them at zero
+#[cfg(feature = "extended-precision")]
+const PRECISION_REPLICATES: usize = 200;
+
+// Standard deviations of the reduced chi-square the self-test allows above one
+const SELFTEST_CHI_SIGMAS: f64 = 5.0;
+
+// Draws the self-test makes before saving the generator state and again after restoring it
+const SELFTEST_DRAWS: usize = 1000;
+
+// Bins of the rngtest chi-square, the lags of its serial correlations (the
+// Marsaglia generator combines draws 97 and 33 apart) and the p-value below
+// which a test fails
+const RNGTEST_BINS: usize = 100;
+const RNGTEST_LAGS: [usize; 3] = [1, 33, 97];
+const RNGTEST_SIGNIFICANCE: f64 = 1e-3;
+
+// Variance reduction of antithetic pairs beyond which the work is taken to be
+// linear in the noise, cancelling within each pair up to rounding
+const ANTITHETIC_CANCELLATION: f64 = 1e6;
+
+// Smallest level probability shown in the quantum Crooks relation
+const QUANTUM_FLOOR: f64 = 1e-8;
+
+// Points along one period of the quantum characteristic function checked for the Crooks symmetry
+const QUANTUM_CHECK_POINTS: usize = 1000;
+
+// Run forward and reverse pulling ensembles of the configured model
+fn run_experiment(config: &Config) -> PairedEnsembles {
+    ensemble::run_paired(config.model().as_ref(), &config.protocol(), config.trajectories, config.seed)
+}
+
+// Flag a bath that breaks the assumptions behind the fluctuation theorems: with
+// colored noise the bath has memory and no fluctuation-dissipation relation with
+// the friction, so Crooks, Jarzynski and the entropy theorems may all fail
+fn report_bath(config: &Config) {
+    if config.noise == Noise::Colored {
+        println!(
+            "WARNING: colored bath noise with correlation time {}; the fluctuation theorems assume a white, \
+             memoryless bath and may not hold",
+            config.units.format_value(config.correlation_time)
+        );
     }
-    sum
 }
 
-fn main() {
-    // Create a new window
-    let mut window = Window::new(
-        "Crooks Fluctuation Theorem Simulation",
+// Report the work statistics and the binned Crooks ratio on stdout
+fn run_simulation(config: &Config) {
+    let ensembles = run_experiment(config);
+    let (units, bath) = (config.units, config.bath());
+    match (config.model, &config.expression) {
+        (ModelKind::Spin, _) => println!("Model: two-state spin, E = -lambda sigma, flip rate {}", config.spin_rate),
+        (ModelKind::Chain, _) => println!(
+            "Model: chain of {} particles, {:?} interaction, end pulled by the trap",
+            config.particles, config.interaction
+        ),
+        (ModelKind::Markov, _) => println!(
+            "Model: {} lattice sites from {} spaced {}, hop rate {}",
+            config.lattice_sites, config.lattice_start, config.lattice_spacing, config.hop_rate
+        ),
+        (_, Some(expression)) => println!("Integrator: {:?}, U(x, lambda) = {}", config.integrator, expression.source()),
+        (_, None) => println!("Integrator: {:?}, landscape: {:?}", config.integrator, config.landscape),
+    }
+    println!("Schedule: {:?}", config.schedule);
+    report_bath(config);
+    let (model, protocol) = (config.model(), config.protocol());
+    let exact_delta_f = model.exact_delta_f(&protocol);
+    // Dissipated work is measured against the exact Delta F when there is one
+    let reference_delta_f =
+        exact_delta_f.unwrap_or_else(|| estimators::bar(&ensembles.forward.work, &ensembles.reverse.work, bath));
+    let summaries = WorkSummary::paired(&ensembles, reference_delta_f);
+    for (ensemble, moments) in [&ensembles.forward, &ensembles.reverse].into_iter().zip(&summaries) {
+        println!(
+            "{:?}: {} trajectories, <W> = {}, <Q> = {}, <x_final> = {}",
+            ensemble.direction,
+            ensemble.len(),
+            units.format_energy(ensemble.mean_work(), bath),
+            units.format_energy(ensemble.mean_heat(), bath),
+            units.format_value(ensemble.mean_final_position())
+        );
+        println!(
+            "  heat fluctuations: std(Q) = {}, var(Q) = {}",
+            units.format_energy(ensemble.heat_std(), bath),
+            units.format_value(ensemble.heat_std() * ensemble.heat_std())
+        );
+        println!(
+            "  first law Delta U = W + Q: rms residual {} per trajectory",
+            units.format_energy(ensemble.first_law_rms(), bath)
+        );
+        println!(
+            "  <sigma> = {:.4} k_B total entropy production ({})",
+            ensemble.mean_entropy_production(),
+            if ensemble.entropy_exact { "exact" } else { "KDE estimate of p(x, t)" }
+        );
+        report_integral_theorems(ensemble, exact_delta_f, bath);
+        let correlated = correlation::correlated_mean(&ensemble.work);
+        println!(
+            "  <W> = {} +- {} (tau = {:.2}, g = {:.2}, N_eff = {:.0})",
+            units.format_value(correlated.mean),
+            units.format_value(correlated.std_error),
+            correlated.tau,
+            correlated.inefficiency,
+            correlated.effective_samples
+        );
+        if ensemble.antithetic {
+            let paired = antithetic::paired_mean(&ensemble.work);
+            // Work linear in the noise, as in a harmonic trap, cancels exactly within a pair
+            let reduction = if paired.variance_reduction < ANTITHETIC_CANCELLATION {
+                format!("variance of the mean {:.2}x smaller than independent", paired.variance_reduction)
+            } else {
+                "the noise cancels within pairs".to_string()
+            };
+            println!(
+                "  antithetic pairs: <W> = {} +- {} over {} pairs, {}",
+                units.format_value(paired.mean),
+                units.format_value(paired.std_error),
+                paired.pairs,
+                reduction
+            );
+        }
+        if let Some(block) = correlation::block_analysis(&ensemble.work).last() {
+            println!(
+                "  block-averaged error {} ({} blocks of {})",
+                units.format_value(block.std_error),
+                block.blocks,
+                block.block_size
+            );
+        }
+        println!(
+            "  work moments: var = {}, skewness = {:.4}, excess kurtosis = {:.4}, <W_diss> = {} ({} Delta F)",
+            units.format_value(moments.variance),
+            moments.skewness,
+            moments.kurtosis,
+            units.format_energy(moments.dissipated, bath),
+            if exact_delta_f.is_some() { "exact" } else { "BAR" }
+        );
+        report_gaussian_work(ensemble, moments, units, bath);
+    }
+    if let Some(path) = &config.summary_output {
+        let rows: Vec<_> = summaries.into_iter().map(|s| (Vec::new(), s)).collect();
+        if let Err(e) = summary::write_csv(path, &[], &rows) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        println!("Work moments written to {}", path);
+    }
+
+    let ratio = analysis::crooks_ratio(&ensembles.forward.work, &ensembles.reverse.work, config.bins);
+    println!();
+    println!("{:>10} {:>16}", "W", "ln(P_F/P_R)");
+    for point in ratio.iter().flat_map(|ratio| &ratio.points) {
+        println!("{:>10} {:>16.4}", units.format_value(point.work), point.log_ratio);
+    }
+    println!();
+    println!("{:>10} {:>12} {:>12}", "Q", "P_F(Q)", "P_R(-Q)");
+    let (forward_heat, reverse_heat) = analysis::heat_histograms(&ensembles.forward.heat, &ensembles.reverse.heat, config.bins);
+    let (forward_density, reverse_density) = (
+        forward_heat.heights(Normalization::Density),
+        reverse_heat.heights(Normalization::Density),
+    );
+    for i in 0..config.bins {
+        println!(
+            "{:>10} {:>12.4} {:>12.4}",
+            units.format_value(forward_heat.centre(i)),
+            forward_density[i],
+            reverse_density[i]
+        );
+    }
+    println!();
+    match &ratio {
+        Some(ratio) => {
+            println!(
+                "Fitted slope:        {} (beta = {})",
+                units.format_value(ratio.slope),
+                units.format_value(bath.beta())
+            );
+            println!("Fitted Delta F:      {}", units.format_energy(ratio.delta_f(), bath));
+        }
+        None => println!("Crooks fit:          no overlap of P_F(W) and P_R(-W) to fit a line through"),
+    }
+    if let Some(delta_f) = exact_delta_f {
+        println!("Exact Delta F:       {}", units.format_energy(delta_f, bath));
+    }
+
+    // The reverse ensemble estimates -Delta F, so its sign is flipped for comparison
+    let forward = estimators::jarzynski(&ensembles.forward.work, bath);
+    let reverse = estimators::jarzynski(&ensembles.reverse.work, bath);
+    println!(
+        "Jarzynski forward:   {} (bias ~{}, N = {})",
+        units.format_energy(forward.delta_f, bath),
+        units.format_energy(forward.bias, bath),
+        forward.samples
+    );
+    println!(
+        "Jarzynski reverse:   {} (bias ~{}, N = {})",
+        units.format_energy(-reverse.delta_f, bath),
+        units.format_energy(reverse.bias, bath),
+        reverse.samples
+    );
+    for (name, estimate) in [("forward", &forward), ("reverse", &reverse)] {
+        if estimate.effective_samples < config.min_effective_samples {
+            println!(
+                "WARNING: the {} exponential average rests on N_eff = {:.1} of {} trajectories, the lowest-work one \
+                 carrying {:.0}% of the weight; rare low-work trajectories are undersampled and the Jarzynski \
+                 estimate is likely biased toward <W>",
+                name,
+                estimate.effective_samples,
+                estimate.samples,
+                100.0 * estimate.max_weight
+            );
+        }
+    }
+
+    // The exact work distributions of the harmonic trap as control variates
+    let reference = match config.model {
+        ModelKind::Particle => analytic::harmonic_reference(&config.trap_params(), &protocol),
+        _ => None,
+    };
+    let mut controlled = Vec::new();
+    if config.control_variates {
+        match &reference {
+            Some(reference) => {
+                for (name, ensemble, exact, sign) in [
+                    ("forward", &ensembles.forward, reference.forward, 1.0),
+                    ("reverse", &ensembles.reverse, reference.reverse, -1.0),
+                ] {
+                    match estimators::control_variate(&ensemble.work, bath, exact.mean, exact.variance) {
+                        Some(estimate) => {
+                            println!(
+                                "Controlled {}:  {} +- {} (control variates, variance {:.2}x smaller)",
+                                name,
+                                units.format_energy(sign * estimate.delta_f, bath),
+                                units.format_energy(estimate.std_error, bath),
+                                estimate.variance_reduction
+                            );
+                            controlled.push((name, sign * estimate.delta_f));
+                        }
+                        None => println!("Control variates {}: corrected average not positive, too few samples", name),
+                    }
+                }
+            }
+            None => println!("Control variates: need the analytic work distributions of a harmonic or flat landscape"),
+        }
+    }
+
+    let order = config.cumulant_order;
+    let cumulant_forward = estimators::cumulant(&ensembles.forward.work, bath, order);
+    let cumulant_reverse = -estimators::cumulant(&ensembles.reverse.work, bath, order);
+    println!("Cumulant forward:    {} (order {})", units.format_energy(cumulant_forward, bath), order);
+    println!("Cumulant reverse:    {} (order {})", units.format_energy(cumulant_reverse, bath), order);
+    let bar = estimators::bar(&ensembles.forward.work, &ensembles.reverse.work, bath);
+    println!("BAR:                 {}", units.format_energy(bar, bath));
+
+    let crossing = analysis::crossing(&ensembles.forward.work, &ensembles.reverse.work, config.bins);
+    println!(
+        "Histogram crossing:  {} ({:?} within [{}, {}])",
+        units.format_energy(crossing.delta_f, bath),
+        crossing.method,
+        units.format_value(crossing.lower),
+        units.format_value(crossing.upper)
+    );
+
+    // Smooth crossing of P_F(W) and P_R(-W), with both bandwidth rules
+    for rule in [Bandwidth::Silverman, Bandwidth::CrossValidated] {
+        match kde::work_crossing(&ensembles.forward.work, &ensembles.reverse.work, rule, KDE_GRID) {
+            Some((w, h)) => println!(
+                "KDE crossing ({:?}, h = {}): {}",
+                rule,
+                units.format_value(h),
+                units.format_energy(w, bath)
+            ),
+            None => println!("KDE crossing ({:?}): no crossing found", rule),
+        }
+    }
+
+    if let Some(exact) = exact_delta_f {
+        println!();
+        println!("Errors against the exact Delta F:");
+        if let Some(ratio) = &ratio {
+            println!("  {:<20} {}", "Fitted", units.format_energy(ratio.delta_f() - exact, bath));
+        }
+        for (name, estimate) in [
+            ("Jarzynski forward", forward.delta_f),
+            ("Jarzynski reverse", -reverse.delta_f),
+            ("Cumulant forward", cumulant_forward),
+            ("Cumulant reverse", cumulant_reverse),
+            ("BAR", bar),
+            ("Histogram crossing", crossing.delta_f),
+        ] {
+            println!("  {:<20} {}", name, units.format_energy(estimate - exact, bath));
+        }
+        for (name, estimate) in &controlled {
+            let name = format!("Controlled {}", name);
+            println!("  {:<20} {}", name, units.format_energy(estimate - exact, bath));
+        }
+    }
+    if config.counterdiabatic {
+        println!(
+            "Counterdiabatic driving: the density follows the target trap without lag, dissipating <W_diss> = {} \
+             in either direction",
+            units.format_energy(analytic::counterdiabatic_dissipation(&config.trap_params(), &protocol), bath)
+        );
+    }
+    if let Some(reference) = &reference {
+        println!();
+        println!(
+            "Analytic Gaussian work distributions, Delta F = {}:",
+            units.format_energy(reference.delta_f, bath)
+        );
+        for (ensemble, exact) in [(&ensembles.forward, reference.forward), (&ensembles.reverse, reference.reverse)] {
+            println!(
+                "  {:?}: <W> = {}, std = {} (simulated {}, {})",
+                ensemble.direction,
+                units.format_energy(exact.mean, bath),
+                units.format_energy(exact.std(), bath),
+                units.format_energy(ensemble.mean_work(), bath),
+                units.format_energy(ensemble.work_std(), bath)
+            );
+        }
+    }
+
+    if config.bootstrap > 0 {
+        report_bootstrap(config, &ensembles);
+    }
+}
+
+// Integral fluctuation theorem self-checks <e^-sigma> = 1 and, when Delta F of
+// the forward protocol is known exactly, <e^-beta(W - Delta F)> = 1
+fn report_integral_theorems(ensemble: &Ensemble, exact_delta_f: Option<f64>, bath: Temperature) {
+    let entropy = estimators::integral_check(&ensemble.entropy_production);
+    println!("  <e^-sigma> = {:.4} +- {:.4} (integral theorem: 1)", entropy.mean, entropy.std_error);
+    if let Some(delta_f) = exact_delta_f {
+        let delta_f = match ensemble.direction {
+            Direction::Forward => delta_f,
+            Direction::Reverse => -delta_f,
+        };
+        let dissipated: Vec<f64> = ensemble.work.iter().map(|w| bath.beta() * (w - delta_f)).collect();
+        let jarzynski = estimators::integral_check(&dissipated);
+        println!(
+            "  <e^-beta(W - Delta F)> = {:.4} +- {:.4} (Jarzynski: 1)",
+            jarzynski.mean, jarzynski.std_error
+        );
+    }
+}
+
+// Anderson-Darling test of the work distribution against a Gaussian. Gaussian
+// work obeys the fluctuation-dissipation relation var(W) = 2 kT <W_diss>, which
+// is then compared with the measured variance.
+fn report_gaussian_work(ensemble: &Ensemble, moments: &WorkSummary, units: UnitSystem, bath: Temperature) {
+    let test = normality::anderson_darling(&ensemble.work);
+    if !test.gaussian {
+        println!("  Anderson-Darling A*^2 = {:.4}: work not Gaussian at the 5% level", test.statistic);
+        return;
+    }
+    println!(
+        "  Anderson-Darling A*^2 = {:.4}: Gaussian work, var(W) = {} against 2 kT <W_diss> = {}",
+        test.statistic,
+        units.format_value(moments.variance),
+        units.format_value(2.0 * bath.kt() * moments.dissipated)
+    );
+}
+
+// Bootstrap confidence intervals for every Delta F estimator
+fn report_bootstrap(config: &Config, ensembles: &PairedEnsembles) {
+    let (units, bath, bins, order) = (config.units, config.bath(), config.bins, config.cumulant_order);
+
+    // Resample in blocks spanning the longer of the two correlation times
+    let block = [&ensembles.forward.work, &ensembles.reverse.work]
+        .iter()
+        .map(|work| correlation::correlated_mean(work).inefficiency.round().max(1.0) as usize)
+        .max()
+        .unwrap_or(1);
+    type Estimator = Box<dyn Fn(&[f64], &[f64]) -> f64 + Sync>;
+    let estimators: Vec<(&str, Estimator)> = vec![
+        ("Crooks fit", Box::new(move |f, r| analysis::crooks_ratio(f, r, bins).map_or(f64::NAN, |ratio| ratio.delta_f()))),
+        ("Jarzynski forward", Box::new(move |f, _| estimators::jarzynski(f, bath).delta_f)),
+        ("Jarzynski reverse", Box::new(move |_, r| -estimators::jarzynski(r, bath).delta_f)),
+        ("Cumulant forward", Box::new(move |f, _| estimators::cumulant(f, bath, order))),
+        ("Cumulant reverse", Box::new(move |_, r| -estimators::cumulant(r, bath, order))),
+        ("BAR", Box::new(move |f, r| estimators::bar(f, r, bath))),
+        ("Histogram crossing", Box::new(move |f, r| analysis::crossing(f, r, bins).delta_f)),
+        ("KDE crossing", Box::new(|f, r| {
+            kde::work_crossing(f, r, Bandwidth::Silverman, KDE_GRID).map_or(f64::NAN, |(w, _)| w)
+        })),
+    ];
+
+    println!();
+    println!(
+        "Bootstrap {:.0}% confidence intervals ({} replicates, block length {}):",
+        100.0 * config.confidence,
+        config.bootstrap,
+        block
+    );
+    for (name, estimator) in &estimators {
+        let interval = resample::bootstrap(
+            &ensembles.forward.work,
+            &ensembles.reverse.work,
+            block,
+            config.bootstrap,
+            config.confidence,
+            resample::replicate_seed(config.seed, config.trajectories),
+            estimator,
+        );
+        // The fit and the KDE crossing give nothing when the histograms
+        // hardly overlap
+        if !interval.estimate.is_finite() {
+            println!("  {:<20} no overlap of P_F(W) and P_R(-W) to estimate from", name);
+            continue;
+        }
+        println!(
+            "  {:<20} {} [{}, {}] +- {}",
+            name,
+            units.format_value(interval.estimate),
+            units.format_value(interval.lower),
+            units.format_value(interval.upper),
+            units.format_value(interval.std_error)
+        );
+    }
+}
+
+// Combine work from several switching rates, and equilibrium samples at
+// intermediate lambda states, into MBAR free energies with their covariance
+fn run_mbar(config: &Config) {
+    report_bath(config);
+    let (units, bath, model) = (config.units, config.bath(), config.model());
+
+    println!("Switching rates combined by MBAR:");
+    let runs: Vec<_> = config
+        .rate_protocols()
+        .iter()
+        .map(|protocol| (protocol.duration, ensemble::run_paired(model.as_ref(), protocol, config.trajectories, config.seed)))
+        .collect();
+    for (duration, ensembles) in &runs {
+        println!(
+            "  duration {}: BAR {}",
+            units.format_value(*duration),
+            units.format_energy(estimators::bar(&ensembles.forward.work, &ensembles.reverse.work, bath), bath)
+        );
+    }
+    let sets: Vec<(&[f64], &[f64])> = runs
+        .iter()
+        .map(|(_, e)| (e.forward.work.as_slice(), e.reverse.work.as_slice()))
+        .collect();
+    let combined = mbar::combine_work(&sets, bath);
+    println!(
+        "  combined Delta F:    {} +- {} ({} iterations)",
+        units.format_energy(combined.delta_f(0, 1, bath), bath),
+        units.format_energy(combined.delta_f_error(0, 1, bath), bath),
+        combined.iterations
+    );
+
+    // The lambda states below hold the stiffness at its configured value
+    if config.stiffness_schedule.is_some() {
+        println!();
+        println!("MBAR over lambda states skipped: the protocol also drives the stiffness");
+        return;
+    }
+
+    // Equilibrium samples at states evenly spaced along the schedule, each
+    // state's from its own block of the seed's substreams after the switching
+    // trajectories'
+    let states = config.mbar_states.max(2);
+    let protocol = config.protocol();
+    let lambdas: Vec<f64> = (0..states)
+        .map(|k| protocol.lambda_at(protocol.duration * k as f64 / (states - 1) as f64))
+        .collect();
+    let samples: Vec<_> = lambdas
+        .iter()
+        .enumerate()
+        .flat_map(|(k, &lambda)| {
+            let seed = unirand::stream_seed(config.seed, (config.trajectories + k * config.mbar_samples) as u64);
+            ensemble::equilibrium_samples(model.as_ref(), lambda, config.mbar_samples, seed)
+        })
+        .collect();
+    let reduced: Vec<Vec<f64>> = lambdas
+        .iter()
+        .map(|&lambda| samples.iter().map(|state| bath.beta() * model.energy(state, lambda)).collect())
+        .collect();
+    let result = mbar::solve(&reduced, &vec![config.mbar_samples; states]);
+
+    println!();
+    println!("MBAR over {} lambda states ({} samples each, {} iterations):", states, config.mbar_samples, result.iterations);
+    println!("{:>10} {:>16} {:>16}", "lambda", "Delta F", "error");
+    for (k, lambda) in lambdas.iter().enumerate() {
+        println!(
+            "{:>10} {:>16} {:>16}",
+            units.format_value(*lambda),
+            units.format_energy(result.delta_f(0, k, bath), bath),
+            units.format_energy(result.delta_f_error(0, k, bath), bath)
+        );
+    }
+    println!();
+    println!("Covariance of beta F_k:");
+    for row in &result.covariance {
+        println!("  {}", row.iter().map(|c| format!("{:>12.3e}", c)).collect::<Vec<_>>().join(" "));
+    }
+}
+
+// Check the detailed fluctuation theorem P(sigma)/P(-sigma) = e^sigma on the
+// total entropy production of the forward ensemble, whose fitted slope of
+// ln(P(sigma)/P(-sigma)) against sigma should be one
+fn run_entropy(config: &Config) {
+    report_bath(config);
+    let ensemble = Ensemble::run(
+        config.model().as_ref(),
+        &config.protocol(),
+        config.direction,
+        config.trajectories,
+        config.seed,
+    );
+    let sigma = &ensemble.entropy_production;
+    let ratio = analysis::crooks_ratio(sigma, sigma, config.bins);
+
+    println!(
+        "{:?}: {} trajectories, <sigma> = {:.4} k_B ({})",
+        ensemble.direction,
+        ensemble.len(),
+        ensemble.mean_entropy_production(),
+        if ensemble.entropy_exact { "exact" } else { "KDE estimate of p(x, t)" }
+    );
+    let entropy = estimators::integral_check(sigma);
+    println!("<e^-sigma> = {:.4} +- {:.4} (integral theorem: 1)", entropy.mean, entropy.std_error);
+    println!();
+    println!("{:>10} {:>16}", "sigma", "ln(P/P(-))");
+    for point in ratio.iter().flat_map(|ratio| &ratio.points) {
+        println!("{:>10.4} {:>16.4}", point.work, point.log_ratio);
+    }
+    println!();
+    match &ratio {
+        Some(ratio) => {
+            println!("Fitted slope:        {:.4} (detailed theorem: 1)", ratio.slope);
+            println!("Fitted intercept:    {:.4} (detailed theorem: 0)", ratio.intercept);
+        }
+        None => println!("Detailed theorem:    no overlap of P(sigma) and P(-sigma) to fit a line through"),
+    }
+}
+
+// Trap parameters for the profile commands, which need the particle model with
+// a built-in landscape so that the trap bias is known separately
+fn landscape_params(config: &Config, command: &str) -> TrapParams {
+    if config.model != ModelKind::Particle || config.expression.is_some() {
+        eprintln!("{}: needs the particle model with a built-in landscape, so the trap bias is known", command);
+        std::process::exit(2);
+    }
+    config.trap_params()
+}
+
+// Print a reconstructed profile next to the true landscape, shifted to its
+// minimum over the visited bins, and write it out when requested
+fn report_profile(config: &Config, params: &TrapParams, profile: &Profile) {
+    let (units, bath) = (config.units, config.bath());
+    let visited = || profile.positions.iter().zip(&profile.free_energy).filter(|(_, g)| g.is_finite());
+    let floor = visited().map(|(&x, _)| params.landscape_energy(x)).fold(f64::INFINITY, f64::min);
+    println!("{:>10} {:>16} {:>16}", "x", "G(x)", "U0(x)");
+    for (&x, &g) in visited() {
+        println!(
+            "{:>10} {:>16} {:>16}",
+            units.format_value(x),
+            units.format_energy(g, bath),
+            units.format_energy(params.landscape_energy(x) - floor, bath)
+        );
+    }
+    if let Some(path) = &config.profile_output {
+        if let Err(e) = profile.write_csv(path) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        println!("Profile written to {}", path);
+    }
+}
+
+// Reconstruct the unperturbed landscape G(x) from position-resolved forward
+// work with the Hummer-Szabo estimator
+fn run_profile(config: &Config) {
+    report_bath(config);
+    let params = landscape_params(config, "profile");
+    let protocol = config.protocol().oriented(config.direction);
+    let slices = profile::record_slices(&params, &protocol, config.trajectories, config.seed, config.profile_stride);
+    let profile = profile::hummer_szabo(&slices, &params, config.bins);
+    println!(
+        "Hummer-Szabo profile from {} time slices of {} {:?} trajectories:",
+        slices.len(),
+        config.trajectories,
+        config.direction
+    );
+    report_profile(config, &params, &profile);
+}
+
+// Stitch equilibrium umbrella windows with the trap held at evenly spaced
+// points of the schedule into one profile with WHAM, each window drawn from
+// its own block of the seed's substreams
+fn run_wham(config: &Config) {
+    report_bath(config);
+    let params = landscape_params(config, "wham");
+    let protocol = config.protocol();
+    let count = config.windows.max(2);
+    let windows: Vec<wham::Window> = (0..count)
+        .map(|k| {
+            let lambda = protocol.lambda_at(protocol.duration * k as f64 / (count - 1) as f64);
+            let seed = unirand::stream_seed(config.seed, (k * config.window_samples) as u64);
+            let samples = ensemble::equilibrium_samples(&params, lambda, config.window_samples, seed);
+            wham::Window {
+                lambda,
+                positions: samples.iter().map(|s| s.x[0]).collect(),
+            }
+        })
+        .collect();
+
+    let trap = |x: f64, lambda: f64| 0.5 * params.stiffness * params.minimum_image(x - lambda).powi(2);
+    let result = wham::wham(&windows, trap, config.bath(), config.bins, params.box_length);
+    println!(
+        "WHAM profile from {} windows of {} samples ({} iterations):",
+        count, config.window_samples, result.iterations
+    );
+    report_profile(config, &params, &result.profile);
+
+    let (units, bath) = (config.units, config.bath());
+    println!();
+    println!("{:>10} {:>16}", "lambda", "Delta F");
+    for (window, f) in windows.iter().zip(&result.window_free_energies) {
+        println!("{:>10} {:>16}", units.format_value(window.lambda), units.format_energy(bath.kt() * f, bath));
+    }
+}
+
+// Drive the particle with measurement and feedback and check the generalized
+// Jarzynski equality of Sagawa and Ueda with the mutual information term
+fn run_feedback(config: &Config) {
+    report_bath(config);
+    if config.model != ModelKind::Particle {
+        eprintln!("feedback: needs the particle model, whose trap can be moved after a measurement");
+        std::process::exit(2);
+    }
+    let (units, bath) = (config.units, config.bath());
+    let params = config.trap_params();
+    let results = feedback::run(
+        &params,
+        &config.protocol().oriented(config.direction),
+        config.measurement_error,
+        config.feedback_gain,
+        config.trajectories,
+        config.seed,
+    );
+    let n = results.len() as f64;
+    let mean = |f: &dyn Fn(&feedback::FeedbackTrajectory) -> f64| results.iter().map(f).sum::<f64>() / n;
+    let (work, delta_f, information) = (mean(&|r| r.work), mean(&|r| r.delta_f), mean(&|r| r.information));
+    println!(
+        "Feedback: measurement error {}, gain {}, {} {:?} trajectories",
+        units.format_value(config.measurement_error),
+        config.feedback_gain,
+        results.len(),
+        config.direction
+    );
+    println!("<W> = {}, <Delta F> = {}", units.format_energy(work, bath), units.format_energy(delta_f, bath));
+    println!("<I> = {:.4} nats of mutual information", information);
+    println!(
+        "<W - Delta F> = {} >= -kT <I> = {} (second law with information)",
+        units.format_energy(work - delta_f, bath),
+        units.format_energy(-bath.kt() * information, bath)
+    );
+
+    let dissipated: Vec<f64> = results.iter().map(|r| bath.beta() * (r.work - r.delta_f)).collect();
+    let with_information: Vec<f64> = results.iter().zip(&dissipated).map(|(r, d)| d + r.information).collect();
+    let sagawa_ueda = estimators::integral_check(&with_information);
+    let efficacy = estimators::integral_check(&dissipated);
+    println!(
+        "<e^-beta(W - Delta F) - I> = {:.4} +- {:.4} (Sagawa-Ueda: 1)",
+        sagawa_ueda.mean, sagawa_ueda.std_error
+    );
+    println!(
+        "<e^-beta(W - Delta F)> = {:.4} +- {:.4} (feedback efficacy, 1 without feedback)",
+        efficacy.mean, efficacy.std_error
+    );
+}
+
+// Mean force on the trap against its position for forward and reverse pulls,
+// and the area of the hysteresis loop they enclose against the dissipated work
+fn run_hysteresis(config: &Config) {
+    if config.model != ModelKind::Particle || config.escort != 0.0 || config.stiffness_schedule.is_some() {
+        eprintln!("hysteresis: needs the particle model pulled by the trap centre alone, without escort");
+        std::process::exit(2);
+    }
+    report_bath(config);
+    let (units, bath) = (config.units, config.bath());
+    let params = config.trap_params();
+    let result = hysteresis::record(&params, &config.protocol(), config.trajectories, config.seed);
+    let (forward, reverse) = (&result.forward, &result.reverse);
+
+    // The reverse pull retraces the forward path, so its step i sits at forward step n - 1 - i
+    let n = forward.lambda.len();
+    let rows = config.bins.clamp(2, n);
+    println!("{:>10} {:>14} {:>14}", "lambda", "<f_F>", "<f_R>");
+    for row in 0..rows {
+        let i = row * (n - 1) / (rows - 1);
+        println!(
+            "{:>10} {:>14} {:>14}",
+            units.format_value(forward.lambda[i]),
+            units.format_value(forward.force[i]),
+            units.format_value(reverse.force[n - 1 - i])
+        );
+    }
+    println!();
+    let area = result.loop_area();
+    let (forward_work, reverse_work) = (forward.mean_work(), reverse.mean_work());
+    let delta_f = estimators::bar(&forward.work, &reverse.work, bath);
+    println!("Hysteresis loop area:       {}", units.format_energy(area, bath));
+    println!(
+        "<W_F> + <W_R>:              {} (equal to the area; Delta F cancels)",
+        units.format_energy(forward_work + reverse_work, bath)
+    );
+    println!(
+        "<W_diss,F> + <W_diss,R>:    {} + {} with the BAR Delta F = {}",
+        units.format_energy(forward_work - delta_f, bath),
+        units.format_energy(reverse_work + delta_f, bath),
+        units.format_energy(delta_f, bath)
+    );
+    if let Some(path) = &config.hysteresis_output {
+        let mut text = String::from("lambda,f_forward,f_reverse\n");
+        for i in 0..n {
+            text.push_str(&format!("{},{},{}\n", forward.lambda[i], forward.force[i], reverse.force[n - 1 - i]));
+        }
+        if let Err(e) = std::fs::write(path, text) {
+            eprintln!("hysteresis: cannot write {}: {}", path, e);
+            std::process::exit(1);
+        }
+        println!("Force-extension curves written to {}", path);
+    }
+    if let Some(path) = &config.summary_output {
+        let rows = vec![
+            (Vec::new(), WorkSummary::new(Direction::Forward, &forward.work, delta_f)),
+            (Vec::new(), WorkSummary::new(Direction::Reverse, &reverse.work, delta_f)),
+        ];
+        if let Err(e) = summary::write_csv(path, &[], &rows) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        println!("Work moments written to {}", path);
+    }
+}
+
+// Run the protocol at every listed temperature and compare the work
+// distributions and the fitted Crooks slope with beta at each one
+fn run_replicas(config: &Config) {
+    report_bath(config);
+    let units = config.units;
+    let replicas = replica::run(config);
+    println!("{} replicas of {} trajectories per direction:", replicas.len(), config.trajectories);
+    println!(
+        "{:>10} {:>12} {:>12} {:>12} {:>12} {:>10} {:>10} {:>12}",
+        "T", "<W_F>", "std(W_F)", "<W_R>", "std(W_R)", "slope", "beta", "BAR"
+    );
+    for replica in &replicas {
+        let (forward, reverse) = (&replica.ensembles.forward, &replica.ensembles.reverse);
+        let ratio = analysis::crooks_ratio(&forward.work, &reverse.work, config.bins);
+        println!(
+            "{:>10} {:>12} {:>12} {:>12} {:>12} {:>10} {:>10} {:>12}",
+            units.format_value(replica.setting),
+            units.format_value(forward.mean_work()),
+            units.format_value(forward.work_std()),
+            units.format_value(reverse.mean_work()),
+            units.format_value(reverse.work_std()),
+            ratio.map_or("no overlap".to_string(), |ratio| units.format_value(ratio.slope)),
+            units.format_value(replica.bath.beta()),
+            units.format_value(estimators::bar(&forward.work, &reverse.work, replica.bath))
+        );
+    }
+    if let Some(path) = &config.replica_output {
+        if let Err(e) = replica::write_csv(&replicas, path) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        println!("Work of every replica written to {}", path);
+    }
+    if let Some(path) = &config.summary_output {
+        let rows: Vec<_> = replicas
+            .iter()
+            .flat_map(|replica| {
+                let (forward, reverse) = (&replica.ensembles.forward, &replica.ensembles.reverse);
+                let delta_f = estimators::bar(&forward.work, &reverse.work, replica.bath);
+                WorkSummary::paired(&replica.ensembles, delta_f).map(|s| (vec![replica.setting, replica.bath.kt()], s))
+            })
+            .collect();
+        if let Err(e) = summary::write_csv(path, &["temperature", "kT"], &rows) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        println!("Work moments of every replica written to {}", path);
+    }
+}
+
+// Drive the configured model periodically into its steady state and check the
+// steady-state fluctuation theorem ln P(W_n)/P(-W_n) -> beta W_n for the work
+// over n cycles, whose slope approaches beta only as n grows
+fn run_steady(config: &Config) {
+    report_bath(config);
+    if config.model == ModelKind::Chain {
+        eprintln!("steady: the chain model relaxes afresh from each start, so it cannot carry its state across cycles");
+        std::process::exit(2);
+    }
+    let (units, bath) = (config.units, config.bath());
+    let result = steady::run(
+        config.model().as_ref(),
+        &config.protocol(),
+        config.transient_cycles,
+        config.cycles,
+        config.trajectories,
+        config.seed,
+    );
+    println!(
+        "Steady state: {} trajectories, {} transient and {} recorded cycles of the schedule and its reverse",
+        config.trajectories, config.transient_cycles, config.cycles
+    );
+    println!(
+        "<W> = {}, <Q> = {} per cycle (balance in steady state: <W> + <Q> = {})",
+        units.format_energy(result.mean_work(), bath),
+        units.format_energy(result.mean_heat(), bath),
+        units.format_energy(result.mean_work() + result.mean_heat(), bath)
+    );
+    println!();
+    println!("{:>8} {:>10} {:>14} {:>18}", "cycles", "samples", "<W_n>", "slope / beta");
+    let mut n = 1;
+    while n <= config.cycles {
+        let work = result.block_work(n);
+        let mean = work.iter().sum::<f64>() / work.len() as f64;
+        let ratio = analysis::crooks_ratio(&work, &work, config.bins);
+        let slope = ratio.map_or("no W_n < 0".to_string(), |ratio| format!("{:.4}", ratio.slope * bath.kt()));
+        println!("{:>8} {:>10} {:>14} {:>18}", n, work.len(), units.format_energy(mean, bath), slope);
+        n *= 2;
+    }
+    println!();
+    println!("Steady-state theorem: slope / beta -> 1 as the number of cycles grows");
+}
+
+// Record forward trajectories of the particle model frame by frame and write
+// them to the record file for playback
+fn run_record(config: &Config) {
+    if config.model != ModelKind::Particle {
+        eprintln!("record: needs the particle model, whose full trajectories are available");
+        std::process::exit(2);
+    }
+    let params = config.trap_params();
+    let recording = Recording {
+        direction: config.direction,
+        trajectories: recording::record(
+            &params,
+            &config.protocol().oriented(config.direction),
+            config.trajectories,
+            config.seed,
+            config.record_stride,
+            config.record_count,
+            config.record_select,
+        ),
+    };
+    if let Err(e) = recording.write_csv(&config.record_file) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    let trajectories = &recording.trajectories;
+    let negative = trajectories.iter().filter(|f| f.last().is_some_and(|f| f.work < 0.0)).count();
+    println!(
+        "{} {:?} trajectories ({:?} of {}) written to {}, {} with negative final work",
+        trajectories.len(),
+        recording.direction,
+        config.record_select,
+        config.trajectories,
+        config.record_file,
+        negative
+    );
+}
+
+// Replay recorded trajectories in a window at playback_speed time units per
+// second; space pauses, R restarts and the animation loops at the end
+fn run_playback(config: &Config) {
+    let recording = Recording::read_csv(&config.record_file).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let trajectories = &recording.trajectories;
+    let end = trajectories.iter().filter_map(|f| f.last()).map(|f| f.t).fold(0.0, f64::max);
+    let title = format!("Trajectory playback ({:?}): W vs x", recording.direction);
+    let mut window = Window::new(&title, WIDTH, HEIGHT, WindowOptions::default())
+        .unwrap_or_else(|e| {
+            panic!("{}", e);
+        });
+    let mut buffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
+    let mut t = 0.0;
+    let mut paused = false;
+    let mut last = Instant::now();
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        if window.is_key_pressed(Key::Space, KeyRepeat::No) {
+            paused = !paused;
+        }
+        if window.is_key_pressed(Key::R, KeyRepeat::No) {
+            t = 0.0;
+        }
+        let now = Instant::now();
+        if !paused {
+            t += (now - last).as_secs_f64() * config.playback_speed;
+            if t > end {
+                t = 0.0;
+            }
+        }
+        last = now;
+        plot::draw_playback(&mut buffer, WIDTH, HEIGHT, trajectories, t);
+        window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
+    }
+}
+
+// Search piecewise trap schedules for the least dissipation at the configured
+// duration, check the result on fresh trajectories against the linear ramp
+// between the same end points, and show both schedules in a window
+fn run_optimize(config: &Config) {
+    report_bath(config);
+    let (units, bath) = (config.units, config.bath());
+    let model = config.model();
+    let linear = Protocol {
+        schedule: Schedule::Linear {
+            from: config.lambda_start,
+            to: config.lambda_end,
+        },
+        ..config.protocol()
+    };
+    let result = optimize::optimize(
+        model.as_ref(),
+        &linear,
+        config.optimize_knots,
+        config.optimize_trajectories,
+        config.seed,
+        config.optimize_iterations,
+    );
+    println!(
+        "Optimized {} knots over {} ensembles of {} trajectories: <W> = {} on the training streams",
+        config.optimize_knots,
+        result.evaluations,
+        config.optimize_trajectories,
+        units.format_energy(result.mean_work, bath)
+    );
+    let knots: Vec<String> = result.knots.iter().map(|(s, l)| format!("{:.4}:{:.4}", s, l)).collect();
+    println!("schedule_knots = \"{}\"", knots.join(", "));
+
+    // Fresh streams, the substreams of the seed past the training ones, so the
+    // comparison is not flattered by fitting their noise
+    let seed = unirand::stream_seed(config.seed, config.optimize_trajectories as u64);
+    let baseline = ensemble::run_paired(model.as_ref(), &linear, config.trajectories, seed);
+    let optimized = Ensemble::run(model.as_ref(), &result.protocol, Direction::Forward, config.trajectories, seed);
+    let (delta_f, source) = match model.exact_delta_f(&linear) {
+        Some(delta_f) => (delta_f, "exact"),
+        None => (estimators::bar(&baseline.forward.work, &baseline.reverse.work, bath), "BAR"),
+    };
+    println!(
+        "Checked on {} fresh trajectories, {} Delta F = {}:",
+        config.trajectories,
+        source,
+        units.format_energy(delta_f, bath)
+    );
+    for (name, ensemble) in [("linear ramp", &baseline.forward), ("optimized", &optimized)] {
+        println!(
+            "  {:<12} <W> = {}, <W_diss> = {}",
+            name,
+            units.format_energy(ensemble.mean_work(), bath),
+            units.format_energy(ensemble.mean_work() - delta_f, bath)
+        );
+    }
+
+    let mut buffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
+    plot::draw_schedules(&mut buffer, WIDTH, HEIGHT, &linear.schedule, &result.protocol.schedule, linear.duration);
+    let mut window = match Window::new("Optimized lambda(t) against the linear ramp", WIDTH, HEIGHT, WindowOptions::default()) {
+        Ok(window) => window,
+        Err(e) => {
+            eprintln!("optimize: cannot open a window: {}", e);
+            return;
+        }
+    };
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
+    }
+}
+
+// Measure the thermodynamic metric along the trap path and compare the linear
+// ramp with the geodesic schedule, by the linear-response prediction of their
+// excess work and by simulating both
+fn run_metric(config: &Config) {
+    if config.model != ModelKind::Particle || config.stiffness_schedule.is_some() {
+        eprintln!("metric: needs the particle model pulled by the trap centre alone");
+        std::process::exit(2);
+    }
+    report_bath(config);
+    let (units, bath) = (config.units, config.bath());
+    let metric = config.metric();
+    println!(
+        "Thermodynamic metric from {} equilibrium runs of {} held at each lambda:",
+        config.metric_points,
+        units.format_value(config.metric_time)
+    );
+    println!("{:>10} {:>12} {:>12} {:>12}", "lambda", "var(X)", "tau_X", "zeta");
+    for point in &metric.points {
+        println!(
+            "{:>10} {:>12} {:>12} {:>12}",
+            units.format_value(point.lambda),
+            units.format_value(point.variance),
+            units.format_value(point.relaxation),
+            units.format_value(point.zeta)
+        );
+    }
+    let length = metric.length();
+    println!();
+    println!(
+        "Thermodynamic length L = {}, excess work bound L^2 / duration = {}",
+        units.format_value(length),
+        units.format_energy(length * length / config.duration, bath)
+    );
+    let knots = metric.geodesic_knots();
+    let text: Vec<String> = knots.iter().map(|(s, l)| format!("{:.4}:{:.4}", s, l)).collect();
+    println!("Geodesic schedule_knots = \"{}\"", text.join(", "));
+
+    let base = config.protocol();
+    let linear = Protocol {
+        schedule: Schedule::Linear {
+            from: config.lambda_start,
+            to: config.lambda_end,
+        },
+        ..base.clone()
+    };
+    let geodesic = Protocol {
+        schedule: Schedule::Piecewise {
+            knots: Arc::new(knots),
+            reversed: false,
+        },
+        ..base
+    };
+    let model = config.model();
+    let ramp = ensemble::run_paired(model.as_ref(), &linear, config.trajectories, config.seed);
+    let (delta_f, source) = match model.exact_delta_f(&linear) {
+        Some(delta_f) => (delta_f, "exact"),
+        None => (estimators::bar(&ramp.forward.work, &ramp.reverse.work, bath), "BAR"),
+    };
+    let shortest = Ensemble::run(model.as_ref(), &geodesic, Direction::Forward, config.trajectories, config.seed);
+    println!();
+    println!("Excess work with the {} Delta F = {}:", source, units.format_energy(delta_f, bath));
+    for (name, protocol, ensemble) in [("linear ramp", &linear, &ramp.forward), ("geodesic", &geodesic, &shortest)] {
+        println!(
+            "  {:<12} predicted {}, simulated {}",
+            name,
+            units.format_energy(metric.excess_work(protocol), bath),
+            units.format_energy(ensemble.mean_work() - delta_f, bath)
+        );
+    }
+}
+
+// Fluctuation-dissipation ratio X(omega) of the trap dragged at constant speed:
+// one at every frequency in equilibrium, and departing from it as the driving
+// pushes the particle out of equilibrium
+fn run_fdt(config: &Config) {
+    if config.model != ModelKind::Particle || config.expression.is_some() || config.stiffness_schedule.is_some() {
+        eprintln!("fdt: needs the particle model in a built-in landscape with a fixed trap stiffness");
+        std::process::exit(2);
+    }
+    report_bath(config);
+    let units = config.units;
+    let speed = (config.lambda_end - config.lambda_start) / config.duration;
+    let points = fdt::measure(config);
+    println!(
+        "Trap dragged at speed {}, wiggled by {}: {} trajectories of {} after a transient of {}",
+        units.format_value(speed),
+        units.format_value(config.fdt_amplitude),
+        config.fdt_trajectories,
+        units.format_value(config.fdt_time),
+        units.format_value(config.fdt_transient)
+    );
+    println!(
+        "{:>10} {:>12} {:>12} {:>16} {:>10}",
+        "omega", "chi'", "chi''", "beta w C(w)", "X(omega)"
+    );
+    for point in &points {
+        println!(
+            "{:>10} {:>12} {:>12} {:>16} {:>10.4}",
+            units.format_value(point.omega),
+            units.format_value(point.in_phase),
+            units.format_value(point.dissipative),
+            units.format_value(point.fluctuation),
+            point.ratio()
+        );
+    }
+}
+
+// Characteristic functions of the forward work and of the reweighted reverse
+// work, which the Crooks theorem continued to complex argument makes equal
+fn run_characteristic(config: &Config) {
+    let ensembles = run_experiment(config);
+    report_bath(config);
+    let (units, bath) = (config.units, config.bath());
+    let (forward, reverse) = (&ensembles.forward.work, &ensembles.reverse.work);
+    let (delta_f, source) = match config.model().exact_delta_f(&config.protocol()) {
+        Some(delta_f) => (delta_f, "exact"),
+        None => (estimators::bar(forward, reverse, bath), "BAR"),
+    };
+    let curves = characteristic::crooks_symmetry(
+        forward,
+        reverse,
+        bath,
+        delta_f,
+        config.characteristic_bins,
+        config.characteristic_points,
+    );
+    // Beyond this |G_F| is comparable to the sampling noise of either curve
+    let floor = 3.0 / (forward.len().min(reverse.len()) as f64).sqrt();
+    let u_max = curves.resolved_range(floor);
+    println!(
+        "G_F(u) = <e^(iuW_F)> against G_R(-u + i beta) e^(-beta Delta F), {} Delta F = {}:",
+        source,
+        units.format_energy(delta_f, bath)
+    );
+    println!(
+        "{:>10} {:>12} {:>12} {:>12} {:>12}",
+        "u", "Re G_F", "Im G_F", "Re mirror", "Im mirror"
+    );
+    let shown = curves.u.iter().take_while(|&&u| u <= u_max).count();
+    for i in (0..shown).step_by(shown.div_ceil(20).max(1)) {
+        let (f, m) = (curves.forward[i], curves.mirrored[i]);
+        println!(
+            "{:>10.4} {:>12.4} {:>12.4} {:>12.4} {:>12.4}",
+            curves.u[i], f.re, f.im, m.re, m.im
+        );
+    }
+    println!(
+        "Largest |G_F(u) - G_R(-u + i beta) e^(-beta Delta F)| = {:.4} for u up to {:.4}, where |G_F| >= {:.4}",
+        curves.max_deviation(u_max),
+        u_max,
+        floor
+    );
+    if let Some(path) = &config.characteristic_output {
+        if let Err(e) = curves.write_csv(path) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        println!("Characteristic functions written to {}", path);
+    }
+}
+
+// Exact two-point-measurement work distributions of the quantum oscillator
+// dragged by the trap, their Crooks relation and the classical Gaussian they
+// approach as hbar Omega falls below kT; H toggles from the ratio to the
+// distributions
+fn run_quantum(config: &Config) {
+    if config.model != ModelKind::Particle || config.counterdiabatic {
+        eprintln!("quantum: needs the particle model without counterdiabatic driving");
+        std::process::exit(2);
+    }
+    let Some(reference) = quantum::oscillator(&config.trap_params(), &config.protocol(), config.hbar()) else {
+        eprintln!("quantum: needs a flat or harmonic landscape and a fixed trap stiffness");
+        std::process::exit(2);
+    };
+    let (units, bath) = (config.units, config.bath());
+    let (forward, reverse) = (&reference.forward, &reference.reverse);
+    let beta = bath.beta();
+    println!(
+        "Quantum oscillator: hbar Omega = {}, beta hbar Omega = {:.4}, thermal occupation n = {:.4}",
+        units.format_energy(forward.hbar_omega, bath),
+        beta * forward.hbar_omega,
+        forward.occupation
+    );
+    println!(
+        "Two-point measurement, isolated during the drag: exact Delta F = {}",
+        units.format_energy(forward.delta_f, bath)
+    );
+    for (name, work) in [("Forward", forward), ("Reverse", reverse)] {
+        println!(
+            "{}: {:.4} quanta excited, <W> = {}, var(W) = {} quantum, {} classical",
+            name,
+            work.excitation,
+            units.format_energy(work.mean(), bath),
+            units.format_value(work.variance()),
+            units.format_value(work.classical_variance())
+        );
+    }
+
+    // Levels of the forward work against the reverse one at -W, with the
+    // classical densities scaled to probabilities per level
+    let pairs = reference.paired_levels(QUANTUM_FLOOR);
+    println!();
+    println!(
+        "{:>6} {:>10} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "m", "W", "P_F(W)", "P_R(-W)", "ln ratio", "beta(W-dF)", "classical"
+    );
+    for pair in pairs.iter().step_by(pairs.len().div_ceil(30).max(1)) {
+        println!(
+            "{:>6} {:>10} {:>12.4e} {:>12.4e} {:>12.4} {:>12.4} {:>12.4e}",
+            pair.quanta,
+            units.format_value(pair.work),
+            pair.forward,
+            pair.reverse,
+            (pair.forward / pair.reverse).ln(),
+            beta * (pair.work - forward.delta_f),
+            forward.hbar_omega * forward.classical_density(pair.work)
+        );
+    }
+
+    // G_F(u) against G_R(-u + i beta) e^(-beta Delta F) over one period of G_F
+    let period = 2.0 * PI / forward.hbar_omega;
+    let deviation = (0..=QUANTUM_CHECK_POINTS)
+        .map(|i| {
+            let u = Complex64::new(period * i as f64 / QUANTUM_CHECK_POINTS as f64, 0.0);
+            let mirror = reverse.characteristic(-u + Complex64::new(0.0, beta)) * (-beta * forward.delta_f).exp();
+            (forward.characteristic(u) - mirror).norm()
+        })
+        .fold(0.0, f64::max);
+    println!();
+    println!("Largest |G_F(u) - G_R(-u + i beta) e^(-beta Delta F)| over a period of G_F: {:.2e}", deviation);
+
+    if let Some(path) = &config.quantum_output {
+        if let Err(e) = quantum::write_csv(path, &reference, &pairs) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        println!("Work levels written to {}", path);
+    }
+
+    let ratio = reference.crooks_ratio(QUANTUM_FLOOR);
+    let mut ratio_buffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
+    let mut distribution_buffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
+    plot::draw_crooks_ratio(&mut ratio_buffer, WIDTH, HEIGHT, Some(&ratio));
+    plot::draw_quantum_work(&mut distribution_buffer, WIDTH, HEIGHT, &reference);
+    let mut window = match Window::new("Quantum Crooks ratio ln(P_F/P_R) vs W", WIDTH, HEIGHT, WindowOptions::default()) {
+        Ok(window) => window,
+        Err(e) => {
+            eprintln!("quantum: cannot open a window: {}", e);
+            return;
+        }
+    };
+    let mut distributions = false;
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        if window.is_key_pressed(Key::H, KeyRepeat::No) {
+            distributions = !distributions;
+        }
+        let buffer = if distributions { &distribution_buffer } else { &ratio_buffer };
+        window.update_with_buffer(buffer, WIDTH, HEIGHT).unwrap();
+    }
+}
+
+// Physics smoke test: run the configured ensembles, by default the selftest
+// preset, and check the Crooks relation and the Jarzynski equality against the
+// exact Delta F, exiting with status 1 if any check fails
+fn run_selftest(config: &Config) {
+    let ensembles = run_experiment(config);
+    let Some(exact) = config.model().exact_delta_f(&config.protocol()) else {
+        eprintln!("selftest: needs a model with Delta F known exactly");
+        std::process::exit(2);
+    };
+    let (units, bath) = (config.units, config.bath());
+    let (beta, tolerance) = (bath.beta(), config.selftest_tolerance);
+    let ratio = analysis::crooks_ratio(&ensembles.forward.work, &ensembles.reverse.work, config.bins);
+    let forward = estimators::jarzynski(&ensembles.forward.work, bath);
+    let reverse = estimators::jarzynski(&ensembles.reverse.work, bath);
+    println!(
+        "Self-test: {} forward and reverse trajectories, exact Delta F = {}, tolerance {} kT",
+        config.trajectories,
+        units.format_energy(exact, bath),
+        tolerance
+    );
+
+    // Free-energy errors are compared in units of kT, the slope relative to beta;
+    // the reduced chi-square of a straight line through the counting noise
+    // scatters about one by sqrt(2 / dof)
+    let dof = ratio.as_ref().map_or(0, |ratio| ratio.points.len()).saturating_sub(2).max(1) as f64;
+    let chi_limit = 1.0 + SELFTEST_CHI_SIGMAS * (2.0 / dof).sqrt();
+
+    // A generator rebuilt from its saved state must continue the stream bit for bit
+    let mut original = MarsagliaUniRng::with_gaussian(config.gaussian);
+    if let Err(e) = original.initialise(config.seed) {
+        eprintln!("selftest: {}", e);
+        std::process::exit(2);
+    }
+    for _ in 0..SELFTEST_DRAWS {
+        original.generate();
+    }
+    let mismatches = match MarsagliaUniRng::load_state(&original.save_state()) {
+        Ok(mut restored) => (0..SELFTEST_DRAWS).filter(|_| original.generate_f64().to_bits() != restored.generate_f64().to_bits()).count(),
+        Err(_) => SELFTEST_DRAWS,
+    };
+    // The checks on the fit of ln(P_F/P_R) also fail when the histograms
+    // overlap in too few bins to fit a line through; the others stand alone
+    let fitted = ratio.as_ref().is_some_and(|ratio| ratio.points.len() >= 3);
+    if !fitted {
+        println!("  no overlap of P_F(W) and P_R(-W) in three bins or more to fit a line through");
+    }
+    let (chi_square, slope_error, crooks_error) = match &ratio {
+        Some(ratio) => (ratio.reduced_chi_square(), (ratio.slope / beta - 1.0).abs(), beta * (ratio.delta_f() - exact).abs()),
+        None => (f64::NAN, f64::NAN, f64::NAN),
+    };
+    let checks = [
+        ("ln(P_F/P_R) linear in W", chi_square, chi_limit, "reduced chi-square", true),
+        ("fitted slope equals beta", slope_error, tolerance, "relative error", true),
+        ("Crooks Delta F", crooks_error, tolerance, "error in kT", true),
+        ("Jarzynski forward", beta * (forward.delta_f - exact).abs(), tolerance, "error in kT", false),
+        ("Jarzynski reverse", beta * (-reverse.delta_f - exact).abs(), tolerance, "error in kT", false),
+        ("generator state restored", mismatches as f64, 0.0, "mismatched draws", false),
+    ];
+    let mut failures = 0;
+    for (name, value, limit, measure, needs_fit) in checks {
+        let passed = (fitted || !needs_fit) && value <= limit;
+        if !passed {
+            failures += 1;
+        }
+        println!(
+            "  {:<26} {} {} = {:.4} (limit {:.4})",
+            name,
+            if passed { "PASS" } else { "FAIL" },
+            measure,
+            value,
+            limit
+        );
+    }
+    if failures > 0 {
+        println!("Self-test FAILED: {} of {} checks", failures, checks.len());
+        std::process::exit(1);
+    }
+    println!("Self-test passed");
+}
+
+// Statistical tests of the configured generator on substream 0 of the seed:
+// uniformity by chi-square and Kolmogorov-Smirnov, independence by serial
+// correlations, the Gaussian sampler by Kolmogorov-Smirnov against the
+// normal distribution, and the exponential, gamma and Poisson variates by
+// their first two moments. Any p-value below RNGTEST_SIGNIFICANCE fails, so a
+// sound generator fails a test about once in a thousand runs per test.
+fn run_rngtest(config: &Config) {
+    let draws = config.rngtest_draws;
+    unirand::seed_stream(config.seed, 0);
+    let (mut uniforms, mut gaussians) = (vec![0.0; draws], vec![0.0; draws]);
+    simulation::fill_uniform(&mut uniforms);
+    simulation::fill_gaussian(&mut gaussians);
+    println!(
+        "Generator test: {:?} with the {:?} sampler, {} uniform and {} Gaussian draws",
+        config.rng, config.gaussian, draws, draws
+    );
+
+    let mut tests = vec![
+        rngtest::chi_square(&uniforms, RNGTEST_BINS),
+        rngtest::kolmogorov_smirnov("uniform", &uniforms, |u| u.clamp(0.0, 1.0)),
+    ];
+    tests.extend(RNGTEST_LAGS.iter().map(|&lag| rngtest::serial_correlation(&uniforms, lag)));
+    tests.push(rngtest::kolmogorov_smirnov("Gaussian", &gaussians, normality::normal_cdf));
+
+    // Variates built on the stream, against their exact mean and variance
+    type Variate = Box<dyn Fn() -> f64>;
+    let variates: [(&str, Variate, f64, f64); 5] = [
+        ("exponential, rate 1", Box::new(|| simulation::exponential(1.0)), 1.0, 1.0),
+        ("gamma, shape 0.5", Box::new(|| simulation::gamma(0.5, 1.0)), 0.5, 0.5),
+        ("gamma, shape 3.5 scale 2", Box::new(|| simulation::gamma(3.5, 2.0)), 7.0, 14.0),
+        ("Poisson, mean 3", Box::new(|| simulation::poisson(3.0) as f64), 3.0, 3.0),
+        ("Poisson, mean 250", Box::new(|| simulation::poisson(250.0) as f64), 250.0, 250.0),
+    ];
+    for (name, draw, mean, variance) in &variates {
+        let samples: Vec<f64> = (0..draws).map(|_| draw()).collect();
+        tests.push(rngtest::moments(name, &samples, *mean, *variance));
+    }
+
+    let failures = tests.iter().filter(|test| test.p_value < RNGTEST_SIGNIFICANCE).count();
+    for test in &tests {
+        println!(
+            "  {:<36} {} statistic = {:.6} p = {:.4}",
+            test.name,
+            if test.p_value < RNGTEST_SIGNIFICANCE { "FAIL" } else { "PASS" },
+            test.statistic,
+            test.p_value
+        );
+    }
+    if failures > 0 {
+        println!("Generator test FAILED: {} of {} tests", failures, tests.len());
+        std::process::exit(1);
+    }
+    println!("Generator test passed");
+}
+
+// Rerun the free-energy estimators in double-double arithmetic and set the
+// change in each against its bootstrap standard error: a discrepancy between
+// estimators far larger than the change is statistical, not rounding
+#[cfg(feature = "extended-precision")]
+fn run_precision(config: &Config) {
+    let ensembles = run_experiment(config);
+    report_bath(config);
+    let (units, bath, order) = (config.units, config.bath(), config.cumulant_order);
+    let (forward, reverse) = (&ensembles.forward.work, &ensembles.reverse.work);
+    type Estimator = Box<dyn Fn(&[f64], &[f64]) -> f64 + Sync>;
+    type Extended = Box<dyn Fn(&[f64], &[f64]) -> precision::DoubleDouble>;
+    let estimators: Vec<(&str, Estimator, Extended)> = vec![
+        (
+            "Jarzynski forward",
+            Box::new(move |f, _| estimators::jarzynski(f, bath).delta_f),
+            Box::new(move |f, _| precision::jarzynski(f, bath)),
+        ),
+        (
+            "Jarzynski reverse",
+            Box::new(move |_, r| -estimators::jarzynski(r, bath).delta_f),
+            Box::new(move |_, r| -precision::jarzynski(r, bath)),
+        ),
+        (
+            "Cumulant forward",
+            Box::new(move |f, _| estimators::cumulant(f, bath, order)),
+            Box::new(move |f, _| precision::cumulant(f, bath, order)),
+        ),
+        (
+            "Cumulant reverse",
+            Box::new(move |_, r| -estimators::cumulant(r, bath, order)),
+            Box::new(move |_, r| -precision::cumulant(r, bath, order)),
+        ),
+        (
+            "BAR",
+            Box::new(move |f, r| estimators::bar(f, r, bath)),
+            Box::new(move |f, r| precision::bar(f, r, bath)),
+        ),
+    ];
+
+    let replicates = if config.bootstrap > 0 { config.bootstrap } else { PRECISION_REPLICATES };
+    let seed = resample::replicate_seed(config.seed, config.trajectories);
+    println!(
+        "Estimators in double and double-double precision, {} trajectories each way, \
+         standard errors from {} bootstrap replicates:",
+        config.trajectories, replicates
+    );
+    println!(
+        "{:<20} {:>14} {:>24} {:>12} {:>12}",
+        "estimator", "double", "double-double", "rounding", "std error"
+    );
+    for (name, estimator, extended) in &estimators {
+        let double = estimator(forward, reverse);
+        let exact = extended(forward, reverse);
+        let rounding = (exact - double.into()).to_f64().abs();
+        let interval = resample::bootstrap(forward, reverse, 1, replicates, config.confidence, seed, estimator);
+        println!(
+            "{:<20} {:>14} {:>24.17} {:>12.2e} {:>12.2e}",
+            name,
+            units.format_energy(double, bath),
+            exact.to_f64(),
+            rounding,
+            interval.std_error
+        );
+    }
+}
+
+// Panels of the plot window
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum View {
+    Ratio,
+    Histograms,
+    Heat,
+    Density,
+}
+
+// Show ln(P_F/P_R) against W and its fitted line in a window; H toggles
+// to the underlying forward and reverse work histograms, Q to the heat
+// histograms, D to the density of final positions projected onto the x-y plane
+fn run_crooks_plot(config: &Config) {
+    let ensembles = run_experiment(config);
+    let ratio = analysis::crooks_ratio(&ensembles.forward.work, &ensembles.reverse.work, config.bins);
+    let (forward, reverse) = analysis::work_histograms(&ensembles.forward.work, &ensembles.reverse.work, config.bins);
+
+    let mut ratio_buffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
+    let mut histogram_buffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
+    let mut heat_buffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
+    let mut density_buffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
+    let (forward_heat, reverse_heat) = analysis::heat_histograms(&ensembles.forward.heat, &ensembles.reverse.heat, config.bins);
+    if ratio.is_none() {
+        println!("Crooks ratio: no overlap of P_F(W) and P_R(-W) to fit a line through");
+    }
+    plot::draw_crooks_ratio(&mut ratio_buffer, WIDTH, HEIGHT, ratio.as_ref());
+    plot::draw_work_histograms(&mut histogram_buffer, WIDTH, HEIGHT, &forward, &reverse);
+    plot::draw_work_histograms(&mut heat_buffer, WIDTH, HEIGHT, &forward_heat, &reverse_heat);
+    plot::draw_position_density(
+        &mut density_buffer,
+        WIDTH,
+        HEIGHT,
+        &ensembles.forward.final_states,
+        &ensembles.reverse.final_states,
+    );
+
+    let mut window = Window::new("Crooks Ratio ln(P_F/P_R) vs W", WIDTH, HEIGHT, WindowOptions::default())
+        .unwrap_or_else(|e| {
+            panic!("{}", e);
+        });
+    let mut view = View::Ratio;
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        for (key, toggled) in [(Key::H, View::Histograms), (Key::Q, View::Heat), (Key::D, View::Density)] {
+            if window.is_key_pressed(key, KeyRepeat::No) {
+                view = if view == toggled { View::Ratio } else { toggled };
+            }
+        }
+        let buffer = match view {
+            View::Ratio => &ratio_buffer,
+            View::Histograms => &histogram_buffer,
+            View::Heat => &heat_buffer,
+            View::Density => &density_buffer,
+        };
+        window.update_with_buffer(buffer, WIDTH, HEIGHT).unwrap();
+    }
+}
+
+// Render the animation to numbered PNGs without opening a window, for servers,
+// CI and external video pipelines. Frames are those of the window left running
+// at the configured colormap, scale, layout and overlay, without the frame rate.
+fn run_headless(config: &Config) {
+    let fail = |e: String| -> ! {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    };
+    std::fs::create_dir_all(&config.output_dir)
+        .unwrap_or_else(|e| fail(format!("headless: cannot create '{}': {}", config.output_dir, e)));
+    let mut scene = Scene::new(config, WIDTH, HEIGHT);
+    let start = Instant::now();
+    for index in 0..config.frames {
+        let mut buffer = scene.render(config);
+        if config.overlay {
+            scene.draw_overlay(&mut buffer, &scene.describe(config));
+        }
+        capture::save_numbered(&buffer, WIDTH, HEIGHT, &config.output_dir, index).unwrap_or_else(|e| fail(e));
+        scene.advance();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    println!(
+        "Wrote {} frames of {}x{} to {}/ in {:.1} s ({:.1} frames/s)",
+        config.frames,
         WIDTH,
         HEIGHT,
-        WindowOptions::default(),
-    )
-    .unwrap_or_else(|e| {
+        config.output_dir.trim_end_matches('/'),
+        elapsed,
+        config.frames as f64 / elapsed.max(1e-9)
+    );
+}
+
+// Window of the animation: a resizable one of the given size, or a borderless
+// one of the screen's size at its top-left corner, which minifb offers in
+// place of a true fullscreen mode
+fn animation_window(size: (usize, usize), fullscreen: bool) -> minifb::Result<Window> {
+    let options = WindowOptions {
+        borderless: fullscreen,
+        title: !fullscreen,
+        resize: !fullscreen,
+        ..WindowOptions::default()
+    };
+    let mut window = Window::new("Crooks Fluctuation Theorem Simulation", size.0, size.1, options)?;
+    if fullscreen {
+        window.set_position(0, 0);
+    }
+    Ok(window)
+}
+
+fn main() {
+    // An optional subcommand followed by --key value configuration overrides
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (command, options) = match args.first() {
+        Some(first) if !first.starts_with("--") => (Some(first.as_str()), &args[1..]),
+        _ => (None, &args[..]),
+    };
+    // The self-test starts from its own preset, which the options can still override
+    let options: Vec<String> = match command {
+        Some("selftest") => ["--preset", "selftest"].iter().map(|s| s.to_string()).chain(options.iter().cloned()).collect(),
+        _ => options.to_vec(),
+    };
+    let config = Config::from_args(&options).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    if config.seed_drawn {
+        println!("Seed: {} (drawn from OS entropy; --seed {} replays this run)", config.seed, config.seed);
+    } else {
+        println!("Seed: {}", config.seed);
+    }
+
+    match command {
+        Some("simulate") => return run_simulation(&config),
+        Some("plot") => return run_crooks_plot(&config),
+        Some("mbar") => return run_mbar(&config),
+        Some("entropy") => return run_entropy(&config),
+        Some("profile") => return run_profile(&config),
+        Some("wham") => return run_wham(&config),
+        Some("feedback") => return run_feedback(&config),
+        Some("record") => return run_record(&config),
+        Some("playback") => return run_playback(&config),
+        Some("steady") => return run_steady(&config),
+        Some("replicas") => return run_replicas(&config),
+        Some("hysteresis") => return run_hysteresis(&config),
+        Some("optimize") => return run_optimize(&config),
+        Some("metric") => return run_metric(&config),
+        Some("fdt") => return run_fdt(&config),
+        Some("characteristic") => return run_characteristic(&config),
+        Some("quantum") => return run_quantum(&config),
+        Some("selftest") => return run_selftest(&config),
+        Some("rngtest") => return run_rngtest(&config),
+        #[cfg(feature = "extended-precision")]
+        Some("precision") => return run_precision(&config),
+        #[cfg(not(feature = "extended-precision"))]
+        Some("precision") => {
+            eprintln!("precision: rebuild with --features extended-precision");
+            std::process::exit(2);
+        }
+        Some(other) => {
+            eprintln!("unknown command '{}'", other);
+            std::process::exit(2);
+        }
+        None if config.headless => return run_headless(&config),
+        #[cfg(feature = "egui")]
+        None if config.panel => {
+            return panel::run(&config, WIDTH, HEIGHT).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            })
+        }
+        #[cfg(not(feature = "egui"))]
+        None if config.panel => {
+            eprintln!("panel: rebuild with --features egui");
+            std::process::exit(2);
+        }
+        #[cfg(feature = "tui")]
+        None if config.tui => {
+            return tui::run(&config, WIDTH, HEIGHT).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            })
+        }
+        #[cfg(not(feature = "tui"))]
+        None if config.tui => {
+            eprintln!("tui: rebuild with --features tui");
+            std::process::exit(2);
+        }
+        None if config.sixel => {
+            return sixel::run(&config, WIDTH, HEIGHT).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            })
+        }
+        None => {}
+    }
+
+    // Create a new window
+    let mut window = animation_window((WIDTH, HEIGHT), false).unwrap_or_else(|e| {
         panic!("{}", e);
     });
+    let mut windowed: Option<(usize, usize)> = None; // Size to go back to, while fullscreen
 
-    let terms = 100;
-    let coefficient = 2.0;
-    let exponent = 3.0;
-    let mut time = 0.0;
-    let time_step = 0.05;
-    let scale_factor = 1e3; // Adjusted scale factor for better variability
+    let mut scene = Scene::new(&config, WIDTH, HEIGHT);
+    let mut overlay = config.overlay;
+    let mut fps = 0.0;
+    let mut last_frame = Instant::now();
+    let mut paused = false;
+    let mut drag: Option<(f32, f32)> = None; // Mouse position when the last frame was drawn, while dragging
+    let mut flash: Option<(String, Instant)> = None; // Capture message and when it appeared
+    let mut gif: Option<capture::GifRecorder> = None;
+    let mut video: Option<capture::VideoRecorder> = None;
+    let live = config.live.then(|| live::LiveRun::start(&config));
+    let mut panel: Vec<u32> = vec![0; LIVE_PANEL_WIDTH * LIVE_PANEL_HEIGHT];
+    let mut convergence_panel: Vec<u32> = vec![0; LIVE_PANEL_WIDTH * LIVE_PANEL_HEIGHT];
 
     // Main loop
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        let mut image = ImageBuffer::new(WIDTH as u32, HEIGHT as u32);
-
-        // Compute the colour values for each pixel in parallel
-        image.enumerate_pixels_mut().par_bridge().for_each(|(x, y, pixel)| {
-            let value = crooks_fluctuation_theorem(terms, coefficient, exponent, time + (x as f64) / 100.0 + (y as f64) / 100.0) * scale_factor;
+        if window.is_key_pressed(Key::C, KeyRepeat::No) {
+            scene.colormap = scene.colormap.next();
+            println!("Colormap: {:?}", scene.colormap);
+        }
+        if window.is_key_pressed(Key::V, KeyRepeat::No) {
+            scene.colour_scale = scene.colour_scale.next();
+            println!("Colour scale: {:?}", scene.colour_scale);
+        }
+        if window.is_key_pressed(Key::O, KeyRepeat::No) {
+            overlay = !overlay;
+        }
+        if window.is_key_pressed(Key::X, KeyRepeat::No) {
+            scene.split = !scene.split;
+        }
+        if window.is_key_pressed(Key::I, KeyRepeat::No) {
+            scene.surface = !scene.surface;
+        }
+        if window.is_key_pressed(Key::L, KeyRepeat::No) {
+            scene.contours = !scene.contours;
+        }
+        if window.is_key_pressed(Key::Space, KeyRepeat::No) {
+            paused = !paused;
+        }
+        // F11 swaps the window for a borderless one covering the screen, and back
+        // to a window of the size it had before
+        if window.is_key_pressed(Key::F11, KeyRepeat::No) {
+            let (size, fullscreen) = match windowed {
+                Some(size) => (size, false),
+                None => ((config.screen_width, config.screen_height), true),
+            };
+            match animation_window(size, fullscreen) {
+                Ok(replacement) => {
+                    windowed = fullscreen.then(|| window.get_size());
+                    window = replacement;
+                }
+                Err(e) => eprintln!("fullscreen: {}", e),
+            }
+        }
+        // A single step while paused, repeating while the key is held
+        let step = paused && window.is_key_pressed(Key::Right, KeyRepeat::Yes);
+        // The scroll wheel zooms about the mouse, one step per notch whatever the
+        // platform reports for it, a left-button drag pans, or turns the
+        // surface, and Home resets the view
+        let mouse = window.get_mouse_pos(MouseMode::Discard);
+        if let (Some((_, notches)), Some((x, y))) = (window.get_scroll_wheel(), mouse) {
+            if notches != 0.0 {
+                scene.zoom_at(x as f64, y as f64, ZOOM_STEP.powf(notches.signum() as f64));
+            }
+        }
+        drag = match (window.get_mouse_down(MouseButton::Left), mouse, drag) {
+            (true, Some((x, y)), Some((from_x, from_y))) => {
+                let (dx, dy) = ((x - from_x) as f64, (y - from_y) as f64);
+                if scene.surface {
+                    scene.rotate(dx, dy);
+                } else {
+                    scene.pan(from_x as f64, dx, dy);
+                }
+                Some((x, y))
+            }
+            (true, Some(position), None) => Some(position),
+            _ => None,
+        };
+        if window.is_key_pressed(Key::Home, KeyRepeat::No) {
+            scene.reset_view();
+        }
 
-            // Use custom RNG for random factors and convert them to f64
-            let random_factor_r = RNG.with(|rng| rng.borrow_mut().generate() as f64);
-            let random_factor_g = RNG.with(|rng| rng.borrow_mut().generate() as f64);
-            let random_factor_b = RNG.with(|rng| rng.borrow_mut().generate() as f64);
-            let normalized_value = value.sin() * 0.5 + 0.5;
+        // Recompute at the window's new size, keeping the point of the plane at
+        // the top-left corner and the zoom, so resizing shows more or less of
+        // the field without stretching it. Recordings have a fixed frame size
+        // and stop at a resize.
+        let (width, height) = window.get_size();
+        if width < MIN_FRAME || height < MIN_FRAME {
+            window.update();
+            continue;
+        }
+        if (width, height) != (scene.width, scene.height) {
+            scene.resize(width, height);
+            let mut messages = Vec::new();
+            if let Some(recorder) = gif.take() {
+                let path = recorder.path.clone();
+                messages.push(match recorder.finish() {
+                    Ok(frames) => format!("window resized, saved {} ({} frames)", path, frames),
+                    Err(e) => e,
+                });
+            }
+            if let Some(recorder) = video.take() {
+                let path = recorder.path.clone();
+                messages.push(match recorder.finish() {
+                    Ok(frames) => format!("window resized, saved {} ({} frames)", path, frames),
+                    Err(e) => e,
+                });
+            }
+            for message in messages {
+                println!("{}", message);
+                flash = Some((message, Instant::now()));
+            }
+        }
 
-            // Enhanced colour mapping with different random factors for each colour channel
-            let red = (normalized_value * random_factor_r * 255.0) as u8;
-            let green = ((1.0 - normalized_value) * random_factor_g * 255.0) as u8;
-            let blue = ((0.5 - (normalized_value - 0.5).abs()) * 2.0 * random_factor_b * 255.0) as u8;
+        let mut buffer = scene.render(&config);
 
-            // Set pixel data
-            *pixel = image::Rgb([red, green, blue]);
-        });
+        // Frame rate smoothed over roughly the last ten frames
+        let now = Instant::now();
+        let rate = 1.0 / (now - last_frame).as_secs_f64().max(1e-6);
+        fps = if fps == 0.0 { rate } else { fps + OVERLAY_SMOOTHING * (rate - fps) };
+        last_frame = now;
+        // Work histograms of the samples the live run has gathered so far
+        let samples = live.as_ref().map(|run| run.snapshot());
+        if let Some(samples) = samples.as_ref().filter(|s| !s.forward.is_empty() && !s.reverse.is_empty()) {
+            let (forward, reverse) = analysis::work_histograms(&samples.forward, &samples.reverse, config.bins);
+            plot::draw_work_histograms(&mut panel, LIVE_PANEL_WIDTH, LIVE_PANEL_HEIGHT, &forward, &reverse);
+            let (x, y) = (width.saturating_sub(LIVE_PANEL_WIDTH + OVERLAY_MARGIN), height.saturating_sub(LIVE_PANEL_HEIGHT + OVERLAY_MARGIN));
+            plot::blit(&mut buffer, width, &panel, LIVE_PANEL_WIDTH, x, y);
+        }
+        // Running Delta F estimates over the last batches in the bottom-left corner
+        if let (Some(run), Some(samples)) = (&live, &samples) {
+            let recent = &samples.history[samples.history.len().saturating_sub(LIVE_HISTORY)..];
+            if !recent.is_empty() {
+                plot::draw_convergence(&mut convergence_panel, LIVE_PANEL_WIDTH, LIVE_PANEL_HEIGHT, recent, run.exact_delta_f);
+                let y = height.saturating_sub(LIVE_PANEL_HEIGHT + OVERLAY_MARGIN);
+                plot::blit(&mut buffer, width, &convergence_panel, LIVE_PANEL_WIDTH, OVERLAY_MARGIN, y);
+            }
+        }
+        if overlay {
+            let mut lines = vec![format!("{}{:.1} fps", if scene.split { "forward, " } else { "" }, fps)];
+            lines.extend(scene.describe(&config));
+            if paused {
+                lines[1].push_str(" (paused)");
+            }
+            if gif.is_some() {
+                lines.push("recording gif".to_string());
+            }
+            if video.is_some() {
+                lines.push("recording video".to_string());
+            }
+            if let (Some(run), Some(samples)) = (&live, &samples) {
+                lines.push(format!("trajectories {}/{}", samples.forward.len(), run.trajectories));
+                if let Some(latest) = samples.history.last() {
+                    lines.push(format!("Jarzynski F {:.4}, R {:.4}", latest.jarzynski_forward, latest.jarzynski_reverse));
+                    lines.push(format!("BAR {:.4}", latest.bar));
+                }
+            }
+            // The field under the mouse while it hovers over the window
+            if let Some((x, y)) = mouse {
+                lines.extend(scene.probe(&config, x as usize, y as usize));
+            }
+            scene.draw_overlay(&mut buffer, &lines);
+        }
 
-        // Create a buffer to display the image in the window
-        let mut buffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
-        for (x, y, pixel) in image.enumerate_pixels() {
-            let red = pixel[0] as u32;
-            let green = pixel[1] as u32;
-            let blue = pixel[2] as u32;
-            let colour = (red << 16) | (green << 8) | blue;
-            buffer[y as usize * WIDTH + x as usize] = colour;
+        // Save the frame as shown, before any capture message goes on it
+        if window.is_key_pressed(Key::S, KeyRepeat::No) {
+            let message = match capture::save_screenshot(&buffer, width, height, &config.output_dir) {
+                Ok(path) => format!("saved {}", path),
+                Err(e) => e,
+            };
+            println!("{}", message);
+            flash = Some((message, Instant::now()));
+        }
+        // G starts and stops a GIF of the frames as shown
+        if window.is_key_pressed(Key::G, KeyRepeat::No) {
+            let message = match gif.take() {
+                Some(recorder) => {
+                    let path = recorder.path.clone();
+                    match recorder.finish() {
+                        Ok(frames) => format!("saved {} ({} frames)", path, frames),
+                        Err(e) => e,
+                    }
+                }
+                None => match capture::GifRecorder::start(&config.output_dir, config.gif_skip, config.gif_speed) {
+                    Ok(recorder) => {
+                        let message = format!("recording {}", recorder.path);
+                        gif = Some(recorder);
+                        message
+                    }
+                    Err(e) => e,
+                },
+            };
+            println!("{}", message);
+            flash = Some((message, Instant::now()));
+        }
+        if let Some(recorder) = &mut gif {
+            recorder.push(&buffer, width, height);
+        }
+        // M starts and stops an MP4 encoded by ffmpeg
+        let mut stop_video = false;
+        if window.is_key_pressed(Key::M, KeyRepeat::No) {
+            if video.is_some() {
+                stop_video = true;
+            } else {
+                let message = match capture::VideoRecorder::start(
+                    &config.output_dir,
+                    width,
+                    height,
+                    config.video_fps,
+                    config.video_codec,
+                    &config.video_bitrate,
+                ) {
+                    Ok(recorder) => {
+                        let message = format!("recording {}", recorder.path);
+                        video = Some(recorder);
+                        message
+                    }
+                    Err(e) => e,
+                };
+                println!("{}", message);
+                flash = Some((message, Instant::now()));
+            }
+        }
+        if let Some(recorder) = &mut video {
+            if let Err(e) = recorder.push(&buffer) {
+                eprintln!("{}", e);
+                stop_video = true;
+            }
+        }
+        if stop_video {
+            if let Some(recorder) = video.take() {
+                let path = recorder.path.clone();
+                let message = match recorder.finish() {
+                    Ok(frames) => format!("saved {} ({} frames)", path, frames),
+                    Err(e) => e,
+                };
+                println!("{}", message);
+                flash = Some((message, Instant::now()));
+            }
+        }
+        if let Some((message, since)) = &flash {
+            let age = since.elapsed().as_secs_f64();
+            if age < FLASH_BORDER_SECONDS {
+                for (i, row) in buffer.chunks_mut(width).enumerate() {
+                    if !(FLASH_BORDER..height - FLASH_BORDER).contains(&i) {
+                        row.fill(OVERLAY_COLOUR);
+                    } else {
+                        row[..FLASH_BORDER].fill(OVERLAY_COLOUR);
+                        row[width - FLASH_BORDER..].fill(OVERLAY_COLOUR);
+                    }
+                }
+            }
+            if age < FLASH_SECONDS {
+                let lines = [message.clone()];
+                let x = width.saturating_sub(text::panel_size(&lines, OVERLAY_SCALE).0) / 2;
+                text::draw_panel(&mut buffer, width, x, OVERLAY_MARGIN, &lines, OVERLAY_COLOUR, OVERLAY_SCALE);
+            } else {
+                flash = None;
+            }
         }
 
         // Update the window with the new image
-        window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
-        time += time_step;
+        window.update_with_buffer(&buffer, width, height).unwrap();
+        // The evolution advances only while running, or by one step while paused
+        if !paused || step {
+            scene.advance();
+        }
+    }
+
+    // Close recordings still running when the window goes
+    if let Some(recorder) = gif {
+        let path = recorder.path.clone();
+        match recorder.finish() {
+            Ok(frames) => println!("saved {} ({} frames)", path, frames),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    if let Some(recorder) = video {
+        let path = recorder.path.clone();
+        match recorder.finish() {
+            Ok(frames) => println!("saved {} ({} frames)", path, frames),
+            Err(e) => eprintln!("{}", e),
+        }
     }
 }
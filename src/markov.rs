@@ -0,0 +1,215 @@
+// markov.rs
+// Continuous-time Markov jump process on a lattice, simulated with Gillespie's algorithm
+
+use crate::integrator::State;
+use crate::model::Model;
+use crate::protocol::Protocol;
+use crate::simulation::{exponential, uniform, Trajectory};
+use crate::units::Temperature;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Largest uniformisation rate times step used when propagating the master equation
+const MAX_UNIFORM_STEP: f64 = 10.0;
+
+// A particle hopping between neighbouring sites x_i = start + i * spacing of a
+// finite lattice, dragged by the harmonic trap E_i(lambda) = k/2 (x_i - lambda)^2.
+// Hops i -> j happen at rate r exp(-beta (E_j - E_i) / 2), which satisfies
+// detailed balance at every fixed lambda. Rates are held constant over each
+// time step dt, within which the jumps are drawn exactly.
+pub struct MarkovParams {
+    pub sites: usize,
+    pub start: f64,   // Position of site 0
+    pub spacing: f64, // Distance between neighbouring sites
+    pub stiffness: f64,
+    pub rate: f64, // Hop attempt rate
+    pub temperature: Temperature,
+    pub dt: f64,
+    // Occupation probabilities at the end of each protocol, keyed by its
+    // start, end and duration, from integrating the master equation once
+    final_distributions: Mutex<HashMap<[u64; 3], Arc<Vec<f64>>>>,
+}
+
+impl MarkovParams {
+    pub fn new(sites: usize, start: f64, spacing: f64, stiffness: f64, rate: f64, temperature: Temperature, dt: f64) -> Self {
+        Self {
+            sites,
+            start,
+            spacing,
+            stiffness,
+            rate,
+            temperature,
+            dt,
+            final_distributions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Position of a lattice site
+    fn position(&self, site: usize) -> f64 {
+        self.start + site as f64 * self.spacing
+    }
+
+    // Lattice site nearest to a position
+    fn site(&self, x: f64) -> usize {
+        (((x - self.start) / self.spacing).round().max(0.0) as usize).min(self.sites - 1)
+    }
+
+    // Energy of a site with the trap at lambda
+    fn site_energy(&self, site: usize, lambda: f64) -> f64 {
+        let d = self.position(site) - lambda;
+        0.5 * self.stiffness * d * d
+    }
+
+    // Rate of hopping from one site to a neighbour at fixed lambda
+    fn hop_rate(&self, from: usize, to: usize, lambda: f64) -> f64 {
+        let cost = self.site_energy(to, lambda) - self.site_energy(from, lambda);
+        self.rate * (-0.5 * cost * self.temperature.beta()).exp()
+    }
+
+    // Rates to the left and right neighbours, zero at the ends of the lattice
+    fn neighbour_rates(&self, site: usize, lambda: f64) -> (f64, f64) {
+        let left = if site > 0 { self.hop_rate(site, site - 1, lambda) } else { 0.0 };
+        let right = if site + 1 < self.sites { self.hop_rate(site, site + 1, lambda) } else { 0.0 };
+        (left, right)
+    }
+
+    // Boltzmann occupation probabilities at fixed lambda
+    fn equilibrium(&self, lambda: f64) -> Vec<f64> {
+        let energies: Vec<f64> = (0..self.sites).map(|i| self.site_energy(i, lambda)).collect();
+        let floor = energies.iter().cloned().fold(f64::INFINITY, f64::min);
+        let weights: Vec<f64> = energies.iter().map(|e| (-(e - floor) * self.temperature.beta()).exp()).collect();
+        let total: f64 = weights.iter().sum();
+        weights.iter().map(|w| w / total).collect()
+    }
+
+    // Propagate occupation probabilities through time t at fixed lambda by
+    // uniformisation, p(t) = sum_n Poisson(n; L t) (I + G / L)^n p(0)
+    fn propagate(&self, p: &mut Vec<f64>, lambda: f64, t: f64) {
+        let rates: Vec<(f64, f64)> = (0..self.sites).map(|i| self.neighbour_rates(i, lambda)).collect();
+        let uniform_rate = rates.iter().map(|(l, r)| l + r).fold(0.0, f64::max);
+        if uniform_rate == 0.0 {
+            return;
+        }
+        let substeps = (uniform_rate * t / MAX_UNIFORM_STEP).ceil().max(1.0) as usize;
+        let mean = uniform_rate * t / substeps as f64;
+
+        for _ in 0..substeps {
+            let mut term = p.clone();
+            let mut weight = (-mean).exp();
+            let mut accumulated = weight;
+            let mut next: Vec<f64> = term.iter().map(|q| weight * q).collect();
+            let mut n = 0;
+            while accumulated < 1.0 - 1e-14 && n < 1000 {
+                n += 1;
+                let mut jumped = vec![0.0; self.sites];
+                for (i, &(left, right)) in rates.iter().enumerate() {
+                    jumped[i] += term[i] * (1.0 - (left + right) / uniform_rate);
+                    if i > 0 {
+                        jumped[i - 1] += term[i] * left / uniform_rate;
+                    }
+                    if i + 1 < self.sites {
+                        jumped[i + 1] += term[i] * right / uniform_rate;
+                    }
+                }
+                term = jumped;
+                weight *= mean / n as f64;
+                accumulated += weight;
+                next.iter_mut().zip(&term).for_each(|(a, q)| *a += weight * q);
+            }
+            *p = next;
+        }
+    }
+
+    // Occupation probabilities at the end of a protocol started in equilibrium
+    fn final_distribution(&self, protocol: &Protocol) -> Arc<Vec<f64>> {
+        let key = [
+            protocol.lambda_start().to_bits(),
+            protocol.lambda_at(protocol.duration).to_bits(),
+            protocol.duration.to_bits(),
+        ];
+        if let Some(p) = self.final_distributions.lock().unwrap().get(&key) {
+            return p.clone();
+        }
+
+        let mut p = self.equilibrium(protocol.lambda_start());
+        for step in 1..=self.steps(protocol) {
+            self.propagate(&mut p, protocol.lambda_at(step as f64 * self.dt), self.dt);
+        }
+        let p = Arc::new(p);
+        self.final_distributions.lock().unwrap().insert(key, p.clone());
+        p
+    }
+
+    // Number of constant-rate slices covering a protocol
+    fn steps(&self, protocol: &Protocol) -> usize {
+        (protocol.duration / self.dt).round() as usize
+    }
+}
+
+impl Model for MarkovParams {
+    fn temperature(&self) -> Temperature {
+        self.temperature
+    }
+
+    fn energy(&self, state: &State, lambda: f64) -> f64 {
+        self.site_energy(self.site(state.x[0]), lambda)
+    }
+
+    fn sample_equilibrium(&self, lambda: f64) -> State {
+        let p = self.equilibrium(lambda);
+        let target = uniform();
+        let mut cumulative = 0.0;
+        let site = p
+            .iter()
+            .position(|q| {
+                cumulative += q;
+                cumulative > target
+            })
+            .unwrap_or(self.sites - 1);
+        State::at(self.position(site))
+    }
+
+    // Each slice first moves the trap at fixed site, which is work, then draws
+    // the Gillespie jumps within the slice at fixed rates, whose energy changes
+    // are heat. The total entropy production adds the medium entropy -beta Q to
+    // the change in system entropy -ln p(x, t) along the exact occupation
+    // probabilities of the master equation.
+    fn run_trajectory(&self, protocol: &Protocol, start: State) -> Trajectory {
+        let first = self.site(start.x[0]);
+        let mut site = first;
+        let mut work = 0.0;
+        let mut heat = 0.0;
+        let mut lambda = protocol.lambda_start();
+
+        for step in 1..=self.steps(protocol) {
+            let next_lambda = protocol.lambda_at(step as f64 * self.dt);
+            work += self.site_energy(site, next_lambda) - self.site_energy(site, lambda);
+            lambda = next_lambda;
+
+            let mut t = 0.0;
+            loop {
+                let (left, right) = self.neighbour_rates(site, lambda);
+                let total = left + right;
+                t += exponential(total);
+                if t > self.dt {
+                    break;
+                }
+                let next = if uniform() * total < left { site - 1 } else { site + 1 };
+                heat += self.site_energy(next, lambda) - self.site_energy(site, lambda);
+                site = next;
+            }
+        }
+
+        let initial = self.equilibrium(protocol.lambda_start())[first];
+        let last = self.final_distribution(protocol)[site];
+        let system_entropy = initial.ln() - last.ln();
+        let final_state = State::at(self.position(site));
+        Trajectory {
+            work,
+            heat,
+            energy_change: self.site_energy(site, lambda) - self.site_energy(first, protocol.lambda_start()),
+            final_state,
+            entropy_production: Some(system_entropy - heat * self.temperature.beta()),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+// mbar.rs
+// Multistate Bennett acceptance ratio (Shirts & Chodera 2008)
+
+use crate::estimators::log_sum_exp;
+use crate::units::Temperature;
+
+const MAX_ITERATIONS: usize = 10_000;
+const TOLERANCE: f64 = 1e-10;
+
+// Dimensionless free energies f_k = beta F_k relative to state 0, with their
+// asymptotic covariance
+pub struct MbarResult {
+    pub free_energies: Vec<f64>,
+    pub covariance: Vec<Vec<f64>>,
+    pub iterations: usize,
+}
+
+impl MbarResult {
+    // Delta F between two states in energy units
+    pub fn delta_f(&self, from: usize, to: usize, temperature: Temperature) -> f64 {
+        temperature.kt() * (self.free_energies[to] - self.free_energies[from])
+    }
+
+    // Standard error of delta_f(from, to) in energy units
+    pub fn delta_f_error(&self, from: usize, to: usize, temperature: Temperature) -> f64 {
+        let c = &self.covariance;
+        let variance = c[from][from] + c[to][to] - 2.0 * c[from][to];
+        temperature.kt() * variance.max(0.0).sqrt()
+    }
+}
+
+// ln of the MBAR mixture denominator sum_k N_k exp(f_k - u_kn) for every sample
+fn log_denominators(reduced: &[Vec<f64>], counts: &[usize], f: &[f64]) -> Vec<f64> {
+    (0..reduced[0].len())
+        .map(|n| {
+            log_sum_exp(
+                (0..counts.len())
+                    .filter(|&k| counts[k] > 0)
+                    .map(|k| (counts[k] as f64).ln() + f[k] - reduced[k][n]),
+            )
+        })
+        .collect()
+}
+
+// Eigen-decomposition of a small symmetric matrix by cyclic Jacobi rotations,
+// returning eigenvalues and eigenvectors as the columns of the second matrix
+fn symmetric_eigen(mut a: Vec<Vec<f64>>) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let k = a.len();
+    let mut v: Vec<Vec<f64>> = (0..k).map(|i| (0..k).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect();
+    for _ in 0..100 {
+        let off: f64 = (0..k).flat_map(|i| (0..k).filter(move |&j| j != i).map(move |j| (i, j))).map(|(i, j)| a[i][j].powi(2)).sum();
+        if off < 1e-30 {
+            break;
+        }
+        for p in 0..k {
+            for q in p + 1..k {
+                if a[p][q].abs() < 1e-300 {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let t = if theta == 0.0 { 1.0 } else { t };
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in a.iter_mut() {
+                    let (arp, arq) = (row[p], row[q]);
+                    row[p] = c * arp - s * arq;
+                    row[q] = s * arp + c * arq;
+                }
+                let (row_p, row_q) = (a[p].clone(), a[q].clone());
+                for (r, (apr, aqr)) in row_p.into_iter().zip(row_q).enumerate() {
+                    a[p][r] = c * apr - s * aqr;
+                    a[q][r] = s * apr + c * aqr;
+                }
+                for row in v.iter_mut() {
+                    let (vp, vq) = (row[p], row[q]);
+                    row[p] = c * vp - s * vq;
+                    row[q] = s * vp + c * vq;
+                }
+            }
+        }
+    }
+    ((0..k).map(|i| a[i][i]).collect(), v)
+}
+
+// Solve the MBAR equations for K states. `reduced[k][n]` is the reduced energy
+// beta U_k(x_n) of pooled sample n evaluated in state k, and `counts[k]` is how
+// many of the pooled samples were drawn from state k, in any order. States with
+// no samples are still estimated.
+pub fn solve(reduced: &[Vec<f64>], counts: &[usize]) -> MbarResult {
+    let states = counts.len();
+    let mut f = vec![0.0; states];
+    let mut iterations = 0;
+
+    // Self-consistent iteration f_i = -ln sum_n exp(-u_in) / sum_k N_k exp(f_k - u_kn)
+    while iterations < MAX_ITERATIONS {
+        iterations += 1;
+        let denominators = log_denominators(reduced, counts, &f);
+        let mut next: Vec<f64> = (0..states)
+            .map(|i| -log_sum_exp(denominators.iter().zip(&reduced[i]).map(|(d, u)| -u - d)))
+            .collect();
+        let shift = next[0];
+        next.iter_mut().for_each(|v| *v -= shift);
+
+        let change = next.iter().zip(&f).map(|(a, b)| (a - b).abs()).fold(0.0, f64::max);
+        f = next;
+        if change < TOLERANCE {
+            break;
+        }
+    }
+
+    MbarResult {
+        covariance: covariance(reduced, counts, &f),
+        free_energies: f,
+        iterations,
+    }
+}
+
+// Asymptotic covariance Theta = V S (I - S V^T N V S)^+ S V^T, from the thin
+// decomposition of the weight matrix W_nk = exp(f_k - u_kn) / denominator_n
+fn covariance(reduced: &[Vec<f64>], counts: &[usize], f: &[f64]) -> Vec<Vec<f64>> {
+    let states = counts.len();
+    let denominators = log_denominators(reduced, counts, f);
+    let weights: Vec<Vec<f64>> = (0..states)
+        .map(|k| (0..denominators.len()).map(|n| (f[k] - reduced[k][n] - denominators[n]).exp()).collect())
+        .collect();
+
+    // W^T W = V S^2 V^T
+    let gram: Vec<Vec<f64>> = (0..states)
+        .map(|i| (0..states).map(|j| weights[i].iter().zip(&weights[j]).map(|(a, b)| a * b).sum()).collect())
+        .collect();
+    let (eigenvalues, v) = symmetric_eigen(gram);
+    let s: Vec<f64> = eigenvalues.iter().map(|e| e.max(0.0).sqrt()).collect();
+
+    // M = I - S V^T N V S
+    let m: Vec<Vec<f64>> = (0..states)
+        .map(|i| {
+            (0..states)
+                .map(|j| {
+                    let vnv: f64 = (0..states).map(|k| v[k][i] * counts[k] as f64 * v[k][j]).sum();
+                    (if i == j { 1.0 } else { 0.0 }) - s[i] * vnv * s[j]
+                })
+                .collect()
+        })
+        .collect();
+
+    // Pseudo-inverse of M through its eigen-decomposition, dropping the null space
+    let (m_values, m_vectors) = symmetric_eigen(m);
+    let largest = m_values.iter().fold(0.0f64, |a, &b| a.max(b.abs()));
+    let m_pinv: Vec<Vec<f64>> = (0..states)
+        .map(|i| {
+            (0..states)
+                .map(|j| {
+                    (0..states)
+                        .filter(|&k| m_values[k].abs() > 1e-10 * largest)
+                        .map(|k| m_vectors[i][k] * m_vectors[j][k] / m_values[k])
+                        .sum()
+                })
+                .collect()
+        })
+        .collect();
+
+    // Theta = (V S) M^+ (V S)^T
+    let vs: Vec<Vec<f64>> = (0..states).map(|i| (0..states).map(|j| v[i][j] * s[j]).collect()).collect();
+    (0..states)
+        .map(|i| {
+            (0..states)
+                .map(|j| {
+                    (0..states)
+                        .flat_map(|a| (0..states).map(move |b| (a, b)))
+                        .map(|(a, b)| vs[i][a] * m_pinv[a][b] * vs[j][b])
+                        .sum()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Combine forward/reverse work sets from several protocols between the same two
+// end states (e.g. several switching rates) into one estimate. Each trajectory
+// is a sample in path space with reduced energies u_A = 0 and u_B = beta W, W
+// being its work expressed in the forward direction, so the two-state MBAR
+// solution is the optimal pooled estimate of beta Delta F.
+pub fn combine_work(sets: &[(&[f64], &[f64])], temperature: Temperature) -> MbarResult {
+    let beta = temperature.beta();
+    let forward: Vec<f64> = sets.iter().flat_map(|(f, _)| f.iter().cloned()).collect();
+    let reverse: Vec<f64> = sets.iter().flat_map(|(_, r)| r.iter().map(|w| -w)).collect();
+
+    let path_work: Vec<f64> = forward.iter().chain(&reverse).cloned().collect();
+    let reduced = vec![vec![0.0; path_work.len()], path_work.iter().map(|w| beta * w).collect()];
+    solve(&reduced, &[forward.len(), reverse.len()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::estimators;
+    use crate::unirand::{GaussianMethod, MarsagliaUniRng, UniformRng};
+
+    // Generator on Marsaglia's own test seeds, so the checks are the same on
+    // every run
+    fn rng() -> MarsagliaUniRng {
+        let mut rng = MarsagliaUniRng::with_gaussian(GaussianMethod::BoxMuller);
+        rng.start(12, 34, 56, 78);
+        rng
+    }
+
+    #[test]
+    fn two_states_agree_with_bar() {
+        // Gaussian work obeying the Crooks relation: mean Delta F +- beta
+        // sigma^2 / 2 forward and reverse
+        let mut rng = rng();
+        let (delta_f, sigma) = (1.5, 1.2);
+        let temperature = Temperature::from_kt(1.0);
+        let dissipated = 0.5 * sigma * sigma / temperature.kt();
+        let forward: Vec<f64> = (0..3000).map(|_| delta_f + dissipated + sigma * rng.generate_gaussian()).collect();
+        let reverse: Vec<f64> = (0..2000).map(|_| -delta_f + dissipated + sigma * rng.generate_gaussian()).collect();
+
+        let combined = combine_work(&[(&forward, &reverse)], temperature);
+        let bar = estimators::bar(&forward, &reverse, temperature);
+        assert!((combined.delta_f(0, 1, temperature) - bar).abs() < 1e-6, "MBAR {} against BAR {}", combined.delta_f(0, 1, temperature), bar);
+        assert!(combined.delta_f_error(0, 1, temperature) > 0.0);
+    }
+
+    #[test]
+    fn covariance_is_symmetric_positive_semidefinite() {
+        // Unit harmonic wells at 0, 1 and 2, each sampled exactly, so every
+        // state has the same free energy
+        let mut rng = rng();
+        let (states, samples) = (3, 1000);
+        let positions: Vec<f64> = (0..states).flat_map(|k| (0..samples).map(move |_| k as f64)).map(|centre| centre + rng.generate_gaussian()).collect();
+        let reduced: Vec<Vec<f64>> = (0..states).map(|k| positions.iter().map(|x| 0.5 * (x - k as f64).powi(2)).collect()).collect();
+
+        let result = solve(&reduced, &vec![samples; states]);
+        let c = &result.covariance;
+        let scale = (0..states).map(|i| c[i][i].abs()).fold(0.0, f64::max);
+        assert!(scale > 0.0);
+        for (i, row) in c.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                assert!((value - c[j][i]).abs() <= 1e-12 * scale, "covariance not symmetric at ({}, {})", i, j);
+            }
+        }
+        let (eigenvalues, _) = symmetric_eigen(c.clone());
+        assert!(eigenvalues.iter().all(|&e| e >= -1e-10 * scale), "negative eigenvalue in {:?}", eigenvalues);
+        assert!(result.free_energies.iter().all(|f| f.abs() < 0.2), "free energies {:?}", result.free_energies);
+    }
+}
@@ -0,0 +1,127 @@
+// metric.rs
+// Thermodynamic metric of the trap position and the thermodynamic length of a protocol
+
+use crate::correlation;
+use crate::hysteresis;
+use crate::protocol::Protocol;
+use crate::schedule::Schedule;
+use crate::simulation::{self, TrapParams};
+use crate::unirand;
+use rayon::prelude::*;
+
+// Grid over the protocol on which its linear-response excess work is integrated
+const EXCESS_WORK_STEPS: usize = 1000;
+
+// Equilibrium fluctuations of the generalized force X = dU/dlambda at one lambda
+pub struct MetricPoint {
+    pub lambda: f64,
+    pub variance: f64,   // <dX^2> at equilibrium
+    pub relaxation: f64, // Integrated correlation time of X, in units of time
+    pub zeta: f64,       // Friction beta <dX^2> relaxation = beta int <dX(0) dX(t)> dt
+}
+
+// The metric on a grid of lambda values between the ends of a protocol
+pub struct Metric {
+    pub points: Vec<MetricPoint>,
+}
+
+impl Metric {
+    // zeta at lambda, interpolated linearly between grid points and held
+    // constant beyond either end of the grid
+    pub fn zeta_at(&self, lambda: f64) -> f64 {
+        let ascending = self.points[0].lambda <= self.points[self.points.len() - 1].lambda;
+        let i = self
+            .points
+            .partition_point(|p| (p.lambda < lambda) == ascending)
+            .clamp(1, self.points.len() - 1);
+        let (a, b) = (&self.points[i - 1], &self.points[i]);
+        let s = ((lambda - a.lambda) / (b.lambda - a.lambda)).clamp(0.0, 1.0);
+        a.zeta + (b.zeta - a.zeta) * s
+    }
+
+    // Thermodynamic length up to every grid point, int sqrt(zeta) |dlambda|,
+    // by the trapezoid rule; the last entry is the length of the whole path
+    pub fn cumulative_length(&self) -> Vec<f64> {
+        let mut length = vec![0.0];
+        for pair in self.points.windows(2) {
+            let step = 0.5 * (pair[0].zeta.sqrt() + pair[1].zeta.sqrt()) * (pair[1].lambda - pair[0].lambda).abs();
+            length.push(length[length.len() - 1] + step);
+        }
+        length
+    }
+
+    // Thermodynamic length L of the path from the first grid point to the last
+    pub fn length(&self) -> f64 {
+        self.cumulative_length().last().copied().unwrap_or(0.0)
+    }
+
+    // Excess work predicted by linear response, int zeta(lambda) (dlambda/dt)^2 dt
+    // (Sivak and Crooks 2012), accurate for slow protocols. No protocol of this
+    // duration between the same end points can do better than L^2 / duration.
+    pub fn excess_work(&self, protocol: &Protocol) -> f64 {
+        let dt = protocol.duration / EXCESS_WORK_STEPS as f64;
+        (0..EXCESS_WORK_STEPS)
+            .map(|i| {
+                let (from, to) = (protocol.lambda_at(i as f64 * dt), protocol.lambda_at((i + 1) as f64 * dt));
+                self.zeta_at(0.5 * (from + to)) * (to - from).powi(2) / dt
+            })
+            .sum()
+    }
+
+    // Knots (s, lambda) of the geodesic schedule, which advances at constant
+    // speed in thermodynamic length and so spreads the dissipation evenly along
+    // the path: one at every grid point, at the fraction of the length already
+    // covered. Without any measured friction the ramp stays linear.
+    pub fn geodesic_knots(&self) -> Vec<(f64, f64)> {
+        let cumulative = self.cumulative_length();
+        let total = cumulative[cumulative.len() - 1];
+        let last = self.points.len() - 1;
+        self.points
+            .iter()
+            .zip(&cumulative)
+            .enumerate()
+            .map(|(i, (p, l))| (if total > 0.0 { l / total } else { i as f64 / last as f64 }, p.lambda))
+            .collect()
+    }
+}
+
+// Measure the metric at `points` evenly spaced trap positions from lambda_start
+// to lambda_end. At each one an equilibrium trajectory of the given length,
+// held at that lambda on substream i of the seed, records X every `stride` steps;
+// zeta is beta var(X) times the integrated correlation time of the record.
+pub fn measure(params: &TrapParams, lambda_start: f64, lambda_end: f64, points: usize, time: f64, stride: usize, seed: i32) -> Metric {
+    let beta = params.temperature.beta();
+    let stride = stride.max(1);
+    let points = (0..points)
+        .into_par_iter()
+        .map(|i| {
+            let lambda = lambda_start + (lambda_end - lambda_start) * i as f64 / (points - 1) as f64;
+            let held = Protocol {
+                schedule: Schedule::Linear { from: lambda, to: lambda },
+                stiffness: None,
+                duration: time,
+            };
+            let control = params.control(&held, 0.0);
+            unirand::seed_stream(seed, i);
+            let start = simulation::sample_equilibrium(params, control);
+            let mut forces = Vec::new();
+            simulation::run_recorded(params, &held, start, |step, state, _| {
+                if step % stride == 0 {
+                    forces.push(hysteresis::lambda_force(params, state, control));
+                }
+            });
+
+            let n = forces.len() as f64;
+            let mean = forces.iter().sum::<f64>() / n;
+            let variance = forces.iter().map(|f| (f - mean).powi(2)).sum::<f64>() / (n - 1.0).max(1.0);
+            let relaxation = correlation::integrated_time(&forces) * stride as f64 * params.dt;
+            MetricPoint {
+                lambda,
+                variance,
+                relaxation,
+                zeta: beta * variance * relaxation,
+            }
+        })
+        .collect();
+    Metric { points }
+}
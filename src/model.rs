@@ -0,0 +1,62 @@
+// model.rs
+// Interchangeable stochastic systems driven by the control parameter lambda
+
+use crate::integrator::State;
+use crate::protocol::Protocol;
+use crate::simulation::Trajectory;
+use crate::units::Temperature;
+use std::str::FromStr;
+
+// Names of the model backends, as selected in the configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModelKind {
+    Particle,
+    Spin,
+    Markov,
+    Chain,
+}
+
+impl FromStr for ModelKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "particle" | "langevin" => Ok(ModelKind::Particle),
+            "spin" | "two-state" => Ok(ModelKind::Spin),
+            "markov" | "lattice" => Ok(ModelKind::Markov),
+            "chain" | "polymer" => Ok(ModelKind::Chain),
+            _ => Err(format!("unknown model '{}'", name)),
+        }
+    }
+}
+
+// A system that can be equilibrated at fixed lambda and driven along a protocol
+pub trait Model: Sync {
+    // Temperature of the heat bath
+    fn temperature(&self) -> Temperature;
+
+    // Total energy of a state with the control parameter at lambda
+    fn energy(&self, state: &State, lambda: f64) -> f64;
+
+    // Draw a state from the Boltzmann distribution at fixed lambda
+    fn sample_equilibrium(&self, lambda: f64) -> State;
+
+    // Draw a state from equilibrium at the start of a protocol
+    fn sample_start(&self, protocol: &Protocol) -> State {
+        self.sample_equilibrium(protocol.lambda_start())
+    }
+
+    // Drive the system along a protocol from the given start, recording work and heat
+    fn run_trajectory(&self, protocol: &Protocol, start: State) -> Trajectory;
+
+    // Free energy F(lambda) when it is known in closed form
+    fn exact_free_energy(&self, _lambda: f64) -> Option<f64> {
+        None
+    }
+
+    // Exact Delta F between the start and the end of a protocol, when known
+    fn exact_delta_f(&self, protocol: &Protocol) -> Option<f64> {
+        let end = self.exact_free_energy(protocol.lambda_at(protocol.duration))?;
+        Some(end - self.exact_free_energy(protocol.lambda_start())?)
+    }
+}
@@ -0,0 +1,64 @@
+// normality.rs
+// Anderson-Darling test of whether work samples are consistent with a Gaussian
+
+// Critical value of the adjusted statistic at the 5% level, for a Gaussian whose
+// mean and variance are both estimated from the sample (Stephens 1974)
+const CRITICAL_5_PERCENT: f64 = 0.752;
+
+// Outcome of the Anderson-Darling test
+pub struct NormalityTest {
+    pub statistic: f64, // A*^2 = A^2 (1 + 0.75/N + 2.25/N^2)
+    pub gaussian: bool, // Whether the statistic stays below the 5% critical value
+}
+
+// Complementary error function, from the Chebyshev fit in Numerical Recipes
+// with a relative error below 1.2e-7 everywhere
+pub fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807 + t * (-1.13520398 + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    let value = t * poly.exp();
+    if x >= 0.0 {
+        value
+    } else {
+        2.0 - value
+    }
+}
+
+// Standard normal cumulative distribution
+pub fn normal_cdf(z: f64) -> f64 {
+    0.5 * erfc(-z / std::f64::consts::SQRT_2)
+}
+
+// Anderson-Darling statistic of the sorted standardized samples z_i,
+// A^2 = -N - (1/N) sum (2i - 1) [ln Phi(z_i) + ln(1 - Phi(z_{N+1-i}))],
+// which weights the tails more heavily than Kolmogorov-Smirnov and so catches
+// the skewed or heavy low-work tails that make the Jarzynski average hard
+pub fn anderson_darling(samples: &[f64]) -> NormalityTest {
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let std = (samples.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / (n - 1.0).max(1.0)).sqrt();
+    let mut sorted: Vec<f64> = samples.iter().map(|v| (v - mean) / std).collect();
+    sorted.sort_by(f64::total_cmp);
+
+    // Clamp the tail probabilities so a far outlier gives a large but finite statistic
+    let tiny = f64::MIN_POSITIVE;
+    let sum: f64 = (0..sorted.len())
+        .map(|i| {
+            let lower = normal_cdf(sorted[i]).max(tiny).ln();
+            let upper = normal_cdf(-sorted[sorted.len() - 1 - i]).max(tiny).ln();
+            (2 * i + 1) as f64 * (lower + upper)
+        })
+        .sum();
+    let a2 = -n - sum / n;
+    let statistic = a2 * (1.0 + 0.75 / n + 2.25 / (n * n));
+    NormalityTest {
+        statistic,
+        gaussian: statistic < CRITICAL_5_PERCENT,
+    }
+}
@@ -0,0 +1,124 @@
+// optimize.rs
+// Nelder-Mead search over piecewise schedule knots for the protocol of least dissipation
+
+use crate::ensemble::Ensemble;
+use crate::model::Model;
+use crate::protocol::{Direction, Protocol};
+use crate::schedule::Schedule;
+use std::sync::Arc;
+
+// Best protocol found by the search
+pub struct Optimized {
+    pub protocol: Protocol,
+    pub knots: Vec<(f64, f64)>,
+    pub mean_work: f64,     // <W> of the optimized protocol on the training streams
+    pub evaluations: usize, // Ensembles run by the search
+}
+
+// Knots of a schedule from lambda_start to lambda_end through free values at
+// s = 1/(m+1), ..., m/(m+1). Values pushed close to the end points let the
+// search approach the jumps at either end of the optimal protocol for a
+// dragged harmonic trap (Schmiedl and Seifert 2007).
+pub fn knots(lambda_start: f64, lambda_end: f64, values: &[f64]) -> Vec<(f64, f64)> {
+    let spacing = 1.0 / (values.len() + 1) as f64;
+    let mut knots = vec![(0.0, lambda_start)];
+    knots.extend(values.iter().enumerate().map(|(i, &v)| ((i + 1) as f64 * spacing, v)));
+    knots.push((1.0, lambda_end));
+    knots
+}
+
+// The protocol with the given free knot values in place of the trap schedule
+fn with_knots(base: &Protocol, lambda_start: f64, lambda_end: f64, values: &[f64]) -> Protocol {
+    Protocol {
+        schedule: Schedule::Piecewise {
+            knots: Arc::new(knots(lambda_start, lambda_end, values)),
+            reversed: false,
+        },
+        ..base.clone()
+    }
+}
+
+// Minimize f from a start point with the Nelder-Mead simplex method
+// (reflection 1, expansion 2, contraction 1/2, shrink 1/2), stopping after the
+// iteration budget or once the simplex values agree to within tolerance
+fn nelder_mead(f: &mut dyn FnMut(&[f64]) -> f64, start: &[f64], step: f64, iterations: usize, tolerance: f64) -> (Vec<f64>, f64) {
+    let n = start.len();
+    let mut simplex: Vec<(Vec<f64>, f64)> = (0..=n)
+        .map(|i| {
+            let mut point = start.to_vec();
+            if i > 0 {
+                point[i - 1] += step;
+            }
+            let value = f(&point);
+            (point, value)
+        })
+        .collect();
+    let towards = |from: &[f64], to: &[f64], by: f64| -> Vec<f64> { from.iter().zip(to).map(|(a, b)| a + by * (b - a)).collect() };
+
+    for _ in 0..iterations {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        if simplex[n].1 - simplex[0].1 <= tolerance {
+            break;
+        }
+        let centroid: Vec<f64> = (0..n).map(|d| simplex[..n].iter().map(|(p, _)| p[d]).sum::<f64>() / n as f64).collect();
+        let worst = simplex[n].clone();
+
+        let reflected = towards(&centroid, &worst.0, -1.0);
+        let reflected_value = f(&reflected);
+        if reflected_value < simplex[0].1 {
+            let expanded = towards(&centroid, &worst.0, -2.0);
+            let expanded_value = f(&expanded);
+            simplex[n] = if expanded_value < reflected_value {
+                (expanded, expanded_value)
+            } else {
+                (reflected, reflected_value)
+            };
+        } else if reflected_value < simplex[n - 1].1 {
+            simplex[n] = (reflected, reflected_value);
+        } else {
+            // Contract towards the better of the worst point and its reflection
+            let outside = reflected_value < worst.1;
+            let contracted = towards(&centroid, if outside { &reflected } else { &worst.0 }, 0.5);
+            let contracted_value = f(&contracted);
+            if contracted_value < reflected_value.min(worst.1) {
+                simplex[n] = (contracted, contracted_value);
+            } else {
+                let best = simplex[0].0.clone();
+                for vertex in simplex.iter_mut().skip(1) {
+                    vertex.0 = towards(&best, &vertex.0, 0.5);
+                    vertex.1 = f(&vertex.0);
+                }
+            }
+        }
+    }
+    simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+    simplex.swap_remove(0)
+}
+
+// Search the piecewise trap schedules with `free_knots` values between lambda
+// at the start and end of the base protocol, at its duration, for the least
+// mean forward work. Delta F is fixed by the end points, so this minimizes
+// <W_diss>. Every evaluation reuses the same seeds, which makes the noisy
+// objective a deterministic function of the knots that the simplex can follow.
+pub fn optimize(model: &dyn Model, base: &Protocol, free_knots: usize, trajectories: usize, seed: i32, iterations: usize) -> Optimized {
+    let (lambda_start, lambda_end) = (base.lambda_at(0.0), base.lambda_at(base.duration));
+    // Start from the linear ramp through the same knots
+    let start: Vec<f64> = (1..=free_knots)
+        .map(|i| lambda_start + (lambda_end - lambda_start) * i as f64 / (free_knots + 1) as f64)
+        .collect();
+    let step = 0.25 * (lambda_end - lambda_start).abs().max(1.0);
+
+    let mut evaluations = 0;
+    let mut mean_work = |values: &[f64]| {
+        evaluations += 1;
+        let protocol = with_knots(base, lambda_start, lambda_end, values);
+        Ensemble::run(model, &protocol, Direction::Forward, trajectories, seed).mean_work()
+    };
+    let (best, value) = nelder_mead(&mut mean_work, &start, step, iterations, 1e-6);
+    Optimized {
+        protocol: with_knots(base, lambda_start, lambda_end, &best),
+        knots: knots(lambda_start, lambda_end, &best),
+        mean_work: value,
+        evaluations,
+    }
+}
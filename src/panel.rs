@@ -0,0 +1,219 @@
+// panel.rs
+// The animation in an eframe window beside an egui panel of its parameters,
+// which take effect while it runs
+
+use crate::animation::Scene;
+use crate::colormap::{ColourScale, Colormap};
+use crate::config::Config;
+use crate::live::LiveRun;
+use crate::units::UnitSystem;
+use eframe::egui;
+
+// Width of the control panel in points
+const PANEL_WIDTH: f32 = 300.0;
+
+// Zoom factor per point of scrolling over the field
+const SCROLL_ZOOM: f64 = 1.002;
+
+struct PanelApp {
+    config: Config,
+    scene: Scene,
+    overlay: bool,
+    paused: bool,
+    live: Option<LiveRun>,
+    texture: Option<egui::TextureHandle>,
+    error: Option<String>, // Last setting the configuration refused, and why
+}
+
+impl PanelApp {
+    // Apply a simulation setting, restarting the live run on the new configuration
+    fn update_setting(&mut self, key: &str, value: String) {
+        match self.config.update(key, &value) {
+            Ok(()) => {
+                self.error = None;
+                if self.live.is_some() {
+                    self.live = Some(LiveRun::start(&self.config));
+                }
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    // Sliders and choices of the animation, changed in place
+    fn animation_controls(&mut self, ui: &mut egui::Ui) {
+        let scene = &mut self.scene;
+        ui.add(egui::Slider::new(&mut scene.terms, 1..=100).text("terms"));
+        ui.add(egui::Slider::new(&mut scene.coefficient, 0.1..=5.0).text("coefficient"));
+        // Whole exponents only, as a fractional power of a negative term is undefined
+        ui.add(egui::Slider::new(&mut scene.exponent, 1.0..=9.0).step_by(1.0).text("exponent"));
+        ui.add(egui::Slider::new(&mut scene.time_step, -0.2..=0.2).text("time step"));
+        ui.add(egui::Slider::new(&mut scene.scale_factor, 1.0..=1e6).logarithmic(true).text("wave factor"));
+        let mut colormap = Colormap::Viridis;
+        egui::ComboBox::from_label("colormap")
+            .selected_text(format!("{:?}", scene.colormap))
+            .show_ui(ui, |ui| loop {
+                ui.selectable_value(&mut scene.colormap, colormap, format!("{:?}", colormap));
+                colormap = colormap.next();
+                if colormap == Colormap::Viridis {
+                    break;
+                }
+            });
+        let mut colour_scale = ColourScale::Wave;
+        egui::ComboBox::from_label("colour scale")
+            .selected_text(format!("{:?}", scene.colour_scale))
+            .show_ui(ui, |ui| loop {
+                ui.selectable_value(&mut scene.colour_scale, colour_scale, format!("{:?}", colour_scale));
+                colour_scale = colour_scale.next();
+                if colour_scale == ColourScale::Wave {
+                    break;
+                }
+            });
+        ui.checkbox(&mut scene.split, "split forward and reverse");
+        ui.checkbox(&mut scene.contours, "contour lines");
+        ui.checkbox(&mut scene.surface, "3D surface");
+        ui.add_enabled(scene.surface, egui::Slider::new(&mut scene.azimuth, 0.0..=360.0).text("azimuth"));
+        ui.add_enabled(scene.surface, egui::Slider::new(&mut scene.elevation, 5.0..=90.0).text("elevation"));
+        ui.checkbox(&mut self.overlay, "overlay");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.paused, "paused");
+            if ui.add_enabled(self.paused, egui::Button::new("step")).clicked() {
+                self.scene.advance();
+            }
+            if ui.button("reset view").clicked() {
+                self.scene.reset_view();
+            }
+        });
+    }
+
+    // Sliders of the simulated ensembles, each change applied through the
+    // configuration as the command line would set it
+    fn simulation_controls(&mut self, ui: &mut egui::Ui) {
+        let mut live = self.live.is_some();
+        if ui.checkbox(&mut live, "live ensembles").changed() {
+            self.live = live.then(|| LiveRun::start(&self.config));
+        }
+        let mut settings = vec![
+            ("stiffness", self.config.stiffness, 0.1..=10.0),
+            ("friction", self.config.friction, 0.1..=10.0),
+            ("duration", self.config.duration, 0.1..=20.0),
+            ("lambda_end", self.config.lambda_end, -5.0..=5.0),
+        ];
+        settings.push(match self.config.units {
+            UnitSystem::Reduced => ("kt", self.config.kt, 0.05..=10.0),
+            UnitSystem::Si => ("temperature", self.config.temperature, 1.0..=1000.0),
+        });
+        for (key, mut value, range) in settings {
+            let logarithmic = *range.start() > 0.0;
+            if ui.add(egui::Slider::new(&mut value, range).logarithmic(logarithmic).text(key)).changed() {
+                self.update_setting(key, value.to_string());
+            }
+        }
+        let mut trajectories = self.config.trajectories;
+        let slider = egui::Slider::new(&mut trajectories, 64..=100_000).logarithmic(true).text("trajectories");
+        if ui.add(slider).changed() {
+            self.update_setting("trajectories", trajectories.to_string());
+        }
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::LIGHT_RED, error);
+        }
+        if let Some(run) = &self.live {
+            let samples = run.snapshot();
+            ui.label(format!("trajectories {}/{}", samples.forward.len(), run.trajectories));
+            if let Some(latest) = samples.history.last() {
+                ui.label(format!("Jarzynski F {:.4}, R {:.4}", latest.jarzynski_forward, latest.jarzynski_reverse));
+                ui.label(format!("BAR {:.4}", latest.bar));
+            }
+            if let Some(exact) = run.exact_delta_f {
+                ui.label(format!("exact {:.4}", exact));
+            }
+        }
+    }
+}
+
+impl eframe::App for PanelApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::SidePanel::left("controls").exact_width(PANEL_WIDTH).show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.heading("Animation");
+                self.animation_controls(ui);
+                ui.separator();
+                ui.heading("Simulation");
+                self.simulation_controls(ui);
+            });
+        });
+        egui::CentralPanel::default().frame(egui::Frame::NONE).show(ctx, |ui| {
+            // Render at the panel's size in physical pixels, so the field is
+            // never stretched
+            let pixels_per_point = ctx.pixels_per_point();
+            let available = ui.available_size();
+            let width = ((available.x * pixels_per_point) as usize).max(1);
+            let height = ((available.y * pixels_per_point) as usize).max(1);
+            if (width, height) != (self.scene.width, self.scene.height) {
+                self.scene.resize(width, height);
+            }
+            let mut buffer = self.scene.render(&self.config);
+            if self.overlay {
+                let mut lines = self.scene.describe(&self.config);
+                // The field under the pointer while it hovers over the picture
+                let area = ui.max_rect();
+                if let Some(pointer) = ctx.pointer_hover_pos().filter(|&pointer| area.contains(pointer)) {
+                    let (x, y) = ((pointer.x - area.min.x) * pixels_per_point, (pointer.y - area.min.y) * pixels_per_point);
+                    lines.extend(self.scene.probe(&self.config, x as usize, y as usize));
+                }
+                self.scene.draw_overlay(&mut buffer, &lines);
+            }
+            let rgb: Vec<u8> = buffer.iter().flat_map(|&pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]).collect();
+            let image = egui::ColorImage::from_rgb([width, height], &rgb);
+            let texture = match &mut self.texture {
+                Some(texture) => {
+                    texture.set(image, egui::TextureOptions::NEAREST);
+                    texture
+                }
+                None => self.texture.insert(ctx.load_texture("field", image, egui::TextureOptions::NEAREST)),
+            };
+            let response = ui.add(egui::Image::new((texture.id(), available)).sense(egui::Sense::drag()));
+
+            // Scrolling zooms about the pointer and dragging pans or turns the surface,
+            // as in the plain window
+            if let Some(pointer) = response.hover_pos() {
+                let (x, y) = ((pointer.x - response.rect.min.x) * pixels_per_point, (pointer.y - response.rect.min.y) * pixels_per_point);
+                let scroll = ui.input(|input| input.smooth_scroll_delta.y);
+                if scroll != 0.0 {
+                    self.scene.zoom_at(x as f64, y as f64, SCROLL_ZOOM.powf(scroll as f64));
+                }
+                if response.dragged() {
+                    let delta = response.drag_delta() * pixels_per_point;
+                    if self.scene.surface {
+                        self.scene.rotate(delta.x as f64, delta.y as f64);
+                    } else {
+                        self.scene.pan(x as f64, delta.x as f64, delta.y as f64);
+                    }
+                }
+            }
+        });
+        if !self.paused {
+            self.scene.advance();
+        }
+        ctx.request_repaint();
+    }
+}
+
+// Open the window with the field at the given size and run until it is closed
+pub fn run(config: &Config, width: usize, height: usize) -> Result<(), String> {
+    let app = PanelApp {
+        config: config.clone(),
+        scene: Scene::new(config, width, height),
+        overlay: config.overlay,
+        paused: false,
+        live: config.live.then(|| LiveRun::start(config)),
+        texture: None,
+        error: None,
+    };
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_title("Crooks Fluctuation Theorem Simulation")
+            .with_inner_size([width as f32 + PANEL_WIDTH, height as f32]),
+        ..Default::default()
+    };
+    eframe::run_native("crooks", options, Box::new(|_| Ok(Box::new(app)))).map_err(|e| format!("panel: {}", e))
+}
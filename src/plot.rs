@@ -0,0 +1,387 @@
+// plot.rs
+// Minimal scatter plot rendering into a minifb pixel buffer
+
+use crate::analysis::CrooksRatio;
+use crate::histogram::{Histogram, Normalization};
+use crate::integrator::State;
+use crate::live::Convergence;
+use crate::quantum::{QuantumReference, TwoPointWork};
+use crate::recording::{self, Frame};
+use crate::schedule::Schedule;
+
+const BACKGROUND: u32 = 0x101010;
+const AXIS: u32 = 0x808080;
+const POINT: u32 = 0xffa000;
+const FIT: u32 = 0x40c0ff;
+const FORWARD: u32 = 0xff6040;
+const REVERSE: u32 = 0x40a0ff;
+const TRAIL: u32 = 0x4080a0;
+const NEGATIVE_WORK: u32 = 0xff6040;
+const MARGIN: usize = 40;
+const DENSITY_CELL: usize = 4; // Pixels per side of a position density cell
+const SCHEDULE_POINTS: usize = 400; // Samples along each drawn schedule
+const CLASSICAL_FORWARD: u32 = 0xffc0a0;
+const CLASSICAL_REVERSE: u32 = 0xa0d0ff;
+
+// Maps data coordinates onto the pixel area inside the margins
+struct Viewport {
+    width: usize,
+    height: usize,
+    x_min: f64,
+    x_max: f64,
+    y_min: f64,
+    y_max: f64,
+}
+
+impl Viewport {
+    fn to_pixel(&self, x: f64, y: f64) -> (i64, i64) {
+        let px = MARGIN as f64 + (x - self.x_min) / (self.x_max - self.x_min) * (self.width - 2 * MARGIN) as f64;
+        let py = (self.height - MARGIN) as f64 - (y - self.y_min) / (self.y_max - self.y_min) * (self.height - 2 * MARGIN) as f64;
+        (px.round() as i64, py.round() as i64)
+    }
+}
+
+// Set a single pixel, ignoring anything outside the buffer
+fn put_pixel(buffer: &mut [u32], width: usize, x: i64, y: i64, colour: u32) {
+    let height = buffer.len() / width;
+    if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+        buffer[y as usize * width + x as usize] = colour;
+    }
+}
+
+// Bresenham line between two pixel positions
+fn draw_line(buffer: &mut [u32], width: usize, from: (i64, i64), to: (i64, i64), colour: u32) {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let sx = if x < to.0 { 1 } else { -1 };
+    let sy = if y < to.1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    loop {
+        put_pixel(buffer, width, x, y, colour);
+        if x == to.0 && y == to.1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+// Copy a smaller image into the buffer with its top-left corner at (x, y),
+// clipped to the buffer
+pub fn blit(buffer: &mut [u32], width: usize, image: &[u32], image_width: usize, x: usize, y: usize) {
+    let height = buffer.len() / width;
+    for (row, line) in image.chunks(image_width).enumerate().take(height.saturating_sub(y)) {
+        let columns = image_width.min(width.saturating_sub(x));
+        let start = (y + row) * width + x;
+        buffer[start..start + columns].copy_from_slice(&line[..columns]);
+    }
+}
+
+// Draw ln(P_F/P_R) against W with the fitted line and the W = 0, ln = 0 axes,
+// or just the background when the histograms overlap too little for a fit
+pub fn draw_crooks_ratio(buffer: &mut [u32], width: usize, height: usize, ratio: Option<&CrooksRatio>) {
+    buffer.iter_mut().for_each(|p| *p = BACKGROUND);
+    let Some(ratio) = ratio.filter(|ratio| !ratio.points.is_empty()) else {
+        return;
+    };
+
+    let x_min = ratio.points.iter().map(|p| p.work).fold(f64::INFINITY, f64::min);
+    let x_max = ratio.points.iter().map(|p| p.work).fold(f64::NEG_INFINITY, f64::max);
+    let y_min = ratio.points.iter().map(|p| p.log_ratio).fold(f64::INFINITY, f64::min);
+    let y_max = ratio.points.iter().map(|p| p.log_ratio).fold(f64::NEG_INFINITY, f64::max);
+    let pad_x = (x_max - x_min).max(1e-9) * 0.05;
+    let pad_y = (y_max - y_min).max(1e-9) * 0.05;
+    let view = Viewport {
+        width,
+        height,
+        x_min: x_min - pad_x,
+        x_max: x_max + pad_x,
+        y_min: y_min - pad_y,
+        y_max: y_max + pad_y,
+    };
+
+    // Axes through the origin, when it is in view
+    let (ox, oy) = view.to_pixel(0.0, 0.0);
+    draw_line(buffer, width, (ox, MARGIN as i64), (ox, (height - MARGIN) as i64), AXIS);
+    draw_line(buffer, width, (MARGIN as i64, oy), ((width - MARGIN) as i64, oy), AXIS);
+
+    // Fitted line across the plotted range
+    let from = view.to_pixel(view.x_min, ratio.slope * view.x_min + ratio.intercept);
+    let to = view.to_pixel(view.x_max, ratio.slope * view.x_max + ratio.intercept);
+    draw_line(buffer, width, from, to, FIT);
+
+    // Data points as small crosses
+    for point in &ratio.points {
+        let (px, py) = view.to_pixel(point.work, point.log_ratio);
+        draw_line(buffer, width, (px - 3, py), (px + 3, py), POINT);
+        draw_line(buffer, width, (px, py - 3), (px, py + 3), POINT);
+    }
+}
+
+// Draw P_F(W) and P_R(-W), or the same pair for the heat Q, as step outlines
+// sharing one grid and one density axis
+pub fn draw_work_histograms(buffer: &mut [u32], width: usize, height: usize, forward: &Histogram, reverse: &Histogram) {
+    buffer.iter_mut().for_each(|p| *p = BACKGROUND);
+
+    let densities = [
+        forward.heights(Normalization::Density),
+        reverse.heights(Normalization::Density),
+    ];
+    let y_max = densities.iter().flatten().cloned().fold(0.0, f64::max).max(1e-12);
+    let view = Viewport {
+        width,
+        height,
+        x_min: forward.min,
+        x_max: forward.max(),
+        y_min: 0.0,
+        y_max: y_max * 1.05,
+    };
+
+    let (ox, oy) = view.to_pixel(0.0, 0.0);
+    draw_line(buffer, width, (ox, MARGIN as i64), (ox, (height - MARGIN) as i64), AXIS);
+    draw_line(buffer, width, (MARGIN as i64, oy), ((width - MARGIN) as i64, oy), AXIS);
+
+    for (density, colour) in densities.iter().zip([FORWARD, REVERSE]) {
+        let mut previous = view.to_pixel(forward.min, 0.0);
+        for (i, &d) in density.iter().enumerate() {
+            let left = view.to_pixel(forward.min + i as f64 * forward.width, d);
+            let right = view.to_pixel(forward.min + (i + 1) as f64 * forward.width, d);
+            draw_line(buffer, width, previous, left, colour);
+            draw_line(buffer, width, left, right, colour);
+            previous = right;
+        }
+        draw_line(buffer, width, previous, view.to_pixel(forward.max(), 0.0), colour);
+    }
+}
+
+// Project the final positions of both ensembles onto the x-y plane as a density
+// map, the forward ensemble in the red channel and the reverse in the blue one.
+// One-dimensional runs collapse onto the y = 0 line.
+pub fn draw_position_density(buffer: &mut [u32], width: usize, height: usize, forward: &[State], reverse: &[State]) {
+    buffer.iter_mut().for_each(|p| *p = BACKGROUND);
+    let all = || forward.iter().chain(reverse);
+    let x_min = all().map(|s| s.x[0]).fold(f64::INFINITY, f64::min);
+    let x_max = all().map(|s| s.x[0]).fold(f64::NEG_INFINITY, f64::max);
+    let y_reach = all().map(|s| s.x[1].abs()).fold(0.0, f64::max).max(1e-9);
+    if !x_min.is_finite() {
+        return;
+    }
+    let pad_x = (x_max - x_min).max(1e-9) * 0.05;
+    let view = Viewport {
+        width,
+        height,
+        x_min: x_min - pad_x,
+        x_max: x_max + pad_x,
+        y_min: -1.05 * y_reach,
+        y_max: 1.05 * y_reach,
+    };
+
+    let (columns, rows) = (width / DENSITY_CELL, height / DENSITY_CELL);
+    let count = |states: &[State]| {
+        let mut cells = vec![0u32; columns * rows];
+        for state in states {
+            let (px, py) = view.to_pixel(state.x[0], state.x[1]);
+            let (cx, cy) = (px / DENSITY_CELL as i64, py / DENSITY_CELL as i64);
+            if cx >= 0 && cy >= 0 && (cx as usize) < columns && (cy as usize) < rows {
+                cells[cy as usize * columns + cx as usize] += 1;
+            }
+        }
+        cells
+    };
+    let (red, blue) = (count(forward), count(reverse));
+    let peak = red.iter().chain(&blue).cloned().max().unwrap_or(0).max(1) as f64;
+
+    // Logarithmic brightness keeps sparse tails visible next to the peak
+    let level = |n: u32| ((1.0 + n as f64).ln() / (1.0 + peak).ln() * 255.0) as u32;
+    for (y, row) in buffer.chunks_mut(width).enumerate().take(rows * DENSITY_CELL) {
+        for (x, pixel) in row.iter_mut().enumerate().take(columns * DENSITY_CELL) {
+            let cell = (y / DENSITY_CELL) * columns + x / DENSITY_CELL;
+            if red[cell] > 0 || blue[cell] > 0 {
+                *pixel = (level(red[cell]) << 16) | (level(blue[cell].min(red[cell])) << 8) | level(blue[cell]);
+            }
+        }
+    }
+
+    let (ox, oy) = view.to_pixel(0.0, 0.0);
+    draw_line(buffer, width, (ox, MARGIN as i64), (ox, (height - MARGIN) as i64), AXIS);
+    draw_line(buffer, width, (MARGIN as i64, oy), ((width - MARGIN) as i64, oy), AXIS);
+}
+
+// Draw recorded trajectories up to time t in the position-work plane: each
+// path so far as a trail with a cross at its current point, highlighted while
+// its work is negative, and the trap position lambda(t) as a vertical line
+pub fn draw_playback(buffer: &mut [u32], width: usize, height: usize, trajectories: &[Vec<Frame>], t: f64) {
+    buffer.iter_mut().for_each(|p| *p = BACKGROUND);
+    let frames = || trajectories.iter().flatten();
+    let x_min = frames().map(|f| f.x.min(f.lambda)).fold(f64::INFINITY, f64::min);
+    let x_max = frames().map(|f| f.x.max(f.lambda)).fold(f64::NEG_INFINITY, f64::max);
+    let w_min = frames().map(|f| f.work).fold(0.0, f64::min);
+    let w_max = frames().map(|f| f.work).fold(0.0, f64::max);
+    if !x_min.is_finite() {
+        return;
+    }
+    let (pad_x, pad_w) = ((x_max - x_min).max(1e-9) * 0.05, (w_max - w_min).max(1e-9) * 0.05);
+    let view = Viewport {
+        width,
+        height,
+        x_min: x_min - pad_x,
+        x_max: x_max + pad_x,
+        y_min: w_min - pad_w,
+        y_max: w_max + pad_w,
+    };
+
+    let (_, oy) = view.to_pixel(0.0, 0.0);
+    draw_line(buffer, width, (MARGIN as i64, oy), ((width - MARGIN) as i64, oy), AXIS);
+    if let Some(frames) = trajectories.first() {
+        let (lx, _) = view.to_pixel(frames[recording::frame_at(frames, t)].lambda, 0.0);
+        draw_line(buffer, width, (lx, MARGIN as i64), (lx, (height - MARGIN) as i64), FIT);
+    }
+
+    for frames in trajectories {
+        let now = recording::frame_at(frames, t);
+        let colour = if frames[now].work < 0.0 { NEGATIVE_WORK } else { TRAIL };
+        for pair in frames[..=now].windows(2) {
+            let from = view.to_pixel(pair[0].x, pair[0].work);
+            let to = view.to_pixel(pair[1].x, pair[1].work);
+            draw_line(buffer, width, from, to, colour);
+        }
+        let (px, py) = view.to_pixel(frames[now].x, frames[now].work);
+        draw_line(buffer, width, (px - 3, py), (px + 3, py), POINT);
+        draw_line(buffer, width, (px, py - 3), (px, py + 3), POINT);
+    }
+}
+
+// Draw lambda(t) of a baseline schedule and a second one over the same
+// duration, the baseline in the fit colour and the other in the forward colour
+pub fn draw_schedules(buffer: &mut [u32], width: usize, height: usize, baseline: &Schedule, other: &Schedule, duration: f64) {
+    buffer.iter_mut().for_each(|p| *p = BACKGROUND);
+    let curves: Vec<Vec<(f64, f64)>> = [baseline, other]
+        .iter()
+        .map(|schedule| {
+            (0..=SCHEDULE_POINTS)
+                .map(|i| {
+                    let t = duration * i as f64 / SCHEDULE_POINTS as f64;
+                    (t, schedule.at(t, duration))
+                })
+                .collect()
+        })
+        .collect();
+    let y_min = curves.iter().flatten().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    let y_max = curves.iter().flatten().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+    let pad_y = (y_max - y_min).max(1e-9) * 0.05;
+    let view = Viewport {
+        width,
+        height,
+        x_min: 0.0,
+        x_max: duration,
+        y_min: y_min - pad_y,
+        y_max: y_max + pad_y,
+    };
+
+    let (ox, oy) = view.to_pixel(0.0, 0.0);
+    draw_line(buffer, width, (ox, MARGIN as i64), (ox, (height - MARGIN) as i64), AXIS);
+    draw_line(buffer, width, (MARGIN as i64, oy), ((width - MARGIN) as i64, oy), AXIS);
+
+    for (curve, colour) in curves.iter().zip([FIT, FORWARD]) {
+        for pair in curve.windows(2) {
+            draw_line(buffer, width, view.to_pixel(pair[0].0, pair[0].1), view.to_pixel(pair[1].0, pair[1].1), colour);
+        }
+    }
+}
+
+// Draw the quantum P_F(W) and P_R(-W) as spikes at their energy levels, the
+// reverse one a pixel to the right, over the classical Gaussian densities
+// scaled by hbar Omega to probabilities per level
+pub fn draw_quantum_work(buffer: &mut [u32], width: usize, height: usize, reference: &QuantumReference) {
+    buffer.iter_mut().for_each(|p| *p = BACKGROUND);
+    let (forward, reverse) = (reference.forward.levels(), reference.reverse.levels());
+    let spikes: Vec<(f64, f64)> = forward.iter().map(|l| (l.work, l.probability)).collect();
+    let mirrored: Vec<(f64, f64)> = reverse.iter().map(|l| (-l.work, l.probability)).collect();
+    let x_min = spikes.iter().chain(&mirrored).map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let x_max = spikes.iter().chain(&mirrored).map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+    let curve = |work: &TwoPointWork, sign: f64| -> Vec<(f64, f64)> {
+        (0..=SCHEDULE_POINTS)
+            .map(|i| {
+                let w = x_min + (x_max - x_min) * i as f64 / SCHEDULE_POINTS as f64;
+                (w, work.hbar_omega * work.classical_density(sign * w))
+            })
+            .collect()
+    };
+    let classical = [curve(&reference.forward, 1.0), curve(&reference.reverse, -1.0)];
+    let y_max = spikes.iter().chain(&mirrored).chain(classical.iter().flatten()).map(|p| p.1).fold(0.0, f64::max);
+    let pad_x = (x_max - x_min).max(1e-9) * 0.05;
+    let view = Viewport {
+        width,
+        height,
+        x_min: x_min - pad_x,
+        x_max: x_max + pad_x,
+        y_min: 0.0,
+        y_max: y_max.max(1e-12) * 1.05,
+    };
+
+    let (ox, oy) = view.to_pixel(0.0, 0.0);
+    draw_line(buffer, width, (ox, MARGIN as i64), (ox, (height - MARGIN) as i64), AXIS);
+    draw_line(buffer, width, (MARGIN as i64, oy), ((width - MARGIN) as i64, oy), AXIS);
+
+    for (curve, colour) in classical.iter().zip([CLASSICAL_FORWARD, CLASSICAL_REVERSE]) {
+        for pair in curve.windows(2) {
+            draw_line(buffer, width, view.to_pixel(pair[0].0, pair[0].1), view.to_pixel(pair[1].0, pair[1].1), colour);
+        }
+    }
+    for (levels, colour, offset) in [(&spikes, FORWARD, 0), (&mirrored, REVERSE, 1)] {
+        for &(w, p) in levels {
+            let (px, py) = view.to_pixel(w, p);
+            draw_line(buffer, width, (px + offset, oy), (px + offset, py), colour);
+        }
+    }
+}
+
+// Draw the running Jarzynski estimates of both directions and the BAR estimate
+// against the number of trajectories, with the exact Delta F as a level line
+// when it is known
+pub fn draw_convergence(buffer: &mut [u32], width: usize, height: usize, history: &[Convergence], exact: Option<f64>) {
+    buffer.iter_mut().for_each(|p| *p = BACKGROUND);
+    let (Some(first), Some(last)) = (history.first(), history.last()) else {
+        return;
+    };
+    let curves: Vec<Vec<(f64, f64)>> = [
+        |c: &Convergence| c.jarzynski_forward,
+        |c: &Convergence| c.jarzynski_reverse,
+        |c: &Convergence| c.bar,
+    ]
+    .iter()
+    .map(|estimate| history.iter().map(|c| (c.samples as f64, estimate(c))).collect())
+    .collect();
+    let values = || curves.iter().flatten().map(|p| p.1).chain(exact).filter(|v| v.is_finite());
+    let y_min = values().fold(f64::INFINITY, f64::min);
+    let y_max = values().fold(f64::NEG_INFINITY, f64::max);
+    if y_min > y_max {
+        return;
+    }
+    let pad_y = (y_max - y_min).max(1e-9) * 0.05;
+    let view = Viewport {
+        width,
+        height,
+        x_min: first.samples as f64,
+        x_max: (last.samples as f64).max(first.samples as f64 + 1.0),
+        y_min: y_min - pad_y,
+        y_max: y_max + pad_y,
+    };
+
+    if let Some(exact) = exact {
+        draw_line(buffer, width, view.to_pixel(view.x_min, exact), view.to_pixel(view.x_max, exact), AXIS);
+    }
+    for (curve, colour) in curves.iter().zip([FORWARD, REVERSE, FIT]) {
+        for pair in curve.windows(2) {
+            draw_line(buffer, width, view.to_pixel(pair[0].0, pair[0].1), view.to_pixel(pair[1].0, pair[1].1), colour);
+        }
+    }
+}
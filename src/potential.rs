@@ -0,0 +1,157 @@
+// potential.rs
+// Static energy landscapes the trapped particle is pulled through
+
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+// Names of the built-in landscapes, as selected in the configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Landscape {
+    Flat,
+    Harmonic,
+    DoubleWell,
+    Morse,
+    Periodic,
+}
+
+impl FromStr for Landscape {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "flat" | "none" => Ok(Landscape::Flat),
+            "harmonic" => Ok(Landscape::Harmonic),
+            "double-well" | "doublewell" => Ok(Landscape::DoubleWell),
+            "morse" => Ok(Landscape::Morse),
+            "periodic" | "washboard" => Ok(Landscape::Periodic),
+            _ => Err(format!("unknown landscape '{}'", name)),
+        }
+    }
+}
+
+// A one-dimensional landscape U0(x) with analytic energy and force
+pub trait Potential: Send + Sync {
+    // Energy at position x
+    fn energy(&self, x: f64) -> f64;
+
+    // Force -dU0/dx at position x
+    fn force(&self, x: f64) -> f64;
+
+    // Global minimum of the energy, used for exact Boltzmann rejection sampling
+    fn minimum(&self) -> f64;
+
+    // Stiffness and centre when the landscape is a parabola, so that the
+    // Boltzmann distribution in the trap is Gaussian and can be drawn directly
+    fn quadratic(&self) -> Option<(f64, f64)> {
+        None
+    }
+}
+
+// No landscape: a free particle held only by the trap
+pub struct Flat;
+
+// U0 = k0/2 (x - centre)^2
+pub struct Harmonic {
+    pub stiffness: f64,
+    pub centre: f64,
+}
+
+// U0 = barrier ((x / position)^2 - 1)^2, minima at +-position separated by the barrier
+pub struct DoubleWell {
+    pub barrier: f64,
+    pub position: f64,
+}
+
+// U0 = depth (1 - exp(-(x - centre) / range))^2, a bond that breaks when stretched
+pub struct Morse {
+    pub depth: f64,
+    pub range: f64,
+    pub centre: f64,
+}
+
+// U0 = amplitude cos(2 pi x / period), a washboard landscape
+pub struct Periodic {
+    pub amplitude: f64,
+    pub period: f64,
+}
+
+impl Potential for Flat {
+    fn energy(&self, _x: f64) -> f64 {
+        0.0
+    }
+
+    fn force(&self, _x: f64) -> f64 {
+        0.0
+    }
+
+    fn minimum(&self) -> f64 {
+        0.0
+    }
+
+    fn quadratic(&self) -> Option<(f64, f64)> {
+        Some((0.0, 0.0))
+    }
+}
+
+impl Potential for Harmonic {
+    fn energy(&self, x: f64) -> f64 {
+        0.5 * self.stiffness * (x - self.centre).powi(2)
+    }
+
+    fn force(&self, x: f64) -> f64 {
+        -self.stiffness * (x - self.centre)
+    }
+
+    fn minimum(&self) -> f64 {
+        0.0
+    }
+
+    fn quadratic(&self) -> Option<(f64, f64)> {
+        Some((self.stiffness, self.centre))
+    }
+}
+
+impl Potential for DoubleWell {
+    fn energy(&self, x: f64) -> f64 {
+        let u = x / self.position;
+        self.barrier * (u * u - 1.0).powi(2)
+    }
+
+    fn force(&self, x: f64) -> f64 {
+        let u = x / self.position;
+        -4.0 * self.barrier * u * (u * u - 1.0) / self.position
+    }
+
+    fn minimum(&self) -> f64 {
+        0.0
+    }
+}
+
+impl Potential for Morse {
+    fn energy(&self, x: f64) -> f64 {
+        self.depth * (1.0 - (-(x - self.centre) / self.range).exp()).powi(2)
+    }
+
+    fn force(&self, x: f64) -> f64 {
+        let e = (-(x - self.centre) / self.range).exp();
+        -2.0 * self.depth * (1.0 - e) * e / self.range
+    }
+
+    fn minimum(&self) -> f64 {
+        0.0
+    }
+}
+
+impl Potential for Periodic {
+    fn energy(&self, x: f64) -> f64 {
+        self.amplitude * (2.0 * PI * x / self.period).cos()
+    }
+
+    fn force(&self, x: f64) -> f64 {
+        2.0 * PI * self.amplitude / self.period * (2.0 * PI * x / self.period).sin()
+    }
+
+    fn minimum(&self) -> f64 {
+        -self.amplitude.abs()
+    }
+}
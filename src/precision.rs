@@ -0,0 +1,236 @@
+// precision.rs
+// Double-double arithmetic and extended-precision versions of the free-energy
+// estimators, to tell floating-point artifacts from statistical discrepancies
+
+use crate::units::Temperature;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+// Unevaluated sum hi + lo of two doubles with |lo| <= ulp(hi)/2, carrying about
+// 106 bits of significand (Dekker 1971; Hida, Li and Bailey 2001)
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct DoubleDouble {
+    pub hi: f64,
+    pub lo: f64,
+}
+
+// ln 2 to double-double precision
+const LN_2: DoubleDouble = DoubleDouble {
+    hi: std::f64::consts::LN_2,
+    lo: 2.319_046_813_846_299_6e-17,
+};
+
+// Halvings of the reduced exponent before its Taylor series, undone by squaring
+const EXP_HALVINGS: i32 = 10;
+
+// Sum of two doubles with its exact rounding error
+fn two_sum(a: f64, b: f64) -> DoubleDouble {
+    let hi = a + b;
+    let v = hi - a;
+    DoubleDouble {
+        hi,
+        lo: (a - (hi - v)) + (b - v),
+    }
+}
+
+// two_sum for |a| >= |b|
+fn quick_two_sum(a: f64, b: f64) -> DoubleDouble {
+    let hi = a + b;
+    DoubleDouble { hi, lo: b - (hi - a) }
+}
+
+// Product of two doubles with its exact rounding error, by fused multiply-add
+fn two_prod(a: f64, b: f64) -> DoubleDouble {
+    let hi = a * b;
+    DoubleDouble {
+        hi,
+        lo: a.mul_add(b, -hi),
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(value: f64) -> Self {
+        Self { hi: value, lo: 0.0 }
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let s = two_sum(self.hi, other.hi);
+        let t = two_sum(self.lo, other.lo);
+        let s = quick_two_sum(s.hi, s.lo + t.hi);
+        quick_two_sum(s.hi, s.lo + t.lo)
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self { hi: -self.hi, lo: -self.lo }
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let p = two_prod(self.hi, other.hi);
+        quick_two_sum(p.hi, p.lo + (self.hi * other.lo + self.lo * other.hi))
+    }
+}
+
+impl Div for DoubleDouble {
+    type Output = Self;
+
+    // Long division: three quotient digits, each from the remainder left by the last
+    fn div(self, other: Self) -> Self {
+        let q1 = self.hi / other.hi;
+        let r = self - other * q1.into();
+        let q2 = r.hi / other.hi;
+        let r = r - other * q2.into();
+        let q3 = r.hi / other.hi;
+        quick_two_sum(q1, q2) + q3.into()
+    }
+}
+
+impl DoubleDouble {
+    // Nearest double
+    pub fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+
+    // Multiply by a power of two, which is exact
+    fn scale(self, factor: f64) -> Self {
+        Self {
+            hi: self.hi * factor,
+            lo: self.lo * factor,
+        }
+    }
+
+    // e^x: x = k ln 2 + r, with e^r from the Taylor series of r / 2^EXP_HALVINGS
+    // squared back up and shifted by 2^k
+    pub fn exp(self) -> Self {
+        if self.hi < -745.0 {
+            return 0.0.into();
+        }
+        if self.hi > 709.0 {
+            return f64::INFINITY.into();
+        }
+        let k = (self.hi / LN_2.hi).round();
+        let r = (self - LN_2 * k.into()).scale(0.5f64.powi(EXP_HALVINGS));
+        let (mut sum, mut term) = (Self::from(1.0), Self::from(1.0));
+        for n in 1..30 {
+            term = term * r / (n as f64).into();
+            sum = sum + term;
+            if term.hi.abs() < 1e-34 * sum.hi {
+                break;
+            }
+        }
+        for _ in 0..EXP_HALVINGS {
+            sum = sum * sum;
+        }
+        // Shifted in two halves so that 2^k cannot overflow on the way
+        let half = (k / 2.0).trunc();
+        sum.scale(2f64.powi(half as i32)).scale(2f64.powi((k - half) as i32))
+    }
+
+    // ln x by Newton's method on e^y = x from the double-precision logarithm
+    pub fn ln(self) -> Self {
+        let mut y = Self::from(self.hi.ln());
+        for _ in 0..2 {
+            y = y + self * (-y).exp() - 1.0.into();
+        }
+        y
+    }
+}
+
+// Inverse temperature 1/kT to double-double precision
+fn beta(temperature: Temperature) -> DoubleDouble {
+    DoubleDouble::from(1.0) / temperature.kt().into()
+}
+
+// ln(sum(exp(values))) in double-double
+fn log_sum_exp(values: &[DoubleDouble]) -> DoubleDouble {
+    let max = values.iter().copied().fold(DoubleDouble::from(f64::NEG_INFINITY), |a, b| if b > a { b } else { a });
+    let sum = values.iter().fold(DoubleDouble::from(0.0), |sum, &v| sum + (v - max).exp());
+    max + sum.ln()
+}
+
+// Jarzynski Delta F = -kT ln <exp(-beta W)>, as estimators::jarzynski
+pub fn jarzynski(work: &[f64], temperature: Temperature) -> DoubleDouble {
+    let beta = beta(temperature);
+    let exponents: Vec<DoubleDouble> = work.iter().map(|&w| -(beta * w.into())).collect();
+    let ln_mean = log_sum_exp(&exponents) - DoubleDouble::from(work.len() as f64).ln();
+    -(ln_mean / beta)
+}
+
+// Cumulant expansion of Delta F truncated after the given order, as estimators::cumulant
+pub fn cumulant(work: &[f64], temperature: Temperature, order: usize) -> DoubleDouble {
+    let beta = beta(temperature);
+    let n = DoubleDouble::from(work.len() as f64);
+    let mean = work.iter().fold(DoubleDouble::from(0.0), |sum, &w| sum + w.into()) / n;
+    let moment = |k: usize| {
+        work.iter().fold(DoubleDouble::from(0.0), |sum, &w| {
+            let d = DoubleDouble::from(w) - mean;
+            sum + (1..k).fold(d, |p, _| p * d)
+        }) / n
+    };
+    let variance = moment(2);
+    let cumulants = [mean, variance, moment(3), moment(4) - DoubleDouble::from(3.0) * variance * variance];
+    let (mut total, mut coefficient) = (DoubleDouble::from(0.0), DoubleDouble::from(1.0));
+    for (k, &kappa) in cumulants.iter().take(order).enumerate() {
+        if k > 0 {
+            coefficient = -(coefficient * beta) / ((k + 1) as f64).into();
+        }
+        total = total + coefficient * kappa;
+    }
+    total
+}
+
+// Fermi function 1/(1 + e^x), from whichever side keeps the exponential finite
+fn fermi(x: DoubleDouble) -> DoubleDouble {
+    let one = DoubleDouble::from(1.0);
+    if x.hi > 0.0 {
+        let e = (-x).exp();
+        e / (one + e)
+    } else {
+        one / (one + x.exp())
+    }
+}
+
+// Bennett acceptance ratio Delta F by bisection on the self-consistent
+// equation, as estimators::bar, carried on to double-double resolution
+pub fn bar(forward: &[f64], reverse: &[f64], temperature: Temperature) -> DoubleDouble {
+    let beta = beta(temperature);
+    let m = (DoubleDouble::from(forward.len() as f64) / (reverse.len() as f64).into()).ln();
+    let imbalance = |delta_f: DoubleDouble| {
+        let lhs = forward.iter().fold(DoubleDouble::from(0.0), |sum, &w| sum + fermi(m + beta * (DoubleDouble::from(w) - delta_f)));
+        let rhs = reverse.iter().fold(DoubleDouble::from(0.0), |sum, &w| sum + fermi(-m + beta * (DoubleDouble::from(w) + delta_f)));
+        lhs - rhs
+    };
+
+    let mut lo = DoubleDouble::from(forward.iter().chain(reverse).fold(f64::INFINITY, |a, &w| a.min(w).min(-w)));
+    let mut hi = DoubleDouble::from(forward.iter().chain(reverse).fold(f64::NEG_INFINITY, |a, &w| a.max(w).max(-w)));
+    for _ in 0..300 {
+        let mid = (lo + hi).scale(0.5);
+        if imbalance(mid).hi < 0.0 {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+        if (hi - lo).hi <= 1e-30 * (hi.hi.abs() + lo.hi.abs()).max(f64::MIN_POSITIVE) {
+            break;
+        }
+    }
+    (lo + hi).scale(0.5)
+}
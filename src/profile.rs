@@ -0,0 +1,116 @@
+// profile.rs
+// Hummer-Szabo reconstruction of the unperturbed free-energy profile G(x)
+
+use crate::estimators::log_sum_exp;
+use crate::histogram::Histogram;
+use crate::protocol::Protocol;
+use crate::simulation::{self, TrapParams};
+use crate::unirand;
+use rayon::prelude::*;
+use std::fs;
+
+// Positions along the pulling axis and accumulated work of every trajectory at
+// one recorded time, with the trap centre and stiffness at that time
+pub struct Slice {
+    pub lambda: f64,
+    pub stiffness: f64,
+    pub positions: Vec<f64>,
+    pub work: Vec<f64>,
+}
+
+// Free energy G(x) on bin centres, relative to its minimum; NaN where no
+// trajectory visited the bin
+pub struct Profile {
+    pub positions: Vec<f64>,
+    pub free_energy: Vec<f64>,
+}
+
+// Run forward trajectories with the same per-trajectory streams as an ensemble,
+// recording position and work every `stride` steps, including the start
+pub fn record_slices(params: &TrapParams, protocol: &Protocol, trajectories: usize, seed: i32, stride: usize) -> Vec<Slice> {
+    let stride = stride.max(1);
+    let records: Vec<Vec<(f64, f64)>> = (0..trajectories)
+        .into_par_iter()
+        .map(|i| {
+            unirand::seed_stream(seed, i);
+            let start = simulation::sample_equilibrium(params, params.control(protocol, 0.0));
+            let mut record = Vec::new();
+            simulation::run_recorded(params, protocol, start, |step, state, work| {
+                if step % stride == 0 {
+                    record.push((state.x[0], work));
+                }
+            });
+            record
+        })
+        .collect();
+
+    (0..records.first().map_or(0, |r| r.len()))
+        .map(|t| {
+            let control = params.control(protocol, (t * stride) as f64 * params.dt);
+            Slice {
+                lambda: control.centre,
+                stiffness: control.stiffness,
+                positions: records.iter().map(|r| r[t].0).collect(),
+                work: records.iter().map(|r| r[t].1).collect(),
+            }
+        })
+        .collect()
+}
+
+// Hummer & Szabo (PNAS 2001): combine the time slices as
+//   G(x) = -kT ln [ sum_t <delta(x - x_t) e^(-beta W_t)> / <e^(-beta W_t)> ]
+//                   / [ sum_t e^(-beta u(x, lambda_t)) / <e^(-beta W_t)> ]
+// where u = k_t/2 (x - lambda_t)^2 is the trap bias, each slice reweighted by its
+// Jarzynski average so every time contributes in proportion to its overlap. In
+// a periodic box the grid spans the box and the bias uses the nearest trap image.
+pub fn hummer_szabo(slices: &[Slice], params: &TrapParams, bins: usize) -> Profile {
+    let (kt, beta) = (params.temperature.kt(), params.temperature.beta());
+    let all: Vec<f64> = slices.iter().flat_map(|s| s.positions.iter().cloned()).collect();
+    let grid = Histogram::for_positions([all.as_slice()], params.box_length, bins);
+    let ln_width = grid.width.ln();
+
+    let mut numerator: Vec<Vec<f64>> = vec![Vec::new(); bins];
+    let mut normalisers = Vec::with_capacity(slices.len());
+    for slice in slices {
+        let ln_n = (slice.work.len() as f64).ln();
+        let ln_norm = log_sum_exp(slice.work.iter().map(|w| -beta * w)) - ln_n;
+        normalisers.push((slice.lambda, slice.stiffness, ln_norm));
+        for (&x, &w) in slice.positions.iter().zip(&slice.work) {
+            if let Some(bin) = grid.bin_of(x) {
+                numerator[bin].push(-beta * w - ln_n - ln_width - ln_norm);
+            }
+        }
+    }
+
+    let positions: Vec<f64> = (0..bins).map(|i| grid.centre(i)).collect();
+    let mut free_energy: Vec<f64> = positions
+        .iter()
+        .zip(&numerator)
+        .map(|(&x, terms)| {
+            if terms.is_empty() {
+                return f64::NAN;
+            }
+            let ln_denominator = log_sum_exp(
+                normalisers
+                    .iter()
+                    .map(|&(lambda, k, ln_norm)| -beta * 0.5 * k * params.minimum_image(x - lambda).powi(2) - ln_norm),
+            );
+            -kt * (log_sum_exp(terms.iter().cloned()) - ln_denominator)
+        })
+        .collect();
+
+    let floor = free_energy.iter().cloned().filter(|g| g.is_finite()).fold(f64::INFINITY, f64::min);
+    free_energy.iter_mut().for_each(|g| *g -= floor);
+    Profile { positions, free_energy }
+}
+
+impl Profile {
+    // Write the curve as `x,G` CSV lines, skipping unvisited bins
+    pub fn write_csv(&self, path: &str) -> Result<(), String> {
+        let mut text = String::from("x,G\n");
+        for (x, g) in self.positions.iter().zip(&self.free_energy).filter(|(_, g)| g.is_finite()) {
+            text.push_str(&format!("{},{}\n", x, g));
+        }
+        fs::write(path, text).map_err(|e| format!("profile: cannot write {}: {}", path, e))
+    }
+}
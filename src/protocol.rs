@@ -0,0 +1,68 @@
+// protocol.rs
+// Forward and time-reversed pulling protocols
+
+use crate::schedule::Schedule;
+use std::str::FromStr;
+
+// Which way a protocol was run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Reverse,
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "forward" => Ok(Direction::Forward),
+            "reverse" | "backward" => Ok(Direction::Reverse),
+            _ => Err(format!("unknown direction '{}'", name)),
+        }
+    }
+}
+
+// Trap centre driven by a schedule over a fixed duration, optionally with the
+// trap stiffness driven by an independent schedule at the same time
+#[derive(Clone)]
+pub struct Protocol {
+    pub schedule: Schedule,
+    pub stiffness: Option<Schedule>,
+    pub duration: f64,
+}
+
+impl Protocol {
+    // Control parameter at time t
+    pub fn lambda_at(&self, t: f64) -> f64 {
+        self.schedule.at(t, self.duration)
+    }
+
+    // Trap stiffness at time t, when the protocol drives it
+    pub fn stiffness_at(&self, t: f64) -> Option<f64> {
+        self.stiffness.as_ref().map(|schedule| schedule.at(t, self.duration))
+    }
+
+    // Control parameter before the protocol starts
+    pub fn lambda_start(&self) -> f64 {
+        self.lambda_at(0.0)
+    }
+
+    // The time-reversed protocol, lambda_R(t) = lambda_F(duration - t), with
+    // every driven parameter reversed together
+    pub fn reversed(&self) -> Protocol {
+        Protocol {
+            schedule: self.schedule.reversed(),
+            stiffness: self.stiffness.as_ref().map(Schedule::reversed),
+            duration: self.duration,
+        }
+    }
+
+    // The protocol as run in the given direction
+    pub fn oriented(&self, direction: Direction) -> Protocol {
+        match direction {
+            Direction::Forward => self.clone(),
+            Direction::Reverse => self.reversed(),
+        }
+    }
+}
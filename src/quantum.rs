@@ -0,0 +1,228 @@
+// quantum.rs
+// Exact two-point-measurement work statistics of the dragged quantum harmonic oscillator
+
+use crate::analysis::{CrooksPoint, CrooksRatio};
+use crate::analytic;
+use crate::protocol::Protocol;
+use crate::simulation::TrapParams;
+use rustfft::num_complex::Complex64;
+use std::fs;
+
+// Fine steps used to integrate the classical orbit along a protocol
+const ORBIT_STEPS: usize = 100_000;
+
+// Standard deviations of the quanta count covered by the tabulated levels
+const LEVEL_SPREAD: f64 = 8.0;
+
+// Work statistics of one direction of the protocol. Energy is measured in the
+// eigenbasis of the initial and of the final Hamiltonian; with the trap frequency
+// fixed, the drag only displaces the oscillator, so the work is Delta F plus
+// hbar Omega times the quanta absorbed minus those emitted. Starting from a
+// thermal state with occupation n, these are Poisson counts with means
+// e (n + 1) and e n, e being the coherent excitation of the drag.
+#[derive(Clone, Copy, Debug)]
+pub struct TwoPointWork {
+    pub delta_f: f64,
+    pub hbar_omega: f64,
+    pub occupation: f64, // Thermal occupation 1/(e^(beta hbar Omega) - 1) of the start
+    pub excitation: f64, // Quanta |alpha|^2 of the coherent displacement left by the drag
+    pub kt: f64,
+}
+
+// Work probability of one energy level W = Delta F + m hbar Omega
+pub struct WorkLevel {
+    pub quanta: i64,
+    pub work: f64,
+    pub probability: f64,
+}
+
+impl TwoPointWork {
+    // Characteristic function G(u) = <e^(iuW)> = e^(iu Delta F)
+    // exp(e ((n + 1)(e^(iu hbar Omega) - 1) + n (e^(-iu hbar Omega) - 1))),
+    // at complex u so the Crooks symmetry can be evaluated at -u + i beta
+    pub fn characteristic(&self, u: Complex64) -> Complex64 {
+        let i = Complex64::new(0.0, 1.0);
+        let n = self.occupation;
+        let phase = (i * u * self.hbar_omega).exp();
+        (i * u * self.delta_f + self.excitation * ((n + 1.0) * (phase - 1.0) + n * (1.0 / phase - 1.0))).exp()
+    }
+
+    // Mean work Delta F + e hbar Omega, the same as for the classical oscillator
+    pub fn mean(&self) -> f64 {
+        self.delta_f + self.excitation * self.hbar_omega
+    }
+
+    // Work variance e (hbar Omega)^2 (2n + 1) = E hbar Omega coth(beta hbar Omega / 2),
+    // E being the displacement energy
+    pub fn variance(&self) -> f64 {
+        self.excitation * self.hbar_omega * self.hbar_omega * (2.0 * self.occupation + 1.0)
+    }
+
+    // Variance 2 kT E of the classical isolated oscillator, which the quantum
+    // variance approaches once hbar Omega is small against kT
+    pub fn classical_variance(&self) -> f64 {
+        2.0 * self.kt * self.excitation * self.hbar_omega
+    }
+
+    // Classical Gaussian work density at W, with the same mean and the classical variance
+    pub fn classical_density(&self, work: f64) -> f64 {
+        let variance = self.classical_variance();
+        if variance <= 0.0 {
+            return 0.0;
+        }
+        (-(work - self.mean()).powi(2) / (2.0 * variance)).exp() / (2.0 * std::f64::consts::PI * variance).sqrt()
+    }
+
+    // Probabilities of the levels within LEVEL_SPREAD standard deviations of the
+    // mean, each the sum over emitted quanta j of Poisson(m + j; e (n + 1))
+    // Poisson(j; e n), evaluated in log space
+    pub fn levels(&self) -> Vec<WorkLevel> {
+        let (up, down) = (self.excitation * (self.occupation + 1.0), self.excitation * self.occupation);
+        let spread = LEVEL_SPREAD * (up + down).sqrt() + 3.0;
+        let (low, high) = ((up - down - spread).floor() as i64, (up - down + spread).ceil() as i64);
+        let emitted = (down + LEVEL_SPREAD * down.sqrt()).ceil() as i64 + 3;
+
+        let mut ln_factorial = vec![0.0];
+        for n in 1..=(high + emitted).max(0) as usize {
+            ln_factorial.push(ln_factorial[n - 1] + (n as f64).ln());
+        }
+        let ln_poisson = |count: i64, rate: f64| match (count, rate > 0.0) {
+            (0, false) => 0.0,
+            (_, false) => f64::NEG_INFINITY,
+            _ => count as f64 * rate.ln() - rate - ln_factorial[count as usize],
+        };
+        (low..=high)
+            .map(|m| WorkLevel {
+                quanta: m,
+                work: self.delta_f + m as f64 * self.hbar_omega,
+                probability: (0.max(-m)..=emitted).map(|j| (ln_poisson(m + j, up) + ln_poisson(j, down)).exp()).sum(),
+            })
+            .filter(|level| level.probability > 0.0)
+            .collect()
+    }
+}
+
+// Forward and reverse two-point-measurement work of the same drag
+pub struct QuantumReference {
+    pub forward: TwoPointWork,
+    pub reverse: TwoPointWork,
+}
+
+// Forward level W = Delta F + m hbar Omega beside the reverse level at -W, m
+// quanta emitted in the reverse direction
+pub struct LevelPair {
+    pub quanta: i64,
+    pub work: f64,
+    pub forward: f64, // P_F(W)
+    pub reverse: f64, // P_R(-W)
+}
+
+impl QuantumReference {
+    // Levels where both P_F(W) and P_R(-W) are at least the floor
+    pub fn paired_levels(&self, floor: f64) -> Vec<LevelPair> {
+        let reverse = self.reverse.levels();
+        self.forward
+            .levels()
+            .into_iter()
+            .filter_map(|level| {
+                let mirrored = reverse.iter().find(|r| r.quanta == -level.quanta)?;
+                (level.probability.min(mirrored.probability) >= floor).then_some(LevelPair {
+                    quanta: level.quanta,
+                    work: level.work,
+                    forward: level.probability,
+                    reverse: mirrored.probability,
+                })
+            })
+            .collect()
+    }
+
+    // ln(P_F(W) / P_R(-W)) at every level both directions reach, against the
+    // exact line beta (W - Delta F) in place of a fit
+    pub fn crooks_ratio(&self, floor: f64) -> CrooksRatio {
+        let beta = 1.0 / self.forward.kt;
+        CrooksRatio {
+            points: self
+                .paired_levels(floor)
+                .iter()
+                .map(|pair| CrooksPoint {
+                    work: pair.work,
+                    log_ratio: (pair.forward / pair.reverse).ln(),
+                    weight: 1.0,
+                })
+                .collect(),
+            slope: beta,
+            intercept: -beta * self.forward.delta_f,
+        }
+    }
+}
+
+// Energy the classical frictionless oscillator is left with above the final
+// potential minimum, having started at rest at the initial one; for the
+// quantum oscillator this is the energy of the coherent displacement, hbar
+// Omega |alpha|^2. RK4 with the trap moved before each step, as analytic does.
+fn displacement_energy(params: &TrapParams, protocol: &Protocol, kappa: f64, centre: f64) -> f64 {
+    let (k, m, f) = (params.stiffness, params.mass, params.tilt);
+    let minimum = |lambda: f64| (k * lambda + kappa * centre + f) / (k + kappa);
+    let derivative = |(x, v): (f64, f64), lambda: f64| (v, -(k + kappa) * (x - minimum(lambda)) / m);
+
+    let mut state = (minimum(protocol.lambda_start()), 0.0);
+    let h = protocol.duration / ORBIT_STEPS as f64;
+    for step in 1..=ORBIT_STEPS {
+        let lambda = protocol.lambda_at(step as f64 * h);
+        let shift = |s: (f64, f64), d: (f64, f64), by: f64| (s.0 + by * d.0, s.1 + by * d.1);
+        let k1 = derivative(state, lambda);
+        let k2 = derivative(shift(state, k1, 0.5 * h), lambda);
+        let k3 = derivative(shift(state, k2, 0.5 * h), lambda);
+        let k4 = derivative(shift(state, k3, h), lambda);
+        state.0 += h / 6.0 * (k1.0 + 2.0 * k2.0 + 2.0 * k3.0 + k4.0);
+        state.1 += h / 6.0 * (k1.1 + 2.0 * k2.1 + 2.0 * k3.1 + k4.1);
+    }
+    let offset = state.0 - minimum(protocol.lambda_at(protocol.duration));
+    0.5 * m * state.1 * state.1 + 0.5 * (k + kappa) * offset * offset
+}
+
+// Exact quantum work statistics of the trap centre dragged along a protocol,
+// the oscillator isolated from the bath between the two energy measurements
+// and prepared in the thermal state of the bath. Delta F is the classical one,
+// the zero-point and thermal terms depending only on the fixed frequency
+// Omega = sqrt((k + kappa) / m). None unless the potential is quadratic and
+// the stiffness fixed.
+pub fn oscillator(params: &TrapParams, protocol: &Protocol, hbar: f64) -> Option<QuantumReference> {
+    if protocol.stiffness.is_some() {
+        return None;
+    }
+    let (kappa, centre) = analytic::quadratic(params)?;
+    let start = params.control(protocol, 0.0);
+    let delta_f = analytic::free_energy(params, params.control(protocol, protocol.duration))? - analytic::free_energy(params, start)?;
+    let hbar_omega = hbar * ((params.stiffness + kappa) / params.mass).sqrt();
+    let kt = params.temperature.kt();
+    let direction = |protocol: &Protocol, delta_f: f64| TwoPointWork {
+        delta_f,
+        hbar_omega,
+        occupation: 1.0 / (hbar_omega / kt).exp_m1(),
+        excitation: displacement_energy(params, protocol, kappa, centre) / hbar_omega,
+        kt,
+    };
+    Some(QuantumReference {
+        forward: direction(protocol, delta_f),
+        reverse: direction(&protocol.reversed(), -delta_f),
+    })
+}
+
+// Write `W,P_F,P_R_minus,classical_F,classical_R_minus` CSV lines for the given
+// levels, the classical densities scaled by hbar Omega to probabilities per level
+pub fn write_csv(path: &str, reference: &QuantumReference, pairs: &[LevelPair]) -> Result<(), String> {
+    let (forward, reverse) = (&reference.forward, &reference.reverse);
+    let mut text = String::from("W,P_F,P_R_minus,classical_F,classical_R_minus\n");
+    for pair in pairs {
+        text.push_str(&format!(
+            "{},{},{},{},{}\n",
+            pair.work,
+            pair.forward,
+            pair.reverse,
+            forward.hbar_omega * forward.classical_density(pair.work),
+            reverse.hbar_omega * reverse.classical_density(-pair.work)
+        ));
+    }
+    fs::write(path, text).map_err(|e| format!("quantum: cannot write {}: {}", path, e))
+}
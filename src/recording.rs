@@ -0,0 +1,148 @@
+// recording.rs
+// Full trajectories (t, x, v, lambda, W) saved to disk for later playback
+
+use crate::protocol::{Direction, Protocol};
+use crate::simulation::{self, TrapParams};
+use crate::unirand;
+use rayon::prelude::*;
+use std::fs;
+use std::str::FromStr;
+
+// State of one trajectory at a recorded time, along the pulling axis
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    pub t: f64,
+    pub x: f64,
+    pub v: f64,
+    pub lambda: f64,
+    pub work: f64,
+}
+
+// Recorded trajectories tagged with the direction of the protocol that produced them
+pub struct Recording {
+    pub direction: Direction,
+    pub trajectories: Vec<Vec<Frame>>,
+}
+
+// Which of the simulated trajectories are kept
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selection {
+    First,  // The first trajectories in seed order
+    Lowest, // Those with the lowest final work, where the rare events are
+}
+
+impl FromStr for Selection {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "first" => Ok(Selection::First),
+            "lowest" | "lowest-work" => Ok(Selection::Lowest),
+            _ => Err(format!("unknown trajectory selection '{}'", name)),
+        }
+    }
+}
+
+// Run trajectories of the protocol with the same per-trajectory streams as an
+// ensemble, recording a frame every `stride` steps including the start, and
+// keep `keep` of them chosen by the selection
+pub fn record(
+    params: &TrapParams,
+    protocol: &Protocol,
+    trajectories: usize,
+    seed: i32,
+    stride: usize,
+    keep: usize,
+    selection: Selection,
+) -> Vec<Vec<Frame>> {
+    let stride = stride.max(1);
+    let count = match selection {
+        Selection::First => trajectories.min(keep),
+        Selection::Lowest => trajectories,
+    };
+    let mut recorded: Vec<Vec<Frame>> = (0..count)
+        .into_par_iter()
+        .map(|i| {
+            unirand::seed_stream(seed, i);
+            let start = simulation::sample_equilibrium(params, params.control(protocol, 0.0));
+            let mut frames = Vec::new();
+            simulation::run_recorded(params, protocol, start, |step, state, work| {
+                if step % stride == 0 {
+                    let t = step as f64 * params.dt;
+                    frames.push(Frame {
+                        t,
+                        x: state.x[0],
+                        v: state.v[0],
+                        lambda: protocol.lambda_at(t),
+                        work,
+                    });
+                }
+            });
+            frames
+        })
+        .collect();
+
+    if selection == Selection::Lowest {
+        let final_work = |frames: &Vec<Frame>| frames.last().map_or(0.0, |f| f.work);
+        recorded.sort_by(|a, b| final_work(a).total_cmp(&final_work(b)));
+        recorded.truncate(keep);
+    }
+    recorded
+}
+
+impl Recording {
+    // Write the trajectories as `trajectory,direction,t,x,v,lambda,W` CSV lines
+    pub fn write_csv(&self, path: &str) -> Result<(), String> {
+        let direction = format!("{:?}", self.direction).to_lowercase();
+        let mut text = String::from("trajectory,direction,t,x,v,lambda,W\n");
+        for (i, frames) in self.trajectories.iter().enumerate() {
+            for f in frames {
+                text.push_str(&format!("{},{},{},{},{},{},{}\n", i, direction, f.t, f.x, f.v, f.lambda, f.work));
+            }
+        }
+        fs::write(path, text).map_err(|e| format!("recording: cannot write {}: {}", path, e))
+    }
+
+    // Read trajectories written by write_csv, in order of their index
+    pub fn read_csv(path: &str) -> Result<Recording, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("recording: cannot read {}: {}", path, e))?;
+        let mut direction = None;
+        let mut trajectories: Vec<Vec<Frame>> = Vec::new();
+        for (number, line) in text.lines().enumerate().skip(1) {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let malformed = || format!("recording: {} line {}: expected index, direction and 5 numbers", path, number + 1);
+            if fields.len() != 7 {
+                return Err(malformed());
+            }
+            let index: usize = fields[0].parse().map_err(|_| malformed())?;
+            let tag: Direction = fields[1].parse()?;
+            if *direction.get_or_insert(tag) != tag {
+                return Err(format!("recording: {} mixes forward and reverse trajectories", path));
+            }
+            let values: Vec<f64> = fields[2..]
+                .iter()
+                .map(|v| v.parse().map_err(|_| malformed()))
+                .collect::<Result<_, _>>()?;
+            if index >= trajectories.len() {
+                trajectories.resize(index + 1, Vec::new());
+            }
+            trajectories[index].push(Frame {
+                t: values[0],
+                x: values[1],
+                v: values[2],
+                lambda: values[3],
+                work: values[4],
+            });
+        }
+        trajectories.retain(|frames| !frames.is_empty());
+        match direction {
+            Some(direction) if !trajectories.is_empty() => Ok(Recording { direction, trajectories }),
+            _ => Err(format!("recording: {} holds no trajectories", path)),
+        }
+    }
+}
+
+// Frame of a trajectory at time t, holding the last one once it has ended
+pub fn frame_at(frames: &[Frame], t: f64) -> usize {
+    frames.partition_point(|f| f.t <= t).saturating_sub(1)
+}
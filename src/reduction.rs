@@ -0,0 +1,39 @@
+// reduction.rs
+// Parallel folds whose result can be made bit-identical whatever the number of threads
+
+use rayon::prelude::*;
+use std::sync::OnceLock;
+
+// Items folded in order within one task in deterministic mode
+const DETERMINISTIC_CHUNK: usize = 64;
+
+// Whether parallel folds use fixed chunks, off unless selected
+static DETERMINISTIC: OnceLock<bool> = OnceLock::new();
+
+// Choose deterministic folds for the whole run; later calls are ignored
+pub fn select_deterministic(deterministic: bool) {
+    let _ = DETERMINISTIC.set(deterministic);
+}
+
+// Fold items 0..count in parallel and merge the partial results. Normally
+// each rayon worker folds whatever items it is handed, so floating-point sums
+// are grouped, and rounded, differently with the number of threads; in
+// deterministic mode the items are folded in fixed chunks of
+// DETERMINISTIC_CHUNK and the chunks merged in order, which gives the same
+// bits on one thread or many.
+pub fn fold_reduce<T, I, F, R>(count: usize, identity: I, fold: F, reduce: R) -> T
+where
+    T: Send,
+    I: Fn() -> T + Sync + Send,
+    F: Fn(T, usize) -> T + Sync + Send,
+    R: Fn(T, T) -> T + Sync + Send,
+{
+    if !DETERMINISTIC.get().copied().unwrap_or(false) {
+        return (0..count).into_par_iter().fold(&identity, &fold).reduce(&identity, &reduce);
+    }
+    let partials: Vec<T> = (0..count.div_ceil(DETERMINISTIC_CHUNK))
+        .into_par_iter()
+        .map(|chunk| (chunk * DETERMINISTIC_CHUNK..((chunk + 1) * DETERMINISTIC_CHUNK).min(count)).fold(identity(), &fold))
+        .collect();
+    partials.into_iter().fold(identity(), &reduce)
+}
@@ -0,0 +1,47 @@
+// replica.rs
+// The same protocol run at several bath temperatures, collected into one dataset
+
+use crate::config::Config;
+use crate::ensemble::{self, PairedEnsembles};
+use crate::units::{Temperature, UnitSystem};
+use std::fs;
+
+// Forward and reverse ensembles at one bath temperature
+pub struct Replica {
+    pub setting: f64, // Temperature as configured: kT in reduced units, kelvin in SI
+    pub bath: Temperature,
+    pub ensembles: PairedEnsembles,
+}
+
+// Run the configured protocol in both directions at every listed temperature.
+// Every replica reuses the same seeds, so the differences between them come
+// from the temperature rather than from the random streams.
+pub fn run(config: &Config) -> Vec<Replica> {
+    config
+        .temperature_replicas()
+        .iter()
+        .map(|replica| Replica {
+            setting: match replica.units {
+                UnitSystem::Reduced => replica.kt,
+                UnitSystem::Si => replica.temperature,
+            },
+            bath: replica.bath(),
+            ensembles: ensemble::run_paired(replica.model().as_ref(), &replica.protocol(), replica.trajectories, replica.seed),
+        })
+        .collect()
+}
+
+// Write every work value of every replica as `temperature,kT,direction,W` CSV
+// lines, one dataset comparing all temperatures
+pub fn write_csv(replicas: &[Replica], path: &str) -> Result<(), String> {
+    let mut text = String::from("temperature,kT,direction,W\n");
+    for replica in replicas {
+        for ensemble in [&replica.ensembles.forward, &replica.ensembles.reverse] {
+            let direction = format!("{:?}", ensemble.direction).to_lowercase();
+            for w in &ensemble.work {
+                text.push_str(&format!("{},{},{},{}\n", replica.setting, replica.bath.kt(), direction, w));
+            }
+        }
+    }
+    fs::write(path, text).map_err(|e| format!("replica: cannot write {}: {}", path, e))
+}
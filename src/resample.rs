@@ -0,0 +1,88 @@
+// resample.rs
+// Bootstrap confidence intervals for free-energy estimators
+
+use crate::simulation::uniform;
+use crate::unirand;
+use rayon::prelude::*;
+
+// Point estimate with a percentile bootstrap interval
+pub struct Interval {
+    pub estimate: f64,
+    pub lower: f64,
+    pub upper: f64,
+    pub std_error: f64,
+}
+
+// Draw n samples with replacement, as runs of `block` consecutive samples
+// (a moving-block bootstrap) so that serial correlation survives resampling
+fn resample(samples: &[f64], block: usize) -> Vec<f64> {
+    let n = samples.len();
+    let block = block.clamp(1, n);
+    let starts = n - block + 1;
+    let mut drawn = Vec::with_capacity(n + block);
+    while drawn.len() < n {
+        let start = ((uniform() * starts as f64) as usize).min(starts - 1);
+        drawn.extend_from_slice(&samples[start..start + block]);
+    }
+    drawn.truncate(n);
+    drawn
+}
+
+// Seed of the bootstrap replicates of an ensemble of `trajectories`: the
+// substreams after its forward trajectories', so the resampling draws none of
+// the noise the work values came from
+pub fn replicate_seed(seed: i32, trajectories: usize) -> i32 {
+    unirand::stream_seed(seed, trajectories as u64)
+}
+
+// Bootstrap an estimator of forward and reverse work. Each replicate resamples
+// the forward and reverse sets separately, keeping their sizes, so the two
+// protocol directions are never mixed; block > 1 resamples correlated series
+// in blocks of that length. Replicates run in parallel with one RNG
+// stream each; any that produce a non-finite estimate (e.g. no crossing) are
+// left out of the interval.
+pub fn bootstrap<F>(
+    forward: &[f64],
+    reverse: &[f64],
+    block: usize,
+    replicates: usize,
+    confidence: f64,
+    seed: i32,
+    estimator: F,
+) -> Interval
+where
+    F: Fn(&[f64], &[f64]) -> f64 + Sync,
+{
+    let estimate = estimator(forward, reverse);
+    let mut values: Vec<f64> = (0..replicates)
+        .into_par_iter()
+        .map(|r| {
+            unirand::seed_stream(seed, r);
+            estimator(&resample(forward, block), &resample(reverse, block))
+        })
+        .filter(|v| v.is_finite())
+        .collect();
+
+    if values.is_empty() {
+        return Interval {
+            estimate,
+            lower: f64::NAN,
+            upper: f64::NAN,
+            std_error: f64::NAN,
+        };
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let std_error = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0).max(1.0)).sqrt();
+    let tail = 0.5 * (1.0 - confidence);
+    let quantile = |q: f64| values[((n - 1.0) * q).round() as usize];
+
+    Interval {
+        estimate,
+        lower: quantile(tail),
+        upper: quantile(1.0 - tail),
+        std_error,
+    }
+}
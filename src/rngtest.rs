@@ -0,0 +1,103 @@
+// rngtest.rs
+// Statistical tests of the uniform and Gaussian streams of the random generator
+
+use crate::normality::{erfc, normal_cdf};
+
+// Outcome of one test: its statistic and the probability of a statistic at
+// least as extreme from a perfect generator
+pub struct RngTest {
+    pub name: String,
+    pub statistic: f64,
+    pub p_value: f64,
+}
+
+// Pearson chi-square of the counts of uniforms in equal bins on [0, 1), its
+// upper tail from the Wilson-Hilferty cube-root normal approximation, which is
+// accurate to a few per cent of p for tens of degrees of freedom and more
+pub fn chi_square(uniforms: &[f64], bins: usize) -> RngTest {
+    let mut counts = vec![0usize; bins];
+    for &u in uniforms {
+        counts[((u * bins as f64) as usize).min(bins - 1)] += 1;
+    }
+    let expected = uniforms.len() as f64 / bins as f64;
+    let statistic: f64 = counts.iter().map(|&c| (c as f64 - expected).powi(2) / expected).sum();
+    let dof = (bins - 1) as f64;
+    let spread = 2.0 / (9.0 * dof);
+    let z = ((statistic / dof).cbrt() - (1.0 - spread)) / spread.sqrt();
+    RngTest {
+        name: format!("chi-square, {} bins", bins),
+        statistic,
+        p_value: normal_cdf(-z),
+    }
+}
+
+// Kolmogorov distribution Q(lambda) = 2 sum (-1)^(j-1) exp(-2 j^2 lambda^2),
+// the chance of a scaled Kolmogorov-Smirnov distance above lambda
+fn kolmogorov_tail(lambda: f64) -> f64 {
+    if lambda < 0.2 {
+        return 1.0;
+    }
+    let sum: f64 = (1..=100)
+        .map(|j| {
+            let sign = if j % 2 == 1 { 1.0 } else { -1.0 };
+            sign * (-2.0 * (j * j) as f64 * lambda * lambda).exp()
+        })
+        .sum();
+    (2.0 * sum).clamp(0.0, 1.0)
+}
+
+// Kolmogorov-Smirnov distance D between the empirical distribution of the
+// samples and a cumulative distribution, with Stephens' finite-N scaling
+// (sqrt(N) + 0.12 + 0.11 / sqrt(N)) D of the asymptotic tail
+pub fn kolmogorov_smirnov(name: &str, samples: &[f64], cdf: impl Fn(f64) -> f64) -> RngTest {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let n = sorted.len() as f64;
+    let statistic = sorted
+        .iter()
+        .enumerate()
+        .map(|(i, &x)| {
+            let f = cdf(x);
+            (f - i as f64 / n).max((i + 1) as f64 / n - f)
+        })
+        .fold(0.0, f64::max);
+    let root = n.sqrt();
+    RngTest {
+        name: format!("Kolmogorov-Smirnov, {}", name),
+        statistic,
+        p_value: kolmogorov_tail((root + 0.12 + 0.11 / root) * statistic),
+    }
+}
+
+// Autocorrelation of the uniforms at one lag, whose sqrt(N) multiple is a
+// standard normal for independent draws; the p-value is two-sided
+pub fn serial_correlation(uniforms: &[f64], lag: usize) -> RngTest {
+    let n = uniforms.len() as f64;
+    let mean = uniforms.iter().sum::<f64>() / n;
+    let variance: f64 = uniforms.iter().map(|u| (u - mean).powi(2)).sum();
+    let covariance: f64 = uniforms.iter().zip(&uniforms[lag..]).map(|(a, b)| (a - mean) * (b - mean)).sum();
+    let statistic = covariance / variance;
+    RngTest {
+        name: format!("serial correlation, lag {}", lag),
+        statistic,
+        p_value: erfc(statistic.abs() * (n - lag as f64).sqrt() / std::f64::consts::SQRT_2),
+    }
+}
+
+// Sample mean and variance against the exact ones: the mean's z-score from
+// the sample variance and the variance's from the sample fourth central
+// moment, the p-value of the worse of the two, doubled for the pair
+pub fn moments(name: &str, samples: &[f64], mean: f64, variance: f64) -> RngTest {
+    let n = samples.len() as f64;
+    let sample_mean = samples.iter().sum::<f64>() / n;
+    let central = |power: i32| samples.iter().map(|x| (x - sample_mean).powi(power)).sum::<f64>() / n;
+    let (sample_variance, fourth) = (central(2), central(4));
+    let z_mean = (sample_mean - mean) / (sample_variance / n).sqrt();
+    let z_variance = (sample_variance - variance) / ((fourth - sample_variance * sample_variance) / n).sqrt();
+    let statistic = z_mean.abs().max(z_variance.abs());
+    RngTest {
+        name: format!("moments, {}", name),
+        statistic,
+        p_value: (2.0 * erfc(statistic / std::f64::consts::SQRT_2)).min(1.0),
+    }
+}
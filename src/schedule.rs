@@ -0,0 +1,150 @@
+// schedule.rs
+// Control schedules lambda(t) and their exact time reverses
+
+use crate::expr::Expression;
+use std::f64::consts::PI;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Names of the built-in schedule shapes, as selected in the configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Linear,
+    Sinusoidal,
+    Piecewise,
+    Custom,
+    Geodesic, // Piecewise knots at constant thermodynamic speed, measured at startup
+}
+
+impl FromStr for Shape {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "linear" => Ok(Shape::Linear),
+            "sinusoidal" | "cosine" => Ok(Shape::Sinusoidal),
+            "piecewise" | "table" => Ok(Shape::Piecewise),
+            "custom" => Ok(Shape::Custom),
+            "geodesic" => Ok(Shape::Geodesic),
+            _ => Err(format!("unknown schedule '{}'", name)),
+        }
+    }
+}
+
+// Maps protocol time onto the control parameter lambda
+#[derive(Clone)]
+pub enum Schedule {
+    // Constant speed from one value to another
+    Linear { from: f64, to: f64 },
+    // Half-cosine ease in and out, starting and stopping at zero speed
+    Sinusoidal { from: f64, to: f64 },
+    // Linear interpolation between (s, lambda) knots, s = t / duration in [0, 1];
+    // reversed schedules evaluate the same knots at 1 - s, so a jump (two knots
+    // at one s) happens at exactly the mirrored step of the time grid
+    Piecewise { knots: Arc<Vec<(f64, f64)>>, reversed: bool },
+    // User expression in t (protocol time) and s (t / duration); reversed
+    // schedules evaluate it at duration - t
+    Custom { expression: Arc<Expression>, reversed: bool },
+}
+
+impl Schedule {
+    // Control parameter at time t of a protocol lasting duration
+    pub fn at(&self, t: f64, duration: f64) -> f64 {
+        let s = (t / duration).clamp(0.0, 1.0);
+        match self {
+            Schedule::Linear { from, to } => from + (to - from) * s,
+            Schedule::Sinusoidal { from, to } => from + (to - from) * 0.5 * (1.0 - (PI * s).cos()),
+            Schedule::Piecewise { knots, reversed } => {
+                let s = if *reversed { 1.0 - s } else { s };
+                let i = knots.partition_point(|&(ks, _)| ks < s).clamp(1, knots.len() - 1);
+                let (s0, l0) = knots[i - 1];
+                let (s1, l1) = knots[i];
+                if s1 > s0 {
+                    l0 + (l1 - l0) * ((s - s0) / (s1 - s0)).clamp(0.0, 1.0)
+                } else {
+                    l1
+                }
+            }
+            Schedule::Custom { expression, reversed } => {
+                let t = if *reversed { duration - t } else { t };
+                expression.eval(&[t, t / duration])
+            }
+        }
+    }
+
+    // The exact time reverse, lambda_R(t) = lambda_F(duration - t)
+    pub fn reversed(&self) -> Schedule {
+        match self {
+            Schedule::Linear { from, to } => Schedule::Linear { from: *to, to: *from },
+            Schedule::Sinusoidal { from, to } => Schedule::Sinusoidal { from: *to, to: *from },
+            Schedule::Piecewise { knots, reversed } => Schedule::Piecewise {
+                knots: knots.clone(),
+                reversed: !reversed,
+            },
+            Schedule::Custom { expression, reversed } => Schedule::Custom {
+                expression: expression.clone(),
+                reversed: !reversed,
+            },
+        }
+    }
+}
+
+// Parse knots written as "s:lambda, s:lambda, ..." with s increasing from 0 to 1
+pub fn parse_knots(text: &str) -> Result<Vec<(f64, f64)>, String> {
+    let knots = text
+        .split(',')
+        .map(|pair| {
+            let (s, lambda) = pair
+                .split_once(':')
+                .ok_or_else(|| format!("schedule: knot '{}' is not s:lambda", pair.trim()))?;
+            let parse = |v: &str| v.trim().parse::<f64>().map_err(|_| format!("schedule: invalid number in knot '{}'", pair.trim()));
+            Ok((parse(s)?, parse(lambda)?))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if knots.len() < 2 {
+        return Err("schedule: piecewise schedules need at least two knots".to_string());
+    }
+    if knots.windows(2).any(|w| w[1].0 < w[0].0) || knots[0].0 != 0.0 || knots[knots.len() - 1].0 != 1.0 {
+        return Err("schedule: knot times must increase from 0 to 1".to_string());
+    }
+    Ok(knots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn schedules() -> Vec<Schedule> {
+        let expression = Expression::parse("sin(t) + s^2", &["t", "s"], &HashMap::new()).unwrap();
+        vec![
+            Schedule::Linear { from: -1.0, to: 2.0 },
+            Schedule::Sinusoidal { from: 0.5, to: -3.0 },
+            Schedule::Piecewise {
+                knots: Arc::new(parse_knots("0:0, 0.3:2, 0.5:-1, 1:4").unwrap()),
+                reversed: false,
+            },
+            Schedule::Custom {
+                expression: Arc::new(expression),
+                reversed: false,
+            },
+        ]
+    }
+
+    #[test]
+    fn reversed_runs_backwards_in_time() {
+        let duration = 2.5;
+        for (n, schedule) in schedules().iter().enumerate() {
+            let reversed = schedule.reversed();
+            let twice = reversed.reversed();
+            for step in 0..=40 {
+                let t = duration * step as f64 / 40.0;
+                let forward = schedule.at(duration - t, duration);
+                let backward = reversed.at(t, duration);
+                assert!((forward - backward).abs() < 1e-12, "schedule {} at t = {}: {} vs {}", n, t, forward, backward);
+                assert_eq!(schedule.at(t, duration), twice.at(t, duration), "schedule {} reversed twice at t = {}", n, t);
+            }
+        }
+    }
+}
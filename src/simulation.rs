@@ -0,0 +1,473 @@
+// simulation.rs
+// Langevin particle dragged through an energy landscape by a harmonic trap
+
+use crate::analytic;
+use crate::distributions;
+use crate::expr::Expression;
+use crate::integrator::{self, Integrator, State, Vector};
+use crate::model::Model;
+use crate::potential::Potential;
+use crate::protocol::Protocol;
+use crate::unirand::RNG;
+use crate::units::Temperature;
+use std::f64::consts::PI;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Grid used to tabulate the Boltzmann distribution of expression potentials
+const SAMPLING_POINTS: usize = 4001;
+
+// Parameters of the pulling experiment: U(r, lambda) = U0(x) + k/2 |r - lambda e_x|^2,
+// with the landscape acting along the pulling axis x and the trap isotropic in
+// every simulated dimension, unless a user expression replaces U(x, lambda) as
+// a whole in one dimension
+pub struct TrapParams {
+    pub landscape: Box<dyn Potential>,         // Static landscape U0(x)
+    pub expression: Option<Arc<Expression>>, // Full U(x, lambda) from the configuration
+    pub sample_range: f64,                     // Half-width of the sampling grid for expressions
+    pub dimensions: usize,                     // Number of simulated axes, 1 to 3
+    pub stiffness: f64, // Trap spring constant k
+    pub friction: f64,  // Friction coefficient gamma
+    pub mass: f64,      // Particle mass, used only by inertial integrators
+    pub temperature: Temperature, // Bath temperature, kT and beta
+    pub dt: f64,        // Integration time step
+    pub integrator: Integrator,
+    pub initial: Initial, // How the starting point of each trajectory is drawn
+    pub equilibration: f64, // Time the particle relaxes at fixed lambda before the protocol
+    pub tolerance: Option<f64>, // Error tolerance of adaptive time stepping, when enabled
+    pub escort: f64, // Fraction of each trap displacement applied to the particle, 0 for plain pulling
+    pub correlation_time: Option<f64>, // Correlation time of an Ornstein-Uhlenbeck bath, None for white noise
+    pub tilt: f64, // Constant external force f along the pulling axis, adding -f x to the potential
+    pub box_length: Option<f64>, // Length of the periodic box along the pulling axis, None when unbounded
+    pub walls: (f64, f64), // Reflecting walls bounding the pulling axis, infinite when absent
+    pub counterdiabatic: bool, // Whether the trap carries the shortcut term that cancels the lag
+}
+
+// Driven parameters of the trap at one instant: its centre lambda along the
+// pulling axis and its stiffness, fixed unless the protocol drives it too
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Control {
+    pub centre: f64,
+    pub stiffness: f64,
+}
+
+// Initial condition of each trajectory before any equilibration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Initial {
+    Boltzmann, // Drawn exactly from the Boltzmann distribution at the initial lambda
+    Trap,      // At rest at the trap centre, relying on the equilibration period
+}
+
+impl FromStr for Initial {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "boltzmann" | "equilibrium" => Ok(Initial::Boltzmann),
+            "trap" | "centre" => Ok(Initial::Trap),
+            _ => Err(format!("unknown initial condition '{}'", name)),
+        }
+    }
+}
+
+// Outcome of a single pulling trajectory
+pub struct Trajectory {
+    pub work: f64,          // Work done on the particle by moving the trap
+    pub heat: f64,          // Energy absorbed from the bath through friction and noise
+    pub energy_change: f64, // Total energy change, Delta U = W + Q up to discretisation error
+    pub final_state: State, // Phase-space point at the end of the protocol
+    pub entropy_production: Option<f64>, // Total entropy production in units of k_B, when the model tracks it
+}
+
+impl TrapParams {
+    // The trap at lambda with the configured stiffness
+    pub fn fixed(&self, lambda: f64) -> Control {
+        Control {
+            centre: lambda,
+            stiffness: self.stiffness,
+        }
+    }
+
+    // The trap at time t of a protocol, with the configured stiffness unless
+    // the protocol drives it
+    pub fn control(&self, protocol: &Protocol, t: f64) -> Control {
+        let target = |t: f64| Control {
+            centre: protocol.lambda_at(t),
+            stiffness: protocol.stiffness_at(t).unwrap_or(self.stiffness),
+        };
+        let control = target(t);
+        if !self.counterdiabatic || t < 0.5 * self.dt || t > protocol.duration - 0.5 * self.dt {
+            return control;
+        }
+
+        // Shortcut to isothermality for overdamped motion in a harmonic trap
+        // (Martinez et al. 2016, Li et al. 2017): with kappa' = kappa +
+        // gamma kappa_dot / (2 kappa) and the centre led by gamma lambda_dot /
+        // kappa', the density stays the equilibrium one of the target trap at
+        // every instant. Rates are central differences over one step; the
+        // target trap itself is used at both ends, where the drive is at rest.
+        let (before, after) = (target(t - 0.5 * self.dt), target(t + 0.5 * self.dt));
+        let stiffness = control.stiffness + self.friction * (after.stiffness - before.stiffness) / (2.0 * self.dt * control.stiffness);
+        Control {
+            centre: control.centre + self.friction * (after.centre - before.centre) / (self.dt * stiffness),
+            stiffness,
+        }
+    }
+
+    // Position x mapped into the periodic box [-L/2, L/2), unchanged without one
+    pub fn wrap(&self, x: f64) -> f64 {
+        match self.box_length {
+            Some(length) => x - length * (x / length + 0.5).floor(),
+            None => x,
+        }
+    }
+
+    // Shortest periodic image of a displacement along the pulling axis, so the
+    // particle feels only the nearest copy of the trap
+    pub fn minimum_image(&self, dx: f64) -> f64 {
+        match self.box_length {
+            Some(length) => dx - length * (dx / length).round(),
+            None => dx,
+        }
+    }
+
+    // Whether a position along the pulling axis lies between the walls
+    pub fn inside(&self, x: f64) -> bool {
+        (self.walls.0..=self.walls.1).contains(&x)
+    }
+
+    // Whether either wall is in place
+    pub fn walled(&self) -> bool {
+        self.walls.0.is_finite() || self.walls.1.is_finite()
+    }
+
+    // Bring the particle back into the simulated region after a move: wrapped
+    // into a periodic box, or mirrored off the reflecting walls with its velocity
+    // reversed. The walls are static, so the potential energy the mirror image
+    // gains or loses is exchanged with the bath and returned as heat.
+    pub fn confine(&self, state: &mut State, control: Control) -> f64 {
+        state.x[0] = self.wrap(state.x[0]);
+        if self.inside(state.x[0]) {
+            return 0.0;
+        }
+        let before = self.potential(&state.x, control);
+        let (lo, hi) = self.walls;
+        let (x, width) = (state.x[0], hi - lo);
+        // Between two walls the mirror images repeat every 2 (hi - lo), the
+        // second half of each period reflected an odd number of times; past a
+        // lone wall there is a single reflection. Either way a position that
+        // is not finite stays so rather than bouncing for ever.
+        let (folded, reflected) = if width.is_finite() {
+            let y = (x - lo).rem_euclid(2.0 * width);
+            if y <= width {
+                (lo + y, false)
+            } else {
+                (hi - (y - width), true)
+            }
+        } else if x < lo {
+            (2.0 * lo - x, true)
+        } else {
+            (2.0 * hi - x, true)
+        };
+        state.x[0] = folded;
+        if reflected {
+            state.v[0] = -state.v[0];
+        }
+        self.potential(&state.x, control) - before
+    }
+
+    // Static landscape tilted by the constant external force, U0(x) - f x
+    pub fn landscape_energy(&self, x: f64) -> f64 {
+        self.landscape.energy(x) - self.tilt * x
+    }
+
+    // Potential energy of the particle at position r in the given trap
+    pub fn potential(&self, r: &Vector, control: Control) -> f64 {
+        if let Some(expression) = &self.expression {
+            return expression.eval(&[r[0], control.centre]) - self.tilt * r[0];
+        }
+        let transverse: f64 = r[1..self.dimensions].iter().map(|y| y * y).sum();
+        let along = self.minimum_image(r[0] - control.centre);
+        self.landscape_energy(r[0]) + 0.5 * control.stiffness * (along * along + transverse)
+    }
+
+    // Total force exerted on the particle by the landscape and the trap
+    pub fn force(&self, r: &Vector, control: Control) -> Vector {
+        if let Some(expression) = &self.expression {
+            return [self.tilt - expression.derivative(&[r[0], control.centre]), 0.0, 0.0];
+        }
+        let mut force = [0.0; 3];
+        force[0] = self.landscape.force(r[0]) + self.tilt - control.stiffness * self.minimum_image(r[0] - control.centre);
+        for d in 1..self.dimensions {
+            force[d] = -control.stiffness * r[d];
+        }
+        force
+    }
+
+    // Total energy, including kinetic energy when the integrator is inertial
+    pub fn energy(&self, state: &State, control: Control) -> f64 {
+        let kinetic = if self.integrator.is_inertial() {
+            0.5 * self.mass * state.v.iter().map(|v| v * v).sum::<f64>()
+        } else {
+            0.0
+        };
+        self.potential(&state.x, control) + kinetic
+    }
+
+    // Number of integration steps needed to cover a protocol
+    pub fn steps(&self, protocol: &Protocol) -> usize {
+        (protocol.duration / self.dt).round() as usize
+    }
+}
+
+// Draw a uniform deviate in [0, 1) from the thread-local RNG
+pub fn uniform() -> f64 {
+    RNG.with(|rng| rng.borrow_mut().uniform())
+}
+
+// Draw a standard normal deviate from the thread-local RNG, by the sampler it was built with
+pub fn gaussian() -> f64 {
+    RNG.with(|rng| rng.borrow_mut().generate_gaussian())
+}
+
+// Fill a slice with uniform deviates in [0, 1) from the thread-local RNG,
+// borrowing it once for the whole slice
+pub fn fill_uniform(values: &mut [f64]) {
+    RNG.with(|rng| rng.borrow_mut().fill_uniform(values))
+}
+
+// Fill a slice with standard normal deviates from the thread-local RNG, borrowing it once
+pub fn fill_gaussian(values: &mut [f64]) {
+    RNG.with(|rng| rng.borrow_mut().fill_gaussian(values))
+}
+
+// Draw an exponential waiting time of the given rate from the thread-local RNG
+pub fn exponential(rate: f64) -> f64 {
+    RNG.with(|rng| distributions::exponential(&mut **rng.borrow_mut(), rate))
+}
+
+// Draw a gamma variate of the given shape and scale from the thread-local RNG
+pub fn gamma(shape: f64, scale: f64) -> f64 {
+    RNG.with(|rng| distributions::gamma(&mut **rng.borrow_mut(), shape, scale))
+}
+
+// Draw a Poisson count of the given mean from the thread-local RNG
+pub fn poisson(mean: f64) -> u64 {
+    RNG.with(|rng| distributions::poisson(&mut **rng.borrow_mut(), mean))
+}
+
+// Potential along the pulling axis tabulated on a grid of half-width
+// sample_range around the trap centre, or over exactly one periodic box when
+// there is one, cut down to the walls, returned with the grid start and spacing
+pub fn tabulate(params: &TrapParams, control: Control) -> (f64, f64, Vec<f64>) {
+    let reach = params.box_length.map_or(params.sample_range, |length| 0.5 * length);
+    let (lo, span) = sampling_window(params.walls, control.centre, reach);
+    let step = span / (SAMPLING_POINTS - 1) as f64;
+    let energies = (0..SAMPLING_POINTS)
+        .map(|i| lo + i as f64 * step)
+        .map(|x| if params.inside(x) { params.potential(&[x, 0.0, 0.0], control) } else { f64::INFINITY })
+        .collect();
+    (lo, step, energies)
+}
+
+// Start and width of the sampling grid: reach either side of the centre, cut
+// down to the walls, or where the walls leave none of that free, the stretch
+// as wide inside them next to the centre
+fn sampling_window((lo, hi): (f64, f64), centre: f64, reach: f64) -> (f64, f64) {
+    let (start, end) = ((centre - reach).max(lo), (centre + reach).min(hi));
+    if start == centre - reach && end == centre + reach {
+        (start, 2.0 * reach)
+    } else if start < end {
+        (start, end - start)
+    } else if lo > centre {
+        (lo, (lo + 2.0 * reach).min(hi) - lo)
+    } else {
+        let start = (hi - 2.0 * reach).max(lo);
+        (start, hi - start)
+    }
+}
+
+// Free energy F = -kT ln of the integral of exp(-U / kT) by quadrature on the
+// sampling grid along the pulling axis, plus the Gaussian integral over every
+// transverse axis, which depends on the trap only through its stiffness
+pub fn free_energy(params: &TrapParams, control: Control) -> f64 {
+    let (_, step, energies) = tabulate(params, control);
+    let (kt, beta) = (params.temperature.kt(), params.temperature.beta());
+    let floor = energies.iter().cloned().fold(f64::INFINITY, f64::min);
+    let sum: f64 = energies.iter().map(|e| (-(e - floor) * beta).exp()).sum();
+    let transverse = (params.dimensions - 1) as f64 * 0.5 * kt * (control.stiffness / (2.0 * PI * kt)).ln();
+    floor - kt * (sum * step).ln() + transverse
+}
+
+// Sample a position from exp(-U(x, lambda) / kT) tabulated on a grid around
+// lambda, inverting the cumulative distribution with linear interpolation;
+// the half cells at the walls are kept inside them
+fn sample_tabulated(params: &TrapParams, control: Control) -> f64 {
+    let (lo, step, energies) = tabulate(params, control);
+    let floor = energies.iter().cloned().fold(f64::INFINITY, f64::min);
+
+    let mut cumulative = Vec::with_capacity(SAMPLING_POINTS);
+    let mut total = 0.0;
+    for e in &energies {
+        total += (-(e - floor) * params.temperature.beta()).exp();
+        cumulative.push(total);
+    }
+
+    let target = uniform() * total;
+    let i = cumulative.partition_point(|&c| c < target).min(SAMPLING_POINTS - 1);
+    let below = if i == 0 { 0.0 } else { cumulative[i - 1] };
+    let fraction = (target - below) / (cumulative[i] - below);
+    (lo + (i as f64 - 0.5 + fraction) * step).clamp(params.walls.0, params.walls.1)
+}
+
+// Sample a phase-space point from the Boltzmann distribution in a fixed trap.
+// A parabolic landscape makes the distribution along the pulling axis Gaussian,
+// drawn directly. Otherwise positions are proposed from the Gaussian of the trap
+// and the tilt, centred at lambda + f/k, and accepted with probability
+// exp(-(U0(x) - min U0) / kT), which is exact for any landscape bounded below.
+// Transverse axes feel only the trap and are Gaussian. Expression potentials
+// have no known minimum, a periodic box truncates the trap's Gaussian at its
+// nearest image, and walls may leave little or none of it between them, so
+// all three are sampled from a table.
+fn sample_boltzmann(params: &TrapParams, control: Control) -> State {
+    let kt = params.temperature.kt();
+    let (lambda, k) = (control.centre, control.stiffness);
+    let spread = (kt / k).sqrt();
+    let mut state = State::at(0.0);
+    state.x[0] = if params.expression.is_some() || params.box_length.is_some() || params.walled() {
+        sample_tabulated(params, control)
+    } else if let Some((stiffness, centre)) = params.landscape.quadratic() {
+        let total = k + stiffness;
+        (k * lambda + stiffness * centre + params.tilt) / total + (kt / total).sqrt() * gaussian()
+    } else {
+        let floor = params.landscape.minimum();
+        loop {
+            let x = lambda + params.tilt / k + spread * gaussian();
+            if uniform() < (-(params.landscape.energy(x) - floor) * params.temperature.beta()).exp() {
+                break x;
+            }
+        }
+    };
+    for d in 1..params.dimensions {
+        state.x[d] = spread * gaussian();
+    }
+    if params.integrator.is_inertial() {
+        for d in 0..params.dimensions {
+            state.v[d] = (kt / params.mass).sqrt() * gaussian();
+        }
+    }
+    state
+}
+
+// Starting point of a trajectory in a fixed trap: the configured initial
+// condition inside any periodic box or walls, with any colored bath force drawn
+// from its stationary Gaussian, then the equilibration period integrated with
+// the trap held still
+pub fn sample_equilibrium(params: &TrapParams, control: Control) -> State {
+    let mut state = match params.initial {
+        Initial::Boltzmann => sample_boltzmann(params, control),
+        Initial::Trap => State::at(control.centre.clamp(params.walls.0, params.walls.1)),
+    };
+    state.x[0] = params.wrap(state.x[0]);
+    if let Some(tau) = params.correlation_time {
+        let spread = (params.friction * params.temperature.kt() / tau).sqrt();
+        for d in 0..params.dimensions {
+            state.noise[d] = spread * gaussian();
+        }
+    }
+    for _ in 0..(params.equilibration / params.dt).round() as usize {
+        params.integrator.step(params, &mut state, control);
+    }
+    state
+}
+
+// Integrate one trajectory with the configured integrator, recording work and
+// heat separately (Sekimoto). Each step first moves the trap at fixed x, which
+// is work, then lets the particle evolve at fixed lambda, exchanging heat with
+// the bath as measured by the integrator from the friction and noise forces.
+//
+// With a nonzero escort the particle is also translated by escort times the
+// trap displacement as the trap moves, and the energy change of that combined
+// move is the escorted work of Vaikuntanathan & Jarzynski (EPL 2008). The map
+// is a translation with unit Jacobian, so <exp(-beta W)> = exp(-beta Delta F)
+// and the Crooks relation still hold for the escorted work, while a good
+// escort keeps the particle near equilibrium and shrinks the dissipation:
+// k / (k + kappa) is perfect for a harmonic landscape of stiffness kappa.
+pub fn run_trajectory(params: &TrapParams, protocol: &Protocol, start: State) -> Trajectory {
+    run_recorded(params, protocol, start, |_, _, _| {})
+}
+
+// As run_trajectory, calling observe(step, state, work so far) at the start and
+// after every step, with the trap at lambda(step * dt)
+pub fn run_recorded(
+    params: &TrapParams,
+    protocol: &Protocol,
+    start: State,
+    mut observe: impl FnMut(usize, &State, f64),
+) -> Trajectory {
+    let mut state = start;
+    let mut work = 0.0;
+    let mut heat = 0.0;
+    let mut control = params.control(protocol, 0.0);
+    let initial_energy = params.energy(&state, control);
+    observe(0, &state, work);
+
+    for step in 1..=params.steps(protocol) {
+        let next = params.control(protocol, step as f64 * params.dt);
+        if let Some(tolerance) = params.tolerance {
+            let t = (step - 1) as f64 * params.dt;
+            let (w, q) = integrator::adaptive_step(params, protocol, &mut state, t, tolerance);
+            work += w;
+            heat += q;
+        } else {
+            let mut escorted = state;
+            escorted.x[0] += params.escort * (next.centre - control.centre);
+            work += params.potential(&escorted.x, next) - params.potential(&state.x, control);
+            state = escorted;
+            heat += params.integrator.step(params, &mut state, next);
+        }
+        control = next;
+        observe(step, &state, work);
+    }
+
+    Trajectory {
+        work,
+        heat,
+        energy_change: params.energy(&state, control) - initial_energy,
+        final_state: state,
+        entropy_production: None,
+    }
+}
+
+impl Model for TrapParams {
+    fn temperature(&self) -> Temperature {
+        self.temperature
+    }
+
+    fn energy(&self, state: &State, lambda: f64) -> f64 {
+        TrapParams::energy(self, state, self.fixed(lambda))
+    }
+
+    fn sample_equilibrium(&self, lambda: f64) -> State {
+        sample_equilibrium(self, self.fixed(lambda))
+    }
+
+    // Equilibrium in the initial trap, including its stiffness when driven
+    fn sample_start(&self, protocol: &Protocol) -> State {
+        sample_equilibrium(self, self.control(protocol, 0.0))
+    }
+
+    fn run_trajectory(&self, protocol: &Protocol, start: State) -> Trajectory {
+        run_trajectory(self, protocol, start)
+    }
+
+    // Known for flat and harmonic landscapes, where the potential is quadratic
+    fn exact_free_energy(&self, lambda: f64) -> Option<f64> {
+        analytic::free_energy(self, self.fixed(lambda))
+    }
+
+    fn exact_delta_f(&self, protocol: &Protocol) -> Option<f64> {
+        let end = analytic::free_energy(self, self.control(protocol, protocol.duration))?;
+        Some(end - analytic::free_energy(self, self.control(protocol, 0.0))?)
+    }
+}
@@ -0,0 +1,116 @@
+// sixel.rs
+// The animation as sixel images written to the terminal, which terminals such
+// as xterm, mlterm, foot and WezTerm draw as pixels, so a remote session sees
+// the real frames without X forwarding
+
+use crate::animation::Scene;
+use crate::config::Config;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+// Levels per channel of the colour cube the frames are quantised to, which
+// with 216 registers stays within the 256 terminals commonly offer
+const LEVELS: u32 = 6;
+const REGISTERS: usize = (LEVELS * LEVELS * LEVELS) as usize;
+
+// Shortest time between frames, so a fast terminal plays at the window's pace
+const FRAME_TIME: Duration = Duration::from_millis(33);
+
+// Colour register of a 0RGB pixel, its channels rounded to the nearest level
+fn register(pixel: u32) -> usize {
+    let level = |shift: u32| ((pixel >> shift & 0xff) * (LEVELS - 1) + 127) / 255;
+    ((level(16) * LEVELS + level(8)) * LEVELS + level(0)) as usize
+}
+
+// A frame as one sixel image: the palette of the registers it uses, then bands
+// of six rows, each drawn colour by colour with runs of a character compressed
+pub fn encode(buffer: &[u32], width: usize, height: usize) -> String {
+    let registers: Vec<usize> = buffer.iter().map(|&pixel| register(pixel)).collect();
+    let mut used = [false; REGISTERS];
+    for &r in &registers {
+        used[r] = true;
+    }
+
+    // Pixel aspect 1:1, unset pixels left as they are, then the raster size
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", width, height);
+    let levels = LEVELS as usize;
+    let percent = |level: usize| level * 100 / (levels - 1);
+    for r in (0..REGISTERS).filter(|&r| used[r]) {
+        let (red, green, blue) = (r / (levels * levels), r / levels % levels, r % levels);
+        out.push_str(&format!("#{};2;{};{};{}", r, percent(red), percent(green), percent(blue)));
+    }
+
+    let mut bits = vec![0u8; REGISTERS * width];
+    for top in (0..height).step_by(6) {
+        // Six bits a column for every register present in the band
+        let mut in_band = [false; REGISTERS];
+        let mut present = Vec::new();
+        for dy in 0..6.min(height - top) {
+            for (x, &r) in registers[(top + dy) * width..][..width].iter().enumerate() {
+                if !in_band[r] {
+                    in_band[r] = true;
+                    present.push(r);
+                }
+                bits[r * width + x] |= 1 << dy;
+            }
+        }
+        for (n, &r) in present.iter().enumerate() {
+            if n > 0 {
+                out.push('$');
+            }
+            out.push_str(&format!("#{}", r));
+            let row = &mut bits[r * width..][..width];
+            let mut x = 0;
+            while x < width {
+                let run = row[x..].iter().take_while(|&&b| b == row[x]).count();
+                let character = (0x3f + row[x]) as char;
+                match run {
+                    1..=3 => (0..run).for_each(|_| out.push(character)),
+                    _ => out.push_str(&format!("!{}{}", run, character)),
+                }
+                x += run;
+            }
+            row.fill(0);
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+// Write the configured number of frames to the terminal, each at the top-left
+// corner over the last, showing what a window of the given size would
+pub fn run(config: &Config, width: usize, height: usize) -> Result<(), String> {
+    let (frame_width, frame_height) = (config.sixel_width, config.sixel_width * height / width);
+    let mut scene = Scene::new(config, frame_width, frame_height);
+    scene.fit(width, height);
+    let mut stdout = std::io::stdout().lock();
+    let fail = |e: std::io::Error| format!("sixel: {}", e);
+    // Clear the screen and hide the cursor while the frames play
+    write!(stdout, "\x1b[2J\x1b[?25l").map_err(fail)?;
+    let start = Instant::now();
+    for _ in 0..config.frames {
+        let started = Instant::now();
+        let mut buffer = scene.render(config);
+        if config.overlay {
+            scene.draw_overlay(&mut buffer, &scene.describe(config));
+        }
+        write!(stdout, "\x1b[H{}", encode(&buffer, frame_width, frame_height)).map_err(fail)?;
+        stdout.flush().map_err(fail)?;
+        scene.advance();
+        if let Some(wait) = FRAME_TIME.checked_sub(started.elapsed()) {
+            std::thread::sleep(wait);
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    writeln!(
+        stdout,
+        "\x1b[?25h\nShowed {} frames of {}x{} in {:.1} s ({:.1} frames/s)",
+        config.frames,
+        frame_width,
+        frame_height,
+        elapsed,
+        config.frames as f64 / elapsed.max(1e-9)
+    )
+    .map_err(fail)
+}
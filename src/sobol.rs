@@ -0,0 +1,117 @@
+// sobol.rs
+// Sobol low-discrepancy sequence behind the UniformRng trait, for quasi-random ensembles
+
+use crate::generators::splitmix64;
+use crate::unirand::{GaussianMethod, UniformRng};
+use std::sync::OnceLock;
+
+// Coordinates taken from the sequence; later draws of a trajectory are pseudo-random
+pub const SOBOL_DIMENSIONS: usize = 21;
+
+// Bits of every coordinate, enough for 2^32 points
+const BITS: usize = 32;
+
+// Degree s, inner coefficients a and initial direction numbers m_1..m_s of
+// the primitive polynomial behind each dimension after the first, which is
+// the van der Corput sequence (Joe and Kuo 2008)
+const POLYNOMIALS: [(usize, u32, &[u32]); SOBOL_DIMENSIONS - 1] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+    (6, 19, &[1, 1, 1, 15, 7, 5]),
+    (6, 22, &[1, 3, 1, 15, 13, 25]),
+    (6, 25, &[1, 1, 5, 5, 19, 61]),
+    (7, 1, &[1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, &[1, 3, 7, 13, 13, 15, 69]),
+];
+
+// Direction numbers v_b = m_(b+1) 2^(31-b) of every dimension, the m beyond
+// the initial ones from Bratley and Fox's recurrence, built on first use
+fn directions() -> &'static [[u32; BITS]; SOBOL_DIMENSIONS] {
+    static TABLE: OnceLock<[[u32; BITS]; SOBOL_DIMENSIONS]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [[0u32; BITS]; SOBOL_DIMENSIONS];
+        for (b, v) in table[0].iter_mut().enumerate() {
+            *v = 1 << (BITS - 1 - b);
+        }
+        for (row, &(s, a, m)) in table[1..].iter_mut().zip(&POLYNOMIALS) {
+            for (b, &mi) in m.iter().enumerate() {
+                row[b] = mi << (BITS - 1 - b);
+            }
+            for b in s..BITS {
+                row[b] = row[b - s] ^ (row[b - s] >> s);
+                for k in 1..s {
+                    if (a >> (s - 1 - k)) & 1 == 1 {
+                        row[b] ^= row[b - k];
+                    }
+                }
+            }
+        }
+        table
+    })
+}
+
+// Point `index` of a Sobol sequence, one coordinate per draw, randomised by a
+// digital shift shared by every point so that estimates stay unbiased and
+// independent seeds give independent replicates. Once the dimensions run out,
+// draws come from a pseudo-random generator.
+pub struct SobolRng {
+    index: u64,
+    dimension: usize, // Coordinate the next draw takes
+    shifts: [u32; SOBOL_DIMENSIONS],
+    tail: Box<dyn UniformRng>, // Generator of the draws past the last dimension
+}
+
+impl SobolRng {
+    // Sequence randomised by a seed, over a pseudo-random generator that supplies
+    // the Gaussian sampler and, split alongside, the draws past the last dimension
+    pub fn new(seed: i32, tail: Box<dyn UniformRng>) -> Self {
+        let mut sequence = seed as u64;
+        Self {
+            index: 0,
+            dimension: 0,
+            shifts: [(); SOBOL_DIMENSIONS].map(|_| splitmix64(&mut sequence) as u32),
+            tail,
+        }
+    }
+}
+
+impl UniformRng for SobolRng {
+    // Coordinate of the point in Gray-code order (Antonov and Saleev 1979)
+    fn uniform(&mut self) -> f64 {
+        if self.dimension >= SOBOL_DIMENSIONS {
+            return self.tail.uniform();
+        }
+        let gray = self.index ^ (self.index >> 1);
+        let row = &directions()[self.dimension];
+        let bits = (0..BITS).filter(|b| (gray >> b) & 1 == 1).fold(self.shifts[self.dimension], |x, b| x ^ row[b]);
+        self.dimension += 1;
+        bits as f64 * (1.0 / 4_294_967_296.0)
+    }
+
+    fn gaussian_method(&self) -> GaussianMethod {
+        self.tail.gaussian_method()
+    }
+
+    // Point `stream` of the same randomised sequence
+    fn split(&self, stream: u64) -> Box<dyn UniformRng> {
+        Box::new(Self {
+            index: stream,
+            dimension: 0,
+            shifts: self.shifts,
+            tail: self.tail.split(stream),
+        })
+    }
+}
@@ -0,0 +1,89 @@
+// spin.rs
+// Two-level system flipped by a heat bath in a time-dependent field
+
+use crate::integrator::State;
+use crate::model::Model;
+use crate::protocol::Protocol;
+use crate::simulation::{uniform, Trajectory};
+use crate::units::Temperature;
+
+// A spin sigma = +-1 with energy E = -h sigma, where the field h is the control
+// parameter lambda. The spin is stored as the position of a State on axis 0.
+pub struct SpinParams {
+    pub rate: f64, // Attempt rate of spin flips
+    pub temperature: Temperature,
+    pub dt: f64, // Time step of the discrete-time dynamics
+}
+
+impl SpinParams {
+    // Probability of flipping during one step with the Glauber (heat-bath) rule,
+    // which satisfies detailed balance at every fixed field
+    fn flip_probability(&self, sigma: f64, field: f64) -> f64 {
+        let cost = 2.0 * field * sigma;
+        (self.rate * self.dt).min(1.0) / (1.0 + (cost * self.temperature.beta()).exp())
+    }
+
+    // Equilibrium probability of spin up at fixed field
+    fn up_probability(&self, field: f64) -> f64 {
+        1.0 / (1.0 + (-2.0 * field * self.temperature.beta()).exp())
+    }
+}
+
+impl Model for SpinParams {
+    fn temperature(&self) -> Temperature {
+        self.temperature
+    }
+
+    fn energy(&self, state: &State, lambda: f64) -> f64 {
+        -lambda * state.x[0]
+    }
+
+    fn sample_equilibrium(&self, lambda: f64) -> State {
+        State::at(if uniform() < self.up_probability(lambda) { 1.0 } else { -1.0 })
+    }
+
+    // Each step changes the field at fixed spin, which is work, then attempts
+    // a flip at fixed field, whose energy change is heat from the bath. The
+    // probability of spin up is propagated alongside by the same discrete-time
+    // master equation, giving the system entropy -ln p(sigma, t) exactly.
+    fn run_trajectory(&self, protocol: &Protocol, start: State) -> Trajectory {
+        let mut state = start;
+        let mut work = 0.0;
+        let mut heat = 0.0;
+        let mut field = protocol.lambda_start();
+        let initial_energy = self.energy(&state, field);
+        let mut up = self.up_probability(field);
+        let occupation = |up: f64, sigma: f64| if sigma > 0.0 { up } else { 1.0 - up };
+        let initial_occupation = occupation(up, state.x[0]);
+
+        let steps = (protocol.duration / self.dt).round() as usize;
+        for step in 1..=steps {
+            let next_field = protocol.lambda_at(step as f64 * self.dt);
+            work += self.energy(&state, next_field) - self.energy(&state, field);
+            field = next_field;
+            up += (1.0 - up) * self.flip_probability(-1.0, field) - up * self.flip_probability(1.0, field);
+
+            if uniform() < self.flip_probability(state.x[0], field) {
+                heat += 2.0 * field * state.x[0];
+                state.x[0] = -state.x[0];
+            }
+        }
+
+        Trajectory {
+            work,
+            heat,
+            energy_change: self.energy(&state, field) - initial_energy,
+            final_state: state,
+            entropy_production: Some(
+                initial_occupation.ln() - occupation(up, state.x[0]).ln() - heat * self.temperature.beta(),
+            ),
+        }
+    }
+
+    // F(h) = -kT ln(2 cosh(beta h)), written to stay finite for strong fields
+    fn exact_free_energy(&self, lambda: f64) -> Option<f64> {
+        let beta = self.temperature.beta();
+        let a = (beta * lambda).abs();
+        Some(-(a + (-2.0 * a).exp().ln_1p()) / beta)
+    }
+}
@@ -0,0 +1,75 @@
+// steady.rs
+// Periodic driving into a non-equilibrium steady state, with the work per cycle
+
+use crate::model::Model;
+use crate::protocol::Protocol;
+use crate::unirand;
+use rayon::prelude::*;
+
+// Work and heat of every recorded cycle, indexed by trajectory then cycle
+pub struct SteadyState {
+    pub work: Vec<Vec<f64>>,
+    pub heat: Vec<Vec<f64>>,
+}
+
+impl SteadyState {
+    // Mean work per cycle over all trajectories and recorded cycles
+    pub fn mean_work(&self) -> f64 {
+        mean(&self.work)
+    }
+
+    // Mean heat absorbed per cycle over all trajectories and recorded cycles
+    pub fn mean_heat(&self) -> f64 {
+        mean(&self.heat)
+    }
+
+    // Work accumulated over consecutive, non-overlapping blocks of n cycles in
+    // every trajectory; trailing cycles that do not fill a block are dropped
+    pub fn block_work(&self, n: usize) -> Vec<f64> {
+        self.work
+            .iter()
+            .flat_map(|cycles| cycles.chunks_exact(n).map(|block| block.iter().sum::<f64>()))
+            .collect()
+    }
+}
+
+fn mean(values: &[Vec<f64>]) -> f64 {
+    let count: usize = values.iter().map(Vec::len).sum();
+    values.iter().flatten().sum::<f64>() / count as f64
+}
+
+// Drive each trajectory through the protocol and straight back along its time
+// reverse, so that lambda is periodic whatever the schedule, and repeat without
+// ever relaxing: every cycle starts where the previous one ended. The first
+// transient cycles carry the system from equilibrium into the steady state and
+// are discarded; the next cycles are recorded. Trajectory i draws from its own
+// substream i of the seed, as for the transient ensembles.
+pub fn run(model: &dyn Model, protocol: &Protocol, transient: usize, cycles: usize, trajectories: usize, seed: i32) -> SteadyState {
+    let legs = [protocol.clone(), protocol.reversed()];
+    let results: Vec<(Vec<f64>, Vec<f64>)> = (0..trajectories)
+        .into_par_iter()
+        .map(|i| {
+            unirand::seed_stream(seed, i);
+            let mut state = model.sample_start(protocol);
+            let mut work = Vec::with_capacity(cycles);
+            let mut heat = Vec::with_capacity(cycles);
+            for cycle in 0..transient + cycles {
+                let (mut w, mut q) = (0.0, 0.0);
+                for leg in &legs {
+                    let trajectory = model.run_trajectory(leg, state);
+                    w += trajectory.work;
+                    q += trajectory.heat;
+                    state = trajectory.final_state;
+                }
+                if cycle >= transient {
+                    work.push(w);
+                    heat.push(q);
+                }
+            }
+            (work, heat)
+        })
+        .collect();
+
+    let (work, heat) = results.into_iter().unzip();
+    SteadyState { work, heat }
+}
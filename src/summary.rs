@@ -0,0 +1,79 @@
+// summary.rs
+// Moments of a work distribution, one record shared by the reports and the CSV exporters
+
+use crate::ensemble::PairedEnsembles;
+use crate::protocol::Direction;
+use std::fs;
+
+// Shape of the work distribution of one protocol direction
+pub struct WorkSummary {
+    pub direction: Direction,
+    pub samples: usize,
+    pub mean: f64,
+    pub variance: f64,   // Sample variance, with the N - 1 denominator
+    pub skewness: f64,   // Third standardized moment, zero for a Gaussian
+    pub kurtosis: f64,   // Excess kurtosis, zero for a Gaussian
+    pub dissipated: f64, // Mean dissipated work <W> - Delta F of this direction
+}
+
+impl WorkSummary {
+    // Column names matching csv_fields
+    pub const CSV_HEADER: &'static str = "direction,N,mean,variance,skewness,kurtosis,W_diss";
+
+    // Moments of the work samples of one direction. Delta F is that of the
+    // forward protocol; the reverse protocol dissipates <W_R> + Delta F.
+    pub fn new(direction: Direction, work: &[f64], delta_f: f64) -> Self {
+        let n = work.len() as f64;
+        let mean = work.iter().sum::<f64>() / n;
+        let moment = |k: i32| work.iter().map(|w| (w - mean).powi(k)).sum::<f64>() / n;
+        let m2 = moment(2);
+        let delta_f = match direction {
+            Direction::Forward => delta_f,
+            Direction::Reverse => -delta_f,
+        };
+        Self {
+            direction,
+            samples: work.len(),
+            mean,
+            variance: m2 * n / (n - 1.0).max(1.0),
+            skewness: moment(3) / m2.powf(1.5),
+            kurtosis: moment(4) / (m2 * m2) - 3.0,
+            dissipated: mean - delta_f,
+        }
+    }
+
+    // Summaries of both directions of a forward and reverse pair
+    pub fn paired(ensembles: &PairedEnsembles, delta_f: f64) -> [Self; 2] {
+        [&ensembles.forward, &ensembles.reverse].map(|e| Self::new(e.direction, &e.work, delta_f))
+    }
+
+    // Comma-separated values in the order of CSV_HEADER
+    pub fn csv_fields(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{}",
+            format!("{:?}", self.direction).to_lowercase(),
+            self.samples,
+            self.mean,
+            self.variance,
+            self.skewness,
+            self.kurtosis,
+            self.dissipated
+        )
+    }
+}
+
+// Write summaries as CSV lines, each prefixed by its own values of the given
+// key columns (e.g. the temperature of a replica); no keys for a single run
+pub fn write_csv(path: &str, keys: &[&str], rows: &[(Vec<f64>, WorkSummary)]) -> Result<(), String> {
+    let mut text: String = keys.iter().map(|k| format!("{},", k)).collect();
+    text.push_str(WorkSummary::CSV_HEADER);
+    text.push('\n');
+    for (values, summary) in rows {
+        for v in values {
+            text.push_str(&format!("{},", v));
+        }
+        text.push_str(&summary.csv_fields());
+        text.push('\n');
+    }
+    fs::write(path, text).map_err(|e| format!("summary: cannot write {}: {}", path, e))
+}
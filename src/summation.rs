@@ -0,0 +1,20 @@
+// summation.rs
+// Compensated summation for long sums of terms of very different sizes
+
+// Sum with Neumaier's variant of Kahan compensation: the rounding error of
+// every addition is carried in a separate term and added back at the end, so
+// the error no longer grows with the number of terms, even when a term is
+// larger than the running sum
+pub fn compensated_sum(values: impl IntoIterator<Item = f64>) -> f64 {
+    let (mut sum, mut compensation) = (0.0f64, 0.0);
+    for value in values {
+        let next = sum + value;
+        compensation += if sum.abs() >= value.abs() {
+            (sum - next) + value
+        } else {
+            (value - next) + sum
+        };
+        sum = next;
+    }
+    sum + compensation
+}
@@ -0,0 +1,155 @@
+// surface.rs
+// The field as a height-mapped surface seen from an angle: a mesh over every
+// few pixels of the frame, raised by the normalised value, coloured as the
+// flat frame, lit from a fixed direction and drawn with a depth buffer
+
+// Frame pixels between mesh nodes
+const MESH_STEP: usize = 4;
+
+// Height of the surface at a normalised value of one, relative to the longer
+// side of the frame
+const RELIEF: f64 = 0.15;
+
+// Direction the light comes from, in the frame's coordinates (x right, y up
+// the screen, z out of it), and the share of the colour that is unlit
+const LIGHT: [f64; 3] = [-0.4, 0.5, 0.77];
+const AMBIENT: f64 = 0.35;
+
+// Colour behind the surface
+const BACKGROUND: u32 = 0x101018;
+
+// Share of the frame the surface's bounding box fills
+const FILL: f64 = 0.9;
+
+// Orthographic camera turned azimuth degrees about the vertical and looking
+// down elevation degrees from the horizontal
+#[derive(Clone, Copy)]
+struct Camera {
+    right: [f64; 3],
+    up: [f64; 3],
+    toward: [f64; 3], // Toward the viewer, so larger depths are nearer
+}
+
+impl Camera {
+    fn new(azimuth: f64, elevation: f64) -> Self {
+        let (sin_a, cos_a) = azimuth.to_radians().sin_cos();
+        let (sin_e, cos_e) = elevation.to_radians().sin_cos();
+        Self {
+            right: [cos_a, sin_a, 0.0],
+            up: [-sin_a * sin_e, cos_a * sin_e, cos_e],
+            toward: [sin_a * cos_e, -cos_a * cos_e, sin_e],
+        }
+    }
+
+    // Screen position (right, up) and depth of a point
+    fn project(&self, p: [f64; 3]) -> (f64, f64, f64) {
+        let dot = |axis: [f64; 3]| axis[0] * p[0] + axis[1] * p[1] + axis[2] * p[2];
+        (dot(self.right), dot(self.up), dot(self.toward))
+    }
+}
+
+// A frame of the given size showing the surface of heights in [0, 1] at every
+// pixel, each point in the colour of the flat frame's pixel under it. At
+// elevation 90 the surface is seen from straight above, as the flat frame.
+pub fn draw(heights: &[f64], colours: &[u32], width: usize, height: usize, azimuth: f64, elevation: f64) -> Vec<u32> {
+    let mut buffer = vec![BACKGROUND; width * height];
+    if width < 2 || height < 2 {
+        return buffer;
+    }
+    let camera = Camera::new(azimuth, elevation);
+    let size = width.max(height) as f64;
+    let world = |x: usize, y: usize| {
+        let h = heights[y * width + x];
+        let h = if h.is_finite() { h.clamp(0.0, 1.0) } else { 0.0 };
+        [(x as f64 - width as f64 / 2.0) / size, (height as f64 / 2.0 - y as f64) / size, h * RELIEF]
+    };
+
+    // Scale and offset fitting the box the surface can fill to the frame, so
+    // the picture keeps its size as the heights change
+    let (half_x, half_y) = (width as f64 / 2.0 / size, height as f64 / 2.0 / size);
+    let corners = [-1.0, 1.0]
+        .iter()
+        .flat_map(|&sx| [-1.0, 1.0].map(move |sy| (sx, sy)))
+        .flat_map(|(sx, sy)| [0.0, RELIEF].map(move |z| camera.project([sx * half_x, sy * half_y, z])));
+    let (mut low, mut high) = ((f64::INFINITY, f64::INFINITY), (f64::NEG_INFINITY, f64::NEG_INFINITY));
+    for (x, y, _) in corners {
+        low = (low.0.min(x), low.1.min(y));
+        high = (high.0.max(x), high.1.max(y));
+    }
+    let scale = FILL * (width as f64 / (high.0 - low.0)).min(height as f64 / (high.1 - low.1));
+    let centre = ((low.0 + high.0) / 2.0, (low.1 + high.1) / 2.0);
+    let to_screen = |p: [f64; 3]| {
+        let (x, y, depth) = camera.project(p);
+        (width as f64 / 2.0 + (x - centre.0) * scale, height as f64 / 2.0 - (y - centre.1) * scale, depth)
+    };
+
+    // Mesh nodes every MESH_STEP pixels, the last row and column included
+    let nodes = |length: usize| -> Vec<usize> {
+        let mut nodes: Vec<usize> = (0..length - 1).step_by(MESH_STEP).collect();
+        nodes.push(length - 1);
+        nodes
+    };
+    let (columns, rows) = (nodes(width), nodes(height));
+    let light = {
+        let norm = LIGHT.iter().map(|c| c * c).sum::<f64>().sqrt();
+        LIGHT.map(|c| c / norm)
+    };
+    let mut depth = vec![f64::NEG_INFINITY; width * height];
+    for j in 0..rows.len() - 1 {
+        for i in 0..columns.len() - 1 {
+            let (x0, x1, y0, y1) = (columns[i], columns[i + 1], rows[j], rows[j + 1]);
+            let quad = [world(x0, y0), world(x1, y0), world(x1, y1), world(x0, y1)];
+
+            // Lambertian shading by the normal of the quad's diagonals
+            let (a, b) = (sub(quad[2], quad[0]), sub(quad[3], quad[1]));
+            let normal = [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+            let norm = normal.iter().map(|c| c * c).sum::<f64>().sqrt().max(1e-12);
+            let lit = (normal[0] * light[0] + normal[1] * light[1] + normal[2] * light[2]).abs() / norm;
+            let colour = shade(colours[y0 * width + x0], AMBIENT + (1.0 - AMBIENT) * lit);
+
+            let screen = quad.map(to_screen);
+            for triangle in [[screen[0], screen[1], screen[2]], [screen[0], screen[2], screen[3]]] {
+                fill(&mut buffer, &mut depth, width, height, triangle, colour);
+            }
+        }
+    }
+    buffer
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+// A 0RGB colour with every channel scaled by the brightness
+fn shade(colour: u32, brightness: f64) -> u32 {
+    let channel = |shift: u32| (((colour >> shift & 0xff) as f64 * brightness).round() as u32).min(255) << shift;
+    channel(16) | channel(8) | channel(0)
+}
+
+// Fill the pixels whose centres lie in the triangle of screen points where it
+// is nearer than what is drawn there, its depth interpolated across it
+fn fill(buffer: &mut [u32], depth: &mut [f64], width: usize, height: usize, triangle: [(f64, f64, f64); 3], colour: u32) {
+    let [p0, p1, p2] = triangle;
+    let area = (p1.0 - p0.0) * (p2.1 - p0.1) - (p2.0 - p0.0) * (p1.1 - p0.1);
+    if area.abs() < 1e-12 {
+        return;
+    }
+    let x_range = p0.0.min(p1.0).min(p2.0).floor().max(0.0) as usize..=(p0.0.max(p1.0).max(p2.0).ceil().min(width as f64 - 1.0)) as usize;
+    let y_range = p0.1.min(p1.1).min(p2.1).floor().max(0.0) as usize..=(p0.1.max(p1.1).max(p2.1).ceil().min(height as f64 - 1.0)) as usize;
+    let edge = |a: (f64, f64, f64), b: (f64, f64, f64), x: f64, y: f64| ((b.0 - a.0) * (y - a.1) - (x - a.0) * (b.1 - a.1)) / area;
+    for y in y_range {
+        for x in x_range.clone() {
+            let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+            let (w0, w1, w2) = (edge(p1, p2, px, py), edge(p2, p0, px, py), edge(p0, p1, px, py));
+            if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                continue;
+            }
+            let z = w0 * p0.2 + w1 * p1.2 + w2 * p2.2;
+            let i = y * width + x;
+            if z > depth[i] {
+                depth[i] = z;
+                buffer[i] = colour;
+            }
+        }
+    }
+}
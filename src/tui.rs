@@ -0,0 +1,253 @@
+// tui.rs
+// The animation in a terminal: the field downsampled to coloured half-block
+// characters beside the live work histograms and Delta F estimates, for use
+// over SSH where there is no display
+
+use crate::analysis;
+use crate::animation::Scene;
+use crate::config::Config;
+use crate::histogram::{Histogram, Normalization};
+use crate::live::{Convergence, LiveRun};
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::symbols::Marker;
+use ratatui::widgets::{Axis, Block, Chart, Dataset, GraphType, Paragraph, Widget};
+use ratatui::{DefaultTerminal, Frame};
+use std::time::{Duration, Instant};
+
+// Width in cells of the statistics column on the right
+const SIDE_WIDTH: u16 = 44;
+
+// Shortest time between frames, which also bounds the wait for a key
+const FRAME_TIME: Duration = Duration::from_millis(33);
+
+// Weight of the newest frame in the smoothed frame rate
+const FPS_SMOOTHING: f64 = 0.1;
+
+// Most recent batches shown by the convergence chart
+const HISTORY: usize = 100;
+
+// Series colours, as in the plot windows
+const FORWARD: Color = Color::Red;
+const REVERSE: Color = Color::Blue;
+const BAR: Color = Color::LightBlue;
+const EXACT: Color = Color::Gray;
+
+// Field pixels drawn two to a cell: the upper one as the foreground of an
+// upper half block and the lower one as its background
+struct FieldView<'a> {
+    pixels: &'a [u32],
+    width: usize,
+}
+
+impl Widget for FieldView<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let colour = |x: usize, y: usize| {
+            let pixel = self.pixels[y * self.width + x];
+            Color::Rgb((pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8)
+        };
+        for row in 0..area.height {
+            for column in 0..area.width {
+                let (x, y) = (column as usize, 2 * row as usize);
+                buf[(area.x + column, area.y + row)]
+                    .set_char('▀')
+                    .set_fg(colour(x, y))
+                    .set_bg(colour(x, y + 1));
+            }
+        }
+    }
+}
+
+// State of the terminal animation between frames
+struct TuiState {
+    scene: Scene,
+    plane: (usize, usize), // Plane rectangle the field is fitted to
+    live: LiveRun,
+    paused: bool,
+    fps: f64,
+}
+
+impl TuiState {
+    // Draw the field on the left and the statistics column on the right
+    fn draw(&mut self, frame: &mut Frame, config: &Config) {
+        let [field_area, side] = Layout::horizontal([Constraint::Min(4), Constraint::Length(SIDE_WIDTH)]).areas(frame.area());
+        let block = Block::bordered().title(" Crooks field ");
+        let inner = block.inner(field_area);
+        frame.render_widget(block, field_area);
+        let (width, height) = (inner.width as usize, 2 * inner.height as usize);
+        if width > 0 && height > 0 {
+            // The whole of the window's picture, whatever the terminal's size
+            if (width, height) != (self.scene.width, self.scene.height) {
+                self.scene.resize(width, height);
+                self.scene.fit(self.plane.0, self.plane.1);
+            }
+            let pixels = self.scene.render(config);
+            frame.render_widget(FieldView { pixels: &pixels, width }, inner);
+        }
+
+        let samples = self.live.snapshot();
+        let mut lines = vec![format!("{:.1} fps{}", self.fps, if self.paused { ", paused" } else { "" })];
+        lines.extend(self.scene.describe(config));
+        lines.push(format!("trajectories {}/{}", samples.forward.len(), self.live.trajectories));
+        if let Some(latest) = samples.history.last() {
+            lines.push(format!("Jarzynski F {:.4}, R {:.4}", latest.jarzynski_forward, latest.jarzynski_reverse));
+            lines.push(format!("BAR {:.4}", latest.bar));
+        }
+        if let Some(exact) = self.live.exact_delta_f {
+            lines.push(format!("exact {:.4}", exact));
+        }
+        lines.push("q quits, space pauses, c v x i l cycle".to_string());
+        let [stats, histograms, convergence] = Layout::vertical([
+            Constraint::Length(lines.len() as u16 + 2),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+        ])
+        .areas(side);
+        frame.render_widget(Paragraph::new(lines.join("\n")).block(Block::bordered().title(" Statistics ")), stats);
+
+        if !samples.forward.is_empty() && !samples.reverse.is_empty() {
+            let (forward, reverse) = analysis::work_histograms(&samples.forward, &samples.reverse, config.bins);
+            draw_histograms(frame, histograms, &forward, &reverse);
+        }
+        let recent = &samples.history[samples.history.len().saturating_sub(HISTORY)..];
+        draw_convergence(frame, convergence, recent, self.live.exact_delta_f);
+    }
+
+    // Act on a key; false when it asks to quit
+    fn key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char(' ') => self.paused = !self.paused,
+            KeyCode::Right if self.paused => self.scene.advance(),
+            KeyCode::Char('c') => self.scene.colormap = self.scene.colormap.next(),
+            KeyCode::Char('v') => self.scene.colour_scale = self.scene.colour_scale.next(),
+            KeyCode::Char('x') => self.scene.split = !self.scene.split,
+            KeyCode::Char('i') => self.scene.surface = !self.scene.surface,
+            KeyCode::Char('l') => self.scene.contours = !self.scene.contours,
+            _ => {}
+        }
+        true
+    }
+}
+
+// P_F(W) and P_R(-W) as densities on their shared grid
+fn draw_histograms(frame: &mut Frame, area: Rect, forward: &Histogram, reverse: &Histogram) {
+    let points = |histogram: &Histogram| -> Vec<(f64, f64)> {
+        let heights = histogram.heights(Normalization::Density);
+        heights.iter().enumerate().map(|(i, &h)| (histogram.centre(i), h)).collect()
+    };
+    let (forward_points, reverse_points) = (points(forward), points(reverse));
+    let x_min = forward.min;
+    let x_max = forward.min + forward.width * forward.counts.len() as f64;
+    let y_max = forward_points.iter().chain(&reverse_points).map(|p| p.1).fold(0.0, f64::max).max(1e-12);
+    let chart = Chart::new(vec![
+        line("P_F(W)", &forward_points, FORWARD),
+        line("P_R(-W)", &reverse_points, REVERSE),
+    ])
+    .block(Block::bordered().title(" Work histograms "))
+    .x_axis(axis(x_min, x_max))
+    .y_axis(axis(0.0, y_max));
+    frame.render_widget(chart, area);
+}
+
+// Running Jarzynski and BAR estimates against the trajectories run, with the
+// exact Delta F when the model knows it
+fn draw_convergence(frame: &mut Frame, area: Rect, history: &[Convergence], exact: Option<f64>) {
+    let block = Block::bordered().title(" Delta F ");
+    let (Some(first), Some(last)) = (history.first(), history.last()) else {
+        frame.render_widget(block, area);
+        return;
+    };
+    let curve = |estimate: fn(&Convergence) -> f64| -> Vec<(f64, f64)> {
+        history.iter().map(|c| (c.samples as f64, estimate(c))).filter(|p| p.1.is_finite()).collect()
+    };
+    let forward = curve(|c| c.jarzynski_forward);
+    let reverse = curve(|c| c.jarzynski_reverse);
+    let bar = curve(|c| c.bar);
+    let (x_min, x_max) = (first.samples as f64, (last.samples as f64).max(first.samples as f64 + 1.0));
+    let exact_line: Vec<(f64, f64)> = exact.map(|e| vec![(x_min, e), (x_max, e)]).unwrap_or_default();
+    let values = || forward.iter().chain(&reverse).chain(&bar).chain(&exact_line).map(|p| p.1);
+    let (y_min, y_max) = (values().fold(f64::INFINITY, f64::min), values().fold(f64::NEG_INFINITY, f64::max));
+    if y_min > y_max {
+        frame.render_widget(block, area);
+        return;
+    }
+    let pad = (y_max - y_min).max(1e-9) * 0.05;
+    let mut datasets = vec![
+        line("Jarzynski F", &forward, FORWARD),
+        line("Jarzynski R", &reverse, REVERSE),
+        line("BAR", &bar, BAR),
+    ];
+    if !exact_line.is_empty() {
+        datasets.push(line("exact", &exact_line, EXACT));
+    }
+    let chart = Chart::new(datasets)
+        .block(block)
+        .x_axis(axis(x_min, x_max))
+        .y_axis(axis(y_min - pad, y_max + pad));
+    frame.render_widget(chart, area);
+}
+
+// A named line through the points in Braille dots
+fn line<'a>(name: &'a str, points: &'a [(f64, f64)], colour: Color) -> Dataset<'a> {
+    Dataset::default()
+        .name(name)
+        .marker(Marker::Braille)
+        .graph_type(GraphType::Line)
+        .style(Style::default().fg(colour))
+        .data(points)
+}
+
+// An axis over [min, max] labelled at its ends
+fn axis<'a>(min: f64, max: f64) -> Axis<'a> {
+    Axis::default()
+        .bounds([min, max])
+        .labels([format!("{:.3}", min), format!("{:.3}", max)])
+        .style(Style::default().fg(EXACT))
+}
+
+// Run the animation until q or Esc, fitting the plane of a window of the given
+// size to the terminal
+fn run_loop(terminal: &mut DefaultTerminal, config: &Config, width: usize, height: usize) -> Result<(), String> {
+    let mut state = TuiState {
+        scene: Scene::new(config, width, height),
+        plane: (width, height),
+        live: LiveRun::start(config),
+        paused: false,
+        fps: 0.0,
+    };
+    let mut last_frame = Instant::now();
+    loop {
+        let started = Instant::now();
+        terminal.draw(|frame| state.draw(frame, config)).map_err(|e| format!("tui: {}", e))?;
+
+        // Keys until the frame's time is up
+        while let Some(wait) = FRAME_TIME.checked_sub(started.elapsed()) {
+            if !event::poll(wait).map_err(|e| format!("tui: {}", e))? {
+                break;
+            }
+            if let Event::Key(key) = event::read().map_err(|e| format!("tui: {}", e))? {
+                if key.kind == KeyEventKind::Press && !state.key(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+        let now = Instant::now();
+        let rate = 1.0 / (now - last_frame).as_secs_f64().max(1e-6);
+        state.fps = if state.fps == 0.0 { rate } else { state.fps + FPS_SMOOTHING * (rate - state.fps) };
+        last_frame = now;
+        if !state.paused {
+            state.scene.advance();
+        }
+    }
+}
+
+// Take over the terminal for the animation and give it back afterwards
+pub fn run(config: &Config, width: usize, height: usize) -> Result<(), String> {
+    let mut terminal = ratatui::try_init().map_err(|e| format!("tui: {}", e))?;
+    let result = run_loop(&mut terminal, config, width, height);
+    ratatui::restore();
+    result
+}
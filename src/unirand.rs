@@ -1,9 +1,190 @@
 // unirand.rs
 
+use crate::antithetic::MirroredRng;
+use crate::generators::{self, Pcg64, Philox4x32, Xoshiro256};
+use crate::latin::LatinRng;
+use crate::sobol::SobolRng;
+use rand::{Error, RngCore, SeedableRng};
 use std::cell::RefCell;
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
 
 const LEN_U: usize = 98;
 
+// First word of a saved generator state
+const STATE_TAG: &str = "marsaglia";
+
+// Layers of the ziggurat, with the start of its tail and the common layer area
+// for 128 layers (Marsaglia and Tsang 2000)
+const ZIGGURAT_LAYERS: usize = 128;
+const ZIGGURAT_TAIL: f64 = 3.442619855899;
+const ZIGGURAT_AREA: f64 = 9.91256303526217e-3;
+
+// How normal deviates are made from the uniform stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GaussianMethod {
+    BoxMuller, // Two uniforms through a logarithm, square root and cosine each
+    Ziggurat,  // Mostly two uniforms and a table comparison, with rare exact fallbacks
+}
+
+impl FromStr for GaussianMethod {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "box-muller" | "boxmuller" => Ok(GaussianMethod::BoxMuller),
+            "ziggurat" => Ok(GaussianMethod::Ziggurat),
+            _ => Err(format!("unknown gaussian method '{}'", name)),
+        }
+    }
+}
+
+// Right edges x_i of the ziggurat layers, x_0 being the base strip's equivalent
+// width v / f(r) and x_128 = 0, with f(x) = exp(-x^2/2) at each edge
+struct Ziggurat {
+    edges: [f64; ZIGGURAT_LAYERS + 1],
+    heights: [f64; ZIGGURAT_LAYERS + 1],
+}
+
+// Ziggurat tables, built on first use and shared by every thread
+fn ziggurat() -> &'static Ziggurat {
+    static TABLES: OnceLock<Ziggurat> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let f = |x: f64| (-0.5 * x * x).exp();
+        let mut edges = [0.0; ZIGGURAT_LAYERS + 1];
+        edges[0] = ZIGGURAT_AREA / f(ZIGGURAT_TAIL);
+        edges[1] = ZIGGURAT_TAIL;
+        for i in 1..ZIGGURAT_LAYERS - 1 {
+            edges[i + 1] = (-2.0 * (ZIGGURAT_AREA / edges[i] + f(edges[i])).ln()).sqrt();
+        }
+        let heights = edges.map(f);
+        Ziggurat { edges, heights }
+    })
+}
+
+// Kind of uniform generator behind every stream of the run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RngKind {
+    Marsaglia, // Marsaglia's universal generator, two 24-bit draws per double
+    Xoshiro,   // xoshiro256++ (Blackman and Vigna 2019)
+    Pcg,       // PCG64, 128-bit LCG with XSL-RR output (O'Neill 2014)
+    Philox,    // Philox4x32-10, counter-based (Salmon et al. 2011)
+}
+
+impl FromStr for RngKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "marsaglia" => Ok(RngKind::Marsaglia),
+            "xoshiro" | "xoshiro256++" => Ok(RngKind::Xoshiro),
+            "pcg" | "pcg64" => Ok(RngKind::Pcg),
+            "philox" => Ok(RngKind::Philox),
+            _ => Err(format!("unknown rng '{}'", name)),
+        }
+    }
+}
+
+// How the trajectories of an ensemble draw their random numbers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sampling {
+    Pseudo, // Each trajectory its own pseudo-random substream
+    Sobol,  // Each trajectory one point of a randomised Sobol sequence, then its substream
+    Latin,  // Each trajectory one point of a Latin hypercube, then its substream
+}
+
+impl FromStr for Sampling {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "pseudo" => Ok(Sampling::Pseudo),
+            "sobol" => Ok(Sampling::Sobol),
+            "latin" | "lhs" => Ok(Sampling::Latin),
+            _ => Err(format!("unknown sampling '{}'", name)),
+        }
+    }
+}
+
+// A source of uniform deviates, from which normal deviates and independent
+// substreams are made the same way whatever the generator
+pub trait UniformRng: Send {
+    // Uniform deviate in [0, 1) carrying at least 48 random bits, so the noise
+    // of the integrators is not quantised at single precision
+    fn uniform(&mut self) -> f64;
+
+    // Sampler behind generate_gaussian, chosen at construction
+    fn gaussian_method(&self) -> GaussianMethod;
+
+    // Independent generator of the same kind for substream `stream` of this
+    // one's seed; the same seed and stream always give the same numbers
+    fn split(&self, stream: u64) -> Box<dyn UniformRng>;
+
+    // Standard normal deviate by the sampler chosen at construction
+    fn generate_gaussian(&mut self) -> f64 {
+        match self.gaussian_method() {
+            GaussianMethod::BoxMuller => box_muller(self),
+            GaussianMethod::Ziggurat => ziggurat_deviate(self),
+        }
+    }
+
+    // Fill a slice with uniform deviates, the same numbers as calling uniform
+    // once per element, for one borrow of the generator and no dynamic
+    // dispatch per draw
+    fn fill_uniform(&mut self, values: &mut [f64]) {
+        for value in values {
+            *value = self.uniform();
+        }
+    }
+
+    // Fill a slice with standard normal deviates, as fill_uniform
+    fn fill_gaussian(&mut self, values: &mut [f64]) {
+        for value in values {
+            *value = self.generate_gaussian();
+        }
+    }
+}
+
+// One Box-Muller deviate from two uniforms, discarding its sine partner
+fn box_muller<R: UniformRng + ?Sized>(rng: &mut R) -> f64 {
+    let u1 = 1.0 - rng.uniform(); // Shift to (0, 1] so ln(u1) is finite
+    let u2 = rng.uniform();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+// Ziggurat sampling: pick a layer and a signed point across its width,
+// accepted at once when it lies under the layer above; otherwise the wedge
+// is resolved against the density, and the base strip falls back to
+// Marsaglia's exact sampler for the tail beyond r
+fn ziggurat_deviate<R: UniformRng + ?Sized>(rng: &mut R) -> f64 {
+    let tables = ziggurat();
+    let f = |x: f64| (-0.5 * x * x).exp();
+    loop {
+        let layer = ((rng.uniform() * ZIGGURAT_LAYERS as f64) as usize).min(ZIGGURAT_LAYERS - 1);
+        let u = 2.0 * rng.uniform() - 1.0;
+        let x = u * tables.edges[layer];
+        if x.abs() < tables.edges[layer + 1] {
+            return x;
+        }
+        if layer == 0 {
+            loop {
+                let a = -(1.0 - rng.uniform()).ln() / ZIGGURAT_TAIL;
+                let b = -(1.0 - rng.uniform()).ln();
+                if 2.0 * b > a * a {
+                    return (ZIGGURAT_TAIL + a).copysign(u);
+                }
+            }
+        }
+        let (upper, lower) = (tables.heights[layer + 1], tables.heights[layer]);
+        if lower + rng.uniform() * (upper - lower) < f(x) {
+            return x;
+        }
+    }
+}
+
 // Marsaglia's Universal Random Number Generator (RNG) structure
 pub struct MarsagliaUniRng {
     recent_values: [f32; LEN_U], // Array holding the recent random numbers
@@ -12,11 +193,17 @@ pub struct MarsagliaUniRng {
     correction_modulus: f32,     // Correction modulus
     current_index: usize,        // Current position in the random values array
     second_index: usize,
+    gaussian: GaussianMethod,    // Sampler behind generate_gaussian
+    seed: i32,                   // Seed given to initialise, from which substreams split
 }
 
 impl MarsagliaUniRng {
-    // Constructor for the random number generator
-    pub fn new() -> Self {
+    // Constructor for the random number generator, choosing how
+    // generate_gaussian makes normal deviates
+    pub fn with_gaussian(gaussian: GaussianMethod) -> Self {
+        if gaussian == GaussianMethod::Ziggurat {
+            ziggurat();
+        }
         Self {
             recent_values: [0.0; LEN_U],
             correction: 0.0,
@@ -24,6 +211,8 @@ impl MarsagliaUniRng {
             correction_modulus: 0.0,
             current_index: 0,
             second_index: 0,
+            gaussian,
+            seed: 0,
         }
     }
 
@@ -58,6 +247,15 @@ impl MarsagliaUniRng {
         new_value
     }
 
+    // Generate a double between 0 and 1 carrying 48 random bits: every draw is
+    // a multiple of 2^-24, so a second draw scaled by 2^-24 fills in the bits
+    // below the first exactly, and the sum stays below 1
+    pub fn generate_f64(&mut self) -> f64 {
+        let high = self.generate() as f64;
+        let low = self.generate() as f64;
+        high + low * (1.0 / 16_777_216.0)
+    }
+
     // Initialise the random values array using four seeds
     pub fn start(&mut self, seed1: i32, seed2: i32, seed3: i32, seed4: i32) {
         let mut i = seed1;
@@ -80,6 +278,9 @@ impl MarsagliaUniRng {
             }
             self.recent_values[ii] = s;
         }
+        // Slot 0 is only ever written by generate; clear it so that reseeding
+        // starts the same stream whatever was drawn before
+        self.recent_values[0] = 0.0;
         self.correction = 362436.0 / 16777216.0;
         self.correction_delta = 7654321.0 / 16777216.0;
         self.correction_modulus = 16777213.0 / 16777216.0;
@@ -88,43 +289,394 @@ impl MarsagliaUniRng {
     }
 
     // Validate and decompose a single seed into four seeds, then initialise the random values array
-    pub fn initialise(&mut self, seed: i32) {
-        if seed < 0 || seed > 900_000_000 {
-            panic!("initialise: seed = {} -- out of range", seed);
+    pub fn initialise(&mut self, seed: i32) -> Result<(), SeedError> {
+        let (i, j, k, l) = seed_parts(seed)?;
+        self.seed = seed;
+        self.start(i, j, k, l);
+        Ok(())
+    }
+}
+
+// Why a seed cannot start the generator
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeedError {
+    SeedOutOfRange(i32),                 // Outside 0..=MAX_SEED
+    DegenerateSeeds(i32, i32, i32, i32), // Four seeds the lattice recurrence cannot start from
+}
+
+impl fmt::Display for SeedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SeedError::SeedOutOfRange(seed) => write!(f, "seed {} is outside 0 to {}", seed, MAX_SEED),
+            SeedError::DegenerateSeeds(i, j, k, l) => write!(f, "seeds {} {} {} {} cannot start the generator", i, j, k, l),
         }
+    }
+}
+
+// The four seeds i, j, k in 1..=178, not all 1, and l in 0..=168 that
+// a single seed decomposes into
+fn seed_parts(seed: i32) -> Result<(i32, i32, i32, i32), SeedError> {
+    if !(0..=MAX_SEED).contains(&seed) {
+        return Err(SeedError::SeedOutOfRange(seed));
+    }
 
-        let ij = seed / 30082;
-        let kl = seed - (30082 * ij);
-        let i = ((ij / 177) % 177) + 2;
-        let j = (ij % 177) + 2;
-        let k = ((kl / 169) % 178) + 1;
-        let l = kl % 169;
+    let ij = seed / 30082;
+    let kl = seed - (30082 * ij);
+    let i = ((ij / 177) % 177) + 2;
+    let j = (ij % 177) + 2;
+    let k = ((kl / 169) % 178) + 1;
+    let l = kl % 169;
 
-        if i <= 0 || i > 178 {
-            panic!("initialise: i = {} -- out of range", i);
+    let lattice = 1..=178;
+    if !lattice.contains(&i) || !lattice.contains(&j) || !lattice.contains(&k) || !(0..=168).contains(&l) || (i, j, k) == (1, 1, 1) {
+        return Err(SeedError::DegenerateSeeds(i, j, k, l));
+    }
+    Ok((i, j, k, l))
+}
+
+// Whether a seed can start the generator, checked once at startup so that
+// seeds from files or the command line fail with an error rather than a panic
+pub fn check_seed(seed: i32) -> Result<(), SeedError> {
+    seed_parts(seed).map(|_| ())
+}
+
+impl MarsagliaUniRng {
+    // The whole generator state as one line of text, the single-precision
+    // values as their bit patterns so that load_state resumes the stream bit
+    // for bit: seed, Gaussian sampler, the two indices, the correction, its
+    // delta and modulus, then the 98 recent values
+    pub fn save_state(&self) -> String {
+        let gaussian = match self.gaussian {
+            GaussianMethod::BoxMuller => "box-muller",
+            GaussianMethod::Ziggurat => "ziggurat",
+        };
+        let mut words = vec![
+            STATE_TAG.to_string(),
+            self.seed.to_string(),
+            gaussian.to_string(),
+            self.current_index.to_string(),
+            self.second_index.to_string(),
+        ];
+        let values = [self.correction, self.correction_delta, self.correction_modulus];
+        words.extend(values.iter().chain(&self.recent_values).map(|value| format!("{:08x}", value.to_bits())));
+        words.join(" ")
+    }
+
+    // Rebuild a generator from the text written by save_state
+    pub fn load_state(state: &str) -> Result<Self, String> {
+        let words: Vec<&str> = state.split_whitespace().collect();
+        if words.len() != 8 + LEN_U || words[0] != STATE_TAG {
+            return Err("load_state: not a saved generator state".to_string());
         }
-        if j <= 0 || j > 178 {
-            panic!("initialise: j = {} -- out of range", j);
+        let integer = |word: &str| word.parse::<i64>().map_err(|_| format!("load_state: bad number '{}'", word));
+        let float = |word: &str| {
+            u32::from_str_radix(word, 16)
+                .map(f32::from_bits)
+                .map_err(|_| format!("load_state: bad value '{}'", word))
+        };
+        let index = |word: &str| match integer(word)? {
+            i @ 0..=97 => Ok(i as usize),
+            i => Err(format!("load_state: index {} out of range", i)),
+        };
+
+        let mut rng = Self::with_gaussian(words[2].parse().map_err(|e| format!("load_state: {}", e))?);
+        rng.seed = integer(words[1])? as i32;
+        check_seed(rng.seed).map_err(|e| format!("load_state: {}", e))?;
+        rng.current_index = index(words[3])?;
+        rng.second_index = index(words[4])?;
+        rng.correction = float(words[5])?;
+        rng.correction_delta = float(words[6])?;
+        rng.correction_modulus = float(words[7])?;
+        for (value, word) in rng.recent_values.iter_mut().zip(&words[8..]) {
+            *value = float(word)?;
+        }
+        Ok(rng)
+    }
+}
+
+impl UniformRng for MarsagliaUniRng {
+    fn uniform(&mut self) -> f64 {
+        self.generate_f64()
+    }
+
+    fn gaussian_method(&self) -> GaussianMethod {
+        self.gaussian
+    }
+
+    fn split(&self, stream: u64) -> Box<dyn UniformRng> {
+        let mut rng = Self::with_gaussian(self.gaussian);
+        rng.initialise(stream_seed(self.seed, stream)).expect("stream seeds lie in range");
+        Box::new(rng)
+    }
+}
+
+// The generator as a rand engine, so rand and rand_distr distributions can draw
+// from it. Every uniform carries 24 random bits, so a 32-bit word takes the
+// whole of one draw and the top eight bits of the next.
+impl RngCore for MarsagliaUniRng {
+    fn next_u32(&mut self) -> u32 {
+        let bits = |value: f32| (value * 16_777_216.0) as u32;
+        let high = bits(self.generate());
+        let low = bits(self.generate());
+        (high << 8) | (low >> 16)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        ((self.next_u32() as u64) << 32) | self.next_u32() as u64
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
         }
-        if k <= 0 || k > 178 {
-            panic!("initialise: k = {} -- out of range", k);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+// Seeding from four little-endian bytes, reduced into the range initialise
+// accepts, with the Gaussian sampler selected for the run
+impl SeedableRng for MarsagliaUniRng {
+    type Seed = [u8; 4];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        let mut rng = Self::with_gaussian(gaussian_method());
+        rng.initialise((u32::from_le_bytes(seed) % (MAX_SEED as u32 + 1)) as i32)
+            .expect("seed reduced into range");
+        rng
+    }
+}
+
+// Gaussian sampler of every generator built from here on, Box-Muller unless selected
+static GAUSSIAN: OnceLock<GaussianMethod> = OnceLock::new();
+
+// Choose the Gaussian sampler for the whole run, before any stream is seeded;
+// later calls are ignored
+pub fn select_gaussian(method: GaussianMethod) {
+    let _ = GAUSSIAN.set(method);
+}
+
+fn gaussian_method() -> GaussianMethod {
+    GAUSSIAN.get().copied().unwrap_or(GaussianMethod::BoxMuller)
+}
+
+// Kind of generator behind every stream built from here on, Marsaglia's unless selected
+static KIND: OnceLock<RngKind> = OnceLock::new();
+
+// Choose the uniform generator for the whole run, before any stream is seeded;
+// later calls are ignored
+pub fn select_rng(kind: RngKind) {
+    let _ = KIND.set(kind);
+}
+
+// How ensemble trajectories are seeded, pseudo-randomly unless selected
+static SAMPLING: OnceLock<Sampling> = OnceLock::new();
+
+// Choose pseudo-random or quasi-random ensembles for the whole run; later calls are ignored
+pub fn select_sampling(sampling: Sampling) {
+    let _ = SAMPLING.set(sampling);
+}
+
+// Whether ensemble trajectories run in antithetic pairs, off unless selected
+static ANTITHETIC: OnceLock<bool> = OnceLock::new();
+
+// Choose antithetic trajectory pairs for the whole run; later calls are ignored
+pub fn select_antithetic(antithetic: bool) {
+    let _ = ANTITHETIC.set(antithetic);
+}
+
+// Whether trajectories 2k and 2k + 1 of every ensemble are antithetic partners
+pub fn antithetic() -> bool {
+    ANTITHETIC.get().copied().unwrap_or(false)
+}
+
+// Root generator of the selected kind and Gaussian sampler for a master seed,
+// from which substreams split
+fn root(master: i32) -> Box<dyn UniformRng> {
+    let gaussian = gaussian_method();
+    if gaussian == GaussianMethod::Ziggurat {
+        ziggurat();
+    }
+    match KIND.get().copied().unwrap_or(RngKind::Marsaglia) {
+        RngKind::Marsaglia => {
+            let mut rng = MarsagliaUniRng::with_gaussian(gaussian);
+            rng.initialise(master).expect("master seed checked at startup");
+            Box::new(rng)
         }
-        if l < 0 || l > 168 {
-            panic!("initialise: l = {} -- out of range", l);
+        RngKind::Xoshiro => Box::new(Xoshiro256::new(master, gaussian)),
+        RngKind::Pcg => Box::new(Pcg64::new(master, gaussian)),
+        RngKind::Philox => Box::new(Philox4x32::new(master, gaussian)),
+    }
+}
+
+// Master seed from which every thread's initial stream is derived, 12345 unless set
+static MASTER_SEED: OnceLock<i32> = OnceLock::new();
+
+// Threads whose generator has been built so far
+static THREADS: AtomicU64 = AtomicU64::new(0);
+
+// Set the master seed for the whole run, before any thread draws a number;
+// later calls are ignored
+pub fn set_master_seed(seed: i32) {
+    let _ = MASTER_SEED.set(seed);
+}
+
+// Seeds the Marsaglia generator accepts
+const SEEDS: u64 = MAX_SEED as u64 + 1;
+
+// Substreams counted down from u64::MAX, the threads' initial streams, that
+// the Marsaglia generator keeps apart from the rest
+const THREAD_STREAMS: u64 = 1 << 16;
+
+// Substreams counted up from zero that the Marsaglia generator keeps apart:
+// every seed not left to the threads
+pub const MAX_STREAMS: u64 = SEEDS - THREAD_STREAMS;
+
+// Halves of the 30-bit words the seeds are shuffled as, and the rounds of the
+// Feistel network that shuffles them
+const HALF_BITS: u32 = 15;
+const HALF_MASK: u64 = (1 << HALF_BITS) - 1;
+const FEISTEL_ROUNDS: u64 = 6;
+
+// Round function of the Feistel network
+fn feistel(half: u64, round: u64) -> u64 {
+    let mut state = (half << 8) | round;
+    generators::splitmix64(&mut state) & HALF_MASK
+}
+
+// The seeds in a scrambled order: a bijection of 0..SEEDS, a Feistel
+// network over 30-bit words walked round until it lands back among the
+// seeds, and its inverse
+fn seed_at(position: u64) -> u64 {
+    let mut word = position;
+    loop {
+        let (mut left, mut right) = (word >> HALF_BITS, word & HALF_MASK);
+        for round in 0..FEISTEL_ROUNDS {
+            (left, right) = (right, left ^ feistel(right, round));
         }
-        if i == 1 && j == 1 && k == 1 {
-            panic!("initialise: 1 1 1 not allowed for first 3 seeds");
+        word = (left << HALF_BITS) | right;
+        if word < SEEDS {
+            return word;
         }
+    }
+}
 
-        self.start(i, j, k, l);
+fn position_of(seed: u64) -> u64 {
+    let mut word = seed;
+    loop {
+        let (mut left, mut right) = (word >> HALF_BITS, word & HALF_MASK);
+        for round in (0..FEISTEL_ROUNDS).rev() {
+            (left, right) = (right ^ feistel(left, round), left);
+        }
+        word = (left << HALF_BITS) | right;
+        if word < SEEDS {
+            return word;
+        }
     }
 }
 
-// Thread-local storage for the random number generator
+// Seed of substream `stream` of a master seed. The seeds stand in a ring in
+// the scrambled order of seed_at, and the substreams of a master are the seeds
+// following it round the ring, the threads' streams the ones before it. So
+// distinct substreams of a master always get distinct seeds, the substreams of
+// substream k are those of the master from k on, and neighbouring master seeds
+// sit at unrelated points of the ring rather than overlapping as seed + i
+// would. Panics for substreams beyond MAX_STREAMS, which would come round again.
+pub fn stream_seed(master: i32, stream: u64) -> i32 {
+    let offset = if stream < MAX_STREAMS {
+        stream
+    } else if u64::MAX - stream < THREAD_STREAMS {
+        SEEDS - 1 - (u64::MAX - stream)
+    } else {
+        panic!("substream {} is beyond the {} the Marsaglia generator keeps apart", stream, MAX_STREAMS);
+    };
+    seed_at((position_of(master as u64) + offset) % SEEDS) as i32
+}
+
+// The n-th thread's initial stream, counted down from the last substream of
+// the master seed so it never meets a trajectory's stream
+fn thread_stream(thread: u64) -> Box<dyn UniformRng> {
+    root(MASTER_SEED.get().copied().unwrap_or(12345)).split(u64::MAX - thread)
+}
+
+// Thread-local storage for the random number generator, each thread starting
+// its own stream
 thread_local! {
-    pub static RNG: RefCell<MarsagliaUniRng> = RefCell::new({
-        let mut rng = MarsagliaUniRng::new();
-        rng.initialise(12345); // Initialise with a seed value
-        rng
-    });
+    pub static RNG: RefCell<Box<dyn UniformRng>> = RefCell::new(thread_stream(THREADS.fetch_add(1, Ordering::Relaxed)));
+}
+
+// Largest seed accepted by `initialise`
+pub const MAX_SEED: i32 = 900_000_000;
+
+// Rebuild this thread's generator, of the selected kind and Gaussian sampler,
+// as substream `stream` of a master seed, so that item `stream` of a parallel
+// ensemble draws the same numbers whichever worker runs it and in whatever order
+pub fn seed_stream(master: i32, stream: usize) {
+    let split = root(master).split(stream as u64);
+    RNG.with(|rng| *rng.borrow_mut() = split);
+}
+
+// Rebuild this thread's generator for trajectory `trajectory` of an ensemble
+// of `trajectories`: substream `trajectory` of the seed, or with Sobol or
+// Latin hypercube sampling point `trajectory` of a Sobol sequence or
+// hypercube randomised by the seed, whose first draws are stratified and the
+// rest from that substream. With antithetic pairs, trajectories 2k and 2k + 1
+// share the generator of pair k, the second mirrored.
+pub fn seed_trajectory(master: i32, trajectory: usize, trajectories: usize) {
+    let (base, count) = if antithetic() {
+        (trajectory / 2, trajectories.div_ceil(2))
+    } else {
+        (trajectory, trajectories)
+    };
+    let point = match SAMPLING.get().copied().unwrap_or(Sampling::Pseudo) {
+        Sampling::Pseudo => root(master).split(base as u64),
+        Sampling::Sobol => SobolRng::new(master, root(master)).split(base as u64),
+        Sampling::Latin => LatinRng::new(master, count, root(master)).split(base as u64),
+    };
+    let point = if antithetic() && trajectory % 2 == 1 {
+        Box::new(MirroredRng { inner: point })
+    } else {
+        point
+    };
+    RNG.with(|rng| *rng.borrow_mut() = point);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensemble::REVERSE_STREAM;
+    use std::collections::HashSet;
+
+    #[test]
+    fn seed_order_is_a_bijection() {
+        for seed in (0..SEEDS).step_by(9_973).chain(SEEDS - 100..SEEDS) {
+            let position = position_of(seed);
+            assert!(position < SEEDS);
+            assert_eq!(seed_at(position), seed);
+        }
+    }
+
+    #[test]
+    fn substreams_never_share_a_seed() {
+        // Forward and reverse trajectories and thread streams of one run
+        let master = 12345;
+        let streams = (0..100_000).chain(REVERSE_STREAM..REVERSE_STREAM + 100_000).chain((0..64).map(|t| u64::MAX - t));
+        let mut seen = HashSet::new();
+        for stream in streams {
+            assert!(seen.insert(stream_seed(master, stream)), "substream {} repeats a seed", stream);
+        }
+    }
+
+    #[test]
+    fn substreams_of_a_substream_follow_the_master() {
+        let master = 777;
+        let start = stream_seed(master, 1000);
+        for stream in 0..100 {
+            assert_eq!(stream_seed(start, stream), stream_seed(master, 1000 + stream));
+        }
+    }
 }
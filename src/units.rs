@@ -0,0 +1,77 @@
+// units.rs
+// Unit systems and the thermal energy scale shared by simulation and estimators.
+// Reduced units (the default) measure energy in whatever scale kT is given in,
+// usually kT = 1. SI units take the bath temperature in kelvin and expect
+// lengths in m, times in s, masses in kg, stiffness in N/m and friction in kg/s.
+
+use std::str::FromStr;
+
+// Boltzmann constant in J/K
+pub const BOLTZMANN: f64 = 1.380649e-23;
+
+// Reduced Planck constant in J s
+pub const HBAR: f64 = 1.054571817e-34;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnitSystem {
+    Reduced,
+    Si,
+}
+
+impl FromStr for UnitSystem {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "reduced" => Ok(UnitSystem::Reduced),
+            "si" | "SI" => Ok(UnitSystem::Si),
+            _ => Err(format!("unknown unit system '{}'", name)),
+        }
+    }
+}
+
+// Thermal energy of the bath, carried as kT with beta = 1/kT derived from it
+#[derive(Clone, Copy, Debug)]
+pub struct Temperature {
+    kt: f64,
+}
+
+impl Temperature {
+    // Bath with thermal energy kT given directly in the working energy unit
+    pub fn from_kt(kt: f64) -> Self {
+        Self { kt }
+    }
+
+    // Bath at an absolute temperature in kelvin, for SI units
+    pub fn from_kelvin(kelvin: f64) -> Self {
+        Self { kt: BOLTZMANN * kelvin }
+    }
+
+    // Thermal energy kT
+    pub fn kt(&self) -> f64 {
+        self.kt
+    }
+
+    // Inverse temperature beta = 1/kT
+    pub fn beta(&self) -> f64 {
+        1.0 / self.kt
+    }
+}
+
+impl UnitSystem {
+    // Format a bare energy value, in scientific notation for SI joules
+    pub fn format_value(&self, energy: f64) -> String {
+        match self {
+            UnitSystem::Reduced => format!("{:.4}", energy),
+            UnitSystem::Si => format!("{:.4e}", energy),
+        }
+    }
+
+    // Format an energy for reports, adding its size in kT when working in SI
+    pub fn format_energy(&self, energy: f64, temperature: Temperature) -> String {
+        match self {
+            UnitSystem::Reduced => format!("{:.4}", energy),
+            UnitSystem::Si => format!("{:.4e} J ({:.4} kT)", energy, energy * temperature.beta()),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// wham.rs
+// Weighted histogram analysis method stitching umbrella windows into one profile
+
+use crate::estimators::log_sum_exp;
+use crate::histogram::Histogram;
+use crate::profile::Profile;
+use crate::units::Temperature;
+
+const MAX_ITERATIONS: usize = 100_000;
+const TOLERANCE: f64 = 1e-10;
+
+// Positions sampled with the control parameter held at lambda
+pub struct Window {
+    pub lambda: f64,
+    pub positions: Vec<f64>,
+}
+
+// Unbiased profile together with the dimensionless window free energies f_k
+pub struct WhamResult {
+    pub profile: Profile,
+    pub window_free_energies: Vec<f64>,
+    pub iterations: usize,
+}
+
+// Solve the WHAM equations (Kumar et al. 1992) on a shared histogram grid
+//   P(x) = sum_k n_k(x) / sum_k N_k exp(f_k - beta u_k(x))
+//   exp(-f_k) = sum_x P(x) exp(-beta u_k(x))
+// in log space, where u_k is the bias energy of window k, and return
+// G(x) = -kT ln P(x) relative to its minimum. With a periodic box the grid
+// spans the whole box and positions wrap into it.
+pub fn wham(
+    windows: &[Window],
+    bias: impl Fn(f64, f64) -> f64,
+    temperature: Temperature,
+    bins: usize,
+    box_length: Option<f64>,
+) -> WhamResult {
+    let (kt, beta) = (temperature.kt(), temperature.beta());
+    let sets: Vec<&[f64]> = windows.iter().map(|w| w.positions.as_slice()).collect();
+
+    let mut total = Histogram::for_positions(sets.iter().cloned(), box_length, bins);
+    for window in windows {
+        window.positions.iter().for_each(|&x| total.add(x));
+    }
+    let positions: Vec<f64> = (0..bins).map(|i| total.centre(i)).collect();
+    let reduced_bias: Vec<Vec<f64>> = windows
+        .iter()
+        .map(|w| positions.iter().map(|&x| beta * bias(x, w.lambda)).collect())
+        .collect();
+    let ln_samples: Vec<f64> = windows.iter().map(|w| (w.positions.len() as f64).ln()).collect();
+
+    let mut f = vec![0.0; windows.len()];
+    let mut ln_p = vec![f64::NEG_INFINITY; bins];
+    let mut iterations = 0;
+    while iterations < MAX_ITERATIONS {
+        iterations += 1;
+        for (b, ln) in ln_p.iter_mut().enumerate() {
+            *ln = if total.counts[b] == 0 {
+                f64::NEG_INFINITY
+            } else {
+                let ln_denominator = log_sum_exp((0..windows.len()).map(|k| ln_samples[k] + f[k] - reduced_bias[k][b]));
+                (total.counts[b] as f64).ln() - ln_denominator
+            };
+        }
+        let mut next: Vec<f64> = reduced_bias
+            .iter()
+            .map(|u| -log_sum_exp(ln_p.iter().zip(u).map(|(p, u)| p - u)))
+            .collect();
+        let shift = next[0];
+        next.iter_mut().for_each(|v| *v -= shift);
+
+        let change = next.iter().zip(&f).map(|(a, b)| (a - b).abs()).fold(0.0, f64::max);
+        f = next;
+        if change < TOLERANCE {
+            break;
+        }
+    }
+
+    let mut free_energy: Vec<f64> = ln_p
+        .iter()
+        .map(|&ln| if ln.is_finite() { -kt * ln } else { f64::NAN })
+        .collect();
+    let floor = free_energy.iter().cloned().filter(|g| g.is_finite()).fold(f64::INFINITY, f64::min);
+    free_energy.iter_mut().for_each(|g| *g -= floor);
+
+    WhamResult {
+        profile: Profile { positions, free_energy },
+        window_free_energies: f,
+        iterations,
+    }
+}